@@ -0,0 +1,70 @@
+//! Adblock throughput on a synthetic EasyList-sized list.
+//!
+//!     cargo bench --bench adblock
+//!
+//! `NYAN_BENCH_FILTERS` and `NYAN_BENCH_REQUESTS` change the list size and
+//! the number of requests checked.
+
+use nyan_browser::features::adblock::{AdBlocker, Request, ResourceType};
+use nyan_browser::test_util::{synthetic_filter_list, synthetic_requests};
+use std::time::{Duration, Instant};
+
+fn env_or(name: &str, default: usize) -> usize {
+    std::env::var(name)
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(default)
+}
+
+fn report(what: &str, elapsed: Duration) {
+    println!("{:<32} {:>10.2} ms", what, elapsed.as_secs_f64() * 1000.0);
+}
+
+fn main() {
+    let filters = env_or("NYAN_BENCH_FILTERS", 60_000);
+    let requests = env_or("NYAN_BENCH_REQUESTS", 100_000);
+
+    let dir = std::env::temp_dir().join(format!("nyan-adblock-bench-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let list = dir.join("synthetic.txt");
+    std::fs::write(&list, synthetic_filter_list(filters, 42)).unwrap();
+    let cache = dir.join("filters.cache.json");
+    let lists = [list];
+
+    let start = Instant::now();
+    let blocker = AdBlocker::new();
+    let stats = blocker.load_lists_cached(&lists, &cache);
+    report("compile (cold cache)", start.elapsed());
+
+    let start = Instant::now();
+    let warm = AdBlocker::new();
+    warm.load_lists_cached(&lists, &cache);
+    report("load (warm cache)", start.elapsed());
+
+    println!(
+        "{} network filters, {} element hiding, {} without a token",
+        stats.network,
+        stats.cosmetic,
+        blocker.untokenized_filter_count()
+    );
+
+    let requests: Vec<_> = synthetic_requests(requests, 7)
+        .into_iter()
+        .map(|(url, source)| Request::new(&url, Some(&source), ResourceType::Script))
+        .collect();
+    let start = Instant::now();
+    let blocked = requests
+        .iter()
+        .filter(|request| blocker.check(request).is_blocked())
+        .count();
+    let elapsed = start.elapsed();
+    report(&format!("check {} requests", requests.len()), elapsed);
+    println!(
+        "{:.2} µs per request, {:.0} requests/s, {} blocked",
+        elapsed.as_secs_f64() * 1e6 / requests.len() as f64,
+        requests.len() as f64 / elapsed.as_secs_f64(),
+        blocked
+    );
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
@@ -0,0 +1,84 @@
+//! Static assets (page templates and styles), compiled into the binary.
+//!
+//! Setting `assets_dev_dir` in the config makes [`Assets`] read each file
+//! from that directory instead, on every use, so pages can be live-edited
+//! without rebuilding. Only the files listed here can be overridden, and a
+//! file missing from the dev dir falls back to the embedded copy.
+
+use crate::config::BrowserConfig;
+use log::{error, warn};
+use std::borrow::Cow;
+use std::path::{Path, PathBuf};
+
+pub const HOME_HTML: &str = "templates/home.html";
+pub const HEADER_HTML: &str = "templates/header.html";
+pub const INTERNAL_HTML: &str = "templates/internal.html";
+pub const MAIN_CSS: &str = "styles/main.css";
+pub const INTERNAL_CSS: &str = "styles/internal.css";
+
+static EMBEDDED: &[(&str, &str)] = &[
+    (HOME_HTML, include_str!("assets/templates/home.html")),
+    (HEADER_HTML, include_str!("assets/templates/header.html")),
+    (
+        INTERNAL_HTML,
+        include_str!("assets/templates/internal.html"),
+    ),
+    (MAIN_CSS, include_str!("assets/styles/main.css")),
+    (INTERNAL_CSS, include_str!("assets/styles/internal.css")),
+];
+
+#[derive(Debug, Clone, Default)]
+pub struct Assets {
+    dev_dir: Option<PathBuf>,
+}
+
+impl Assets {
+    /// Only the copies compiled into the binary.
+    pub fn embedded() -> Self {
+        Self::default()
+    }
+
+    /// Prefers files in `dir`, laid out like `src/assets`.
+    pub fn with_dev_dir(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dev_dir: Some(dir.into()),
+        }
+    }
+
+    pub fn from_config(config: &BrowserConfig) -> Self {
+        Self {
+            dev_dir: config.assets_dev_dir.clone(),
+        }
+    }
+
+    pub fn dev_dir(&self) -> Option<&Path> {
+        self.dev_dir.as_deref()
+    }
+
+    /// Paths of every known asset.
+    pub fn names() -> impl Iterator<Item = &'static str> {
+        EMBEDDED.iter().map(|(name, _)| *name)
+    }
+
+    /// The asset at `name`, or `None` if there is no such asset.
+    pub fn get(&self, name: &str) -> Option<Cow<'static, str>> {
+        let (name, embedded) = EMBEDDED.iter().find(|(n, _)| *n == name)?;
+        if let Some(dir) = &self.dev_dir {
+            match std::fs::read_to_string(dir.join(name)) {
+                Ok(contents) => return Some(Cow::Owned(contents)),
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+                Err(e) => warn!("Using embedded {}, could not read override: {}", name, e),
+            }
+        }
+        Some(Cow::Borrowed(embedded))
+    }
+
+    /// Like [`get`](Self::get) for the constants in this module, which
+    /// always exist.
+    pub fn text(&self, name: &str) -> Cow<'static, str> {
+        self.get(name).unwrap_or_else(|| {
+            error!("Unknown asset {}", name);
+            Cow::Borrowed("")
+        })
+    }
+}
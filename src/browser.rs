@@ -1,36 +1,71 @@
 use crate::{
+    assets::Assets,
     config::BrowserConfig,
-    constants::templates::DEFAULT_PAGE,
+    constants::templates,
     core::{
+        driver::{self, Capabilities},
         error::{BrowserError, Result as BrowserResult},
-        BrowserCache,
+        omnibox::{self, Destination, OmniboxContext},
+        scheme::{self, SchemeRouter},
+        session_store::{SessionRecorder, SessionSnapshot, SessionStore},
+        tabs::{TabKind, TabTracker, Tabs},
+        BrowserCache, DriverBackend, SessionPool,
+    },
+    features::network::{
+        monitor::RequestData, CertificateAuthority, HttpsInterception, InterceptingProxy,
+        NetworkMonitor, NetworkTools,
     },
-    features::network::{monitor::RequestData, NetworkMonitor},
     features::{
-        adblock::AdBlocker, battery_saver::BatterySaver, turbo::TurboMode, vpn::VpnManager,
+        adblock::{
+            allowlist::ALLOWLIST_FILE, cosmetic, request::host_of, AdBlocker, Allowlist,
+            RefreshOutcome, ResourceType, SubscriptionManager,
+        },
+        battery_saver::BatterySaver,
+        turbo::TurboMode,
+        vpn::VpnManager,
+        workspace::Bookmark,
     },
     monitoring::PerformanceMonitor,
+    utils::{BookmarkManager, HistoryStore, RetentionPolicy},
 };
-use anyhow;
 use colored::*;
 use fantoccini::{Client, ClientBuilder};
 use log::{error, info};
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use serde_json::json;
 use std::error::Error;
-use std::net::TcpStream;
+use std::net::{IpAddr, SocketAddr};
 use std::num::NonZeroUsize;
-use std::process::{Child, Command};
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
+use tokio::task::JoinHandle;
 use tokio::time::sleep;
 
+/// Where compiled filter lists are cached, relative to the data dir.
+const ADBLOCK_CACHE_FILE: &str = "adblock/filters.cache.json";
+
 pub struct NyanBrowser {
     client: Arc<Client>,
-    driver: Child,
-    port: u16,
+    backend: Option<Box<dyn DriverBackend>>,
+    driver_url: String,
+    sessions: Arc<SessionPool>,
+    tabs: Arc<TabTracker>,
+    session_store: Arc<SessionStore>,
+    session_recorder: Arc<SessionRecorder>,
+    previous_session: Mutex<Option<SessionSnapshot>>,
+    snapshot_task: Option<JoinHandle<()>>,
+    filter_list_task: Option<JoinHandle<()>>,
+    history: Option<Arc<HistoryStore>>,
+    bookmarks: Option<Arc<BookmarkManager>>,
+    subscriptions: Option<Arc<SubscriptionManager>>,
+    router: Arc<SchemeRouter>,
+    assets: Assets,
     cache: Arc<BrowserCache>,
     network: Arc<NetworkMonitor>,
+    network_tools: Arc<NetworkTools>,
+    /// Proxy the browser's traffic goes through, unless turned off.
+    proxy: Option<InterceptingProxy>,
     config: Arc<RwLock<BrowserConfig>>,
     monitor: Arc<PerformanceMonitor>,
     turbo_mode: Arc<TurboMode>,
@@ -40,27 +75,14 @@ pub struct NyanBrowser {
 }
 
 impl NyanBrowser {
-    async fn find_available_port() -> anyhow::Result<u16> {
-        for port in 4444..5000 {
-            if TcpStream::connect(format!("127.0.0.1:{}", port)).is_err() {
-                return Ok(port);
-            }
-            sleep(Duration::from_millis(100)).await;
-        }
-        Err(anyhow::anyhow!("No available ports found"))
-    }
-
-    async fn create_client(
-        port: u16,
-        caps: serde_json::Map<String, serde_json::Value>,
-    ) -> anyhow::Result<Client> {
+    pub(crate) async fn create_client(url: &str, caps: Capabilities) -> anyhow::Result<Client> {
         let mut attempts = 0;
         let max_attempts = 3;
 
         while attempts < max_attempts {
             match ClientBuilder::native()
                 .capabilities(caps.clone())
-                .connect(&format!("http://localhost:{}", port))
+                .connect(url)
                 .await
             {
                 Ok(client) => return Ok(client),
@@ -83,128 +105,620 @@ impl NyanBrowser {
     }
 
     pub async fn new(config: BrowserConfig) -> anyhow::Result<Self> {
-        info!("{}", "Starting Nyan Browser... (◕ᴗ◕✿)".cyan());
+        let backend = driver::from_config(&config)?;
+        Self::with_backend(config, backend).await
+    }
 
-        let port = Self::find_available_port().await?;
-        info!("{}", format!("Using port {}... (◕ᴗ◕✿)", port).cyan());
+    /// Starts the browser on an explicitly chosen WebDriver backend.
+    pub async fn with_backend(
+        config: BrowserConfig,
+        mut backend: Box<dyn DriverBackend>,
+    ) -> anyhow::Result<Self> {
+        info!("{}", "Starting Nyan Browser... (◕ᴗ◕✿)".cyan());
 
-        // Clone config for feature initialization
-        let config_clone = config.clone();
+        let driver_url = backend.spawn(&config).await?;
 
-        #[cfg(unix)]
-        {
-            let _ = Command::new("pkill").arg("geckodriver").output();
-            sleep(Duration::from_secs(1)).await;
+        let allowlist = match Allowlist::open(&config.config_dir.join(ALLOWLIST_FILE)) {
+            Ok(allowlist) => allowlist,
+            Err(e) => {
+                error!(
+                    "Ad blocking allowlist won't be saved, could not read it: {}",
+                    e
+                );
+                Allowlist::in_memory()
+            }
+        };
+        let ad_blocker = Arc::new(AdBlocker::new().with_allowlist(allowlist));
+        let network =
+            Arc::new(NetworkMonitor::new().with_max_body_bytes(config.network_max_body_kb * 1024));
+        let network_tools = Arc::new(NetworkTools::new());
+        let proxy_host = match config.network_proxy_host {
+            Some(host) => Some(host),
+            None if backend.runs_locally() => Some(IpAddr::from([127, 0, 0, 1])),
+            None => {
+                if config.network_proxy {
+                    info!(
+                        "Network proxy is disabled, the {} WebDriver can't reach it on localhost; set network_proxy_host to an address it can reach",
+                        backend.name()
+                    );
+                }
+                None
+            }
+        };
+        let proxy = if let Some(host) = proxy_host.filter(|_| config.network_proxy) {
+            // Mocks and header rules for HTTPS sites need the CA even when the rest of HTTPS
+            // is left alone.
+            let https = match CertificateAuthority::load_or_create(&config.data_dir) {
+                Ok(ca) if config.network_intercept_https => HttpsInterception::All(Arc::new(ca)),
+                Ok(ca) => HttpsInterception::RuledHosts(Arc::new(ca)),
+                Err(e) => {
+                    error!(
+                        "HTTPS won't be intercepted, could not set up the proxy's CA: {}",
+                        e
+                    );
+                    HttpsInterception::Off
+                }
+            };
+            match InterceptingProxy::start(
+                SocketAddr::new(host, config.network_proxy_port),
+                Arc::clone(&network),
+                Some(Arc::clone(&ad_blocker)),
+                Arc::clone(&network_tools),
+                https,
+            )
+            .await
+            {
+                Ok(proxy) => Some(proxy),
+                Err(e) => {
+                    error!("Network proxy is disabled, could not start it: {}", e);
+                    None
+                }
+            }
+        } else {
+            None
+        };
+        let proxy_addr = proxy.as_ref().map(InterceptingProxy::addr);
+        let intercepts_https = proxy
+            .as_ref()
+            .is_some_and(InterceptingProxy::intercepts_https);
+
+        let mut caps = backend.capabilities(&config);
+        if let Some(addr) = proxy_addr {
+            caps = driver::with_proxy(caps, addr, intercepts_https);
         }
 
-        info!("{}", "Starting GeckoDriver...".cyan());
+        info!("{}", "Connecting to browser...".cyan());
 
-        let driver = Command::new(&config.gecko_path)
-            .arg(format!("--port={}", port))
-            .spawn()?;
+        let client = Arc::new(Self::create_client(&driver_url, caps).await?);
+
+        let session_store = Arc::new(SessionStore::new(&config.config_dir));
+        let previous_session = session_store.load().unwrap_or_else(|e| {
+            error!("Could not read the last session: {}", e);
+            None
+        });
+        if previous_session.as_ref().is_some_and(|s| !s.clean_exit) {
+            info!(
+                "{}",
+                "Browser-chan did not exit cleanly last time, restore_last_session() can bring your tabs back (｡•́︿•̀｡)"
+                    .yellow()
+            );
+        }
+        // Until the first snapshot is taken, a crash must still show up as
+        // one on the next start. The previous tabs are kept in the marker,
+        // so they can still be restored if this run dies early too.
+        let unclean = match &previous_session {
+            Some(previous) => SessionSnapshot {
+                clean_exit: false,
+                ..previous.clone()
+            },
+            None => SessionSnapshot {
+                saved_at: chrono::Utc::now(),
+                clean_exit: false,
+                tabs: Vec::new(),
+            },
+        };
+        if let Err(e) = session_store.save(&unclean) {
+            error!("Could not mark the session as running: {}", e);
+        }
 
-        sleep(Duration::from_secs(2)).await;
+        let history = match HistoryStore::open(&config.data_dir) {
+            Ok(history) => {
+                if let Err(e) = history.apply_retention(&RetentionPolicy::from_config(&config)) {
+                    error!("Could not expire old history: {}", e);
+                }
+                Some(Arc::new(history))
+            }
+            Err(e) => {
+                error!("History is disabled, could not open it: {}", e);
+                None
+            }
+        };
 
-        let mut caps = serde_json::map::Map::new();
-        let mut firefox_opts = serde_json::map::Map::new();
-        let mut prefs = serde_json::map::Map::new();
+        let bookmarks = match BookmarkManager::open_with_legacy(
+            &config.data_dir,
+            &config.legacy_bookmarks_file,
+        ) {
+            Ok(bookmarks) => Some(Arc::new(bookmarks)),
+            Err(e) => {
+                error!("Bookmarks are disabled, could not open them: {}", e);
+                None
+            }
+        };
 
-        prefs.insert("browser.startup.homepage".to_string(), json!("about:blank"));
-        prefs.insert("browser.startup.page".to_string(), json!(0));
-        prefs.insert(
-            "toolkit.legacyUserProfileCustomizations.stylesheets".to_string(),
-            json!(true),
-        );
-        prefs.insert(
-            "browser.shell.checkDefaultBrowser".to_string(),
-            json!(false),
-        );
-        prefs.insert(
-            "browser.sessionstore.resume_from_crash".to_string(),
-            json!(false),
-        );
-        prefs.insert("browser.tabs.drawInTitlebar".to_string(), json!(true));
-        prefs.insert("browser.download.folderList".to_string(), json!(2));
-        prefs.insert(
-            "browser.download.manager.showWhenStarting".to_string(),
-            json!(false),
-        );
-        prefs.insert(
-            "browser.download.manager.useWindow".to_string(),
-            json!(false),
-        );
-        prefs.insert(
-            "browser.helperApps.neverAsk.saveToDisk".to_string(),
-            json!("application/octet-stream"),
+        let subscriptions = match SubscriptionManager::open(&config) {
+            Ok(subscriptions) => Some(Arc::new(subscriptions)),
+            Err(e) => {
+                error!(
+                    "Filter list subscriptions are disabled, could not read them: {}",
+                    e
+                );
+                None
+            }
+        };
+        Self::load_filter_lists(
+            &ad_blocker,
+            &config.adblock_lists,
+            subscriptions.as_deref(),
+            &config.data_dir,
         );
+        let filter_list_task = match &subscriptions {
+            Some(subscriptions) if config.adblock_refresh_minutes > 0 => {
+                Some(tokio::spawn(Self::refresh_filter_lists_periodically(
+                    Arc::clone(subscriptions),
+                    Arc::clone(&ad_blocker),
+                    config.adblock_lists.clone(),
+                    config.data_dir.clone(),
+                    Duration::from_secs(config.adblock_refresh_minutes * 60),
+                )))
+            }
+            _ => None,
+        };
 
-        firefox_opts.insert("prefs".to_string(), json!(prefs));
-        caps.insert("moz:firefoxOptions".to_string(), json!(firefox_opts));
-
-        info!("{}", "Connecting to browser...".cyan());
-
-        let client = Arc::new(Self::create_client(port, caps).await?);
+        let tabs = Arc::new(TabTracker::new());
+        let session_recorder = Arc::new(SessionRecorder::new());
+        let recorder = Arc::clone(&session_recorder);
+        tabs.subscribe(move |event| recorder.handle_event(event));
+
+        let first_tab: String = client.window().await?.into();
+        tabs.record_opened(&first_tab, TabKind::Window);
+        tabs.record_activated(&first_tab);
+
+        let snapshot_task = (config.session_snapshot_interval_seconds > 0).then(|| {
+            tokio::spawn(Self::snapshot_periodically(
+                Arc::clone(&client),
+                Arc::clone(&session_store),
+                Arc::clone(&session_recorder),
+                Duration::from_secs(config.session_snapshot_interval_seconds),
+            ))
+        });
+
+        let sessions = Arc::new(
+            SessionPool::new(config.clone())
+                .with_backend(backend.as_ref())
+                .with_proxy(proxy_addr, intercepts_https),
+        );
 
         info!("{}", "Browser initialized successfully! (◕‿◕✿)".green());
 
         let browser = Self {
             client,
-            driver,
-            port,
+            backend: Some(backend),
+            driver_url,
+            sessions,
+            tabs,
+            session_store,
+            session_recorder,
+            previous_session: Mutex::new(previous_session),
+            snapshot_task,
+            filter_list_task,
+            history,
+            bookmarks,
+            subscriptions,
+            router: Arc::new(SchemeRouter::with_builtin_pages()),
+            assets: Assets::from_config(&config),
+            // The LRU caches are bounded by entry count and preallocate that many
+            // slots, so budget roughly one page (and a few assets) per megabyte.
             cache: Arc::new(BrowserCache::new(
-                NonZeroUsize::new((config.cache_size_mb as usize) * 1024 * 1024).unwrap(),
-                NonZeroUsize::new((config.cache_size_mb as usize) * 512 * 1024).unwrap(),
+                NonZeroUsize::new(config.cache_size_mb.max(1) as usize).unwrap(),
+                NonZeroUsize::new(config.cache_size_mb.max(1) as usize * 4).unwrap(),
             )),
-            network: Arc::new(NetworkMonitor::new()),
-            config: Arc::new(RwLock::new(config)),
-            monitor: Arc::new(PerformanceMonitor::new()),
+            network,
+            network_tools,
+            proxy,
+            monitor: Arc::new(PerformanceMonitor::new().with_ad_blocker(Arc::clone(&ad_blocker))),
             turbo_mode: Arc::new(TurboMode::new()),
             battery_saver: Arc::new(BatterySaver::new()),
-            ad_blocker: Arc::new(AdBlocker::new()),
+            ad_blocker,
             vpn: Arc::new(VpnManager::new()),
+            config: Arc::new(RwLock::new(config)),
         };
 
-        // Initialize features based on cloned config
-        if config_clone.turbo_mode_enabled {
+        // Initialize features based on config
+        if browser.config.read().turbo_mode_enabled {
             browser.turbo_mode.enable();
         }
-        if config_clone.battery_saver_enabled {
+        if browser.config.read().battery_saver_enabled {
             browser.battery_saver.enable();
         }
 
         Ok(browser)
     }
 
-    pub async fn navigate(&self, url: &str) -> BrowserResult<()> {
+    /// Base URL of the WebDriver server this browser is attached to.
+    pub fn driver_url(&self) -> &str {
+        &self.driver_url
+    }
+
+    /// Checks that the WebDriver backend is still alive.
+    pub async fn health_check(&self) -> anyhow::Result<()> {
+        match &self.backend {
+            Some(backend) => backend.health_check().await,
+            None => Err(anyhow::anyhow!("Browser has already been shut down")),
+        }
+    }
+
+    /// Ends the WebDriver session and stops the backend.
+    ///
+    /// Dropping the browser does the same on a best-effort basis, but only this
+    /// method waits for everything to finish and reports errors.
+    pub async fn shutdown(&mut self) -> anyhow::Result<()> {
+        let Some(mut backend) = self.backend.take() else {
+            return Ok(());
+        };
+
+        info!("{}", "Cleaning up browser resources... (◕ᴗ◕✿)".yellow());
+        if let Some(task) = self.snapshot_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.filter_list_task.take() {
+            task.abort();
+        }
+        let snapshot = self.session_recorder.capture(&self.client, true).await;
+        if let Err(e) = self.session_store.save(&snapshot) {
+            error!("Could not save the session: {}", e);
+        }
+        self.sessions.shutdown().await;
+        let session = Client::clone(&self.client).close().await;
+        let driver = backend.shutdown().await;
+        info!("{}", "Cleanup complete! Sayonara~ (◕‿◕✿)".green());
+
+        session.map_err(|e| anyhow::anyhow!("Error closing browser session: {}", e))?;
+        driver
+    }
+
+    /// Loads whatever was typed in the address bar: a URL, a bare host, a
+    /// `kawaii://` page, a bookmark keyword or a search.
+    pub async fn navigate(&self, input: &str) -> BrowserResult<()> {
+        let url = self.navigate_on(&self.client, input).await?;
+        self.tabs.record_navigated(&url);
+        Ok(())
+    }
+
+    /// What `input` would load if typed in the address bar.
+    pub fn resolve_input(&self, input: &str) -> Destination {
+        omnibox::resolve(input, &self.omnibox_context())
+    }
+
+    fn omnibox_context(&self) -> OmniboxContext {
+        let mut context = {
+            let config = self.config.read();
+            let mut context = OmniboxContext::new(config.search_template());
+            for engine in &config.search_engines {
+                if let Some(keyword) = &engine.keyword {
+                    context = context.with_keyword(keyword, &engine.template);
+                }
+            }
+            context
+        };
+        // Bookmark keywords are the user's own and win over engine ones.
+        if let Some(bookmarks) = &self.bookmarks {
+            context.keywords.extend(bookmarks.keywords());
+        }
+        context
+    }
+
+    /// The session left behind by the previous run, until it is restored.
+    pub fn last_session(&self) -> Option<SessionSnapshot> {
+        self.previous_session.lock().clone()
+    }
+
+    /// Whether the previous run ended without shutting down cleanly.
+    pub fn last_session_crashed(&self) -> bool {
+        self.previous_session
+            .lock()
+            .as_ref()
+            .is_some_and(|s| !s.clean_exit)
+    }
+
+    /// Writes a snapshot of the open tabs right away.
+    pub async fn save_session(&self) -> anyhow::Result<()> {
+        let snapshot = self.session_recorder.capture(&self.client, false).await;
+        self.session_store.save(&snapshot)
+    }
+
+    /// Reopens the tabs of the previous run and returns how many were
+    /// restored. The first tab reuses the current window. Does nothing if
+    /// there is no previous session or it was already restored.
+    pub async fn restore_last_session(&self) -> BrowserResult<usize> {
+        let Some(snapshot) = self.previous_session.lock().take() else {
+            return Ok(0);
+        };
+        info!(
+            "{}",
+            format!("Restoring {} tabs... (◕ᴗ◕✿)", snapshot.tabs.len()).cyan()
+        );
+
+        let tabs = self.tabs();
+        let mut active = None;
+        for (index, tab) in snapshot.tabs.iter().enumerate() {
+            let handle = match (index, tab.kind) {
+                (0, _) => tabs.current().await?,
+                (_, TabKind::Tab) => tabs.new_tab(None).await?,
+                (_, TabKind::Window) => tabs.new_window(None).await?,
+            };
+            if tab.url != "about:blank" {
+                if let Err(e) = self.navigate(&tab.url).await {
+                    error!("Could not restore {}: {}", tab.url, e);
+                    continue;
+                }
+            }
+            if tab.scroll_x != 0.0 || tab.scroll_y != 0.0 {
+                let _ = self
+                    .client
+                    .execute(
+                        "window.scrollTo(arguments[0], arguments[1]);",
+                        vec![json!(tab.scroll_x), json!(tab.scroll_y)],
+                    )
+                    .await;
+            }
+            if tab.active {
+                active = Some(handle);
+            }
+        }
+
+        if let Some(handle) = active {
+            tabs.switch_to(&handle).await?;
+        }
+        Ok(snapshot.tabs.len())
+    }
+
+    async fn snapshot_periodically(
+        client: Arc<Client>,
+        store: Arc<SessionStore>,
+        recorder: Arc<SessionRecorder>,
+        every: Duration,
+    ) {
+        let mut ticker = tokio::time::interval(every);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let snapshot = recorder.capture(&client, false).await;
+            if let Err(e) = store.save(&snapshot) {
+                error!("Could not save the session: {}", e);
+            }
+        }
+    }
+
+    /// Loads the configured filter lists and the cached copies of the
+    /// subscribed ones into `ad_blocker`.
+    fn load_filter_lists(
+        ad_blocker: &AdBlocker,
+        lists: &[PathBuf],
+        subscriptions: Option<&SubscriptionManager>,
+        data_dir: &Path,
+    ) {
+        let mut lists = lists.to_vec();
+        if let Some(subscriptions) = subscriptions {
+            lists.extend(subscriptions.list_paths());
+        }
+        if !lists.is_empty() {
+            ad_blocker.load_lists_cached(&lists, &data_dir.join(ADBLOCK_CACHE_FILE));
+        }
+    }
+
+    async fn refresh_filter_lists_periodically(
+        subscriptions: Arc<SubscriptionManager>,
+        ad_blocker: Arc<AdBlocker>,
+        lists: Vec<PathBuf>,
+        data_dir: PathBuf,
+        every: Duration,
+    ) {
+        let mut ticker = tokio::time::interval(every);
+        loop {
+            ticker.tick().await;
+            let outcomes = subscriptions.refresh(false).await;
+            if outcomes
+                .iter()
+                .any(|o| matches!(o, RefreshOutcome::Updated { .. }))
+            {
+                Self::load_filter_lists(&ad_blocker, &lists, Some(&subscriptions), &data_dir);
+            }
+        }
+    }
+
+    /// Updates the subscribed filter lists that are due, or all of them if
+    /// `force`, and reloads the ad blocker if any changed.
+    pub async fn refresh_filter_lists(&self, force: bool) -> Vec<RefreshOutcome> {
+        let Some(subscriptions) = &self.subscriptions else {
+            return Vec::new();
+        };
+        let outcomes = subscriptions.refresh(force).await;
+        if outcomes
+            .iter()
+            .any(|o| matches!(o, RefreshOutcome::Updated { .. }))
+        {
+            let config = self.config.read();
+            Self::load_filter_lists(
+                &self.ad_blocker,
+                &config.adblock_lists,
+                Some(subscriptions),
+                &config.data_dir,
+            );
+        }
+        outcomes
+    }
+
+    /// Filter list subscriptions, unless they could not be read.
+    pub fn subscriptions(&self) -> Option<&Arc<SubscriptionManager>> {
+        self.subscriptions.as_ref()
+    }
+
+    /// Opens, lists, switches and closes tabs and windows.
+    pub fn tabs(&self) -> Tabs<'_> {
+        Tabs::new(&self.client, &self.tabs)
+    }
+
+    /// Tab state and event subscriptions, for features that follow tabs.
+    pub fn tab_tracker(&self) -> &Arc<TabTracker> {
+        &self.tabs
+    }
+
+    /// Resolves `input` and loads it in `client`. Returns the URL loaded.
+    async fn navigate_on(&self, client: &Client, input: &str) -> BrowserResult<String> {
+        let destination = self.resolve_input(input);
+        let url = destination.url().to_string();
         info!("{}", format!("Navigating to {}... (◕ᴗ◕✿)", url).cyan());
 
-        match url {
-            "kawaii://home" => {
-                // Navigate to local file URL
-                let home_path = std::env::current_dir()?
-                    .join("src/assets/templates/home.html")
-                    .to_string_lossy()
-                    .to_string();
-                let file_url = format!("file://{}", home_path);
-                self.client
-                    .goto(&file_url)
+        match destination {
+            Destination::Internal(_) => {
+                let html = self
+                    .router
+                    .render(&url, self)
+                    .await
+                    .map_err(|e| BrowserError::NavigationError(e.to_string()))?;
+                client
+                    .goto(&scheme::data_url(&html))
                     .await
                     .map_err(|e| BrowserError::NavigationError(e.to_string()))?;
             }
             _ => {
-                if self.ad_blocker.should_block(url) {
+                if self
+                    .ad_blocker
+                    .should_block(&url, None, ResourceType::Document)
+                {
                     info!("🚫 Blocked potentially unwanted content");
-                    return Ok(());
+                    return Ok(url);
                 }
-                self.client
-                    .goto(url)
+                client
+                    .goto(&url)
                     .await
                     .map_err(|e| BrowserError::NavigationError(e.to_string()))?;
+                let landed = client
+                    .current_url()
+                    .await
+                    .map(|u| u.to_string())
+                    .unwrap_or_else(|_| url.clone());
+                self.record_visit(client, &landed).await;
+                self.hide_elements(client, &landed).await;
             }
         }
 
         info!("{}", "Navigation complete! ✨".green());
-        Ok(())
+        Ok(url)
+    }
+
+    /// Adds the page `client` just loaded, which ended up at `url` after
+    /// redirects, to the history.
+    async fn record_visit(&self, client: &Client, url: &str) {
+        let Some(history) = &self.history else {
+            return;
+        };
+        let title = client.title().await.unwrap_or_default();
+        if let Err(e) = history.record_visit(url, &title) {
+            error!("Could not record {} in history: {}", url, e);
+        }
+    }
+
+    /// Injects the element hiding stylesheet for `url` into the page
+    /// `client` just loaded.
+    async fn hide_elements(&self, client: &Client, url: &str) {
+        let Some(css) = self.ad_blocker.hiding_stylesheet(&host_of(url)) else {
+            return;
+        };
+        if let Err(e) = client
+            .execute(
+                cosmetic::INJECT_SCRIPT,
+                vec![json!(css), json!(cosmetic::STYLE_ID)],
+            )
+            .await
+        {
+            error!("Could not hide elements on {}: {}", url, e);
+        }
+    }
+
+    /// How many elements on the current page the element hiding filters
+    /// hide.
+    pub async fn hidden_element_count(&self) -> BrowserResult<usize> {
+        let url = self
+            .client
+            .current_url()
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        let selectors = self.ad_blocker.hiding_selectors(&host_of(url.as_str()));
+        if selectors.is_empty() {
+            return Ok(0);
+        }
+        let count = self
+            .client
+            .execute(cosmetic::COUNT_SCRIPT, vec![json!(selectors)])
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        Ok(count.as_u64().unwrap_or_default() as usize)
+    }
+
+    /// The browsing history, unless it could not be opened.
+    pub fn history(&self) -> Option<&Arc<HistoryStore>> {
+        self.history.as_ref()
+    }
+
+    /// Internal `kawaii://` pages. Register a page here to add your own.
+    pub fn scheme_router(&self) -> &SchemeRouter {
+        &self.router
+    }
+
+    /// Page templates and styles.
+    pub fn assets(&self) -> &Assets {
+        &self.assets
+    }
+
+    pub fn ad_blocker(&self) -> &Arc<AdBlocker> {
+        &self.ad_blocker
+    }
+
+    pub fn network(&self) -> &Arc<NetworkMonitor> {
+        &self.network
+    }
+
+    /// Mocks and other rules for the traffic going through the proxy.
+    pub fn network_tools(&self) -> &Arc<NetworkTools> {
+        &self.network_tools
+    }
+
+    /// Address of the proxy the browser's traffic goes through, if any.
+    pub fn proxy_addr(&self) -> Option<SocketAddr> {
+        self.proxy.as_ref().map(InterceptingProxy::addr)
+    }
+
+    /// The bookmarks, unless they could not be opened.
+    pub fn bookmarks(&self) -> Option<&Arc<BookmarkManager>> {
+        self.bookmarks.as_ref()
+    }
+
+    /// Bookmarks the page in the focused tab. Returns whether it was new.
+    pub async fn bookmark_current_page(&self, folder: &[&str]) -> BrowserResult<bool> {
+        let bookmarks = self
+            .bookmarks
+            .as_ref()
+            .ok_or_else(|| BrowserError::SessionError("Bookmarks are unavailable".to_string()))?;
+        let url = self
+            .client
+            .current_url()
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        let title = self.client.title().await.unwrap_or_default();
+        bookmarks
+            .add(Bookmark::new(url.as_str(), &title).in_folder(folder))
+            .map_err(|e| BrowserError::SessionError(e.to_string()))
     }
 
     #[allow(dead_code)]
@@ -224,49 +738,37 @@ impl NyanBrowser {
         Ok(())
     }
 
-    pub async fn batch_navigate(&self, urls: &[String]) -> Result<(), Box<dyn Error>> {
-        let futures: Vec<_> = urls.iter().map(|url| self.navigate(url)).collect();
-        let results = futures::future::join_all(futures).await;
-        for result in results {
-            result?;
-        }
-        Ok(())
+    /// Loads every URL on its own pooled session, up to `max_sessions` at a
+    /// time, and reports the outcome for each URL in input order.
+    pub async fn batch_navigate(&self, urls: &[String]) -> Vec<(String, BrowserResult<()>)> {
+        let futures = urls.iter().map(|url| async move {
+            let result = match self.sessions.lease().await {
+                Ok(session) => self.navigate_on(session.client(), url).await.map(|_| ()),
+                Err(e) => Err(e),
+            };
+            (url.clone(), result)
+        });
+        futures::future::join_all(futures).await
+    }
+
+    /// The pool of extra sessions used for parallel work.
+    pub fn sessions(&self) -> &SessionPool {
+        &self.sessions
     }
 
     pub async fn setup_custom_page(&self) -> Result<(), Box<dyn Error>> {
         info!("{}", "🌸 Setting up kawaii homepage...".cyan());
 
-        // Clear existing content and inject our custom page
+        // Clear existing content and inject our custom page. The page is
+        // passed as an argument rather than spliced into the script, so
+        // nothing in it needs escaping.
         self.client
             .execute(
                 r#"
-                // Create style element
-                let style = document.createElement('style');
-                style.textContent = `
-                    :root {
-                        --primary-color: #e91e63;
-                        --secondary-color: #f48fb1;
-                        --bg-color: #fafafa;
-                        --text-color: #333;
-                        --card-bg: #ffffff;
-                        --shadow-color: rgba(0, 0, 0, 0.1);
-                    }
-                    /* Rest of your CSS from main.css */
-                `;
-                document.head.appendChild(style);
                 document.body.innerHTML = '';
+                document.body.insertAdjacentHTML('beforeend', arguments[0]);
                 "#,
-                vec![],
-            )
-            .await?;
-
-        self.client
-            .execute(
-                &format!(
-                    "document.body.insertAdjacentHTML('beforeend', '{}');",
-                    *DEFAULT_PAGE
-                ),
-                vec![],
+                vec![json!(templates::default_page(&self.assets))],
             )
             .await?;
 
@@ -313,6 +815,11 @@ impl NyanBrowser {
         self.config.read()
     }
 
+    /// Changes the live config, e.g. to add a search engine.
+    pub fn update_config<R>(&self, change: impl FnOnce(&mut BrowserConfig) -> R) -> R {
+        change(&mut self.config.write())
+    }
+
     pub fn get_stats(&self) -> String {
         self.monitor.get_stats()
     }
@@ -356,37 +863,42 @@ impl NyanBrowser {
 
 impl Drop for NyanBrowser {
     fn drop(&mut self) {
+        let Some(mut backend) = self.backend.take() else {
+            return;
+        };
+
         info!("{}", "Cleaning up browser resources... (◕ᴗ◕✿)".yellow());
 
-        let port = self.port;
-        let client = Arc::clone(&self.client);
+        if let Some(task) = self.snapshot_task.take() {
+            task.abort();
+        }
+        if let Some(task) = self.filter_list_task.take() {
+            task.abort();
+        }
+        if let Err(e) = self
+            .session_store
+            .save(&self.session_recorder.snapshot(true))
+        {
+            error!("Could not save the session: {}", e);
+        }
 
-        // Create a new runtime for cleanup outside the current runtime
-        std::thread::spawn(move || {
-            let rt = tokio::runtime::Runtime::new().unwrap();
-            rt.block_on(async {
-                if let Ok(client) = Arc::try_unwrap(client) {
+        let client = Client::clone(&self.client);
+        let sessions = Arc::clone(&self.sessions);
+        match tokio::runtime::Handle::try_current() {
+            Ok(handle) => {
+                handle.spawn(async move {
+                    sessions.shutdown().await;
                     if let Err(e) = client.close().await {
                         error!("Error closing browser session: {}", e);
                     }
-                }
-            });
-        })
-        .join()
-        .unwrap();
-
-        if let Err(e) = self.driver.kill() {
-            error!("Error stopping GeckoDriver: {}", e);
-        }
-
-        #[cfg(unix)]
-        {
-            let _ = Command::new("kill")
-                .arg("-9")
-                .arg(format!(":{}", port))
-                .output();
+                    if let Err(e) = backend.shutdown().await {
+                        error!("Error stopping {}: {}", backend.name(), e);
+                    }
+                });
+            }
+            // Without a runtime the session cannot be closed; dropping the
+            // backend still stops any driver process it owns.
+            Err(_) => drop(backend),
         }
-
-        info!("{}", "Cleanup complete! Sayonara~ (◕‿◕✿)".green());
     }
 }
@@ -0,0 +1,386 @@
+//! Command line interface. Without a subcommand the browser starts as usual;
+//! subcommands work on the user's data without launching a driver.
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use clap::{Args, Parser, Subcommand};
+use colored::*;
+use nyan_browser::config::{BrowserConfig, SearchEngine};
+use nyan_browser::features::adblock::allowlist::ALLOWLIST_FILE;
+use nyan_browser::features::adblock::{Allowlist, RefreshOutcome, SubscriptionManager};
+use nyan_browser::utils::{
+    HistoryEntry, HistoryOrder, HistoryQuery, HistoryStore, RetentionPolicy,
+};
+use std::path::PathBuf;
+
+#[derive(Parser)]
+#[command(name = "nyan_browser", about = "A kawaii web browser written in Rust")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+pub enum Command {
+    /// Search and manage browsing history
+    #[command(subcommand)]
+    History(HistoryCommand),
+    /// Manage address bar search engines
+    #[command(subcommand)]
+    Search(SearchCommand),
+    /// Manage ad blocking filter list subscriptions
+    #[command(subcommand)]
+    Adblock(AdblockCommand),
+}
+
+#[derive(Subcommand)]
+pub enum AdblockCommand {
+    /// List subscriptions and when they were last updated
+    List,
+    /// Subscribe to a filter list by URL (http, https or file)
+    Subscribe {
+        url: String,
+        #[arg(long)]
+        title: Option<String>,
+    },
+    Unsubscribe {
+        url: String,
+    },
+    /// Update the lists that are due
+    Update {
+        /// Update every list, due or not
+        #[arg(long)]
+        force: bool,
+    },
+    /// Turn ad blocking off on a site and its subdomains
+    Allow {
+        site: String,
+    },
+    /// Turn ad blocking back on for an allowlisted site
+    Disallow {
+        site: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum SearchCommand {
+    /// List search engines
+    List,
+    /// Add or replace a search engine
+    Add {
+        name: String,
+        /// Results URL with a {query} placeholder
+        template: String,
+        #[arg(short, long)]
+        keyword: Option<String>,
+        /// Suggestions URL with a {query} placeholder
+        #[arg(long)]
+        suggestions: Option<String>,
+    },
+    /// Add a search engine from an OpenSearch description file
+    Import {
+        file: PathBuf,
+        /// Keyword to use instead of the file's alias
+        #[arg(short, long)]
+        keyword: Option<String>,
+    },
+    Remove {
+        name: String,
+    },
+    /// Use this engine for plain searches
+    Default {
+        name: String,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum HistoryCommand {
+    /// Find pages whose URL or title contains all the given terms
+    Search {
+        terms: Vec<String>,
+        #[command(flatten)]
+        range: DateRange,
+        /// Sort by visit count instead of recency
+        #[arg(long, conflicts_with = "frecent")]
+        most_visited: bool,
+        /// Sort by frecency instead of recency
+        #[arg(long)]
+        frecent: bool,
+        #[arg(short = 'n', long, default_value_t = 20)]
+        limit: usize,
+        /// Print JSON instead of a table
+        #[arg(long)]
+        json: bool,
+    },
+    /// Most visited pages
+    Top {
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Pages ranked by frecency
+    Frecent {
+        #[arg(short = 'n', long, default_value_t = 10)]
+        limit: usize,
+        #[arg(long)]
+        json: bool,
+    },
+    /// Delete visits in a date range, or everything without one
+    Clear {
+        #[command(flatten)]
+        range: DateRange,
+    },
+    /// Apply the retention limits from the config, or the given ones
+    Expire {
+        #[arg(long)]
+        max_age_days: Option<u32>,
+        #[arg(long)]
+        max_entries: Option<usize>,
+    },
+}
+
+#[derive(Args)]
+pub struct DateRange {
+    /// First day to include (YYYY-MM-DD, UTC)
+    #[arg(long, value_parser = parse_day)]
+    since: Option<NaiveDate>,
+    /// Last day to include (YYYY-MM-DD, UTC)
+    #[arg(long, value_parser = parse_day)]
+    until: Option<NaiveDate>,
+}
+
+impl DateRange {
+    /// The range as `[since, until)` timestamps.
+    fn bounds(&self) -> (Option<DateTime<Utc>>, Option<DateTime<Utc>>) {
+        let start_of = |day: NaiveDate| day.and_hms_opt(0, 0, 0).map(|t| t.and_utc());
+        (
+            self.since.and_then(start_of),
+            self.until.and_then(|day| start_of(day + Duration::days(1))),
+        )
+    }
+}
+
+fn parse_day(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(s, "%Y-%m-%d").map_err(|e| format!("{} (expected YYYY-MM-DD)", e))
+}
+
+pub async fn run(command: Command) -> anyhow::Result<()> {
+    let config = BrowserConfig::load()?;
+    match command {
+        Command::History(command) => run_history(&config, command),
+        Command::Search(command) => run_search(config, command),
+        Command::Adblock(command) => run_adblock(&config, command).await,
+    }
+}
+
+async fn run_adblock(config: &BrowserConfig, command: AdblockCommand) -> anyhow::Result<()> {
+    let subscriptions = SubscriptionManager::open(config)?;
+    match command {
+        AdblockCommand::List => {
+            let all = subscriptions.list();
+            if all.is_empty() {
+                println!("{}", "No subscriptions yet (´･ω･`)".yellow());
+            }
+            for subscription in all {
+                let updated = subscription
+                    .last_updated
+                    .map(|at| at.format("%Y-%m-%d %H:%M").to_string())
+                    .unwrap_or_else(|| "never".to_string());
+                println!(
+                    "{} {}  {}",
+                    if subscription.enabled { "♥" } else { " " }.magenta(),
+                    subscription.title.as_deref().unwrap_or("(untitled)").bold(),
+                    format!("updated {}", updated).cyan()
+                );
+                println!("    {}", subscription.url.dimmed());
+                if let Some(error) = subscription.last_error {
+                    println!("    {}", error.red());
+                }
+            }
+        }
+        AdblockCommand::Subscribe { url, title } => {
+            if subscriptions.subscribe(&url, title.as_deref())? {
+                println!("{}", "Subscribed ✧ fetching it now...".magenta());
+                print_outcomes(&subscriptions.refresh(false).await);
+            } else {
+                println!("{}", "Already subscribed to that one ♥".yellow());
+            }
+        }
+        AdblockCommand::Unsubscribe { url } => {
+            if !subscriptions.unsubscribe(&url)? {
+                anyhow::bail!("Not subscribed to {}", url);
+            }
+            println!("{}", "Unsubscribed (｡•́︿•̀｡)".magenta());
+        }
+        AdblockCommand::Update { force } => {
+            let outcomes = subscriptions.refresh(force).await;
+            if outcomes.is_empty() {
+                println!("{}", "Everything is up to date ✧".magenta());
+            }
+            print_outcomes(&outcomes);
+        }
+        AdblockCommand::Allow { site } => {
+            let allowlist = Allowlist::open(&config.config_dir.join(ALLOWLIST_FILE))?;
+            if allowlist.add(&site)? {
+                println!("{}", "Ads are allowed there now ♥".magenta());
+            } else {
+                println!("{}", "That site is already allowlisted ♥".yellow());
+            }
+        }
+        AdblockCommand::Disallow { site } => {
+            let allowlist = Allowlist::open(&config.config_dir.join(ALLOWLIST_FILE))?;
+            if !allowlist.remove(&site)? {
+                anyhow::bail!("{} is not allowlisted", site);
+            }
+            println!("{}", "Blocking ads there again ✧".magenta());
+        }
+    }
+    Ok(())
+}
+
+fn print_outcomes(outcomes: &[RefreshOutcome]) {
+    for outcome in outcomes {
+        match outcome {
+            RefreshOutcome::Updated { url } => println!("{} {}", "✓".green(), url),
+            RefreshOutcome::Failed { url, error } => {
+                println!("{} {}: {}", "✗".red(), url, error.red())
+            }
+        }
+    }
+}
+
+fn run_search(mut config: BrowserConfig, command: SearchCommand) -> anyhow::Result<()> {
+    match command {
+        SearchCommand::List => {
+            let default = config.search_template().to_string();
+            for engine in &config.search_engines {
+                let marker = if engine.template == default {
+                    "♥"
+                } else {
+                    " "
+                };
+                println!(
+                    "{} {:<12} {:<8} {}",
+                    marker.magenta(),
+                    engine.name.bold(),
+                    engine.keyword.as_deref().unwrap_or("-").cyan(),
+                    engine.template.dimmed()
+                );
+            }
+            return Ok(());
+        }
+        SearchCommand::Add {
+            name,
+            template,
+            keyword,
+            suggestions,
+        } => {
+            let mut engine = SearchEngine::new(&name, &template);
+            engine.keyword = keyword;
+            engine.suggestion_url = suggestions;
+            config.add_search_engine(engine)?;
+            println!("{}", format!("Added {} ✧", name).magenta());
+        }
+        SearchCommand::Import { file, keyword } => {
+            let engine = config.import_opensearch(&file, keyword.as_deref())?;
+            println!("{}", format!("Added {} ✧", engine.name).magenta());
+        }
+        SearchCommand::Remove { name } => {
+            if config.remove_search_engine(&name).is_none() {
+                anyhow::bail!("No search engine named {}", name);
+            }
+            println!("{}", format!("Removed {} (｡•́︿•̀｡)", name).magenta());
+        }
+        SearchCommand::Default { name } => {
+            let engine = config
+                .search_engine(&name)
+                .ok_or_else(|| anyhow::anyhow!("No search engine named {}", name))?;
+            config.default_search_engine = Some(engine.name.clone());
+            println!("{}", format!("Searching with {} now ♥", name).magenta());
+        }
+    }
+    config.save()
+}
+
+fn run_history(config: &BrowserConfig, command: HistoryCommand) -> anyhow::Result<()> {
+    let history = HistoryStore::open(&config.data_dir)?;
+    match command {
+        HistoryCommand::Search {
+            terms,
+            range,
+            most_visited,
+            frecent,
+            limit,
+            json,
+        } => {
+            let (since, until) = range.bounds();
+            let order = if most_visited {
+                HistoryOrder::MostVisited
+            } else if frecent {
+                HistoryOrder::Frecency
+            } else {
+                HistoryOrder::Recent
+            };
+            let entries = history.search(&HistoryQuery {
+                text: (!terms.is_empty()).then(|| terms.join(" ")),
+                since,
+                until,
+                order,
+                limit: Some(limit),
+            });
+            print_entries(&entries, json)
+        }
+        HistoryCommand::Top { limit, json } => print_entries(&history.most_visited(limit), json),
+        HistoryCommand::Frecent { limit, json } => print_entries(&history.frecent(limit), json),
+        HistoryCommand::Clear { range } => {
+            let (since, until) = range.bounds();
+            let removed = history.clear_range(since, until)?;
+            println!(
+                "{}",
+                format!("Forgot {} visits (｡•́︿•̀｡)", removed).magenta()
+            );
+            Ok(())
+        }
+        HistoryCommand::Expire {
+            max_age_days,
+            max_entries,
+        } => {
+            let mut policy = RetentionPolicy::from_config(config);
+            if let Some(days) = max_age_days {
+                policy.max_age = Some(Duration::days(days.into()));
+            }
+            if max_entries.is_some() {
+                policy.max_entries = max_entries;
+            }
+            let removed = history.apply_retention(&policy)?;
+            println!("{}", format!("Expired {} visits ✧", removed).magenta());
+            Ok(())
+        }
+    }
+}
+
+fn print_entries(entries: &[HistoryEntry], json: bool) -> anyhow::Result<()> {
+    if json {
+        println!("{}", serde_json::to_string_pretty(entries)?);
+        return Ok(());
+    }
+    if entries.is_empty() {
+        println!("{}", "Nothing found (´･ω･`)".yellow());
+        return Ok(());
+    }
+    for entry in entries {
+        println!(
+            "{}  {:>4}x  {}",
+            entry.last_visit.format("%Y-%m-%d %H:%M").to_string().cyan(),
+            entry.visit_count,
+            if entry.title.is_empty() {
+                entry.url.clone()
+            } else {
+                entry.title.clone()
+            }
+            .bold()
+        );
+        println!("{:>24}{}", "", entry.url.dimmed());
+    }
+    Ok(())
+}
@@ -1,5 +1,22 @@
+pub mod search;
+
+pub use search::SearchEngine;
+
 use serde::{Deserialize, Serialize};
-use std::path::PathBuf;
+use std::net::IpAddr;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DriverKind {
+    /// Spawn a local geckodriver and drive Firefox.
+    #[default]
+    Gecko,
+    /// Spawn a local chromedriver and drive Chrome/Chromium.
+    Chromium,
+    /// Attach to an already running WebDriver server at `webdriver_url`.
+    Remote,
+}
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct BrowserConfig {
@@ -9,34 +26,248 @@ pub struct BrowserConfig {
     pub particles: bool,
     pub mascot_enabled: bool,
     pub custom_search: String,
+    #[serde(default = "search::default_search_engines")]
+    pub search_engines: Vec<SearchEngine>,
+    /// Name of the engine used for plain searches. `custom_search` is used
+    /// when unset.
+    #[serde(default)]
+    pub default_search_engine: Option<String>,
     pub gecko_port: u16,
     pub gecko_path: String,
+    #[serde(default)]
+    pub driver: DriverKind,
+    #[serde(default = "default_chrome_path")]
+    pub chrome_path: String,
+    #[serde(default)]
+    pub webdriver_url: Option<String>,
+    #[serde(default = "default_driver_startup_timeout")]
+    pub driver_startup_timeout_seconds: u64,
+    #[serde(default = "default_driver_max_restarts")]
+    pub driver_max_restarts: u32,
+    #[serde(default = "default_max_sessions")]
+    pub max_sessions: usize,
+    #[serde(default = "default_session_max_uses")]
+    pub session_max_uses: usize,
     pub download_dir: PathBuf,
+    #[serde(default = "default_config_dir")]
+    pub config_dir: PathBuf,
+    #[serde(default = "default_data_dir")]
+    pub data_dir: PathBuf,
+    /// Pre-data-dir bookmarks file, imported into the data dir once.
+    #[serde(default = "default_legacy_bookmarks_file")]
+    pub legacy_bookmarks_file: PathBuf,
+    /// Directory laid out like `src/assets` whose files replace the
+    /// embedded ones, for live-editing pages.
+    #[serde(default)]
+    pub assets_dev_dir: Option<PathBuf>,
+    /// Adblock Plus / EasyList filter lists to load at startup.
+    #[serde(default)]
+    pub adblock_lists: Vec<PathBuf>,
+    /// Directory mirroring filter list URLs as `host/path`, checked before
+    /// downloading a subscribed list.
+    #[serde(default)]
+    pub adblock_mirror: Option<PathBuf>,
+    /// How often to look for subscribed lists that are due an update. 0
+    /// turns automatic updates off.
+    #[serde(default = "default_adblock_refresh_minutes")]
+    pub adblock_refresh_minutes: u64,
+    /// Sends the browser's traffic through an in-process proxy, so the
+    /// network monitor and ad blocker see every request.
+    #[serde(default = "default_network_proxy")]
+    pub network_proxy: bool,
+    /// Port the proxy listens on. 0 picks a free one.
+    #[serde(default)]
+    pub network_proxy_port: u16,
+    /// Address the proxy listens on and the browser is pointed at. Defaults
+    /// to localhost, which a remote WebDriver can't reach, so the proxy stays
+    /// off for `driver = "remote"` unless this is set.
+    #[serde(default)]
+    pub network_proxy_host: Option<IpAddr>,
+    /// Lets the proxy read HTTPS traffic, by answering for the sites with
+    /// certificates from a CA kept in the data directory, which the browser
+    /// is told to accept. When off, only the sites network mocks or header
+    /// rules could apply to are read that way, and other HTTPS goes through tunnels the
+    /// proxy can't see into.
+    #[serde(default = "default_network_intercept_https")]
+    pub network_intercept_https: bool,
+    /// How much of each request and response body the network monitor keeps.
+    #[serde(default = "default_network_max_body_kb")]
+    pub network_max_body_kb: usize,
+    #[serde(default)]
+    pub history_max_age_days: Option<u32>,
+    #[serde(default)]
+    pub history_max_entries: Option<usize>,
+    #[serde(default = "default_session_snapshot_interval")]
+    pub session_snapshot_interval_seconds: u64,
     pub cache_size_mb: u32,
     pub timeout_seconds: u64,
     pub turbo_mode_enabled: bool,
     pub battery_saver_enabled: bool,
 }
 
+const CONFIG_FILE: &str = "config.toml";
+
+fn default_config_dir() -> PathBuf {
+    dirs::config_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("nyan-browser")
+}
+
+fn default_data_dir() -> PathBuf {
+    dirs::data_dir()
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("nyan-browser")
+}
+
+fn default_legacy_bookmarks_file() -> PathBuf {
+    PathBuf::from(crate::utils::bookmarks::LEGACY_BOOKMARKS_FILE)
+}
+
+fn default_adblock_refresh_minutes() -> u64 {
+    60
+}
+
+fn default_network_proxy() -> bool {
+    true
+}
+
+fn default_network_intercept_https() -> bool {
+    true
+}
+
+fn default_network_max_body_kb() -> usize {
+    1024
+}
+
+fn default_session_snapshot_interval() -> u64 {
+    15
+}
+
+fn default_chrome_path() -> String {
+    "chromedriver".to_string()
+}
+
+fn default_driver_startup_timeout() -> u64 {
+    20
+}
+
+fn default_driver_max_restarts() -> u32 {
+    3
+}
+
+fn default_max_sessions() -> usize {
+    4
+}
+
+fn default_session_max_uses() -> usize {
+    50
+}
+
 impl BrowserConfig {
     pub fn load() -> anyhow::Result<Self> {
         let config_dir = dirs::config_dir()
             .ok_or_else(|| anyhow::anyhow!("Could not find config directory"))?
             .join("nyan-browser");
-        std::fs::create_dir_all(&config_dir)?;
+        Self::load_from(&config_dir)
+    }
 
-        let config_path = config_dir.join("config.toml");
+    /// Reads `config.toml` from `config_dir`, writing the defaults there
+    /// first if it doesn't exist yet.
+    pub fn load_from(config_dir: &Path) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(config_dir)?;
+
+        let config_path = config_dir.join(CONFIG_FILE);
 
         if config_path.exists() {
             let content = std::fs::read_to_string(config_path)?;
             Ok(toml::from_str(&content)?)
         } else {
-            let config = Self::default();
+            let config = Self {
+                config_dir: config_dir.to_path_buf(),
+                ..Self::default()
+            };
             let content = toml::to_string_pretty(&config)?;
             std::fs::write(config_path, content)?;
             Ok(config)
         }
     }
+
+    /// Writes the config to `config.toml` in `config_dir`.
+    pub fn save(&self) -> anyhow::Result<()> {
+        std::fs::create_dir_all(&self.config_dir)?;
+        crate::utils::write_atomic(
+            &self.config_dir.join(CONFIG_FILE),
+            toml::to_string_pretty(self)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+
+    /// Template of the engine plain searches go to.
+    pub fn search_template(&self) -> &str {
+        self.default_search_engine
+            .as_deref()
+            .and_then(|name| self.search_engine(name))
+            .map(|engine| engine.template.as_str())
+            .unwrap_or(&self.custom_search)
+    }
+
+    pub fn search_engine(&self, name: &str) -> Option<&SearchEngine> {
+        self.search_engines
+            .iter()
+            .find(|e| e.name.eq_ignore_ascii_case(name))
+    }
+
+    pub fn search_engine_for_keyword(&self, keyword: &str) -> Option<&SearchEngine> {
+        self.search_engines.iter().find(|e| {
+            e.keyword
+                .as_deref()
+                .is_some_and(|k| k.eq_ignore_ascii_case(keyword))
+        })
+    }
+
+    /// Adds `engine`, replacing any engine with the same name. Fails if its
+    /// keyword already belongs to another engine.
+    pub fn add_search_engine(&mut self, engine: SearchEngine) -> anyhow::Result<()> {
+        if let Some(keyword) = &engine.keyword {
+            if let Some(owner) = self.search_engine_for_keyword(keyword) {
+                if !owner.name.eq_ignore_ascii_case(&engine.name) {
+                    anyhow::bail!("Keyword {} is already used by {}", keyword, owner.name);
+                }
+            }
+        }
+        match self
+            .search_engines
+            .iter_mut()
+            .find(|e| e.name.eq_ignore_ascii_case(&engine.name))
+        {
+            Some(existing) => *existing = engine,
+            None => self.search_engines.push(engine),
+        }
+        Ok(())
+    }
+
+    pub fn remove_search_engine(&mut self, name: &str) -> Option<SearchEngine> {
+        let index = self
+            .search_engines
+            .iter()
+            .position(|e| e.name.eq_ignore_ascii_case(name))?;
+        Some(self.search_engines.remove(index))
+    }
+
+    /// Adds the engine described by an OpenSearch XML file. `keyword`
+    /// overrides the alias in the file, if any.
+    pub fn import_opensearch(
+        &mut self,
+        path: &Path,
+        keyword: Option<&str>,
+    ) -> anyhow::Result<SearchEngine> {
+        let mut engine = SearchEngine::from_opensearch_file(path)?;
+        if let Some(keyword) = keyword {
+            engine.keyword = Some(keyword.to_string());
+        }
+        self.add_search_engine(engine.clone())?;
+        Ok(engine)
+    }
 }
 
 impl Default for BrowserConfig {
@@ -48,9 +279,33 @@ impl Default for BrowserConfig {
             particles: true,
             mascot_enabled: true,
             custom_search: "https://duckduckgo.com/?q=".to_string(),
+            search_engines: search::default_search_engines(),
+            default_search_engine: None,
             gecko_port: 4444,
             gecko_path: "geckodriver".to_string(),
+            driver: DriverKind::default(),
+            chrome_path: default_chrome_path(),
+            webdriver_url: None,
+            driver_startup_timeout_seconds: default_driver_startup_timeout(),
+            driver_max_restarts: default_driver_max_restarts(),
+            max_sessions: default_max_sessions(),
+            session_max_uses: default_session_max_uses(),
             download_dir: dirs::download_dir().unwrap_or_else(|| PathBuf::from("downloads")),
+            config_dir: default_config_dir(),
+            data_dir: default_data_dir(),
+            legacy_bookmarks_file: default_legacy_bookmarks_file(),
+            assets_dev_dir: None,
+            adblock_lists: Vec::new(),
+            adblock_mirror: None,
+            adblock_refresh_minutes: default_adblock_refresh_minutes(),
+            network_proxy: default_network_proxy(),
+            network_proxy_port: 0,
+            network_proxy_host: None,
+            network_intercept_https: default_network_intercept_https(),
+            network_max_body_kb: default_network_max_body_kb(),
+            history_max_age_days: None,
+            history_max_entries: None,
+            session_snapshot_interval_seconds: default_session_snapshot_interval(),
             cache_size_mb: 512,
             timeout_seconds: 30,
             turbo_mode_enabled: false,
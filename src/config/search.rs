@@ -0,0 +1,172 @@
+//! Search engines for the address bar, and OpenSearch description import.
+
+use anyhow::{anyhow, bail};
+use quick_xml::events::{BytesStart, Event};
+use quick_xml::Reader;
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SearchEngine {
+    pub name: String,
+    /// Results URL with a `{query}` placeholder.
+    pub template: String,
+    /// Typing the keyword before a query searches this engine, e.g.
+    /// `docs serde`.
+    #[serde(default)]
+    pub keyword: Option<String>,
+    /// Suggestions URL with a `{query}` placeholder, returning OpenSearch
+    /// suggestion JSON.
+    #[serde(default)]
+    pub suggestion_url: Option<String>,
+}
+
+impl SearchEngine {
+    pub fn new(name: &str, template: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            template: template.to_string(),
+            keyword: None,
+            suggestion_url: None,
+        }
+    }
+
+    pub fn with_keyword(mut self, keyword: &str) -> Self {
+        self.keyword = Some(keyword.to_string());
+        self
+    }
+
+    pub fn with_suggestions(mut self, url: &str) -> Self {
+        self.suggestion_url = Some(url.to_string());
+        self
+    }
+
+    /// Reads an OpenSearch description document. Only GET templates are
+    /// supported.
+    pub fn from_opensearch(xml: &str) -> anyhow::Result<Self> {
+        let mut reader = Reader::from_str(xml);
+        reader.config_mut().trim_text(true);
+
+        let mut name = None;
+        let mut alias = None;
+        let mut template = None;
+        let mut suggestion_url = None;
+        let mut text_of: Option<&'static str> = None;
+
+        loop {
+            match reader.read_event()? {
+                Event::Start(e) | Event::Empty(e) => match e.local_name().as_ref() {
+                    b"ShortName" => text_of = Some("name"),
+                    b"Alias" => text_of = Some("alias"),
+                    b"Url" => {
+                        let Some((kind, url)) = opensearch_url(&e)? else {
+                            continue;
+                        };
+                        match kind.as_str() {
+                            "text/html" => template = Some(url),
+                            "application/x-suggestions+json" => suggestion_url = Some(url),
+                            _ => {}
+                        }
+                    }
+                    _ => text_of = None,
+                },
+                Event::Text(t) => {
+                    let text = t.unescape()?.trim().to_string();
+                    match text_of.take() {
+                        Some("name") => name = Some(text),
+                        Some("alias") => alias = Some(text),
+                        _ => {}
+                    }
+                }
+                Event::End(_) => text_of = None,
+                Event::Eof => break,
+                _ => {}
+            }
+        }
+
+        let name = name.ok_or_else(|| anyhow!("OpenSearch description has no ShortName"))?;
+        let template = template
+            .ok_or_else(|| anyhow!("OpenSearch description {} has no text/html Url", name))?;
+        Ok(Self {
+            name,
+            template,
+            keyword: alias.filter(|a| !a.is_empty()),
+            suggestion_url,
+        })
+    }
+
+    pub fn from_opensearch_file(path: &Path) -> anyhow::Result<Self> {
+        Self::from_opensearch(&std::fs::read_to_string(path)?)
+    }
+}
+
+/// The type and converted template of a `<Url>` element, unless it is a
+/// POST URL.
+fn opensearch_url(e: &BytesStart) -> anyhow::Result<Option<(String, String)>> {
+    let mut kind = None;
+    let mut template = None;
+    let mut method = "get".to_string();
+    for attr in e.attributes() {
+        let attr = attr?;
+        let value = attr.unescape_value()?.to_string();
+        match attr.key.local_name().as_ref() {
+            b"type" => kind = Some(value),
+            b"template" => template = Some(value),
+            b"method" => method = value.to_lowercase(),
+            _ => {}
+        }
+    }
+    if method != "get" {
+        return Ok(None);
+    }
+    match (kind, template) {
+        (Some(kind), Some(template)) => Ok(Some((kind, convert_template(&template)?))),
+        _ => Ok(None),
+    }
+}
+
+/// Turns OpenSearch `{searchTerms}` templates into ours, filling in the
+/// other parameters we know and dropping unknown optional ones.
+fn convert_template(template: &str) -> anyhow::Result<String> {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        out.push_str(&rest[..start]);
+        let Some(end) = rest[start..].find('}') else {
+            bail!("Unterminated parameter in {}", template);
+        };
+        let param = &rest[start + 1..start + end];
+        // Parameters may be namespaced, like `{moz:locale}`.
+        let local = param.rsplit(':').next().unwrap_or(param);
+        match local.trim_end_matches('?') {
+            "searchTerms" => out.push_str("{query}"),
+            "inputEncoding" | "outputEncoding" => out.push_str("UTF-8"),
+            "language" | "locale" => out.push('*'),
+            "count" => out.push_str("20"),
+            "startIndex" | "startPage" => out.push('1'),
+            _ if param.ends_with('?') => {}
+            _ => bail!("Unsupported OpenSearch parameter {{{}}}", param),
+        }
+        rest = &rest[start + end + 1..];
+    }
+    out.push_str(rest);
+    if !out.contains("{query}") {
+        bail!("Template {} has no {{searchTerms}}", template);
+    }
+    Ok(out)
+}
+
+/// Engines every new config starts with.
+pub fn default_search_engines() -> Vec<SearchEngine> {
+    vec![
+        SearchEngine::new("DuckDuckGo", "https://duckduckgo.com/?q={query}")
+            .with_keyword("ddg")
+            .with_suggestions("https://duckduckgo.com/ac/?q={query}&type=list"),
+        SearchEngine::new("docs.rs", "https://docs.rs/releases/search?query={query}")
+            .with_keyword("docs"),
+        SearchEngine::new("Rust std", "https://doc.rust-lang.org/std/?search={query}")
+            .with_keyword("rs"),
+        SearchEngine::new("crates.io", "https://crates.io/search?q={query}").with_keyword("crates"),
+        SearchEngine::new("GitHub", "https://github.com/search?q={query}").with_keyword("gh"),
+    ]
+}
@@ -14,21 +14,23 @@ impl ConnectionPool {
         }
     }
 
-    pub async fn acquire(&self) -> PooledConnection {
+    pub fn max_connections(&self) -> usize {
+        self.max_connections
+    }
+
+    pub async fn acquire(&self) -> PooledConnection<'_> {
         let permit = self.semaphore.acquire().await.unwrap();
         PooledConnection {
             permit: Some(permit),
-            pool: self,
         }
     }
 }
 
 pub struct PooledConnection<'a> {
     permit: Option<SemaphorePermit<'a>>,
-    pool: &'a ConnectionPool,
 }
 
-impl<'a> PooledConnection<'a> {
+impl PooledConnection<'_> {
     pub fn release(self) {
         // Permit is automatically released when dropped
         drop(self.permit);
@@ -0,0 +1,101 @@
+use super::{check_status, Capabilities, DriverBackend, DriverCommand, DriverProcess};
+use crate::config::BrowserConfig;
+use crate::core::port_allocator::{PortAllocator, PortReservation};
+use async_trait::async_trait;
+use colored::*;
+use log::info;
+use serde_json::json;
+use std::time::Duration;
+
+/// Spawns a local chromedriver and drives Chrome/Chromium through it.
+pub struct ChromiumBackend {
+    driver: Option<DriverProcess>,
+    port: Option<PortReservation>,
+}
+
+impl ChromiumBackend {
+    pub fn new() -> Self {
+        Self {
+            driver: None,
+            port: None,
+        }
+    }
+}
+
+impl Default for ChromiumBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl DriverBackend for ChromiumBackend {
+    fn name(&self) -> &'static str {
+        "chromedriver"
+    }
+
+    fn fresh(&self) -> Box<dyn DriverBackend> {
+        Box::new(Self::new())
+    }
+
+    async fn spawn(&mut self, config: &BrowserConfig) -> anyhow::Result<String> {
+        let reservation = PortAllocator::new().allocate()?;
+        let port = reservation.port();
+        info!("{}", format!("Using port {}... (◕ᴗ◕✿)", port).cyan());
+        info!("{}", "Starting ChromeDriver...".cyan());
+
+        let driver = DriverProcess::spawn(DriverCommand {
+            name: self.name(),
+            program: config.chrome_path.clone(),
+            args: vec![format!("--port={}", port)],
+            url: format!("http://127.0.0.1:{}", port),
+            startup_timeout: Duration::from_secs(config.driver_startup_timeout_seconds),
+            max_restarts: config.driver_max_restarts,
+            shutdown_grace: Duration::from_secs(5),
+        })
+        .await?;
+
+        let url = driver.url().to_string();
+        self.driver = Some(driver);
+        self.port = Some(reservation);
+        Ok(url)
+    }
+
+    fn capabilities(&self, config: &BrowserConfig) -> Capabilities {
+        let mut caps = serde_json::map::Map::new();
+        let mut chrome_opts = serde_json::map::Map::new();
+
+        chrome_opts.insert(
+            "args".to_string(),
+            json!(["--no-first-run", "--no-default-browser-check"]),
+        );
+        chrome_opts.insert(
+            "prefs".to_string(),
+            json!({
+                "download.default_directory": config.download_dir,
+                "download.prompt_for_download": false,
+            }),
+        );
+
+        caps.insert("browserName".to_string(), json!("chrome"));
+        caps.insert("goog:chromeOptions".to_string(), json!(chrome_opts));
+        caps
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        match &self.driver {
+            Some(driver) if driver.is_running() => check_status(driver.url()).await,
+            Some(_) => Err(anyhow::anyhow!("ChromeDriver is no longer running")),
+            None => Err(anyhow::anyhow!("ChromeDriver has not been started")),
+        }
+    }
+
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        let result = match self.driver.take() {
+            Some(mut driver) => driver.shutdown().await,
+            None => Ok(()),
+        };
+        self.port = None;
+        result
+    }
+}
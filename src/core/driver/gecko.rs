@@ -0,0 +1,123 @@
+use super::{check_status, Capabilities, DriverBackend, DriverCommand, DriverProcess};
+use crate::config::BrowserConfig;
+use crate::core::port_allocator::{PortAllocator, PortReservation};
+use async_trait::async_trait;
+use colored::*;
+use log::info;
+use serde_json::json;
+use std::time::Duration;
+
+/// Spawns a local geckodriver and drives Firefox through it.
+pub struct GeckoBackend {
+    driver: Option<DriverProcess>,
+    port: Option<PortReservation>,
+}
+
+impl GeckoBackend {
+    pub fn new() -> Self {
+        Self {
+            driver: None,
+            port: None,
+        }
+    }
+}
+
+impl Default for GeckoBackend {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[async_trait]
+impl DriverBackend for GeckoBackend {
+    fn name(&self) -> &'static str {
+        "geckodriver"
+    }
+
+    fn fresh(&self) -> Box<dyn DriverBackend> {
+        Box::new(Self::new())
+    }
+
+    fn supports_multiple_sessions(&self) -> bool {
+        false
+    }
+
+    async fn spawn(&mut self, config: &BrowserConfig) -> anyhow::Result<String> {
+        let reservation = PortAllocator::new().prefer(config.gecko_port).allocate()?;
+        let port = reservation.port();
+        info!("{}", format!("Using port {}... (◕ᴗ◕✿)", port).cyan());
+        info!("{}", "Starting GeckoDriver...".cyan());
+
+        let driver = DriverProcess::spawn(DriverCommand {
+            name: self.name(),
+            program: config.gecko_path.clone(),
+            args: vec![format!("--port={}", port)],
+            url: format!("http://127.0.0.1:{}", port),
+            startup_timeout: Duration::from_secs(config.driver_startup_timeout_seconds),
+            max_restarts: config.driver_max_restarts,
+            shutdown_grace: Duration::from_secs(5),
+        })
+        .await?;
+
+        let url = driver.url().to_string();
+        self.driver = Some(driver);
+        self.port = Some(reservation);
+        Ok(url)
+    }
+
+    fn capabilities(&self, _config: &BrowserConfig) -> Capabilities {
+        let mut caps = serde_json::map::Map::new();
+        let mut firefox_opts = serde_json::map::Map::new();
+        let mut prefs = serde_json::map::Map::new();
+
+        prefs.insert("browser.startup.homepage".to_string(), json!("about:blank"));
+        prefs.insert("browser.startup.page".to_string(), json!(0));
+        prefs.insert(
+            "toolkit.legacyUserProfileCustomizations.stylesheets".to_string(),
+            json!(true),
+        );
+        prefs.insert(
+            "browser.shell.checkDefaultBrowser".to_string(),
+            json!(false),
+        );
+        prefs.insert(
+            "browser.sessionstore.resume_from_crash".to_string(),
+            json!(false),
+        );
+        prefs.insert("browser.tabs.drawInTitlebar".to_string(), json!(true));
+        prefs.insert("browser.download.folderList".to_string(), json!(2));
+        prefs.insert(
+            "browser.download.manager.showWhenStarting".to_string(),
+            json!(false),
+        );
+        prefs.insert(
+            "browser.download.manager.useWindow".to_string(),
+            json!(false),
+        );
+        prefs.insert(
+            "browser.helperApps.neverAsk.saveToDisk".to_string(),
+            json!("application/octet-stream"),
+        );
+
+        firefox_opts.insert("prefs".to_string(), json!(prefs));
+        caps.insert("moz:firefoxOptions".to_string(), json!(firefox_opts));
+        caps
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        match &self.driver {
+            Some(driver) if driver.is_running() => check_status(driver.url()).await,
+            Some(_) => Err(anyhow::anyhow!("GeckoDriver is no longer running")),
+            None => Err(anyhow::anyhow!("GeckoDriver has not been started")),
+        }
+    }
+
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        let result = match self.driver.take() {
+            Some(mut driver) => driver.shutdown().await,
+            None => Ok(()),
+        };
+        self.port = None;
+        result
+    }
+}
@@ -0,0 +1,156 @@
+//! WebDriver backends that [`NyanBrowser`](crate::NyanBrowser) can run against.
+//!
+//! A backend knows how to bring a WebDriver server up, which capabilities to
+//! ask it for, how to check that it is still alive and how to tear it down.
+
+pub mod chromium;
+pub mod gecko;
+pub mod process;
+pub mod remote;
+
+pub use chromium::ChromiumBackend;
+pub use gecko::GeckoBackend;
+pub use process::{DriverCommand, DriverProcess};
+pub use remote::RemoteBackend;
+
+use crate::config::{BrowserConfig, DriverKind};
+use async_trait::async_trait;
+use hyper::{Client, Uri};
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+
+pub type Capabilities = Map<String, Value>;
+
+#[async_trait]
+pub trait DriverBackend: Send + Sync {
+    /// Human readable backend name, used in logs.
+    fn name(&self) -> &'static str;
+
+    /// A backend of the same kind and settings that hasn't been spawned
+    /// yet, for opening more sessions like this one.
+    fn fresh(&self) -> Box<dyn DriverBackend>;
+
+    /// Starts (or locates) the WebDriver server and returns its base URL.
+    async fn spawn(&mut self, config: &BrowserConfig) -> anyhow::Result<String>;
+
+    /// Capabilities sent with every new session.
+    fn capabilities(&self, config: &BrowserConfig) -> Capabilities;
+
+    /// Checks that the WebDriver server is up and ready for new sessions.
+    async fn health_check(&self) -> anyhow::Result<()>;
+
+    /// Stops the WebDriver server if this backend owns it.
+    async fn shutdown(&mut self) -> anyhow::Result<()>;
+
+    /// Whether one server can host several sessions at once. Backends that
+    /// cannot get a dedicated server per pooled session.
+    fn supports_multiple_sessions(&self) -> bool {
+        true
+    }
+
+    /// Whether the browser runs on this machine and can reach services
+    /// listening on localhost.
+    fn runs_locally(&self) -> bool {
+        true
+    }
+}
+
+/// Picks the backend described by `config.driver`.
+pub fn from_config(config: &BrowserConfig) -> anyhow::Result<Box<dyn DriverBackend>> {
+    Ok(match config.driver {
+        DriverKind::Gecko => Box::new(GeckoBackend::new()),
+        DriverKind::Chromium => Box::new(ChromiumBackend::new()),
+        DriverKind::Remote => {
+            let url = config.webdriver_url.clone().ok_or_else(|| {
+                anyhow::anyhow!("driver = \"remote\" requires webdriver_url to be set")
+            })?;
+            Box::new(RemoteBackend::new(url))
+        }
+    })
+}
+
+/// Points the browser at the HTTP proxy on `proxy`, for HTTP and HTTPS,
+/// loopback addresses included. Firefox gets it as prefs and Chrome as
+/// switches, other browsers through the standard `proxy` capability. A proxy
+/// that `intercepts_https` answers with certificates of its own making,
+/// which the browser is told to accept.
+pub fn with_proxy(
+    mut caps: Capabilities,
+    proxy: SocketAddr,
+    intercepts_https: bool,
+) -> Capabilities {
+    if intercepts_https {
+        caps.insert("acceptInsecureCerts".to_string(), json!(true));
+    }
+    let host = proxy.ip().to_string();
+    if let Some(Value::Object(firefox)) = caps.get_mut("moz:firefoxOptions") {
+        let prefs = firefox
+            .entry("prefs")
+            .or_insert_with(|| Value::Object(Map::new()));
+        if let Value::Object(prefs) = prefs {
+            prefs.insert("network.proxy.type".to_string(), json!(1));
+            prefs.insert("network.proxy.http".to_string(), json!(host));
+            prefs.insert("network.proxy.http_port".to_string(), json!(proxy.port()));
+            prefs.insert("network.proxy.ssl".to_string(), json!(host));
+            prefs.insert("network.proxy.ssl_port".to_string(), json!(proxy.port()));
+            prefs.insert("network.proxy.no_proxies_on".to_string(), json!(""));
+            prefs.insert(
+                "network.proxy.allow_hijacking_localhost".to_string(),
+                json!(true),
+            );
+        }
+    } else if let Some(Value::Object(chrome)) = caps.get_mut("goog:chromeOptions") {
+        let args = chrome
+            .entry("args")
+            .or_insert_with(|| Value::Array(Vec::new()));
+        if let Value::Array(args) = args {
+            args.push(json!(format!("--proxy-server={}", proxy)));
+            args.push(json!("--proxy-bypass-list=<-loopback>"));
+        }
+    } else {
+        caps.insert(
+            "proxy".to_string(),
+            json!({
+                "proxyType": "manual",
+                "httpProxy": proxy.to_string(),
+                "sslProxy": proxy.to_string(),
+            }),
+        );
+    }
+    caps
+}
+
+/// Readiness reported by a WebDriver server's `/status` endpoint.
+#[derive(Debug, Clone)]
+pub struct DriverStatus {
+    pub ready: bool,
+    pub message: String,
+}
+
+/// Queries the W3C `/status` endpoint of the server at `base_url`.
+pub async fn fetch_status(base_url: &str) -> anyhow::Result<DriverStatus> {
+    let uri: Uri = format!("{}/status", base_url.trim_end_matches('/')).parse()?;
+    let response = Client::new().get(uri).await?;
+    if !response.status().is_success() {
+        anyhow::bail!("WebDriver status returned HTTP {}", response.status());
+    }
+
+    let body = hyper::body::to_bytes(response.into_body()).await?;
+    let status: Value = serde_json::from_slice(&body)?;
+    Ok(DriverStatus {
+        // Servers that omit `ready` predate the W3C spec; treat them as ready.
+        ready: status["value"]["ready"].as_bool().unwrap_or(true),
+        message: status["value"]["message"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string(),
+    })
+}
+
+/// Checks that the server at `base_url` answers `/status`.
+///
+/// Only reachability matters here: geckodriver reports `ready: false` once it
+/// already hosts a session, which is the normal state of a running browser.
+pub async fn check_status(base_url: &str) -> anyhow::Result<()> {
+    fetch_status(base_url).await.map(|_| ())
+}
@@ -0,0 +1,259 @@
+//! Supervision of a locally spawned WebDriver server process.
+//!
+//! A [`DriverProcess`] owns exactly one child process. It waits for the
+//! server's `/status` endpoint to report ready, forwards the child's output to
+//! the log, restarts it if it dies unexpectedly and stops it gracefully on
+//! shutdown. It never touches processes it did not spawn itself.
+
+use super::fetch_status;
+use crate::core::error::BrowserError;
+use log::{error, info, warn};
+use std::process::{ExitStatus, Stdio};
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncBufReadExt, AsyncRead, BufReader};
+use tokio::process::{Child, Command};
+use tokio::sync::oneshot;
+use tokio::task::JoinHandle;
+use tokio::time::{sleep, timeout, Instant};
+
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How to launch and babysit a driver executable.
+#[derive(Debug, Clone)]
+pub struct DriverCommand {
+    /// Name used as the log prefix, e.g. `geckodriver`.
+    pub name: &'static str,
+    pub program: String,
+    pub args: Vec<String>,
+    /// Base URL the driver will serve once it is up.
+    pub url: String,
+    pub startup_timeout: Duration,
+    /// How often a crashed driver is restarted before giving up.
+    pub max_restarts: u32,
+    /// How long to wait after asking the driver to exit before killing it.
+    pub shutdown_grace: Duration,
+}
+
+pub struct DriverProcess {
+    name: &'static str,
+    url: String,
+    pid: Arc<AtomicU32>,
+    restarts: Arc<AtomicU32>,
+    stop: Option<oneshot::Sender<()>>,
+    supervisor: Option<JoinHandle<()>>,
+}
+
+impl DriverProcess {
+    /// Spawns the driver and waits until it reports ready.
+    pub async fn spawn(command: DriverCommand) -> Result<Self, BrowserError> {
+        let child = start(&command).await?;
+
+        let pid = Arc::new(AtomicU32::new(child.id().unwrap_or(0)));
+        let restarts = Arc::new(AtomicU32::new(0));
+        let (stop, stop_rx) = oneshot::channel();
+
+        let supervisor = tokio::spawn(supervise(
+            command.clone(),
+            child,
+            stop_rx,
+            Arc::clone(&pid),
+            Arc::clone(&restarts),
+        ));
+
+        Ok(Self {
+            name: command.name,
+            url: command.url,
+            pid,
+            restarts,
+            stop: Some(stop),
+            supervisor: Some(supervisor),
+        })
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// OS process id of the current driver child, if one is running.
+    pub fn pid(&self) -> Option<u32> {
+        match self.pid.load(Ordering::SeqCst) {
+            0 => None,
+            pid => Some(pid),
+        }
+    }
+
+    /// Number of times the driver has been restarted after crashing.
+    pub fn restarts(&self) -> u32 {
+        self.restarts.load(Ordering::SeqCst)
+    }
+
+    /// Whether the supervisor still has a driver under its care.
+    pub fn is_running(&self) -> bool {
+        self.supervisor
+            .as_ref()
+            .map(|task| !task.is_finished())
+            .unwrap_or(false)
+    }
+
+    /// Asks the driver to exit and waits for it to do so.
+    pub async fn shutdown(&mut self) -> anyhow::Result<()> {
+        if let Some(stop) = self.stop.take() {
+            let _ = stop.send(());
+        }
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.await?;
+        }
+        info!("{} stopped", self.name);
+        Ok(())
+    }
+}
+
+impl Drop for DriverProcess {
+    fn drop(&mut self) {
+        // Aborting the supervisor drops the child, and children are spawned
+        // with `kill_on_drop`, so nothing outlives us.
+        if let Some(supervisor) = self.supervisor.take() {
+            supervisor.abort();
+        }
+    }
+}
+
+/// Launches the child and polls `/status` until it is ready.
+async fn start(command: &DriverCommand) -> Result<Child, BrowserError> {
+    info!("Starting {} at {}", command.name, command.url);
+
+    let mut child = Command::new(&command.program)
+        .args(&command.args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| BrowserError::DriverStartError(format!("{}: {}", command.program, e)))?;
+
+    if let Some(stdout) = child.stdout.take() {
+        tokio::spawn(forward_output(command.name, stdout, false));
+    }
+    if let Some(stderr) = child.stderr.take() {
+        tokio::spawn(forward_output(command.name, stderr, true));
+    }
+
+    let deadline = Instant::now() + command.startup_timeout;
+    loop {
+        let exited = child
+            .try_wait()
+            .map_err(|e| BrowserError::DriverStartError(e.to_string()))?;
+        if let Some(status) = exited {
+            return Err(BrowserError::DriverStartError(format!(
+                "{} exited during startup ({})",
+                command.name, status
+            )));
+        }
+        // A server that takes the connection but never answers mustn't hold
+        // startup past the deadline.
+        let remaining = deadline.saturating_duration_since(Instant::now());
+        let status = timeout(remaining, fetch_status(&command.url)).await;
+        if matches!(status, Ok(Ok(status)) if status.ready) {
+            info!("{} is ready", command.name);
+            return Ok(child);
+        }
+        if Instant::now() >= deadline {
+            let _ = child.kill().await;
+            return Err(BrowserError::DriverStartError(format!(
+                "{} did not become ready within {:?}",
+                command.name, command.startup_timeout
+            )));
+        }
+        sleep(READY_POLL_INTERVAL).await;
+    }
+}
+
+async fn supervise(
+    command: DriverCommand,
+    mut child: Child,
+    mut stop: oneshot::Receiver<()>,
+    pid: Arc<AtomicU32>,
+    restarts: Arc<AtomicU32>,
+) {
+    loop {
+        tokio::select! {
+            _ = &mut stop => {
+                stop_gracefully(&command, &mut child).await;
+                pid.store(0, Ordering::SeqCst);
+                return;
+            }
+            status = child.wait() => {
+                pid.store(0, Ordering::SeqCst);
+                log_exit(&command, status);
+
+                let attempt = restarts.load(Ordering::SeqCst) + 1;
+                if attempt > command.max_restarts {
+                    error!(
+                        "{} crashed {} times, giving up",
+                        command.name, attempt
+                    );
+                    return;
+                }
+
+                warn!(
+                    "Restarting {} ({}/{})",
+                    command.name, attempt, command.max_restarts
+                );
+                restarts.store(attempt, Ordering::SeqCst);
+                match start(&command).await {
+                    Ok(restarted) => {
+                        pid.store(restarted.id().unwrap_or(0), Ordering::SeqCst);
+                        child = restarted;
+                    }
+                    Err(e) => {
+                        error!("Could not restart {}: {}", command.name, e);
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+async fn stop_gracefully(command: &DriverCommand, child: &mut Child) {
+    #[cfg(unix)]
+    {
+        if let Some(pid) = child.id() {
+            // SAFETY: `pid` belongs to a child we spawned and have not reaped yet.
+            unsafe {
+                libc::kill(pid as libc::pid_t, libc::SIGTERM);
+            }
+            if timeout(command.shutdown_grace, child.wait()).await.is_ok() {
+                return;
+            }
+            warn!(
+                "{} ignored SIGTERM for {:?}, killing it",
+                command.name, command.shutdown_grace
+            );
+        }
+    }
+
+    if let Err(e) = child.kill().await {
+        error!("Error stopping {}: {}", command.name, e);
+    }
+}
+
+fn log_exit(command: &DriverCommand, status: std::io::Result<ExitStatus>) {
+    match status {
+        Ok(status) => error!("{} exited unexpectedly ({})", command.name, status),
+        Err(e) => error!("Lost track of {}: {}", command.name, e),
+    }
+}
+
+async fn forward_output(name: &'static str, output: impl AsyncRead + Unpin, is_stderr: bool) {
+    let mut lines = BufReader::new(output).lines();
+    while let Ok(Some(line)) = lines.next_line().await {
+        if is_stderr {
+            warn!("[{}] {}", name, line);
+        } else {
+            info!("[{}] {}", name, line);
+        }
+    }
+}
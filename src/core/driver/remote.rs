@@ -0,0 +1,63 @@
+use super::{check_status, Capabilities, DriverBackend};
+use crate::config::BrowserConfig;
+use async_trait::async_trait;
+use colored::*;
+use log::info;
+
+/// Attaches to a WebDriver server that is already running somewhere else,
+/// e.g. a Selenium grid or a stand-in server used by tests.
+pub struct RemoteBackend {
+    url: String,
+    capabilities: Capabilities,
+}
+
+impl RemoteBackend {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into().trim_end_matches('/').to_string(),
+            capabilities: Capabilities::new(),
+        }
+    }
+
+    /// Extra capabilities to request from the remote server.
+    pub fn with_capabilities(mut self, capabilities: Capabilities) -> Self {
+        self.capabilities = capabilities;
+        self
+    }
+}
+
+#[async_trait]
+impl DriverBackend for RemoteBackend {
+    fn name(&self) -> &'static str {
+        "remote"
+    }
+
+    fn fresh(&self) -> Box<dyn DriverBackend> {
+        Box::new(Self::new(self.url.clone()).with_capabilities(self.capabilities.clone()))
+    }
+
+    async fn spawn(&mut self, _config: &BrowserConfig) -> anyhow::Result<String> {
+        info!(
+            "{}",
+            format!("Attaching to WebDriver at {}... (◕ᴗ◕✿)", self.url).cyan()
+        );
+        Ok(self.url.clone())
+    }
+
+    fn capabilities(&self, _config: &BrowserConfig) -> Capabilities {
+        self.capabilities.clone()
+    }
+
+    async fn health_check(&self) -> anyhow::Result<()> {
+        check_status(&self.url).await
+    }
+
+    async fn shutdown(&mut self) -> anyhow::Result<()> {
+        // Somebody else owns the server, leave it running.
+        Ok(())
+    }
+
+    fn runs_locally(&self) -> bool {
+        false
+    }
+}
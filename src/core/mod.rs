@@ -1,6 +1,19 @@
 pub mod browser_cache;
 pub mod connection_pool;
+pub mod driver;
 pub mod error;
+pub mod omnibox;
+pub mod port_allocator;
+pub mod scheme;
+pub mod session_pool;
+pub mod session_store;
+pub mod tabs;
 
 pub use browser_cache::BrowserCache;
 pub use connection_pool::ConnectionPool;
+pub use driver::DriverBackend;
+pub use port_allocator::PortAllocator;
+pub use scheme::{InternalPage, PageRequest, SchemeRouter};
+pub use session_pool::SessionPool;
+pub use session_store::{SessionRecorder, SessionSnapshot, SessionStore, TabSnapshot};
+pub use tabs::{TabEvent, TabInfo, TabKind, TabTracker, Tabs};
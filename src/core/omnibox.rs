@@ -0,0 +1,216 @@
+//! Address bar input resolution.
+//!
+//! [`resolve`] turns whatever the user typed into the URL to load: an
+//! internal `kawaii://` page, a bookmark keyword, a URL (with the scheme
+//! filled in and international hosts punycoded) or, failing all of those, a
+//! search. It never touches the network or the filesystem, so it is cheap to
+//! call on every keystroke.
+
+use std::collections::HashMap;
+use std::net::{Ipv4Addr, Ipv6Addr};
+use url::{form_urlencoded, Url};
+
+pub const INTERNAL_SCHEME: &str = "kawaii";
+
+/// Schemes accepted as-is when typed without `//`, like `about:blank`.
+const OPAQUE_SCHEMES: &[&str] = &["about", "data", "file", "mailto", "view-source"];
+
+/// What the resolver needs to know about the user's setup.
+#[derive(Debug, Clone, Default)]
+pub struct OmniboxContext {
+    /// Search URL, either with a `{query}` placeholder or a prefix the
+    /// query is appended to (`https://duckduckgo.com/?q=`).
+    pub search_template: String,
+    /// Keyword to URL template. `%s` or `{query}` in the template is
+    /// replaced with whatever follows the keyword.
+    pub keywords: HashMap<String, String>,
+}
+
+impl OmniboxContext {
+    pub fn new(search_template: &str) -> Self {
+        Self {
+            search_template: search_template.to_string(),
+            keywords: HashMap::new(),
+        }
+    }
+
+    pub fn with_keyword(mut self, keyword: &str, template: &str) -> Self {
+        self.keywords
+            .insert(keyword.to_lowercase(), template.to_string());
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Destination {
+    /// A `kawaii://` page, with the scheme and host lowercased.
+    Internal(String),
+    /// A URL, typed with or without a scheme.
+    Url(String),
+    /// A bookmark keyword, with the rest of the input filled in.
+    Keyword { keyword: String, url: String },
+    /// Free text sent to the search engine.
+    Search { query: String, url: String },
+}
+
+impl Destination {
+    /// The URL to load.
+    pub fn url(&self) -> &str {
+        match self {
+            Destination::Internal(url) | Destination::Url(url) => url,
+            Destination::Keyword { url, .. } | Destination::Search { url, .. } => url,
+        }
+    }
+}
+
+/// Decides what `input` means. Empty input goes to the home page and input
+/// starting with `?` is always searched for.
+pub fn resolve(input: &str, context: &OmniboxContext) -> Destination {
+    let input = input.trim();
+    if input.is_empty() {
+        return Destination::Internal(format!("{}://home", INTERNAL_SCHEME));
+    }
+    if let Some(query) = input.strip_prefix('?') {
+        return search(query.trim(), context);
+    }
+    if let Some(internal) = internal_page(input) {
+        return Destination::Internal(internal);
+    }
+    if let Some(keyword) = keyword(input, context) {
+        return keyword;
+    }
+    if let Some(url) = explicit_url(input).or_else(|| bare_host(input)) {
+        return Destination::Url(url);
+    }
+    search(input, context)
+}
+
+/// Fills a search or keyword template with `query`, percent-encoded.
+pub fn fill_template(template: &str, query: &str) -> String {
+    let encoded: String = form_urlencoded::byte_serialize(query.as_bytes()).collect();
+    if template.contains("{query}") {
+        template.replace("{query}", &encoded)
+    } else if template.contains("%s") {
+        template.replace("%s", &encoded)
+    } else {
+        format!("{}{}", template, encoded)
+    }
+}
+
+fn search(query: &str, context: &OmniboxContext) -> Destination {
+    Destination::Search {
+        query: query.to_string(),
+        url: fill_template(&context.search_template, query),
+    }
+}
+
+fn internal_page(input: &str) -> Option<String> {
+    let (scheme, rest) = input.split_once("://")?;
+    if !scheme.eq_ignore_ascii_case(INTERNAL_SCHEME) {
+        return None;
+    }
+    let (page, tail) = match rest.find(['/', '?', '#']) {
+        Some(i) => rest.split_at(i),
+        None => (rest, ""),
+    };
+    Some(format!(
+        "{}://{}{}",
+        INTERNAL_SCHEME,
+        page.to_lowercase(),
+        tail
+    ))
+}
+
+fn keyword(input: &str, context: &OmniboxContext) -> Option<Destination> {
+    let (word, rest) = match input.split_once(char::is_whitespace) {
+        Some((word, rest)) => (word, rest.trim()),
+        None => (input, ""),
+    };
+    let keyword = word.to_lowercase();
+    let template = context.keywords.get(&keyword)?;
+
+    let takes_query = template.contains("%s") || template.contains("{query}");
+    if takes_query == rest.is_empty() {
+        // "gh" alone with a search keyword, or "home foo" with a plain
+        // bookmark, is probably not meant as a keyword.
+        return None;
+    }
+    let url = if takes_query {
+        fill_template(template, rest)
+    } else {
+        template.clone()
+    };
+    Some(Destination::Keyword { keyword, url })
+}
+
+fn explicit_url(input: &str) -> Option<String> {
+    if input.contains(char::is_whitespace) {
+        return None;
+    }
+    let url = Url::parse(input).ok()?;
+    let typed_authority = input[url.scheme().len()..].starts_with("://");
+    if typed_authority || OPAQUE_SCHEMES.contains(&url.scheme()) {
+        return Some(url.to_string());
+    }
+    None
+}
+
+/// `example.com/path`, `localhost:3000`, `192.168.1.1` and friends. Local
+/// addresses get `http://`, everything else `https://`.
+fn bare_host(input: &str) -> Option<String> {
+    if input.contains(char::is_whitespace) {
+        return None;
+    }
+    let authority_end = input.find(['/', '?', '#']).unwrap_or(input.len());
+    let authority = &input[..authority_end];
+    if authority.contains('@') {
+        return None;
+    }
+
+    let host = match authority.strip_prefix('[') {
+        Some(v6) => {
+            let end = v6.find(']')?;
+            v6[..end].parse::<Ipv6Addr>().ok()?;
+            &authority[..end + 2]
+        }
+        None => authority.split(':').next()?,
+    };
+    let port = &authority[host.len()..];
+    if !(port.is_empty() || port.strip_prefix(':').is_some_and(is_port)) {
+        return None;
+    }
+
+    let local = host.eq_ignore_ascii_case("localhost")
+        || host.parse::<Ipv4Addr>().is_ok()
+        || host.starts_with('[');
+    if !local && !looks_like_domain(host) {
+        return None;
+    }
+
+    let scheme = if local { "http" } else { "https" };
+    Url::parse(&format!("{}://{}", scheme, input))
+        .ok()
+        .map(|url| url.to_string())
+}
+
+fn is_port(s: &str) -> bool {
+    !s.is_empty() && s.parse::<u16>().is_ok()
+}
+
+/// At least two non-empty labels and a plausible top-level domain.
+fn looks_like_domain(host: &str) -> bool {
+    let host = host.strip_suffix('.').unwrap_or(host);
+    let labels: Vec<&str> = host.split('.').collect();
+    if labels.len() < 2 || labels.iter().any(|label| label.is_empty()) {
+        return false;
+    }
+    let tld = labels[labels.len() - 1];
+    let tld_ok = tld.starts_with("xn--")
+        || (tld.chars().count() >= 2 && tld.chars().all(char::is_alphabetic));
+    tld_ok
+        && labels.iter().all(|label| {
+            label
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+        })
+}
@@ -0,0 +1,98 @@
+//! Process-wide allocation of local TCP ports for driver servers.
+//!
+//! Ports are found by binding rather than by probing with `connect`, and every
+//! port handed out stays reserved until its [`PortReservation`] is dropped, so
+//! concurrent browsers in one process never pick the same port.
+
+use crate::core::error::{BrowserError, Result};
+use once_cell::sync::Lazy;
+use parking_lot::Mutex;
+use std::collections::HashSet;
+use std::net::{Ipv4Addr, TcpListener};
+use std::ops::Range;
+
+static RESERVED: Lazy<Mutex<HashSet<u16>>> = Lazy::new(|| Mutex::new(HashSet::new()));
+
+/// A port reserved for this process. Releases the reservation when dropped.
+#[derive(Debug)]
+pub struct PortReservation {
+    port: u16,
+}
+
+impl PortReservation {
+    pub fn port(&self) -> u16 {
+        self.port
+    }
+}
+
+impl Drop for PortReservation {
+    fn drop(&mut self) {
+        RESERVED.lock().remove(&self.port);
+    }
+}
+
+/// Hands out free local ports.
+#[derive(Debug, Clone, Default)]
+pub struct PortAllocator {
+    preferred: Option<u16>,
+    range: Option<Range<u16>>,
+}
+
+impl PortAllocator {
+    /// Lets the OS pick any free port.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Tries `port` first and falls back to the usual search if it is taken.
+    /// A preferred port of `0` means "no preference".
+    pub fn prefer(mut self, port: u16) -> Self {
+        self.preferred = (port != 0).then_some(port);
+        self
+    }
+
+    /// Restricts the search to `range` instead of letting the OS pick.
+    pub fn within(mut self, range: Range<u16>) -> Self {
+        self.range = Some(range);
+        self
+    }
+
+    /// Reserves a free port.
+    pub fn allocate(&self) -> Result<PortReservation> {
+        if let Some(reservation) = self.preferred.and_then(try_reserve) {
+            return Ok(reservation);
+        }
+
+        match &self.range {
+            Some(range) => range
+                .clone()
+                .find_map(try_reserve)
+                .ok_or(BrowserError::NoPortsAvailable),
+            None => {
+                // The OS may hand back a port another browser in this process
+                // has reserved but not bound yet, so ask a few times.
+                (0..16)
+                    .find_map(|_| {
+                        let port = TcpListener::bind((Ipv4Addr::LOCALHOST, 0))
+                            .and_then(|listener| listener.local_addr())
+                            .ok()?
+                            .port();
+                        try_reserve(port)
+                    })
+                    .ok_or(BrowserError::NoPortsAvailable)
+            }
+        }
+    }
+}
+
+fn try_reserve(port: u16) -> Option<PortReservation> {
+    let mut reserved = RESERVED.lock();
+    if reserved.contains(&port) {
+        return None;
+    }
+    // Binding proves nobody else is listening; the listener is closed again
+    // right away so the driver can take the port over.
+    TcpListener::bind((Ipv4Addr::LOCALHOST, port)).ok()?;
+    reserved.insert(port);
+    Some(PortReservation { port })
+}
@@ -0,0 +1,163 @@
+//! Router for internal `kawaii://` pages.
+//!
+//! Every page is an [`InternalPage`] registered under its host name, so
+//! `kawaii://history?q=rust` is rendered by the page registered as
+//! `history`. Pages render complete HTML documents from the browser's
+//! [`Assets`], which the browser then loads as a `data:` URL.
+
+mod pages;
+
+use crate::assets::{self, Assets};
+use crate::browser::NyanBrowser;
+use crate::core::omnibox::INTERNAL_SCHEME;
+use async_trait::async_trait;
+use base64::Engine;
+use parking_lot::RwLock;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use url::Url;
+
+/// A parsed `kawaii://` URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PageRequest {
+    pub url: String,
+    /// The host part, e.g. `history`.
+    pub page: String,
+    /// Everything after the host, `/` if nothing.
+    pub path: String,
+    pub query: HashMap<String, String>,
+}
+
+impl PageRequest {
+    pub fn parse(url: &str) -> anyhow::Result<Self> {
+        let parsed = Url::parse(url)?;
+        if parsed.scheme() != INTERNAL_SCHEME {
+            anyhow::bail!("{} is not a {}:// URL", url, INTERNAL_SCHEME);
+        }
+        let page = parsed.host_str().unwrap_or_default().to_lowercase();
+        let path = match parsed.path() {
+            "" => "/".to_string(),
+            path => path.to_string(),
+        };
+        Ok(Self {
+            url: url.to_string(),
+            page,
+            path,
+            query: parsed.query_pairs().into_owned().collect(),
+        })
+    }
+
+    pub fn param(&self, name: &str) -> Option<&str> {
+        self.query.get(name).map(String::as_str)
+    }
+}
+
+/// A built-in or registered `kawaii://` page.
+#[async_trait]
+pub trait InternalPage: Send + Sync {
+    /// Shown on `kawaii://about`.
+    fn description(&self) -> &str;
+
+    /// Renders the page as a complete HTML document.
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String>;
+}
+
+#[derive(Default)]
+pub struct SchemeRouter {
+    pages: RwLock<HashMap<String, Arc<dyn InternalPage>>>,
+}
+
+impl SchemeRouter {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A router with home, history, bookmarks, settings, downloads, network,
+    /// adblock and about registered.
+    pub fn with_builtin_pages() -> Self {
+        let router = Self::new();
+        pages::register_builtin(&router);
+        router
+    }
+
+    /// Registers `page` as `kawaii://{name}`, replacing any page already
+    /// registered under that name.
+    pub fn register(&self, name: &str, page: impl InternalPage + 'static) {
+        self.pages
+            .write()
+            .insert(name.to_lowercase(), Arc::new(page));
+    }
+
+    pub fn unregister(&self, name: &str) -> bool {
+        self.pages.write().remove(&name.to_lowercase()).is_some()
+    }
+
+    /// Registered page names with their descriptions, sorted by name.
+    pub fn pages(&self) -> BTreeMap<String, String> {
+        self.pages
+            .read()
+            .iter()
+            .map(|(name, page)| (name.clone(), page.description().to_string()))
+            .collect()
+    }
+
+    /// Renders the page for `url`. Unknown pages get a "not found" page
+    /// rather than an error.
+    pub async fn render(&self, url: &str, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let request = PageRequest::parse(url)?;
+        let page = self.pages.read().get(&request.page).cloned();
+        match page {
+            Some(page) => page.render(&request, browser).await,
+            None => Ok(layout(
+                browser.assets(),
+                "Page not found (╥﹏╥)",
+                url,
+                &format!(
+                    "<p class=\"empty-state\">There is no page called <code>{}</code>. \
+                     <code>{}://about</code> lists the ones that exist.</p>",
+                    escape_html(&request.page),
+                    INTERNAL_SCHEME
+                ),
+            )),
+        }
+    }
+}
+
+/// `html` as a URL the driver can load.
+pub fn data_url(html: &str) -> String {
+    format!(
+        "data:text/html;charset=utf-8;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(html)
+    )
+}
+
+/// Wraps page content in the shared internal page layout. `content` must
+/// already be escaped.
+pub fn layout(assets: &Assets, title: &str, url: &str, content: &str) -> String {
+    let styles = format!(
+        "{}\n{}",
+        assets.text(assets::MAIN_CSS),
+        assets.text(assets::INTERNAL_CSS)
+    );
+    assets
+        .text(assets::INTERNAL_HTML)
+        .replace("{{styles}}", &styles)
+        .replace("{{title}}", &escape_html(title))
+        .replace("{{url}}", &escape_html(url))
+        .replace("{{content}}", content)
+}
+
+pub fn escape_html(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+    out
+}
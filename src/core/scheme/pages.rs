@@ -0,0 +1,534 @@
+//! The built-in `kawaii://` pages.
+
+use super::{escape_html, layout, InternalPage, PageRequest, SchemeRouter};
+use crate::assets;
+use crate::browser::NyanBrowser;
+use crate::features::network::{NetworkQuery, StatusClass};
+use crate::utils::HistoryQuery;
+use async_trait::async_trait;
+use chrono::{DateTime, Local, Utc};
+use std::cmp::Reverse;
+use std::fmt::Write;
+use url::{form_urlencoded, Url};
+
+/// How many rows list pages show at most.
+const MAX_ROWS: usize = 200;
+
+pub(super) fn register_builtin(router: &SchemeRouter) {
+    router.register("home", HomePage);
+    router.register("history", HistoryPage);
+    router.register("bookmarks", BookmarksPage);
+    router.register("settings", SettingsPage);
+    router.register("downloads", DownloadsPage);
+    router.register("network", NetworkPage);
+    router.register("adblock", AdblockPage);
+    router.register("about", AboutPage);
+}
+
+fn time(at: DateTime<Utc>) -> String {
+    at.with_timezone(&Local)
+        .format("%Y-%m-%d %H:%M")
+        .to_string()
+}
+
+fn link(url: &str, text: &str) -> String {
+    format!(
+        "<a href=\"{}\">{}</a>",
+        escape_html(url),
+        escape_html(if text.is_empty() { url } else { text })
+    )
+}
+
+fn table(headers: &[&str], rows: &[Vec<String>], empty: &str) -> String {
+    if rows.is_empty() {
+        return format!("<p class=\"empty-state\">{}</p>", escape_html(empty));
+    }
+    let mut html = String::from("<table class=\"kawaii-table\"><thead><tr>");
+    for header in headers {
+        let _ = write!(html, "<th>{}</th>", escape_html(header));
+    }
+    html.push_str("</tr></thead><tbody>");
+    for row in rows {
+        html.push_str("<tr>");
+        for cell in row {
+            let _ = write!(html, "<td>{}</td>", cell);
+        }
+        html.push_str("</tr>");
+    }
+    html.push_str("</tbody></table>");
+    html
+}
+
+fn human_size(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KB", "MB", "GB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 {
+        format!("{} B", bytes)
+    } else {
+        format!("{:.1} {}", size, UNITS[unit])
+    }
+}
+
+struct HomePage;
+
+#[async_trait]
+impl InternalPage for HomePage {
+    fn description(&self) -> &str {
+        "Start page"
+    }
+
+    async fn render(&self, _: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let assets = browser.assets();
+        Ok(format!(
+            "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n\
+             <title>Kawaii Browser</title>\n<style>{}</style>\n</head>\n<body>\n{}</body>\n</html>\n",
+            assets.text(assets::MAIN_CSS),
+            assets.text(assets::HOME_HTML)
+        ))
+    }
+}
+
+struct HistoryPage;
+
+#[async_trait]
+impl InternalPage for HistoryPage {
+    fn description(&self) -> &str {
+        "Browsing history, searchable with ?q="
+    }
+
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let query = request.param("q").unwrap_or_default();
+        let entries = match browser.history() {
+            Some(history) => history.search(&HistoryQuery {
+                limit: Some(MAX_ROWS),
+                ..HistoryQuery::text(query)
+            }),
+            None => Vec::new(),
+        };
+        let rows: Vec<_> = entries
+            .iter()
+            .map(|entry| {
+                vec![
+                    time(entry.last_visit),
+                    link(&entry.url, &entry.title),
+                    entry.visit_count.to_string(),
+                ]
+            })
+            .collect();
+
+        let title = if query.is_empty() {
+            "History".to_string()
+        } else {
+            format!("History matching \"{}\"", query)
+        };
+        Ok(layout(
+            browser.assets(),
+            &title,
+            &request.url,
+            &table(
+                &["Last visit", "Page", "Visits"],
+                &rows,
+                "Nothing here yet (´･ω･`)",
+            ),
+        ))
+    }
+}
+
+struct BookmarksPage;
+
+#[async_trait]
+impl InternalPage for BookmarksPage {
+    fn description(&self) -> &str {
+        "Bookmarks by folder, filterable with ?tag="
+    }
+
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let Some(bookmarks) = browser.bookmarks() else {
+            return Ok(layout(
+                browser.assets(),
+                "Bookmarks",
+                &request.url,
+                "<p class=\"empty-state\">Bookmarks are unavailable (╥﹏╥)</p>",
+            ));
+        };
+        let tag = request.param("tag");
+        let mut all = match tag {
+            Some(tag) => bookmarks.with_tag(tag),
+            None => bookmarks.list(),
+        };
+        all.sort_by(|a, b| a.folder.cmp(&b.folder));
+
+        let mut content = String::new();
+        let mut folders: Vec<Vec<String>> = all.iter().map(|b| b.folder.clone()).collect();
+        folders.dedup();
+        for folder in folders {
+            let heading = if folder.is_empty() {
+                "Bookmarks".to_string()
+            } else {
+                folder.join(" / ")
+            };
+            let rows: Vec<_> = all
+                .iter()
+                .filter(|b| b.folder == folder)
+                .map(|b| {
+                    let tags: String = b
+                        .tags
+                        .iter()
+                        .map(|t| format!("<span class=\"tag\">{}</span>", escape_html(t)))
+                        .collect();
+                    vec![
+                        link(&b.url, &b.title),
+                        escape_html(b.keyword.as_deref().unwrap_or_default()),
+                        tags,
+                    ]
+                })
+                .collect();
+            let _ = write!(
+                content,
+                "<h2>{}</h2>{}",
+                escape_html(&heading),
+                table(&["Page", "Keyword", "Tags"], &rows, "")
+            );
+        }
+        if content.is_empty() {
+            content = "<p class=\"empty-state\">No bookmarks yet ♡</p>".to_string();
+        }
+
+        let title = match tag {
+            Some(tag) => format!("Bookmarks tagged \"{}\"", tag),
+            None => "Bookmarks".to_string(),
+        };
+        Ok(layout(browser.assets(), &title, &request.url, &content))
+    }
+}
+
+struct SettingsPage;
+
+#[async_trait]
+impl InternalPage for SettingsPage {
+    fn description(&self) -> &str {
+        "Current configuration"
+    }
+
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let (settings, engines) = {
+            let config = browser.get_config();
+            (
+                toml::Table::try_from(&*config)?,
+                config.search_engines.clone(),
+            )
+        };
+
+        let rows: Vec<_> = settings
+            .iter()
+            .filter(|(key, _)| *key != "search_engines")
+            .map(|(key, value)| vec![escape_html(key), escape_html(&value.to_string())])
+            .collect();
+        let engine_rows: Vec<_> = engines
+            .iter()
+            .map(|e| {
+                vec![
+                    escape_html(&e.name),
+                    escape_html(e.keyword.as_deref().unwrap_or_default()),
+                    escape_html(&e.template),
+                ]
+            })
+            .collect();
+
+        let content = format!(
+            "{}<h2>Search engines</h2>{}",
+            table(&["Setting", "Value"], &rows, ""),
+            table(
+                &["Name", "Keyword", "Template"],
+                &engine_rows,
+                "No search engines"
+            )
+        );
+        Ok(layout(browser.assets(), "Settings", &request.url, &content))
+    }
+}
+
+struct DownloadsPage;
+
+#[async_trait]
+impl InternalPage for DownloadsPage {
+    fn description(&self) -> &str {
+        "Files in the download directory"
+    }
+
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let dir = browser.get_config().download_dir.clone();
+        let mut files = Vec::new();
+        if let Ok(mut entries) = tokio::fs::read_dir(&dir).await {
+            while let Ok(Some(entry)) = entries.next_entry().await {
+                let Ok(metadata) = entry.metadata().await else {
+                    continue;
+                };
+                if metadata.is_file() {
+                    let modified = metadata.modified().ok().map(DateTime::<Utc>::from);
+                    files.push((entry.path(), metadata.len(), modified));
+                }
+            }
+        }
+        files.sort_by_key(|file| Reverse(file.2));
+        files.truncate(MAX_ROWS);
+
+        let rows: Vec<_> = files
+            .iter()
+            .map(|(path, size, modified)| {
+                let name = path
+                    .file_name()
+                    .map(|n| n.to_string_lossy().to_string())
+                    .unwrap_or_default();
+                // `from_file_path` wants an absolute path and escapes
+                // spaces, `#` and the like.
+                let url = std::path::absolute(path)
+                    .ok()
+                    .and_then(|path| Url::from_file_path(path).ok())
+                    .map(String::from)
+                    .unwrap_or_else(|| path.display().to_string());
+                vec![
+                    link(&url, &name),
+                    human_size(*size),
+                    modified.map(time).unwrap_or_default(),
+                ]
+            })
+            .collect();
+
+        let content = format!(
+            "<p class=\"subtitle\">{}</p>{}",
+            escape_html(&dir.display().to_string()),
+            table(&["File", "Size", "Modified"], &rows, "No downloads yet")
+        );
+        Ok(layout(
+            browser.assets(),
+            "Downloads",
+            &request.url,
+            &content,
+        ))
+    }
+}
+
+struct NetworkPage;
+
+#[async_trait]
+impl InternalPage for NetworkPage {
+    fn description(&self) -> &str {
+        "Recently recorded network requests, filterable with ?url= and ?status=4xx"
+    }
+
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let mut query = match request.param("url") {
+            Some(url) if !url.is_empty() => NetworkQuery::url(url)?,
+            _ => NetworkQuery::default(),
+        };
+        query.status = request.param("status").and_then(StatusClass::parse);
+        query.limit = Some(MAX_ROWS);
+        let rows: Vec<_> = browser
+            .network()
+            .query(&query)
+            .iter()
+            .map(|entry| {
+                let status = match (&entry.response, &entry.error) {
+                    (Some(response), _) => response.status.to_string(),
+                    (None, Some(error)) => {
+                        format!("<span title=\"{}\">failed</span>", escape_html(error))
+                    }
+                    (None, None) => "…".to_string(),
+                };
+                let response = entry.response.as_ref();
+                let content_type = response.and_then(|r| r.content_type.as_deref());
+                vec![
+                    escape_html(&entry.request.method),
+                    link(&entry.request.url, ""),
+                    status,
+                    escape_html(content_type.unwrap_or_default()),
+                    response
+                        .map(|r| human_size(r.body_size))
+                        .unwrap_or_default(),
+                    entry
+                        .timings
+                        .map(|t| format!("{} ms", t.total().as_millis()))
+                        .unwrap_or_default(),
+                ]
+            })
+            .collect();
+        Ok(layout(
+            browser.assets(),
+            "Network",
+            &request.url,
+            &table(
+                &["Method", "URL", "Status", "Type", "Size", "Time"],
+                &rows,
+                "No requests recorded",
+            ),
+        ))
+    }
+}
+
+struct AdblockPage;
+
+impl AdblockPage {
+    fn counts<'a>(counts: impl IntoIterator<Item = (&'a str, u64)>, empty: &str) -> String {
+        let rows: Vec<_> = counts
+            .into_iter()
+            .map(|(name, count)| {
+                vec![
+                    format!("<code>{}</code>", escape_html(name)),
+                    count.to_string(),
+                ]
+            })
+            .collect();
+        table(&["", "Blocked"], &rows, empty)
+    }
+}
+
+#[async_trait]
+impl InternalPage for AdblockPage {
+    fn description(&self) -> &str {
+        "Ad blocking status and blocked requests, by page with ?page="
+    }
+
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let blocker = browser.ad_blocker();
+
+        if let Some(page) = request.param("page") {
+            let content = match blocker.stats().page(page) {
+                Some(counts) => format!(
+                    "<p class=\"subtitle\">{} requests blocked on {}</p>\
+                     <h2>By list</h2>{}<h2>By filter</h2>{}",
+                    counts.total,
+                    link(page, ""),
+                    Self::counts(counts.by_list.iter().map(|(l, c)| (l.as_str(), *c)), ""),
+                    Self::counts(counts.top_rules(MAX_ROWS), "")
+                ),
+                None => {
+                    "<p class=\"empty-state\">Nothing blocked on this page (◕‿◕)</p>".to_string()
+                }
+            };
+            return Ok(layout(
+                browser.assets(),
+                "Ad blocking",
+                &request.url,
+                &content,
+            ));
+        }
+
+        let blocked = blocker.stats().global();
+        let status = vec![
+            vec![
+                "Blocking".to_string(),
+                if blocker.is_enabled() { "on" } else { "off" }.to_string(),
+            ],
+            vec![
+                "Network filters".to_string(),
+                blocker.network_filter_count().to_string(),
+            ],
+            vec![
+                "Element hiding filters".to_string(),
+                blocker.cosmetic_filters().len().to_string(),
+            ],
+            vec!["Requests blocked".to_string(), blocked.total.to_string()],
+        ];
+        let allowlisted: Vec<_> = blocker
+            .allowlist()
+            .sites()
+            .iter()
+            .map(|site| vec![escape_html(site)])
+            .collect();
+        let pages: Vec<_> = blocker
+            .stats()
+            .pages()
+            .iter()
+            .take(MAX_ROWS)
+            .map(|(url, counts)| {
+                let details: String = form_urlencoded::byte_serialize(url.as_bytes()).collect();
+                vec![
+                    link(url, ""),
+                    link(
+                        &format!("kawaii://adblock?page={}", details),
+                        &counts.total.to_string(),
+                    ),
+                ]
+            })
+            .collect();
+
+        let content = format!(
+            "{}<h2>Allowlisted sites</h2>{}<h2>By list</h2>{}\
+             <h2>Top filters</h2>{}<h2>By page</h2>{}",
+            table(&["", ""], &status, ""),
+            table(&["Site"], &allowlisted, "No sites allowlisted"),
+            Self::counts(
+                blocked.by_list.iter().map(|(l, c)| (l.as_str(), *c)),
+                "Nothing blocked yet"
+            ),
+            Self::counts(blocked.top_rules(MAX_ROWS), "Nothing blocked yet"),
+            table(&["Page", "Blocked"], &pages, "Nothing blocked yet"),
+        );
+        Ok(layout(
+            browser.assets(),
+            "Ad blocking",
+            &request.url,
+            &content,
+        ))
+    }
+}
+
+struct AboutPage;
+
+#[async_trait]
+impl InternalPage for AboutPage {
+    fn description(&self) -> &str {
+        "Version and internal pages"
+    }
+
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let (driver, config_dir, data_dir) = {
+            let config = browser.get_config();
+            (
+                format!("{:?}", config.driver),
+                config.config_dir.display().to_string(),
+                config.data_dir.display().to_string(),
+            )
+        };
+        let info = vec![
+            vec!["Version".to_string(), env!("CARGO_PKG_VERSION").to_string()],
+            vec!["Driver".to_string(), escape_html(&driver)],
+            vec![
+                "WebDriver URL".to_string(),
+                escape_html(browser.driver_url()),
+            ],
+            vec!["Config directory".to_string(), escape_html(&config_dir)],
+            vec!["Data directory".to_string(), escape_html(&data_dir)],
+        ];
+        let pages: Vec<_> = browser
+            .scheme_router()
+            .pages()
+            .into_iter()
+            .map(|(name, description)| {
+                vec![
+                    format!("<code>kawaii://{}</code>", escape_html(&name)),
+                    escape_html(&description),
+                ]
+            })
+            .collect();
+
+        let content = format!(
+            "<p class=\"subtitle\">{}</p>{}<h2>Internal pages</h2>{}",
+            escape_html(env!("CARGO_PKG_DESCRIPTION")),
+            table(&["", ""], &info, ""),
+            table(&["Page", ""], &pages, "")
+        );
+        Ok(layout(
+            browser.assets(),
+            "About Kawaii Browser",
+            &request.url,
+            &content,
+        ))
+    }
+}
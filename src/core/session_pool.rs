@@ -0,0 +1,212 @@
+//! A pool of WebDriver sessions for running work in parallel.
+//!
+//! Sessions are opened lazily, leased through a [`ConnectionPool`] so no more
+//! than `max_sessions` are in use at once, health-checked before every lease
+//! and recycled after `session_max_uses` leases.
+
+use crate::browser::NyanBrowser;
+use crate::config::BrowserConfig;
+use crate::core::connection_pool::{ConnectionPool, PooledConnection};
+use crate::core::driver::{self, DriverBackend};
+use crate::core::error::{BrowserError, Result as BrowserResult};
+use fantoccini::Client;
+use log::{error, info, warn};
+use parking_lot::Mutex;
+use std::net::SocketAddr;
+use std::ops::Deref;
+
+struct PooledSession {
+    client: Client,
+    /// Driver owned by this session alone, for backends that only host one
+    /// session per server (geckodriver).
+    backend: Option<Box<dyn DriverBackend>>,
+    uses: usize,
+}
+
+struct SharedDriver {
+    backend: Box<dyn DriverBackend>,
+    url: String,
+}
+
+pub struct SessionPool {
+    config: BrowserConfig,
+    /// Pooled sessions get fresh copies of this backend; without one they
+    /// get the backend `config` describes.
+    template: Option<Box<dyn DriverBackend>>,
+    slots: ConnectionPool,
+    idle: Mutex<Vec<PooledSession>>,
+    shared: tokio::sync::Mutex<Option<SharedDriver>>,
+    /// Proxy pooled sessions send their traffic through, and whether it
+    /// intercepts HTTPS.
+    proxy: Option<(SocketAddr, bool)>,
+}
+
+impl SessionPool {
+    pub fn new(config: BrowserConfig) -> Self {
+        Self {
+            slots: ConnectionPool::new(config.max_sessions.max(1)),
+            idle: Mutex::new(Vec::new()),
+            shared: tokio::sync::Mutex::new(None),
+            template: None,
+            proxy: None,
+            config,
+        }
+    }
+
+    /// Opens pooled sessions on backends like `backend` rather than the one
+    /// the config describes.
+    pub fn with_backend(mut self, backend: &dyn DriverBackend) -> Self {
+        self.template = Some(backend.fresh());
+        self
+    }
+
+    /// Sends the traffic of pooled sessions through the proxy on `proxy`,
+    /// accepting its certificates if it `intercepts_https`.
+    pub fn with_proxy(mut self, proxy: Option<SocketAddr>, intercepts_https: bool) -> Self {
+        self.proxy = proxy.map(|proxy| (proxy, intercepts_https));
+        self
+    }
+
+    /// Maximum number of sessions leased at the same time.
+    pub fn size(&self) -> usize {
+        self.slots.max_connections()
+    }
+
+    /// Waits for a free slot and hands out a healthy session.
+    pub async fn lease(&self) -> BrowserResult<SessionLease<'_>> {
+        let slot = self.slots.acquire().await;
+
+        loop {
+            let candidate = self.idle.lock().pop();
+            let Some(session) = candidate else {
+                break;
+            };
+            if session.uses >= self.config.session_max_uses {
+                self.retire(session).await;
+                continue;
+            }
+            if session.client.window().await.is_err() {
+                warn!("Dropping unhealthy pooled session");
+                self.retire(session).await;
+                continue;
+            }
+            return Ok(SessionLease::new(self, session, slot));
+        }
+
+        let session = self.open_session().await?;
+        Ok(SessionLease::new(self, session, slot))
+    }
+
+    /// Closes every idle session and the shared driver, if any.
+    pub async fn shutdown(&self) {
+        let idle: Vec<_> = self.idle.lock().drain(..).collect();
+        for session in idle {
+            self.retire(session).await;
+        }
+        if let Some(mut shared) = self.shared.lock().await.take() {
+            if let Err(e) = shared.backend.shutdown().await {
+                error!("Error stopping {}: {}", shared.backend.name(), e);
+            }
+        }
+    }
+
+    async fn open_session(&self) -> BrowserResult<PooledSession> {
+        let mut backend = match &self.template {
+            Some(template) => template.fresh(),
+            None => driver::from_config(&self.config)
+                .map_err(|e| BrowserError::SessionError(e.to_string()))?,
+        };
+        let mut caps = backend.capabilities(&self.config);
+        if let Some((proxy, intercepts_https)) = self.proxy {
+            caps = driver::with_proxy(caps, proxy, intercepts_https);
+        }
+
+        if backend.supports_multiple_sessions() {
+            let mut shared = self.shared.lock().await;
+            if shared.is_none() {
+                let url = backend
+                    .spawn(&self.config)
+                    .await
+                    .map_err(|e| BrowserError::DriverStartError(e.to_string()))?;
+                *shared = Some(SharedDriver { backend, url });
+            }
+            let url = &shared.as_ref().expect("shared driver was just set").url;
+            let client = NyanBrowser::create_client(url, caps)
+                .await
+                .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+            info!("Opened pooled session on shared {}", url);
+            return Ok(PooledSession {
+                client,
+                backend: None,
+                uses: 0,
+            });
+        }
+
+        let url = backend
+            .spawn(&self.config)
+            .await
+            .map_err(|e| BrowserError::DriverStartError(e.to_string()))?;
+        let client = match NyanBrowser::create_client(&url, caps).await {
+            Ok(client) => client,
+            Err(e) => {
+                let _ = backend.shutdown().await;
+                return Err(BrowserError::SessionError(e.to_string()));
+            }
+        };
+        info!("Opened pooled session on dedicated {}", url);
+        Ok(PooledSession {
+            client,
+            backend: Some(backend),
+            uses: 0,
+        })
+    }
+
+    async fn retire(&self, session: PooledSession) {
+        if let Err(e) = session.client.close().await {
+            warn!("Error closing pooled session: {}", e);
+        }
+        if let Some(mut backend) = session.backend {
+            if let Err(e) = backend.shutdown().await {
+                error!("Error stopping {}: {}", backend.name(), e);
+            }
+        }
+    }
+}
+
+/// A session borrowed from a [`SessionPool`]. Goes back to the pool on drop.
+pub struct SessionLease<'a> {
+    pool: &'a SessionPool,
+    session: Option<PooledSession>,
+    _slot: PooledConnection<'a>,
+}
+
+impl<'a> SessionLease<'a> {
+    fn new(pool: &'a SessionPool, mut session: PooledSession, slot: PooledConnection<'a>) -> Self {
+        session.uses += 1;
+        Self {
+            pool,
+            session: Some(session),
+            _slot: slot,
+        }
+    }
+
+    pub fn client(&self) -> &Client {
+        &self.session.as_ref().expect("lease holds a session").client
+    }
+}
+
+impl Deref for SessionLease<'_> {
+    type Target = Client;
+
+    fn deref(&self) -> &Client {
+        self.client()
+    }
+}
+
+impl Drop for SessionLease<'_> {
+    fn drop(&mut self) {
+        if let Some(session) = self.session.take() {
+            self.pool.idle.lock().push(session);
+        }
+    }
+}
@@ -0,0 +1,198 @@
+//! Snapshots of open tabs so a session can be restored after a restart or a
+//! crash.
+//!
+//! [`SessionRecorder`] follows tab events to know which tabs exist and what
+//! they show. Only the focused tab is queried when a snapshot is taken, so
+//! recording never moves focus around behind the user's back. Snapshots are
+//! written to `session.json` in the config dir by [`SessionStore`].
+
+use crate::core::omnibox::INTERNAL_SCHEME;
+use crate::core::tabs::{TabEvent, TabKind};
+use crate::utils::write_atomic;
+use chrono::{DateTime, Utc};
+use fantoccini::Client;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+const SESSION_FILE: &str = "session.json";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabSnapshot {
+    pub url: String,
+    pub title: String,
+    pub kind: TabKind,
+    pub scroll_x: f64,
+    pub scroll_y: f64,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub saved_at: DateTime<Utc>,
+    /// Set when the browser shut down normally. A snapshot left behind with
+    /// this unset means the previous process crashed or was killed.
+    pub clean_exit: bool,
+    pub tabs: Vec<TabSnapshot>,
+}
+
+pub struct SessionStore {
+    path: PathBuf,
+}
+
+impl SessionStore {
+    pub fn new(config_dir: &Path) -> Self {
+        Self {
+            path: config_dir.join(SESSION_FILE),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The last saved snapshot, if any.
+    pub fn load(&self) -> anyhow::Result<Option<SessionSnapshot>> {
+        match std::fs::read_to_string(&self.path) {
+            Ok(content) => Ok(Some(serde_json::from_str(&content)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    pub fn save(&self, snapshot: &SessionSnapshot) -> anyhow::Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        write_atomic(
+            &self.path,
+            serde_json::to_string_pretty(snapshot)?.as_bytes(),
+        )?;
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone)]
+struct RecordedTab {
+    kind: TabKind,
+    url: String,
+    title: String,
+    scroll: (f64, f64),
+}
+
+#[derive(Default)]
+struct RecorderState {
+    order: Vec<String>,
+    tabs: HashMap<String, RecordedTab>,
+    active: Option<String>,
+}
+
+/// Live view of the open tabs, fed by [`TabEvent`]s.
+#[derive(Default)]
+pub struct SessionRecorder {
+    state: RwLock<RecorderState>,
+}
+
+impl SessionRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn handle_event(&self, event: &TabEvent) {
+        let mut state = self.state.write();
+        match event {
+            TabEvent::Opened { handle, kind } => {
+                if !state.tabs.contains_key(handle) {
+                    state.order.push(handle.clone());
+                }
+                state.tabs.entry(handle.clone()).or_insert(RecordedTab {
+                    kind: *kind,
+                    url: "about:blank".to_string(),
+                    title: String::new(),
+                    scroll: (0.0, 0.0),
+                });
+            }
+            TabEvent::Activated { handle } => state.active = Some(handle.clone()),
+            TabEvent::Navigated { handle, url } => {
+                if let Some(tab) = state.tabs.get_mut(handle) {
+                    tab.url = url.clone();
+                    tab.title.clear();
+                    tab.scroll = (0.0, 0.0);
+                }
+            }
+            TabEvent::Closed { handle } => {
+                state.order.retain(|h| h != handle);
+                state.tabs.remove(handle);
+                if state.active.as_ref() == Some(handle) {
+                    state.active = None;
+                }
+            }
+        }
+    }
+
+    /// Refreshes the focused tab from the driver and returns a snapshot of
+    /// every tab.
+    pub async fn capture(&self, client: &Client, clean_exit: bool) -> SessionSnapshot {
+        let active = self.state.read().active.clone();
+        if let Some(handle) = active {
+            let url = client.current_url().await.map(|u| u.to_string()).ok();
+            let title = client.title().await.ok();
+            let scroll = client
+                .execute("return [window.scrollX, window.scrollY];", vec![])
+                .await
+                .ok()
+                .and_then(|v| Some((v.get(0)?.as_f64()?, v.get(1)?.as_f64()?)));
+
+            let mut state = self.state.write();
+            if let Some(tab) = state.tabs.get_mut(&handle) {
+                // Internal pages are loaded as `data:` URLs, so the driver
+                // can't tell which `kawaii://` page is showing; keep the
+                // tracked one.
+                if let Some(url) = url.filter(|url| !is_rendered_page(url)) {
+                    tab.url = url;
+                }
+                if let Some(title) = title {
+                    tab.title = title;
+                }
+                if let Some(scroll) = scroll {
+                    tab.scroll = scroll;
+                }
+            }
+        }
+        self.snapshot(clean_exit)
+    }
+
+    /// Snapshot of the last known state, without asking the driver.
+    pub fn snapshot(&self, clean_exit: bool) -> SessionSnapshot {
+        let state = self.state.read();
+        let tabs = state
+            .order
+            .iter()
+            .filter_map(|handle| {
+                let tab = state.tabs.get(handle)?;
+                Some(TabSnapshot {
+                    url: tab.url.clone(),
+                    title: tab.title.clone(),
+                    kind: tab.kind,
+                    scroll_x: tab.scroll.0,
+                    scroll_y: tab.scroll.1,
+                    active: state.active.as_ref() == Some(handle),
+                })
+            })
+            .collect();
+
+        SessionSnapshot {
+            saved_at: Utc::now(),
+            clean_exit,
+            tabs,
+        }
+    }
+}
+
+/// Whether the driver's `url` is a page the browser rendered itself rather
+/// than something a session could reload.
+fn is_rendered_page(url: &str) -> bool {
+    let scheme = url.split(':').next().unwrap_or_default();
+    scheme.eq_ignore_ascii_case("data") || scheme.eq_ignore_ascii_case(INTERNAL_SCHEME)
+}
@@ -0,0 +1,341 @@
+//! Tab and window management on top of WebDriver window handles.
+//!
+//! [`TabTracker`] keeps the browser's view of open tabs (order, kind, which
+//! one is focused) and tells subscribers about changes. [`Tabs`] is the
+//! user-facing API, borrowed from [`NyanBrowser::tabs`](crate::NyanBrowser::tabs).
+
+use crate::core::error::{BrowserError, Result as BrowserResult};
+use fantoccini::wd::WindowHandle;
+use fantoccini::Client;
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TabKind {
+    Tab,
+    Window,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TabInfo {
+    pub handle: String,
+    pub kind: TabKind,
+    pub title: String,
+    pub url: String,
+    pub active: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum TabEvent {
+    Opened { handle: String, kind: TabKind },
+    Activated { handle: String },
+    Navigated { handle: String, url: String },
+    Closed { handle: String },
+}
+
+pub type TabListener = Arc<dyn Fn(&TabEvent) + Send + Sync>;
+
+#[derive(Default)]
+struct TabState {
+    tabs: Vec<(String, TabKind)>,
+    active: Option<String>,
+}
+
+/// Tab bookkeeping shared by the browser and everything that hooks tab events.
+#[derive(Default)]
+pub struct TabTracker {
+    state: RwLock<TabState>,
+    listeners: RwLock<Vec<TabListener>>,
+}
+
+impl TabTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Calls `listener` for every future tab event.
+    pub fn subscribe(&self, listener: impl Fn(&TabEvent) + Send + Sync + 'static) {
+        self.listeners.write().push(Arc::new(listener));
+    }
+
+    /// Handle of the focused tab.
+    pub fn active(&self) -> Option<String> {
+        self.state.read().active.clone()
+    }
+
+    /// Handles of all known tabs, in the order they were opened.
+    pub fn handles(&self) -> Vec<String> {
+        self.state
+            .read()
+            .tabs
+            .iter()
+            .map(|(h, _)| h.clone())
+            .collect()
+    }
+
+    pub fn record_opened(&self, handle: &str, kind: TabKind) {
+        {
+            let mut state = self.state.write();
+            if state.tabs.iter().any(|(h, _)| h == handle) {
+                return;
+            }
+            state.tabs.push((handle.to_string(), kind));
+        }
+        self.emit(TabEvent::Opened {
+            handle: handle.to_string(),
+            kind,
+        });
+    }
+
+    pub fn record_activated(&self, handle: &str) {
+        {
+            let mut state = self.state.write();
+            if state.active.as_deref() == Some(handle) {
+                return;
+            }
+            state.active = Some(handle.to_string());
+        }
+        self.emit(TabEvent::Activated {
+            handle: handle.to_string(),
+        });
+    }
+
+    /// Records a navigation in the focused tab.
+    pub fn record_navigated(&self, url: &str) {
+        if let Some(handle) = self.active() {
+            self.emit(TabEvent::Navigated {
+                handle,
+                url: url.to_string(),
+            });
+        }
+    }
+
+    pub fn record_closed(&self, handle: &str) {
+        {
+            let mut state = self.state.write();
+            state.tabs.retain(|(h, _)| h != handle);
+            if state.active.as_deref() == Some(handle) {
+                state.active = None;
+            }
+        }
+        self.emit(TabEvent::Closed {
+            handle: handle.to_string(),
+        });
+    }
+
+    fn kind_of(&self, handle: &str) -> TabKind {
+        self.state
+            .read()
+            .tabs
+            .iter()
+            .find(|(h, _)| h == handle)
+            .map(|(_, kind)| *kind)
+            .unwrap_or(TabKind::Tab)
+    }
+
+    fn emit(&self, event: TabEvent) {
+        let listeners = self.listeners.read().clone();
+        for listener in listeners {
+            listener(&event);
+        }
+    }
+}
+
+/// Tab operations on a browser's main session.
+pub struct Tabs<'a> {
+    client: &'a Client,
+    tracker: &'a TabTracker,
+}
+
+impl<'a> Tabs<'a> {
+    pub(crate) fn new(client: &'a Client, tracker: &'a TabTracker) -> Self {
+        Self { client, tracker }
+    }
+
+    /// Opens a new tab, focuses it and optionally loads `url` in it.
+    pub async fn new_tab(&self, url: Option<&str>) -> BrowserResult<String> {
+        self.open(TabKind::Tab, url).await
+    }
+
+    /// Opens a new top-level window, focuses it and optionally loads `url`.
+    pub async fn new_window(&self, url: Option<&str>) -> BrowserResult<String> {
+        self.open(TabKind::Window, url).await
+    }
+
+    /// Focuses the tab with the given handle.
+    pub async fn switch_to(&self, handle: &str) -> BrowserResult<()> {
+        let window = WindowHandle::try_from(handle)
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        self.client
+            .switch_to_window(window)
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        self.tracker.record_activated(handle);
+        Ok(())
+    }
+
+    /// Focuses the tab at `index` in opening order.
+    pub async fn switch_to_index(&self, index: usize) -> BrowserResult<()> {
+        let handles = self.sync().await?;
+        let handle = handles.get(index).ok_or_else(|| {
+            BrowserError::SessionError(format!(
+                "No tab at index {} ({} open)",
+                index,
+                handles.len()
+            ))
+        })?;
+        self.switch_to(handle).await
+    }
+
+    /// Moves focus to the next tab, wrapping around at the end.
+    pub async fn focus_next(&self) -> BrowserResult<()> {
+        self.focus_relative(1).await
+    }
+
+    /// Moves focus to the previous tab, wrapping around at the start.
+    pub async fn focus_previous(&self) -> BrowserResult<()> {
+        self.focus_relative(-1).await
+    }
+
+    /// Closes the tab with the given handle. Closing the focused tab
+    /// focuses a neighbour, closing another one leaves focus where it was.
+    pub async fn close(&self, handle: &str) -> BrowserResult<()> {
+        let handles = self.sync().await?;
+        let index = handles
+            .iter()
+            .position(|h| h == handle)
+            .ok_or_else(|| BrowserError::SessionError(format!("No tab with handle {}", handle)))?;
+        let active = self.current().await.ok();
+
+        // Only the tab that ends up focused counts as activated.
+        self.focus_quietly(handle).await?;
+        self.client
+            .close_window()
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        self.tracker.record_closed(handle);
+
+        if let Some(active) = active.filter(|active| active != handle) {
+            return self.focus_quietly(&active).await;
+        }
+        let remaining = self.sync().await?;
+        if let Some(next) = remaining.get(index.min(remaining.len().saturating_sub(1))) {
+            self.switch_to(next).await?;
+        }
+        Ok(())
+    }
+
+    /// Closes the focused tab.
+    pub async fn close_current(&self) -> BrowserResult<()> {
+        let handle = self.current().await?;
+        self.close(&handle).await
+    }
+
+    /// Handle of the focused tab, as reported by the driver.
+    pub async fn current(&self) -> BrowserResult<String> {
+        let handle = self
+            .client
+            .window()
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        Ok(handle.into())
+    }
+
+    /// Every open tab with its title and URL.
+    ///
+    /// WebDriver can only read the focused tab, so this briefly visits each
+    /// one and then returns focus to where it was.
+    pub async fn list(&self) -> BrowserResult<Vec<TabInfo>> {
+        let handles = self.sync().await?;
+        let active = self.current().await?;
+        let mut tabs = Vec::with_capacity(handles.len());
+
+        for handle in &handles {
+            self.focus_quietly(handle).await?;
+            let title = self.client.title().await.unwrap_or_default();
+            let url = self
+                .client
+                .current_url()
+                .await
+                .map(|u| u.to_string())
+                .unwrap_or_default();
+            tabs.push(TabInfo {
+                handle: handle.clone(),
+                kind: self.tracker.kind_of(handle),
+                title,
+                url,
+                active: *handle == active,
+            });
+        }
+
+        self.focus_quietly(&active).await?;
+        Ok(tabs)
+    }
+
+    async fn open(&self, kind: TabKind, url: Option<&str>) -> BrowserResult<String> {
+        let response = self
+            .client
+            .new_window(kind == TabKind::Tab)
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        let handle: String = response.handle.into();
+
+        self.tracker.record_opened(&handle, kind);
+        self.switch_to(&handle).await?;
+
+        if let Some(url) = url {
+            self.client
+                .goto(url)
+                .await
+                .map_err(|e| BrowserError::NavigationError(e.to_string()))?;
+            self.tracker.record_navigated(url);
+        }
+        Ok(handle)
+    }
+
+    async fn focus_relative(&self, offset: isize) -> BrowserResult<()> {
+        let handles = self.sync().await?;
+        if handles.is_empty() {
+            return Err(BrowserError::SessionError("No open tabs".to_string()));
+        }
+        let active = self.current().await?;
+        let index = handles.iter().position(|h| *h == active).unwrap_or(0) as isize;
+        let next = (index + offset).rem_euclid(handles.len() as isize) as usize;
+        self.switch_to(&handles[next]).await
+    }
+
+    /// Switches without recording an activation, for temporary visits.
+    async fn focus_quietly(&self, handle: &str) -> BrowserResult<()> {
+        let window = WindowHandle::try_from(handle)
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?;
+        self.client
+            .switch_to_window(window)
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))
+    }
+
+    /// Reconciles the tracker with the driver's handle list, picking up tabs
+    /// opened by pages and dropping ones closed behind our back.
+    async fn sync(&self) -> BrowserResult<Vec<String>> {
+        let handles: Vec<String> = self
+            .client
+            .windows()
+            .await
+            .map_err(|e| BrowserError::SessionError(e.to_string()))?
+            .into_iter()
+            .map(String::from)
+            .collect();
+
+        for known in self.tracker.handles() {
+            if !handles.contains(&known) {
+                self.tracker.record_closed(&known);
+            }
+        }
+        for handle in &handles {
+            self.tracker.record_opened(handle, TabKind::Tab);
+        }
+        Ok(self.tracker.handles())
+    }
+}
@@ -0,0 +1,107 @@
+//! Sites where ad blocking is turned off.
+//!
+//! The allowlist is a plain text file next to `config.toml`, one site per
+//! line, so it can be edited by hand. Allowlisting a site also covers its
+//! subdomains.
+
+use super::filter::is_subdomain_of;
+use super::request::host_of;
+use parking_lot::RwLock;
+use std::collections::BTreeSet;
+use std::path::{Path, PathBuf};
+
+/// Name of the allowlist file in the config directory.
+pub const ALLOWLIST_FILE: &str = "adblock_allowlist.txt";
+
+#[derive(Debug, Default)]
+pub struct Allowlist {
+    /// Where changes are saved, `None` to keep them in memory only.
+    file: Option<PathBuf>,
+    sites: RwLock<BTreeSet<String>>,
+}
+
+impl Allowlist {
+    /// An allowlist that isn't saved anywhere.
+    pub fn in_memory() -> Self {
+        Self::default()
+    }
+
+    /// Reads the allowlist in `file`, which needn't exist yet. Blank lines
+    /// and lines starting with `#` are ignored.
+    pub fn open(file: &Path) -> anyhow::Result<Self> {
+        let sites = match std::fs::read_to_string(file) {
+            Ok(content) => content
+                .lines()
+                .map(str::trim)
+                .filter(|line| !line.is_empty() && !line.starts_with('#'))
+                .filter_map(normalize)
+                .collect(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BTreeSet::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            file: Some(file.to_path_buf()),
+            sites: RwLock::new(sites),
+        })
+    }
+
+    /// Whether pages from `host` are allowlisted.
+    pub fn contains(&self, host: &str) -> bool {
+        let sites = self.sites.read();
+        !sites.is_empty() && sites.iter().any(|site| is_subdomain_of(host, site))
+    }
+
+    /// Allowlists `site`, given as a host name or a URL. Returns false if it
+    /// already was.
+    pub fn add(&self, site: &str) -> anyhow::Result<bool> {
+        let site = normalize(site).ok_or_else(|| anyhow::anyhow!("Not a site: {}", site))?;
+        if !self.sites.write().insert(site) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Removes `site` from the allowlist. Returns false if it wasn't on it.
+    pub fn remove(&self, site: &str) -> anyhow::Result<bool> {
+        let Some(site) = normalize(site) else {
+            return Ok(false);
+        };
+        if !self.sites.write().remove(&site) {
+            return Ok(false);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn sites(&self) -> Vec<String> {
+        self.sites.read().iter().cloned().collect()
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        let Some(file) = &self.file else {
+            return Ok(());
+        };
+        if let Some(dir) = file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut content = String::from("# Sites where ad blocking is turned off, one per line.\n");
+        for site in self.sites() {
+            content.push_str(&site);
+            content.push('\n');
+        }
+        crate::utils::write_atomic(file, content.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// The lowercase host of `site`, which may be a URL.
+fn normalize(site: &str) -> Option<String> {
+    let site = site.trim();
+    let host = if site.contains("://") {
+        host_of(site)
+    } else {
+        site.trim_end_matches('.').to_ascii_lowercase()
+    };
+    (!host.is_empty() && !host.contains(['/', ' '])).then_some(host)
+}
@@ -0,0 +1,42 @@
+//! Element hiding: turning `##` filters into a stylesheet for a page.
+
+/// Id of the `<style>` element holding the hiding rules, so reinjecting
+/// replaces it instead of stacking copies.
+pub const STYLE_ID: &str = "nyan-adblock-hide";
+
+/// Adds or replaces the hiding stylesheet. Takes the CSS as its argument.
+pub const INJECT_SCRIPT: &str = r#"
+const [css, id] = arguments;
+let style = document.getElementById(id);
+if (!style) {
+    style = document.createElement('style');
+    style.id = id;
+    (document.head || document.documentElement).appendChild(style);
+}
+style.textContent = css;
+"#;
+
+/// Counts the distinct elements matching any of the selectors passed as its
+/// argument. Selectors the page's browser can't parse are skipped.
+pub const COUNT_SCRIPT: &str = r#"
+const [selectors] = arguments;
+const hidden = new Set();
+for (const selector of selectors) {
+    try {
+        document.querySelectorAll(selector).forEach(el => hidden.add(el));
+    } catch (e) {}
+}
+return hidden.size;
+"#;
+
+/// A stylesheet hiding everything matched by `selectors`. Each selector gets
+/// its own rule, since one selector the browser rejects invalidates the
+/// whole rule it is part of.
+pub fn stylesheet(selectors: &[String]) -> String {
+    let mut css = String::new();
+    for selector in selectors {
+        css.push_str(selector);
+        css.push_str(" { display: none !important; }\n");
+    }
+    css
+}
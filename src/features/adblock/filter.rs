@@ -0,0 +1,542 @@
+//! Adblock Plus filter syntax, as used by EasyList.
+//!
+//! Network filters look like `||ads.example.com^$script,third-party` and
+//! block (or, with a leading `@@`, allow) requests. Element hiding filters
+//! look like `example.com##.banner` and hide elements on matching pages.
+//! Filters using syntax or options we don't implement are rejected with
+//! [`FilterError::Unsupported`] rather than being half-applied.
+
+use super::index;
+use super::request::Request;
+use regex::Regex;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use thiserror::Error;
+
+#[derive(Error, Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    #[error("unsupported filter syntax: {0}")]
+    Unsupported(String),
+    #[error("invalid filter: {0}")]
+    Invalid(String),
+}
+
+/// What a request is for, as far as `$script`-style options are concerned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResourceType {
+    Document,
+    Subdocument,
+    Script,
+    Image,
+    Stylesheet,
+    Font,
+    Media,
+    Object,
+    XmlHttpRequest,
+    WebSocket,
+    Ping,
+    Other,
+}
+
+impl ResourceType {
+    pub const ALL: [ResourceType; 12] = [
+        ResourceType::Document,
+        ResourceType::Subdocument,
+        ResourceType::Script,
+        ResourceType::Image,
+        ResourceType::Stylesheet,
+        ResourceType::Font,
+        ResourceType::Media,
+        ResourceType::Object,
+        ResourceType::XmlHttpRequest,
+        ResourceType::WebSocket,
+        ResourceType::Ping,
+        ResourceType::Other,
+    ];
+
+    /// The type named by a filter option, e.g. `script` or `xhr`.
+    pub fn from_option(name: &str) -> Option<Self> {
+        Some(match name {
+            "document" | "doc" => ResourceType::Document,
+            "subdocument" | "frame" => ResourceType::Subdocument,
+            "script" => ResourceType::Script,
+            "image" => ResourceType::Image,
+            "stylesheet" | "css" => ResourceType::Stylesheet,
+            "font" => ResourceType::Font,
+            "media" => ResourceType::Media,
+            "object" => ResourceType::Object,
+            "xmlhttprequest" | "xhr" => ResourceType::XmlHttpRequest,
+            "websocket" => ResourceType::WebSocket,
+            "ping" => ResourceType::Ping,
+            "other" => ResourceType::Other,
+            _ => return None,
+        })
+    }
+
+    /// The type of a request with the `Sec-Fetch-Dest` header given, as
+    /// sent by the browser.
+    pub fn from_fetch_dest(dest: &str) -> Self {
+        match dest {
+            "document" => ResourceType::Document,
+            "iframe" | "frame" => ResourceType::Subdocument,
+            "script" | "worker" | "sharedworker" | "serviceworker" => ResourceType::Script,
+            "image" => ResourceType::Image,
+            "style" => ResourceType::Stylesheet,
+            "font" => ResourceType::Font,
+            "audio" | "video" | "track" => ResourceType::Media,
+            "object" | "embed" => ResourceType::Object,
+            "empty" => ResourceType::XmlHttpRequest,
+            "websocket" => ResourceType::WebSocket,
+            _ => ResourceType::Other,
+        }
+    }
+
+    fn bit(self) -> u16 {
+        1 << self as u16
+    }
+}
+
+const ALL_TYPES: u16 = (1 << ResourceType::ALL.len()) - 1;
+
+/// A parsed line of a filter list.
+#[derive(Debug, Clone)]
+pub enum Filter {
+    Network(NetworkFilter),
+    Cosmetic(CosmeticFilter),
+}
+
+impl Filter {
+    /// Parses one line of a filter list. Comments, blank lines and the
+    /// `[Adblock Plus 2.0]` header parse to `None`.
+    pub fn parse(line: &str) -> Result<Option<Self>, FilterError> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('!') || line.starts_with('[') {
+            return Ok(None);
+        }
+        if let Some(cosmetic) = CosmeticFilter::parse(line)? {
+            return Ok(Some(Filter::Cosmetic(cosmetic)));
+        }
+        NetworkFilter::parse(line).map(|f| Some(Filter::Network(f)))
+    }
+}
+
+/// Domains from a `$domain=` option or an element hiding prefix, where a
+/// leading `~` excludes a domain.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DomainList {
+    pub include: Vec<String>,
+    pub exclude: Vec<String>,
+}
+
+impl DomainList {
+    fn parse<'a>(domains: impl Iterator<Item = &'a str>) -> Self {
+        let mut list = Self::default();
+        for domain in domains.map(str::trim).filter(|d| !d.is_empty()) {
+            match domain.strip_prefix('~') {
+                Some(domain) => list.exclude.push(domain.to_lowercase()),
+                None => list.include.push(domain.to_lowercase()),
+            }
+        }
+        list
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.include.is_empty() && self.exclude.is_empty()
+    }
+
+    /// Whether the list applies on `host`. The most specific matching entry
+    /// wins, so `example.com|~shop.example.com` applies on `example.com` but
+    /// not on `shop.example.com`. A list with only exclusions applies
+    /// everywhere else.
+    pub fn matches(&self, host: &str) -> bool {
+        let longest = |domains: &[String]| {
+            domains
+                .iter()
+                .filter(|d| is_subdomain_of(host, d))
+                .map(String::len)
+                .max()
+        };
+        match (longest(&self.include), longest(&self.exclude)) {
+            (Some(include), Some(exclude)) => include > exclude,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => self.include.is_empty(),
+        }
+    }
+}
+
+/// Whether `host` is `domain` or one of its subdomains.
+pub fn is_subdomain_of(host: &str, domain: &str) -> bool {
+    host.strip_suffix(domain)
+        .is_some_and(|rest| rest.is_empty() || rest.ends_with('.'))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+enum Anchor {
+    None,
+    /// `|` — the start of the URL.
+    Start,
+    /// `||` — the start of the host or of any of its labels.
+    Hostname,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+enum Pattern {
+    Glob {
+        anchor: Anchor,
+        /// Literal text with `*` and `^` wildcards, lowercased unless the
+        /// filter is `$match-case`.
+        body: String,
+        /// `|` at the end — the pattern must reach the end of the URL.
+        end_anchor: bool,
+    },
+    Regex(#[serde(with = "regex_source")] Regex),
+}
+
+/// Regexes are cached as their source, with case-insensitivity as a
+/// `(?i)` prefix so the source alone rebuilds the same regex.
+mod regex_source {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(regex: &Regex, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(regex.as_str())
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Regex, D::Error> {
+        let source = String::deserialize(deserializer)?;
+        Regex::new(&source).map_err(serde::de::Error::custom)
+    }
+}
+
+/// The `$` options of a network filter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Options {
+    /// Bit set of the [`ResourceType`]s the filter applies to.
+    types: u16,
+    third_party: Option<bool>,
+    domains: DomainList,
+    match_case: bool,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            types: ALL_TYPES,
+            third_party: None,
+            domains: DomainList::default(),
+            match_case: false,
+        }
+    }
+}
+
+impl Options {
+    fn parse(options: &str) -> Result<Self, FilterError> {
+        let mut parsed = Self::default();
+        let mut included = 0;
+        let mut excluded = 0;
+        for option in options.split(',').map(str::trim) {
+            let (negated, name) = match option.strip_prefix('~') {
+                Some(name) => (true, name),
+                None => (false, option),
+            };
+            match name {
+                "third-party" | "3p" => parsed.third_party = Some(!negated),
+                "first-party" | "1p" => parsed.third_party = Some(negated),
+                "match-case" if !negated => parsed.match_case = true,
+                _ if name.starts_with("domain=") && !negated => {
+                    parsed.domains = DomainList::parse(name["domain=".len()..].split('|'));
+                }
+                _ => match ResourceType::from_option(name) {
+                    Some(kind) if negated => excluded |= kind.bit(),
+                    Some(kind) => included |= kind.bit(),
+                    None => return Err(FilterError::Unsupported(format!("${}", option))),
+                },
+            }
+        }
+        if included != 0 {
+            parsed.types = included;
+        }
+        parsed.types &= !excluded;
+        Ok(parsed)
+    }
+}
+
+/// A URL blocking (or exception) filter.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkFilter {
+    /// The filter as written in the list.
+    pub raw: String,
+    /// `@@` — requests matching this filter are never blocked.
+    pub exception: bool,
+    pattern: Pattern,
+    options: Options,
+}
+
+impl NetworkFilter {
+    pub fn parse(line: &str) -> Result<Self, FilterError> {
+        let raw = line.trim().to_string();
+        let (exception, rest) = match raw.strip_prefix("@@") {
+            Some(rest) => (true, rest),
+            None => (false, raw.as_str()),
+        };
+        let (pattern, options) = split_options(rest);
+        let options = match options {
+            Some(options) => Options::parse(options)?,
+            None => Options::default(),
+        };
+        let pattern = parse_pattern(pattern, options.match_case)
+            .map_err(|e| FilterError::Invalid(format!("{}: {}", raw, e)))?;
+        Ok(Self {
+            raw,
+            exception,
+            pattern,
+            options,
+        })
+    }
+
+    /// The host this filter blocks wholesale, if it is a plain
+    /// `||host^` filter without options.
+    pub fn plain_host(&self) -> Option<&str> {
+        match &self.pattern {
+            Pattern::Glob {
+                anchor: Anchor::Hostname,
+                body,
+                end_anchor: false,
+            } if self.options.types == ALL_TYPES
+                && self.options.third_party.is_none()
+                && self.options.domains.is_empty() =>
+            {
+                let host = body.strip_suffix('^')?;
+                host.bytes()
+                    .all(|b| b.is_ascii_alphanumeric() || b == b'.' || b == b'-')
+                    .then_some(host)
+            }
+            _ => None,
+        }
+    }
+
+    /// Whole words any URL this filter matches must contain, for the
+    /// [`TokenIndex`](super::index::TokenIndex).
+    pub fn tokens(&self) -> Vec<String> {
+        match &self.pattern {
+            Pattern::Glob {
+                anchor,
+                body,
+                end_anchor,
+            } => index::pattern_tokens(body, *anchor != Anchor::None, *end_anchor),
+            Pattern::Regex(_) => Vec::new(),
+        }
+    }
+
+    pub fn matches(&self, request: &Request) -> bool {
+        let options = &self.options;
+        if options.types & request.resource_type.bit() == 0 {
+            return false;
+        }
+        if options
+            .third_party
+            .is_some_and(|third_party| third_party != request.is_third_party())
+        {
+            return false;
+        }
+        if !options.domains.is_empty() && !options.domains.matches(request.source_host()) {
+            return false;
+        }
+
+        let url = if options.match_case {
+            request.url()
+        } else {
+            request.url_lower()
+        };
+        match &self.pattern {
+            Pattern::Regex(regex) => regex.is_match(request.url()),
+            Pattern::Glob {
+                anchor,
+                body,
+                end_anchor,
+            } => {
+                let (url, body) = (url.as_bytes(), body.as_bytes());
+                match anchor {
+                    Anchor::Start => matches_at(body, url, *end_anchor),
+                    Anchor::Hostname => request
+                        .host_label_starts()
+                        .any(|start| matches_at(body, &url[start..], *end_anchor)),
+                    Anchor::None => match body.first() {
+                        None => true,
+                        Some(b'*' | b'^') => {
+                            (0..=url.len()).any(|i| matches_at(body, &url[i..], *end_anchor))
+                        }
+                        Some(first) => (0..url.len())
+                            .filter(|&i| url[i] == *first)
+                            .any(|i| matches_at(body, &url[i..], *end_anchor)),
+                    },
+                }
+            }
+        }
+    }
+}
+
+/// Splits `pattern$options`. A `$` followed by a `/` belongs to a regex
+/// filter rather than starting the options.
+fn split_options(filter: &str) -> (&str, Option<&str>) {
+    match filter.rfind('$') {
+        Some(i) if !filter[i + 1..].contains('/') => (&filter[..i], Some(&filter[i + 1..])),
+        _ => (filter, None),
+    }
+}
+
+fn parse_pattern(pattern: &str, match_case: bool) -> Result<Pattern, regex::Error> {
+    if pattern.len() > 2 && pattern.starts_with('/') && pattern.ends_with('/') {
+        let source = &pattern[1..pattern.len() - 1];
+        return match match_case {
+            true => Regex::new(source),
+            false => Regex::new(&format!("(?i){}", source)),
+        }
+        .map(Pattern::Regex);
+    }
+
+    let (anchor, pattern) = if let Some(rest) = pattern.strip_prefix("||") {
+        (Anchor::Hostname, rest)
+    } else if let Some(rest) = pattern.strip_prefix('|') {
+        (Anchor::Start, rest)
+    } else {
+        (Anchor::None, pattern)
+    };
+    let (end_anchor, pattern) = match pattern.strip_suffix('|') {
+        Some(rest) => (true, rest),
+        None => (false, pattern),
+    };
+
+    // Runs of `*` mean the same as one, and leading or trailing ones only
+    // matter next to an anchor.
+    let mut body = String::with_capacity(pattern.len());
+    for c in pattern.chars() {
+        if !(c == '*' && body.ends_with('*')) {
+            body.push(c);
+        }
+    }
+    if anchor == Anchor::None {
+        body = body.trim_start_matches('*').to_string();
+    }
+    if !end_anchor {
+        body = body.trim_end_matches('*').to_string();
+    }
+    if !match_case {
+        body = body.to_lowercase();
+    }
+    Ok(Pattern::Glob {
+        anchor,
+        body,
+        end_anchor,
+    })
+}
+
+/// `^` matches anything but a letter, digit or one of `_-.%`, and the end of
+/// the URL.
+fn is_separator(b: u8) -> bool {
+    !(b.is_ascii_alphanumeric() || matches!(b, b'_' | b'-' | b'.' | b'%'))
+}
+
+/// Whether `pattern` matches the start of `text`, or all of it if `to_end`.
+fn matches_at(pattern: &[u8], text: &[u8], to_end: bool) -> bool {
+    let (mut p, mut t) = (0, 0);
+    // Where to resume after a mismatch: just past the last `*`, with that
+    // `*` swallowing one more byte.
+    let mut backtrack: Option<(usize, usize)> = None;
+    loop {
+        if p == pattern.len() {
+            if !to_end || t == text.len() {
+                return true;
+            }
+        } else {
+            match pattern[p] {
+                b'*' => {
+                    backtrack = Some((p + 1, t));
+                    p += 1;
+                    continue;
+                }
+                b'^' if t == text.len() => {
+                    p += 1;
+                    continue;
+                }
+                b'^' if is_separator(text[t]) => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                c if t < text.len() && c == text[t] => {
+                    p += 1;
+                    t += 1;
+                    continue;
+                }
+                _ => {}
+            }
+        }
+        match backtrack {
+            Some((resume, swallowed)) if swallowed < text.len() => {
+                backtrack = Some((resume, swallowed + 1));
+                p = resume;
+                t = swallowed + 1;
+            }
+            _ => return false,
+        }
+    }
+}
+
+/// An element hiding filter, `domains##selector`, or an exception to one,
+/// `domains#@#selector`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CosmeticFilter {
+    pub raw: String,
+    pub exception: bool,
+    /// Where the filter applies. Empty means everywhere.
+    pub domains: DomainList,
+    pub selector: String,
+}
+
+impl CosmeticFilter {
+    /// Parses `line` if it is an element hiding filter at all.
+    fn parse(line: &str) -> Result<Option<Self>, FilterError> {
+        let Some(at) = line.find('#') else {
+            return Ok(None);
+        };
+        let rest = &line[at..];
+        let (exception, separator) = if rest.starts_with("##") {
+            (false, "##")
+        } else if rest.starts_with("#@#") {
+            (true, "#@#")
+        } else if ["#?#", "#$#", "#%#", "#@?#", "#@$#", "#@%#"]
+            .iter()
+            .any(|s| rest.starts_with(s))
+        {
+            return Err(FilterError::Unsupported(line.to_string()));
+        } else {
+            return Ok(None);
+        };
+
+        let domains = &line[..at];
+        // `##` inside a URL filter, e.g. `/ads.js$domain=a.com##`, isn't one.
+        if domains.contains(['/', '$', '|', '*']) {
+            return Ok(None);
+        }
+        let selector = rest[separator.len()..].trim();
+        // A brace would let the selector smuggle rules into the stylesheet.
+        if selector.is_empty() || selector.contains(['{', '}']) {
+            return Err(FilterError::Invalid(line.to_string()));
+        }
+        // uBlock Origin scriptlets and HTML filters.
+        if selector.starts_with("+js(") || selector.starts_with('^') {
+            return Err(FilterError::Unsupported(line.to_string()));
+        }
+        Ok(Some(Self {
+            raw: line.to_string(),
+            exception,
+            domains: DomainList::parse(domains.split(',')),
+            selector: selector.to_string(),
+        }))
+    }
+
+    /// Whether the filter applies on pages from `host`.
+    pub fn applies_to(&self, host: &str) -> bool {
+        self.domains.matches(host)
+    }
+}
@@ -0,0 +1,126 @@
+//! Token index over network filters.
+//!
+//! Every filter whose pattern contains a whole word (a run of ASCII letters
+//! and digits bounded on both sides by something that isn't one) can only
+//! match URLs containing that same word. Filters are bucketed under one such
+//! word, their token, and a lookup only tries the buckets for the words in
+//! the URL, which for a full EasyList is a handful of filters instead of
+//! tens of thousands. Filters without a usable token go in a fallback list
+//! that is always scanned.
+
+use super::filter::NetworkFilter;
+use super::request::Request;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+/// Words in nearly every URL, which make poor tokens.
+const COMMON: &[&str] = &["com", "http", "https", "js", "net", "org", "www"];
+
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct TokenIndex {
+    buckets: HashMap<u64, Vec<NetworkFilter>>,
+    fallback: Vec<NetworkFilter>,
+}
+
+impl TokenIndex {
+    pub fn insert(&mut self, filter: NetworkFilter) {
+        let token = filter.tokens().into_iter().min_by_key(|token| {
+            let bucket = self.buckets.get(&hash(token)).map_or(0, Vec::len);
+            (
+                COMMON.contains(&token.as_str()),
+                bucket,
+                usize::MAX - token.len(),
+            )
+        });
+        match token {
+            Some(token) => self.buckets.entry(hash(&token)).or_default().push(filter),
+            None => self.fallback.push(filter),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.buckets.values().map(Vec::len).sum::<usize>() + self.fallback.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Filters that only match without any token, which every lookup scans.
+    pub fn fallback_len(&self) -> usize {
+        self.fallback.len()
+    }
+
+    /// The first filter matching `request`.
+    pub fn find(&self, request: &Request) -> Option<&NetworkFilter> {
+        let mut seen = Vec::new();
+        for word in words(request.url_lower()) {
+            let token = hash(word);
+            if seen.contains(&token) {
+                continue;
+            }
+            seen.push(token);
+            if let Some(filter) = self
+                .buckets
+                .get(&token)
+                .and_then(|bucket| bucket.iter().find(|f| f.matches(request)))
+            {
+                return Some(filter);
+            }
+        }
+        self.fallback.iter().find(|f| f.matches(request))
+    }
+}
+
+fn is_word_byte(b: u8) -> bool {
+    b.is_ascii_alphanumeric()
+}
+
+/// The runs of letters and digits in `text`.
+pub fn words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
+
+/// Whole words of a glob pattern body: those not touching a `*`, nor the
+/// start or end of the body unless it is anchored there.
+pub(super) fn pattern_tokens(body: &str, start_anchored: bool, end_anchored: bool) -> Vec<String> {
+    let bytes = body.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        if !is_word_byte(bytes[i]) {
+            i += 1;
+            continue;
+        }
+        let start = i;
+        while i < bytes.len() && is_word_byte(bytes[i]) {
+            i += 1;
+        }
+        let bounded_before = match start {
+            0 => start_anchored,
+            _ => bytes[start - 1] != b'*',
+        };
+        let bounded_after = match bytes.get(i) {
+            None => end_anchored,
+            Some(b) => *b != b'*',
+        };
+        if bounded_before && bounded_after {
+            tokens.push(body[start..i].to_ascii_lowercase());
+        }
+    }
+    tokens
+}
+
+/// FNV-1a, which unlike the std hasher is stable across builds, so hashed
+/// tokens can be cached on disk.
+pub fn hash(text: &str) -> u64 {
+    hash_more(0xcbf2_9ce4_8422_2325, text.as_bytes())
+}
+
+/// Continues `hash` over `bytes`.
+pub fn hash_more(hash: u64, bytes: &[u8]) -> u64 {
+    bytes.iter().fold(hash, |hash, b| {
+        (hash ^ u64::from(*b)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
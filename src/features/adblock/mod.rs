@@ -0,0 +1,443 @@
+//! Ad blocking with Adblock Plus / EasyList filter lists.
+
+pub mod allowlist;
+pub mod cosmetic;
+pub mod filter;
+pub mod index;
+pub mod request;
+pub mod stats;
+pub mod subscription;
+
+pub use allowlist::Allowlist;
+
+pub use filter::{CosmeticFilter, Filter, FilterError, NetworkFilter, ResourceType};
+pub use index::TokenIndex;
+pub use request::Request;
+pub use stats::{BlockCounts, BlockStats};
+pub use subscription::{ListHeader, RefreshOutcome, Subscription, SubscriptionManager};
+
+use colored::*;
+use log::{error, info, warn};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+
+/// Bumped whenever the cached form of compiled filters changes.
+const CACHE_VERSION: u32 = 2;
+
+/// What filters added one at a time, or from a list without a title, are
+/// counted under.
+pub const CUSTOM_LIST: &str = "Custom filters";
+
+/// Outcome of checking a request against the filters.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Decision {
+    /// No filter matched.
+    Allow,
+    /// Blocked by the filter given.
+    Block(String),
+    /// A blocking filter matched but the exception filter given overrode it.
+    Exception(String),
+}
+
+impl Decision {
+    pub fn is_blocked(&self) -> bool {
+        matches!(self, Decision::Block(_))
+    }
+}
+
+/// How many filters a list contributed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ListStats {
+    pub network: usize,
+    pub cosmetic: usize,
+    /// Lines skipped because they use syntax we don't support.
+    pub unsupported: usize,
+    /// Lines skipped because they don't parse.
+    pub invalid: usize,
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct FilterSet {
+    /// Plain `||host^` filters, by host, which are most of a typical list
+    /// and can be looked up directly.
+    hosts: HashMap<String, String>,
+    blocking: TokenIndex,
+    exceptions: TokenIndex,
+    cosmetic: Vec<CosmeticFilter>,
+    /// Titles of the lists the filters came from.
+    lists: Vec<String>,
+    /// Index into `lists` of the list each blocking filter came from, by
+    /// hash of the filter.
+    origins: HashMap<u64, usize>,
+}
+
+/// Filters compiled from a set of lists, as cached on disk.
+#[derive(Serialize, Deserialize)]
+struct CompiledLists {
+    version: u32,
+    /// Hash of the lists the filters came from.
+    source: u64,
+    stats: ListStats,
+    filters: FilterSet,
+}
+
+impl FilterSet {
+    fn list_id(&mut self, title: &str) -> usize {
+        match self.lists.iter().position(|list| list == title) {
+            Some(id) => id,
+            None => {
+                self.lists.push(title.to_string());
+                self.lists.len() - 1
+            }
+        }
+    }
+
+    fn insert(&mut self, filter: Filter, list: usize) {
+        if let Filter::Network(filter) = &filter {
+            if !filter.exception {
+                self.origins.entry(index::hash(&filter.raw)).or_insert(list);
+            }
+        }
+        match filter {
+            Filter::Network(filter) if filter.exception => self.exceptions.insert(filter),
+            Filter::Network(filter) => match filter.plain_host() {
+                Some(host) => {
+                    self.hosts.insert(host.to_string(), filter.raw);
+                }
+                None => self.blocking.insert(filter),
+            },
+            Filter::Cosmetic(filter) => self.cosmetic.push(filter),
+        }
+    }
+
+    fn blocking_match(&self, request: &Request) -> Option<&str> {
+        let host = request.host();
+        let by_host = std::iter::once(0)
+            .chain(host.match_indices('.').map(|(i, _)| i + 1))
+            .find_map(|start| self.hosts.get(&host[start..]));
+        by_host
+            .or_else(|| self.blocking.find(request).map(|f| &f.raw))
+            .map(String::as_str)
+    }
+
+    /// Title of the list the blocking filter `raw` came from.
+    fn list_of(&self, raw: &str) -> &str {
+        self.origins
+            .get(&index::hash(raw))
+            .and_then(|id| self.lists.get(*id))
+            .map_or(CUSTOM_LIST, String::as_str)
+    }
+
+    /// Adds every filter in `list`, counting them under `title`.
+    fn add_list(&mut self, title: &str, list: &str) -> ListStats {
+        let id = self.list_id(title);
+        let mut stats = ListStats::default();
+        for line in list.lines() {
+            match Filter::parse(line) {
+                Ok(Some(filter)) => {
+                    match filter {
+                        Filter::Network(_) => stats.network += 1,
+                        Filter::Cosmetic(_) => stats.cosmetic += 1,
+                    }
+                    self.insert(filter, id);
+                }
+                Ok(None) => {}
+                Err(FilterError::Unsupported(_)) => stats.unsupported += 1,
+                Err(e) => {
+                    warn!("Skipping filter: {}", e);
+                    stats.invalid += 1;
+                }
+            }
+        }
+        stats
+    }
+}
+
+/// The title of `list`, from its header, or else the file name of `path`.
+fn list_title(list: &str, path: Option<&Path>) -> String {
+    ListHeader::parse(list)
+        .title
+        .or_else(|| {
+            path.and_then(Path::file_name)
+                .map(|name| name.to_string_lossy().into_owned())
+        })
+        .unwrap_or_else(|| CUSTOM_LIST.to_string())
+}
+
+pub struct AdBlocker {
+    filters: RwLock<FilterSet>,
+    /// Filters from `add_filter`, which survive the lists being reloaded.
+    custom: RwLock<Vec<Filter>>,
+    enabled: AtomicBool,
+    allowlist: Allowlist,
+    stats: BlockStats,
+}
+
+impl AdBlocker {
+    pub fn new() -> Self {
+        Self {
+            filters: RwLock::new(FilterSet::default()),
+            custom: RwLock::new(Vec::new()),
+            enabled: AtomicBool::new(true),
+            allowlist: Allowlist::in_memory(),
+            stats: BlockStats::new(),
+        }
+    }
+
+    /// Uses `allowlist` for the sites where nothing is blocked.
+    pub fn with_allowlist(mut self, allowlist: Allowlist) -> Self {
+        self.allowlist = allowlist;
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Turns blocking on or off everywhere.
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if enabled {
+            info!("{}", "Ad blocking enabled! ٩(◕‿◕)۶".green());
+        } else {
+            info!("{}", "Ad blocking disabled (・_・;)".yellow());
+        }
+    }
+
+    /// Flips blocking on or off, returning whether it is now on.
+    pub fn toggle(&self) -> bool {
+        let enabled = !self.enabled.fetch_xor(true, Ordering::Relaxed);
+        self.set_enabled(enabled);
+        enabled
+    }
+
+    pub fn allowlist(&self) -> &Allowlist {
+        &self.allowlist
+    }
+
+    /// Counts of the requests `should_block` blocked.
+    pub fn stats(&self) -> &BlockStats {
+        &self.stats
+    }
+
+    /// Adds one filter in Adblock Plus syntax. Comments are accepted and
+    /// ignored.
+    pub fn add_filter(&self, filter: &str) -> Result<(), FilterError> {
+        if let Some(filter) = Filter::parse(filter)? {
+            let mut filters = self.filters.write();
+            let list = filters.list_id(CUSTOM_LIST);
+            filters.insert(filter.clone(), list);
+            self.custom.write().push(filter);
+        }
+        Ok(())
+    }
+
+    /// Adds every filter in a filter list, skipping lines that don't parse.
+    /// Blocked requests are counted under the list's `! Title:`.
+    pub fn add_filters(&self, list: &str) -> ListStats {
+        self.filters.write().add_list(&list_title(list, None), list)
+    }
+
+    /// Adds the filters in the list at `path`.
+    pub fn load_list(&self, path: &Path) -> anyhow::Result<ListStats> {
+        let list = std::fs::read_to_string(path)
+            .map_err(|e| anyhow::anyhow!("Could not read {}: {}", path.display(), e))?;
+        let stats = self
+            .filters
+            .write()
+            .add_list(&list_title(&list, Some(path)), &list);
+        info!(
+            "Loaded {} network and {} element hiding filters from {} ({} unsupported)",
+            stats.network,
+            stats.cosmetic,
+            path.display(),
+            stats.unsupported
+        );
+        Ok(stats)
+    }
+
+    /// Replaces the filters with those in `lists`. Compiling a big list
+    /// takes a while, so the result is cached in `cache_file` and reused for
+    /// as long as the lists stay the same. Lists that can't be read are
+    /// skipped. Filters added with `add_filter` are kept.
+    pub fn load_lists_cached(&self, lists: &[PathBuf], cache_file: &Path) -> ListStats {
+        let sources: Vec<_> = lists
+            .iter()
+            .filter_map(|path| match std::fs::read_to_string(path) {
+                Ok(list) => Some((path, list)),
+                Err(e) => {
+                    error!("Skipping filter list {}: {}", path.display(), e);
+                    None
+                }
+            })
+            .collect();
+        let source = sources.iter().fold(
+            index::hash(env!("CARGO_PKG_VERSION")),
+            |hash, (path, list)| {
+                let hash = index::hash_more(hash, path.to_string_lossy().as_bytes());
+                index::hash_more(index::hash_more(hash, b"\0"), list.as_bytes())
+            },
+        );
+
+        if let Some(compiled) = Self::read_cache(cache_file)
+            .filter(|c| c.version == CACHE_VERSION && c.source == source)
+        {
+            info!(
+                "Loaded {} compiled filters from cache",
+                compiled.stats.network + compiled.stats.cosmetic
+            );
+            self.replace_filters(compiled.filters);
+            return compiled.stats;
+        }
+
+        let mut filters = FilterSet::default();
+        let mut stats = ListStats::default();
+        for (path, list) in &sources {
+            let list_stats = filters.add_list(&list_title(list, Some(path)), list);
+            info!(
+                "Loaded {} network and {} element hiding filters from {} ({} unsupported)",
+                list_stats.network,
+                list_stats.cosmetic,
+                path.display(),
+                list_stats.unsupported
+            );
+            stats.network += list_stats.network;
+            stats.cosmetic += list_stats.cosmetic;
+            stats.unsupported += list_stats.unsupported;
+            stats.invalid += list_stats.invalid;
+        }
+        let compiled = CompiledLists {
+            version: CACHE_VERSION,
+            source,
+            stats,
+            filters,
+        };
+        if let Err(e) = Self::write_cache(cache_file, &compiled) {
+            warn!("Could not cache compiled filters: {}", e);
+        }
+        self.replace_filters(compiled.filters);
+        stats
+    }
+
+    /// Swaps in `filters` plus the ones from `add_filter`.
+    fn replace_filters(&self, mut filters: FilterSet) {
+        // Same lock order as `add_filter`, so none added meanwhile is lost.
+        let mut current = self.filters.write();
+        let custom = self.custom.read();
+        if !custom.is_empty() {
+            let list = filters.list_id(CUSTOM_LIST);
+            for filter in custom.iter() {
+                filters.insert(filter.clone(), list);
+            }
+        }
+        *current = filters;
+    }
+
+    fn read_cache(cache_file: &Path) -> Option<CompiledLists> {
+        let bytes = std::fs::read(cache_file).ok()?;
+        serde_json::from_slice(&bytes)
+            .map_err(|e| warn!("Ignoring filter cache {}: {}", cache_file.display(), e))
+            .ok()
+    }
+
+    fn write_cache(cache_file: &Path, compiled: &CompiledLists) -> anyhow::Result<()> {
+        if let Some(dir) = cache_file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        crate::utils::write_atomic(cache_file, &serde_json::to_vec(compiled)?)?;
+        Ok(())
+    }
+
+    /// Removes every filter.
+    pub fn clear(&self) {
+        self.custom.write().clear();
+        *self.filters.write() = FilterSet::default();
+    }
+
+    /// Number of network filters, exceptions included.
+    pub fn network_filter_count(&self) -> usize {
+        let filters = self.filters.read();
+        filters.hosts.len() + filters.blocking.len() + filters.exceptions.len()
+    }
+
+    /// Network filters without a token, which every request is checked
+    /// against. Keeping this small keeps lookups fast.
+    pub fn untokenized_filter_count(&self) -> usize {
+        let filters = self.filters.read();
+        filters.blocking.fallback_len() + filters.exceptions.fallback_len()
+    }
+
+    pub fn cosmetic_filters(&self) -> Vec<CosmeticFilter> {
+        self.filters.read().cosmetic.clone()
+    }
+
+    /// CSS selectors to hide on pages from `host`: the generic ones and
+    /// those for `host`, minus any a `#@#` exception lifts there.
+    pub fn hiding_selectors(&self, host: &str) -> Vec<String> {
+        if !self.is_enabled() || self.allowlist.contains(host) {
+            return Vec::new();
+        }
+        let filters = self.filters.read();
+        let applicable = filters.cosmetic.iter().filter(|f| f.applies_to(host));
+        let (exceptions, hiding): (Vec<_>, Vec<_>) = applicable.partition(|f| f.exception);
+        let excepted: HashSet<&str> = exceptions.iter().map(|f| f.selector.as_str()).collect();
+
+        let mut seen = HashSet::new();
+        hiding
+            .into_iter()
+            .map(|f| f.selector.as_str())
+            .filter(|selector| !excepted.contains(selector) && seen.insert(*selector))
+            .map(str::to_string)
+            .collect()
+    }
+
+    /// The element hiding stylesheet for pages from `host`, if anything is
+    /// hidden there.
+    pub fn hiding_stylesheet(&self, host: &str) -> Option<String> {
+        let selectors = self.hiding_selectors(host);
+        (!selectors.is_empty()).then(|| cosmetic::stylesheet(&selectors))
+    }
+
+    /// Checks `request` against the filters. Nothing is blocked while
+    /// blocking is off, nor on allowlisted sites.
+    pub fn check(&self, request: &Request) -> Decision {
+        if !self.is_enabled() || self.allowlist.contains(request.source_host()) {
+            return Decision::Allow;
+        }
+        let filters = self.filters.read();
+        let Some(blocked_by) = filters.blocking_match(request) else {
+            return Decision::Allow;
+        };
+        match filters.exceptions.find(request) {
+            Some(exception) => Decision::Exception(exception.raw.clone()),
+            None => Decision::Block(blocked_by.to_string()),
+        }
+    }
+
+    /// Whether a request for `url`, made by the page at `source_url` (`None`
+    /// for top-level navigations), should be blocked. Blocked requests are
+    /// counted in `stats`.
+    pub fn should_block(
+        &self,
+        url: &str,
+        source_url: Option<&str>,
+        resource_type: ResourceType,
+    ) -> bool {
+        let Decision::Block(rule) = self.check(&Request::new(url, source_url, resource_type))
+        else {
+            return false;
+        };
+        let list = self.filters.read().list_of(&rule).to_string();
+        self.stats.record(source_url.unwrap_or(url), &list, &rule);
+        true
+    }
+}
+
+impl Default for AdBlocker {
+    fn default() -> Self {
+        Self::new()
+    }
+}
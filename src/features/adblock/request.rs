@@ -0,0 +1,110 @@
+use super::filter::ResourceType;
+use url::{Position, Url};
+
+/// Second-level labels under which sites register their own domains, as in
+/// `example.co.uk`. Without a public suffix list this catches the common
+/// cases.
+const SECOND_LEVEL: &[&str] = &["ac", "co", "com", "edu", "gov", "ne", "net", "or", "org"];
+
+/// A request to check against the filters, with the parts filters look at
+/// worked out once up front.
+#[derive(Debug, Clone)]
+pub struct Request {
+    url: String,
+    url_lower: String,
+    host: String,
+    host_start: usize,
+    source_host: Option<String>,
+    pub resource_type: ResourceType,
+}
+
+impl Request {
+    /// `source_url` is the page making the request, `None` for top-level
+    /// navigations.
+    pub fn new(url: &str, source_url: Option<&str>, resource_type: ResourceType) -> Self {
+        let (url, host, host_start) = match Url::parse(url) {
+            Ok(parsed) => {
+                let host = parsed.host_str().unwrap_or_default().to_string();
+                let host_start = parsed[..Position::BeforeHost].len();
+                (parsed.to_string(), host, host_start)
+            }
+            Err(_) => (url.to_string(), String::new(), 0),
+        };
+        let source_host = source_url
+            .and_then(|source| Url::parse(source).ok())
+            .and_then(|source| source.host_str().map(str::to_string));
+        Self {
+            url_lower: url.to_lowercase(),
+            url,
+            host,
+            host_start,
+            source_host,
+            resource_type,
+        }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn url_lower(&self) -> &str {
+        &self.url_lower
+    }
+
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Host of the page making the request, or of the request itself for
+    /// top-level navigations.
+    pub fn source_host(&self) -> &str {
+        self.source_host.as_deref().unwrap_or(&self.host)
+    }
+
+    /// Whether the request goes to a different site than the page making it.
+    pub fn is_third_party(&self) -> bool {
+        match &self.source_host {
+            Some(source) => base_domain(source) != base_domain(&self.host),
+            None => false,
+        }
+    }
+
+    /// Offsets into the URL where the host, or one of its parent domains,
+    /// starts. `||` filters may only match from these.
+    pub(super) fn host_label_starts(&self) -> impl Iterator<Item = usize> + '_ {
+        let start = self.host_start;
+        std::iter::once(start).chain(
+            self.host
+                .match_indices('.')
+                .map(move |(i, _)| start + i + 1),
+        )
+    }
+}
+
+/// The part of `host` a site registers, approximated as the last two labels
+/// (three under [`SECOND_LEVEL`] labels of country domains).
+pub fn base_domain(host: &str) -> &str {
+    if host.parse::<std::net::IpAddr>().is_ok() || host.starts_with('[') {
+        return host;
+    }
+    let mut labels = host.rsplit('.');
+    let tld = labels.next().unwrap_or_default();
+    let second = labels.next().unwrap_or_default();
+    let keep = if tld.len() == 2 && SECOND_LEVEL.contains(&second) {
+        3
+    } else {
+        2
+    };
+    match host.rmatch_indices('.').nth(keep - 1) {
+        Some((i, _)) => &host[i + 1..],
+        None => host,
+    }
+}
+
+/// The host of `url`, empty if it has none.
+pub fn host_of(url: &str) -> String {
+    Url::parse(url)
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_string))
+        .unwrap_or_default()
+}
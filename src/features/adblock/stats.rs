@@ -0,0 +1,99 @@
+//! Counts of blocked requests, overall and per page, by list and by rule,
+//! for telling which filter broke a site.
+
+use lru::LruCache;
+use parking_lot::Mutex;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::num::NonZeroUsize;
+
+/// How many pages per-page counts are kept for.
+const MAX_PAGES: usize = 100;
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct BlockCounts {
+    pub total: u64,
+    /// Blocked requests by the title of the list the filter came from.
+    pub by_list: BTreeMap<String, u64>,
+    /// Blocked requests by filter.
+    pub by_rule: BTreeMap<String, u64>,
+}
+
+impl BlockCounts {
+    fn record(&mut self, list: &str, rule: &str) {
+        self.total += 1;
+        *self.by_list.entry(list.to_string()).or_default() += 1;
+        *self.by_rule.entry(rule.to_string()).or_default() += 1;
+    }
+
+    /// The `n` filters that blocked the most, most first.
+    pub fn top_rules(&self, n: usize) -> Vec<(&str, u64)> {
+        let mut rules: Vec<_> = self
+            .by_rule
+            .iter()
+            .map(|(rule, count)| (rule.as_str(), *count))
+            .collect();
+        rules.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(b.0)));
+        rules.truncate(n);
+        rules
+    }
+}
+
+pub struct BlockStats {
+    global: Mutex<BlockCounts>,
+    /// Counts by page URL, for the most recently active pages.
+    pages: Mutex<LruCache<String, BlockCounts>>,
+}
+
+impl BlockStats {
+    pub fn new() -> Self {
+        Self {
+            global: Mutex::new(BlockCounts::default()),
+            pages: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_PAGES).unwrap())),
+        }
+    }
+
+    /// Counts a request from the page at `page` blocked by `rule`, from the
+    /// list titled `list`.
+    pub fn record(&self, page: &str, list: &str, rule: &str) {
+        self.global.lock().record(list, rule);
+        self.pages
+            .lock()
+            .get_or_insert_mut(page_key(page), BlockCounts::default)
+            .record(list, rule);
+    }
+
+    pub fn global(&self) -> BlockCounts {
+        self.global.lock().clone()
+    }
+
+    pub fn page(&self, url: &str) -> Option<BlockCounts> {
+        self.pages.lock().peek(&page_key(url)).cloned()
+    }
+
+    /// Per-page counts, most recently active page first.
+    pub fn pages(&self) -> Vec<(String, BlockCounts)> {
+        self.pages
+            .lock()
+            .iter()
+            .map(|(url, counts)| (url.clone(), counts.clone()))
+            .collect()
+    }
+
+    pub fn reset(&self) {
+        *self.global.lock() = BlockCounts::default();
+        self.pages.lock().clear();
+    }
+}
+
+impl Default for BlockStats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Pages are counted without their fragment, which doesn't make them a
+/// different page.
+fn page_key(url: &str) -> String {
+    url.split('#').next().unwrap_or(url).to_string()
+}
@@ -0,0 +1,462 @@
+//! Filter list subscriptions: lists fetched from a URL and kept up to date,
+//! with the last good copy of each cached in the data dir.
+//!
+//! Which lists are subscribed to, and what we know about them, is kept in
+//! `adblock_subscriptions.toml` next to `config.toml`. A list is fetched
+//! again once the `! Expires:` period from its header has passed, and a
+//! download that fails, or whose `! Checksum:` doesn't match, leaves the
+//! cached copy in use.
+
+use super::index;
+use crate::config::BrowserConfig;
+use base64::Engine;
+use chrono::{DateTime, Duration, Utc};
+use hyper::{body::HttpBody, Body, Client, Request, StatusCode};
+use hyper_tls::HttpsConnector;
+use log::{info, warn};
+use md5::{Digest, Md5};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use url::Url;
+
+pub const SUBSCRIPTIONS_FILE: &str = "adblock_subscriptions.toml";
+/// Where cached copies of subscribed lists live, under the data dir.
+pub const LISTS_DIR: &str = "adblock/lists";
+
+/// Used when a list doesn't say how long it stays fresh.
+const DEFAULT_EXPIRES_HOURS: u64 = 5 * 24;
+/// Bounds on the `! Expires:` period, so a list can't ask to be fetched
+/// every minute or never again.
+const MIN_EXPIRES_HOURS: u64 = 1;
+const MAX_EXPIRES_HOURS: u64 = 14 * 24;
+/// How long to wait before trying again after a failed update.
+const RETRY_HOURS: i64 = 1;
+const MAX_REDIRECTS: usize = 5;
+/// Lists bigger than this are rejected rather than read into memory.
+const MAX_LIST_BYTES: u64 = 32 * 1024 * 1024;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Subscription {
+    pub url: String,
+    /// From the list's `! Title:` unless given when subscribing.
+    #[serde(default)]
+    pub title: Option<String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub version: Option<String>,
+    /// When the list was last downloaded successfully.
+    #[serde(default)]
+    pub last_updated: Option<DateTime<Utc>>,
+    /// When an update was last attempted, successful or not.
+    #[serde(default)]
+    pub last_checked: Option<DateTime<Utc>>,
+    /// How long the list stays fresh, from its `! Expires:` header.
+    #[serde(default)]
+    pub expires_hours: Option<u64>,
+    /// Why the last update failed, if it did.
+    #[serde(default)]
+    pub last_error: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+impl Subscription {
+    pub fn new(url: &str) -> Self {
+        Self {
+            url: url.to_string(),
+            title: None,
+            enabled: true,
+            version: None,
+            last_updated: None,
+            last_checked: None,
+            expires_hours: None,
+            last_error: None,
+        }
+    }
+
+    /// When the list should next be fetched, `None` if it never has been.
+    pub fn next_update(&self) -> Option<DateTime<Utc>> {
+        let checked = self.last_checked?;
+        let wait = match self.last_error {
+            Some(_) => Duration::hours(RETRY_HOURS),
+            None => Duration::hours(self.expires_hours.unwrap_or(DEFAULT_EXPIRES_HOURS) as i64),
+        };
+        Some(checked + wait)
+    }
+
+    pub fn is_due(&self, now: DateTime<Utc>) -> bool {
+        self.next_update().is_none_or(|next| next <= now)
+    }
+
+    /// Where the last good copy of the list is kept.
+    pub fn cache_file(&self, lists_dir: &Path) -> PathBuf {
+        lists_dir.join(format!("{:016x}.txt", index::hash(&self.url)))
+    }
+}
+
+/// Metadata from the `! Key: value` comments at the top of a list.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ListHeader {
+    pub title: Option<String>,
+    pub version: Option<String>,
+    pub expires_hours: Option<u64>,
+    pub checksum: Option<String>,
+}
+
+impl ListHeader {
+    pub fn parse(list: &str) -> Self {
+        let mut header = Self::default();
+        let comments = list
+            .lines()
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .skip_while(|line| line.starts_with('['))
+            .map_while(|line| line.strip_prefix('!'));
+        for comment in comments {
+            let Some((key, value)) = comment.split_once(':') else {
+                continue;
+            };
+            let value = value.trim().to_string();
+            match key.trim().to_ascii_lowercase().as_str() {
+                "title" => header.title = Some(value),
+                "version" => header.version = Some(value),
+                "expires" => header.expires_hours = parse_expires(&value),
+                "checksum" => header.checksum = Some(value),
+                _ => {}
+            }
+        }
+        header
+    }
+}
+
+/// Parses an `! Expires:` value such as `4 days (update frequency)` or
+/// `12 hours` into hours, clamped to sensible bounds.
+pub fn parse_expires(value: &str) -> Option<u64> {
+    let mut parts = value.split_whitespace();
+    let amount: u64 = parts.next()?.parse().ok()?;
+    let hours = match parts.next().unwrap_or("days").to_ascii_lowercase().as_str() {
+        "h" | "hour" | "hours" => amount,
+        "d" | "day" | "days" => amount.saturating_mul(24),
+        _ => return None,
+    };
+    Some(hours.clamp(MIN_EXPIRES_HOURS, MAX_EXPIRES_HOURS))
+}
+
+/// Checks a list against its `! Checksum:` header, if it has one: the
+/// unpadded base64 MD5 of the list without carriage returns, blank lines
+/// and the checksum line itself, as Adblock Plus defines it.
+pub fn verify_checksum(list: &str) -> anyhow::Result<()> {
+    let Some(expected) = ListHeader::parse(list).checksum else {
+        return Ok(());
+    };
+    let mut collapsed = String::with_capacity(list.len());
+    for c in list.chars().filter(|c| *c != '\r') {
+        if !(c == '\n' && collapsed.ends_with('\n')) {
+            collapsed.push(c);
+        }
+    }
+    let normalized: String = collapsed
+        .split_inclusive('\n')
+        .filter(|line| !(line.ends_with('\n') && is_checksum_line(line)))
+        .collect();
+    let actual =
+        base64::engine::general_purpose::STANDARD_NO_PAD.encode(Md5::digest(normalized.as_bytes()));
+    if actual != expected.trim_end_matches('=') {
+        anyhow::bail!(
+            "checksum mismatch, expected {} but got {}",
+            expected,
+            actual
+        );
+    }
+    Ok(())
+}
+
+fn is_checksum_line(line: &str) -> bool {
+    line.trim_start()
+        .strip_prefix('!')
+        .map(str::trim_start)
+        .and_then(|rest| rest.get(..8))
+        .is_some_and(|key| key.eq_ignore_ascii_case("checksum"))
+}
+
+/// Fetches filter lists. `file://` URLs are read directly. Other URLs are
+/// looked up in the local mirror first, if there is one, as
+/// `{mirror}/{host}/{path}` or just `{mirror}/{file name}`, and only
+/// downloaded if it doesn't have them.
+#[derive(Debug, Clone, Default)]
+pub struct ListFetcher {
+    mirror: Option<PathBuf>,
+}
+
+impl ListFetcher {
+    pub fn new(mirror: Option<PathBuf>) -> Self {
+        Self { mirror }
+    }
+
+    /// The mirrored copy of `url`, if the mirror has one.
+    pub fn mirrored(&self, url: &Url) -> Option<PathBuf> {
+        let mirror = self.mirror.as_ref()?;
+        let path = url.path().trim_start_matches('/');
+        let by_host = mirror.join(url.host_str()?).join(path);
+        let by_name = path.rsplit('/').next().map(|name| mirror.join(name));
+        std::iter::once(by_host)
+            .chain(by_name)
+            .find(|candidate| candidate.is_file())
+    }
+
+    pub async fn fetch(&self, url: &str) -> anyhow::Result<String> {
+        let parsed = Url::parse(url)?;
+        if parsed.scheme() == "file" {
+            let path = parsed
+                .to_file_path()
+                .map_err(|_| anyhow::anyhow!("{} is not a local path", url))?;
+            return Ok(tokio::fs::read_to_string(&path).await?);
+        }
+        if let Some(path) = self.mirrored(&parsed) {
+            info!("Using mirrored {} for {}", path.display(), url);
+            return Ok(tokio::fs::read_to_string(&path).await?);
+        }
+        match parsed.scheme() {
+            "http" | "https" => download(parsed).await,
+            scheme => anyhow::bail!("can't fetch {}:// URLs", scheme),
+        }
+    }
+}
+
+async fn download(mut url: Url) -> anyhow::Result<String> {
+    let client = Client::builder().build::<_, Body>(HttpsConnector::new());
+    for _ in 0..=MAX_REDIRECTS {
+        let request = Request::get(url.as_str())
+            .header(
+                "User-Agent",
+                concat!("nyan_browser/", env!("CARGO_PKG_VERSION")),
+            )
+            .body(Body::empty())?;
+        let response = client.request(request).await?;
+        let status = response.status();
+        if status.is_redirection() {
+            let location = response
+                .headers()
+                .get(hyper::header::LOCATION)
+                .and_then(|l| l.to_str().ok())
+                .ok_or_else(|| anyhow::anyhow!("{} redirect without a location", status))?;
+            url = url.join(location)?;
+            continue;
+        }
+        if status != StatusCode::OK {
+            anyhow::bail!("server replied {}", status);
+        }
+        if response.body().size_hint().lower() > MAX_LIST_BYTES {
+            anyhow::bail!("list is too big");
+        }
+        // Chunked bodies don't say how big they are, so count as they come.
+        let mut body = response.into_body();
+        let mut list = Vec::new();
+        while let Some(chunk) = body.data().await {
+            let chunk = chunk?;
+            if (list.len() + chunk.len()) as u64 > MAX_LIST_BYTES {
+                anyhow::bail!("list is too big");
+            }
+            list.extend_from_slice(&chunk);
+        }
+        return Ok(String::from_utf8(list)?);
+    }
+    anyhow::bail!("too many redirects")
+}
+
+/// What happened to a subscription when it was refreshed.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RefreshOutcome {
+    Updated {
+        url: String,
+    },
+    /// The update failed and the cached copy, if any, stays in use.
+    Failed {
+        url: String,
+        error: String,
+    },
+}
+
+#[derive(Default, Serialize, Deserialize)]
+struct SubscriptionFile {
+    #[serde(default)]
+    subscriptions: Vec<Subscription>,
+}
+
+pub struct SubscriptionManager {
+    file: PathBuf,
+    lists_dir: PathBuf,
+    fetcher: ListFetcher,
+    subscriptions: RwLock<Vec<Subscription>>,
+}
+
+impl SubscriptionManager {
+    pub fn open(config: &BrowserConfig) -> anyhow::Result<Self> {
+        let file = config.config_dir.join(SUBSCRIPTIONS_FILE);
+        let subscriptions = match std::fs::read_to_string(&file) {
+            Ok(content) => toml::from_str::<SubscriptionFile>(&content)?.subscriptions,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Vec::new(),
+            Err(e) => return Err(e.into()),
+        };
+        Ok(Self {
+            file,
+            lists_dir: config.data_dir.join(LISTS_DIR),
+            fetcher: ListFetcher::new(config.adblock_mirror.clone()),
+            subscriptions: RwLock::new(subscriptions),
+        })
+    }
+
+    pub fn list(&self) -> Vec<Subscription> {
+        self.subscriptions.read().clone()
+    }
+
+    pub fn get(&self, url: &str) -> Option<Subscription> {
+        self.subscriptions
+            .read()
+            .iter()
+            .find(|s| s.url == url)
+            .cloned()
+    }
+
+    /// Subscribes to the list at `url`. It is fetched on the next refresh.
+    /// Returns false if already subscribed.
+    pub fn subscribe(&self, url: &str, title: Option<&str>) -> anyhow::Result<bool> {
+        let url = Url::parse(url)?.to_string();
+        {
+            let mut subscriptions = self.subscriptions.write();
+            if subscriptions.iter().any(|s| s.url == url) {
+                return Ok(false);
+            }
+            let mut subscription = Subscription::new(&url);
+            subscription.title = title.map(str::to_string);
+            subscriptions.push(subscription);
+        }
+        self.save()?;
+        Ok(true)
+    }
+
+    /// Unsubscribes from `url` and deletes its cached copy.
+    pub fn unsubscribe(&self, url: &str) -> anyhow::Result<bool> {
+        let removed = {
+            let mut subscriptions = self.subscriptions.write();
+            let Some(index) = subscriptions.iter().position(|s| s.url == url) else {
+                return Ok(false);
+            };
+            subscriptions.remove(index)
+        };
+        let _ = std::fs::remove_file(removed.cache_file(&self.lists_dir));
+        self.save()?;
+        Ok(true)
+    }
+
+    pub fn set_enabled(&self, url: &str, enabled: bool) -> anyhow::Result<bool> {
+        let found = self.modify(url, |s| s.enabled = enabled);
+        if found {
+            self.save()?;
+        }
+        Ok(found)
+    }
+
+    /// Cached copies of the enabled lists that have been fetched.
+    pub fn list_paths(&self) -> Vec<PathBuf> {
+        self.subscriptions
+            .read()
+            .iter()
+            .filter(|s| s.enabled)
+            .map(|s| s.cache_file(&self.lists_dir))
+            .filter(|path| path.is_file())
+            .collect()
+    }
+
+    /// Fetches the enabled subscriptions that are due, or all of them if
+    /// `force`.
+    pub async fn refresh(&self, force: bool) -> Vec<RefreshOutcome> {
+        let now = Utc::now();
+        let due: Vec<_> = self
+            .subscriptions
+            .read()
+            .iter()
+            .filter(|s| s.enabled && (force || s.is_due(now)))
+            .cloned()
+            .collect();
+        if due.is_empty() {
+            return Vec::new();
+        }
+
+        let mut outcomes = Vec::new();
+        for subscription in due {
+            let url = subscription.url.clone();
+            let result = self.update(&subscription).await;
+            self.modify(&url, |s| {
+                s.last_checked = Some(now);
+                match &result {
+                    Ok(header) => {
+                        s.last_updated = Some(now);
+                        s.last_error = None;
+                        s.version = header.version.clone();
+                        s.expires_hours = header.expires_hours;
+                        if s.title.is_none() {
+                            s.title = header.title.clone();
+                        }
+                    }
+                    Err(e) => s.last_error = Some(e.to_string()),
+                }
+            });
+            outcomes.push(match result {
+                Ok(_) => {
+                    info!("Updated filter list {}", url);
+                    RefreshOutcome::Updated { url }
+                }
+                Err(e) => {
+                    warn!("Could not update filter list {}: {}", url, e);
+                    RefreshOutcome::Failed {
+                        url,
+                        error: e.to_string(),
+                    }
+                }
+            });
+        }
+        if let Err(e) = self.save() {
+            warn!("Could not save filter list subscriptions: {}", e);
+        }
+        outcomes
+    }
+
+    /// Fetches and checks one list, replacing its cached copy.
+    async fn update(&self, subscription: &Subscription) -> anyhow::Result<ListHeader> {
+        let list = self.fetcher.fetch(&subscription.url).await?;
+        let first_line = list.trim_start_matches('\u{feff}').trim_start();
+        if first_line.is_empty() || first_line.starts_with('<') {
+            anyhow::bail!("that doesn't look like a filter list");
+        }
+        verify_checksum(&list)?;
+        std::fs::create_dir_all(&self.lists_dir)?;
+        crate::utils::write_atomic(&subscription.cache_file(&self.lists_dir), list.as_bytes())?;
+        Ok(ListHeader::parse(&list))
+    }
+
+    fn modify(&self, url: &str, change: impl FnOnce(&mut Subscription)) -> bool {
+        match self.subscriptions.write().iter_mut().find(|s| s.url == url) {
+            Some(subscription) => {
+                change(subscription);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn save(&self) -> anyhow::Result<()> {
+        if let Some(dir) = self.file.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let file = SubscriptionFile {
+            subscriptions: self.list(),
+        };
+        crate::utils::write_atomic(&self.file, toml::to_string_pretty(&file)?.as_bytes())?;
+        Ok(())
+    }
+}
@@ -1,5 +1,3 @@
-use std::sync::Arc;
-
 pub struct BatterySaver;
 
 impl BatterySaver {
@@ -9,3 +7,9 @@ impl BatterySaver {
 
     pub fn enable(&self) {}
 }
+
+impl Default for BatterySaver {
+    fn default() -> Self {
+        Self::new()
+    }
+}
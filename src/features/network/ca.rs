@@ -0,0 +1,141 @@
+//! The certificate authority the proxy signs its certificates for HTTPS
+//! sites with, so it can read the traffic it passes on.
+//!
+//! Each profile gets its own CA, made on first use and kept in the data
+//! directory. Browsers are told to accept the certificates it signs; users
+//! who want them trusted elsewhere can import `proxy-ca.pem`.
+
+use chrono::{Datelike, Duration, Utc};
+use lru::LruCache;
+use parking_lot::Mutex;
+use rcgen::{
+    BasicConstraints, Certificate, CertificateParams, DistinguishedName, DnType, IsCa, KeyPair,
+    KeyUsagePurpose, SerialNumber,
+};
+use std::collections::hash_map::RandomState;
+use std::fs;
+use std::hash::BuildHasher;
+use std::io::{self, Write};
+use std::num::NonZeroUsize;
+use std::path::Path;
+use tokio_native_tls::native_tls::{Identity, TlsAcceptor as NativeAcceptor};
+use tokio_native_tls::TlsAcceptor;
+
+pub const CA_CERT_FILE: &str = "proxy-ca.pem";
+pub const CA_KEY_FILE: &str = "proxy-ca.key";
+
+/// Sites whose certificates are kept ready.
+const MAX_CACHED_HOSTS: usize = 256;
+
+pub struct CertificateAuthority {
+    cert_pem: String,
+    /// Stands in for the saved certificate when signing: only its name and
+    /// key are used, and those are the same.
+    issuer: Certificate,
+    key: KeyPair,
+    /// Shared by every site certificate.
+    site_key: KeyPair,
+    acceptors: Mutex<LruCache<String, TlsAcceptor>>,
+}
+
+impl CertificateAuthority {
+    /// Loads the CA kept in `dir`, making and saving one if there is none.
+    pub fn load_or_create(dir: &Path) -> anyhow::Result<Self> {
+        let cert_path = dir.join(CA_CERT_FILE);
+        let key_path = dir.join(CA_KEY_FILE);
+        if cert_path.exists() && key_path.exists() {
+            let key = KeyPair::from_pem(&fs::read_to_string(&key_path)?)?;
+            return Self::from_parts(fs::read_to_string(&cert_path)?, key);
+        }
+
+        let ca = Self::generate()?;
+        fs::create_dir_all(dir)?;
+        write_private(&key_path, ca.key.serialize_pem().as_bytes())?;
+        crate::utils::write_atomic(&cert_path, ca.cert_pem.as_bytes())?;
+        Ok(ca)
+    }
+
+    /// A new CA that only lives in memory.
+    pub fn generate() -> anyhow::Result<Self> {
+        let key = KeyPair::generate()?;
+        let cert_pem = ca_params().self_signed(&key)?.pem();
+        Self::from_parts(cert_pem, key)
+    }
+
+    fn from_parts(cert_pem: String, key: KeyPair) -> anyhow::Result<Self> {
+        Ok(Self {
+            issuer: ca_params().self_signed(&key)?,
+            cert_pem,
+            key,
+            site_key: KeyPair::generate()?,
+            acceptors: Mutex::new(LruCache::new(NonZeroUsize::new(MAX_CACHED_HOSTS).unwrap())),
+        })
+    }
+
+    /// The CA certificate, PEM encoded.
+    pub fn certificate_pem(&self) -> &str {
+        &self.cert_pem
+    }
+
+    /// Accepts TLS connections for `host` with a certificate signed by
+    /// this CA.
+    pub(crate) fn acceptor(&self, host: &str) -> anyhow::Result<TlsAcceptor> {
+        if let Some(acceptor) = self.acceptors.lock().get(host) {
+            return Ok(acceptor.clone());
+        }
+        let cert = site_params(host)?.signed_by(&self.site_key, &self.issuer, &self.key)?;
+        let chain = format!("{}{}", cert.pem(), self.cert_pem);
+        let identity =
+            Identity::from_pkcs8(chain.as_bytes(), self.site_key.serialize_pem().as_bytes())?;
+        let acceptor = TlsAcceptor::from(NativeAcceptor::new(identity)?);
+        self.acceptors
+            .lock()
+            .put(host.to_string(), acceptor.clone());
+        Ok(acceptor)
+    }
+}
+
+fn ca_params() -> CertificateParams {
+    let mut params = CertificateParams::default();
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, "Nyan Browser Proxy CA");
+    name.push(DnType::OrganizationName, "Nyan Browser");
+    params.distinguished_name = name;
+    params.is_ca = IsCa::Ca(BasicConstraints::Constrained(0));
+    params.key_usages = vec![
+        KeyUsagePurpose::KeyCertSign,
+        KeyUsagePurpose::CrlSign,
+        KeyUsagePurpose::DigitalSignature,
+    ];
+    params
+}
+
+fn site_params(host: &str) -> anyhow::Result<CertificateParams> {
+    let mut params = CertificateParams::new(vec![host.to_string()])?;
+    let mut name = DistinguishedName::new();
+    name.push(DnType::CommonName, host);
+    params.distinguished_name = name;
+    params.use_authority_key_identifier_extension = true;
+    // The default serial comes from the key, which every site certificate
+    // shares, and browsers refuse two certificates with the same serial.
+    let serial = RandomState::new().hash_one(host);
+    params.serial_number = Some(SerialNumber::from(serial.to_be_bytes().to_vec()));
+    let day = |offset: i64| {
+        let date = (Utc::now() + Duration::days(offset)).date_naive();
+        rcgen::date_time_ymd(date.year(), date.month() as u8, date.day() as u8)
+    };
+    params.not_before = day(-1);
+    params.not_after = day(365);
+    Ok(params)
+}
+
+/// Writes `contents` to `path`, readable only by the user on Unix.
+fn write_private(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    file.write_all(contents)?;
+    file.sync_all()
+}
@@ -0,0 +1,535 @@
+//! HAR 1.2, the HTTP Archive format browsers' developer tools export and
+//! import: <http://www.softwareishard.com/blog/har-12-spec/>.
+//!
+//! Recorded bodies are kept as they came over the wire, while HAR wants
+//! them decoded, so gzip, deflate and brotli bodies are decoded on export.
+//! Bodies in any other `Content-Encoding` are left out with a comment
+//! saying why. Failed requests get status 0 and the error in `_error`, the
+//! way Chrome writes them.
+
+use super::monitor::{self, find_header, NetworkEntry, RequestData, ResponseData, StatusClass};
+use base64::Engine;
+use chrono::{DateTime, Utc};
+use hyper::StatusCode;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Read;
+use std::path::Path;
+use std::time::Duration;
+
+const VERSION: &str = "1.2";
+const HTTP_VERSION: &str = "HTTP/1.1";
+/// Most a body is decoded to, so a small bomb can't fill the memory.
+const MAX_DECODED_BYTES: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Har {
+    pub log: Log,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Log {
+    pub version: String,
+    pub creator: Creator,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub browser: Option<Creator>,
+    #[serde(default)]
+    pub pages: Vec<Page>,
+    pub entries: Vec<Entry>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Creator {
+    pub name: String,
+    pub version: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Page {
+    pub started_date_time: DateTime<Utc>,
+    pub id: String,
+    pub title: String,
+    #[serde(default)]
+    pub page_timings: PageTimings,
+}
+
+/// Milliseconds from the start of the page load, which the proxy can't see.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PageTimings {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_content_load: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_load: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Entry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub pageref: Option<String>,
+    pub started_date_time: DateTime<Utc>,
+    /// Total milliseconds, the sum of the timings.
+    pub time: f64,
+    pub request: Request,
+    pub response: Response,
+    #[serde(default)]
+    pub cache: Cache,
+    pub timings: Timings,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+    #[serde(rename = "_error", default, skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Request {
+    pub method: String,
+    pub url: String,
+    pub http_version: String,
+    #[serde(default)]
+    pub cookies: Vec<Cookie>,
+    pub headers: Vec<NameValue>,
+    #[serde(default)]
+    pub query_string: Vec<NameValue>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub post_data: Option<PostData>,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Response {
+    pub status: u16,
+    pub status_text: String,
+    pub http_version: String,
+    #[serde(default)]
+    pub cookies: Vec<Cookie>,
+    pub headers: Vec<NameValue>,
+    pub content: Content,
+    #[serde(rename = "redirectURL", default)]
+    pub redirect_url: String,
+    pub headers_size: i64,
+    pub body_size: i64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NameValue {
+    pub name: String,
+    pub value: String,
+}
+
+/// Only names and values: the other cookie attributes are optional.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Cookie {
+    pub name: String,
+    pub value: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PostData {
+    pub mime_type: String,
+    #[serde(default)]
+    pub text: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Content {
+    pub size: i64,
+    pub mime_type: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub text: Option<String>,
+    /// `base64` for binary bodies.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub encoding: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub comment: Option<String>,
+}
+
+/// Nothing is known about the browser cache from the proxy.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Cache {}
+
+/// Milliseconds per phase, -1 where it doesn't apply. `connect` includes
+/// `ssl`, as the spec says.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Timings {
+    #[serde(default = "not_applicable")]
+    pub blocked: f64,
+    #[serde(default = "not_applicable")]
+    pub dns: f64,
+    #[serde(default = "not_applicable")]
+    pub connect: f64,
+    pub send: f64,
+    pub wait: f64,
+    pub receive: f64,
+    #[serde(default = "not_applicable")]
+    pub ssl: f64,
+}
+
+fn not_applicable() -> f64 {
+    -1.0
+}
+
+impl Har {
+    /// A log of `entries`, grouped into pages by the page loads among them.
+    pub fn from_entries<'a>(entries: impl IntoIterator<Item = &'a NetworkEntry>) -> Self {
+        let entries: Vec<&NetworkEntry> = entries
+            .into_iter()
+            .filter(|entry| entry.request.method != "CONNECT")
+            .filter(|entry| entry.response.is_some() || entry.error.is_some())
+            .collect();
+        let pages = entries
+            .iter()
+            .filter(|entry| entry.page == Some(entry.id))
+            .map(|entry| Page {
+                started_date_time: entry.started,
+                id: page_id(entry.id),
+                title: entry.request.url.clone(),
+                page_timings: PageTimings::default(),
+            })
+            .collect::<Vec<_>>();
+        let entries = entries
+            .into_iter()
+            .map(|entry| {
+                let mut exported = export_entry(entry);
+                // Only point at pages that made it into the log.
+                exported.pageref = entry
+                    .page
+                    .map(page_id)
+                    .filter(|id| pages.iter().any(|page| &page.id == id));
+                exported
+            })
+            .collect();
+        Har {
+            log: Log {
+                version: VERSION.to_string(),
+                creator: Creator {
+                    name: "Nyan Browser".to_string(),
+                    version: env!("CARGO_PKG_VERSION").to_string(),
+                },
+                browser: None,
+                pages,
+                entries,
+                comment: None,
+            },
+        }
+    }
+
+    pub fn read(path: &Path) -> anyhow::Result<Self> {
+        let har: Har = serde_json::from_str(&fs::read_to_string(path)?)
+            .map_err(|e| anyhow::anyhow!("{} is not a HAR file: {}", path.display(), e))?;
+        if !har.log.version.starts_with("1.") {
+            anyhow::bail!("HAR version {} is not supported", har.log.version);
+        }
+        Ok(har)
+    }
+
+    /// The entries as the monitor records them, with IDs counting from 0 in
+    /// the order of the log. The first entry of each page is taken to be
+    /// its page load.
+    pub fn into_entries(self) -> Vec<NetworkEntry> {
+        let mut page_loads: HashMap<String, u64> = HashMap::new();
+        self.log
+            .entries
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| {
+                let id = index as u64;
+                let page = entry
+                    .pageref
+                    .as_ref()
+                    .map(|page| *page_loads.entry(page.clone()).or_insert(id));
+                import_entry(id, page, entry)
+            })
+            .collect()
+    }
+}
+
+fn page_id(id: u64) -> String {
+    format!("page_{}", id)
+}
+
+fn export_entry(entry: &NetworkEntry) -> Entry {
+    let timings = entry.timings.unwrap_or_default();
+    let request = &entry.request;
+    let content_type = find_header(&request.headers, "content-type");
+    Entry {
+        pageref: None,
+        started_date_time: entry.started,
+        time: millis(timings.total()),
+        request: Request {
+            method: request.method.clone(),
+            url: request.url.clone(),
+            http_version: HTTP_VERSION.to_string(),
+            cookies: request_cookies(request),
+            headers: name_values(&request.headers),
+            query_string: query_string(&request.url),
+            post_data: request.body.as_ref().map(|body| PostData {
+                mime_type: content_type.unwrap_or_default().to_string(),
+                text: String::from_utf8_lossy(body).into_owned(),
+            }),
+            headers_size: -1,
+            body_size: request.body.as_ref().map_or(0, |body| body.len() as i64),
+        },
+        response: match &entry.response {
+            Some(response) => export_response(response),
+            None => Response {
+                status: 0,
+                status_text: String::new(),
+                http_version: String::new(),
+                cookies: Vec::new(),
+                headers: Vec::new(),
+                content: Content {
+                    size: 0,
+                    mime_type: "x-unknown".to_string(),
+                    text: None,
+                    encoding: None,
+                    comment: None,
+                },
+                redirect_url: String::new(),
+                headers_size: -1,
+                body_size: -1,
+            },
+        },
+        cache: Cache::default(),
+        timings: Timings {
+            blocked: -1.0,
+            dns: timings.dns.map_or(-1.0, millis),
+            connect: match (timings.connect, timings.tls) {
+                (Some(connect), tls) => millis(connect + tls.unwrap_or_default()),
+                (None, _) => -1.0,
+            },
+            // Sending is part of waiting for the first byte.
+            send: 0.0,
+            wait: millis(timings.ttfb),
+            receive: millis(timings.download),
+            ssl: timings.tls.map_or(-1.0, millis),
+        },
+        comment: None,
+        error: entry.error.clone(),
+    }
+}
+
+fn export_response(response: &ResponseData) -> Response {
+    let encoding = find_header(&response.headers, "content-encoding");
+    let decoded = response.body.as_ref().map(|body| match encoding {
+        Some(encoding) => decode(body, encoding, response.truncated).ok_or(encoding),
+        None => Ok(body.clone()),
+    });
+    let (text, body_encoding, comment) = match &decoded {
+        None => (None, None, None),
+        Some(Err(encoding)) => (
+            None,
+            None,
+            Some(format!(
+                "Body not decoded from Content-Encoding: {}",
+                encoding
+            )),
+        ),
+        Some(Ok(body)) => {
+            let truncated = response.truncated.then(|| {
+                format!(
+                    "Body cut short at {} bytes",
+                    response.body.as_ref().map_or(0, Vec::len)
+                )
+            });
+            match String::from_utf8(body.clone()) {
+                Ok(text) => (Some(text), None, truncated),
+                Err(_) => (
+                    Some(base64::engine::general_purpose::STANDARD.encode(body)),
+                    Some("base64".to_string()),
+                    truncated,
+                ),
+            }
+        }
+    };
+    // HAR counts the decoded size, which is only known for whole bodies.
+    let size = match &decoded {
+        Some(Ok(body)) if encoding.is_some() && !response.truncated => body.len() as i64,
+        _ => response.body_size as i64,
+    };
+    let redirect_url = match StatusClass::of(response.status) {
+        Some(StatusClass::Redirection) => find_header(&response.headers, "location"),
+        _ => None,
+    };
+    Response {
+        status: response.status,
+        status_text: StatusCode::from_u16(response.status)
+            .ok()
+            .and_then(|status| status.canonical_reason())
+            .unwrap_or_default()
+            .to_string(),
+        http_version: HTTP_VERSION.to_string(),
+        cookies: response_cookies(&response.headers),
+        headers: name_values(&response.headers),
+        content: Content {
+            size,
+            mime_type: response.content_type.clone().unwrap_or_default(),
+            text,
+            encoding: body_encoding,
+            comment,
+        },
+        redirect_url: redirect_url.unwrap_or_default().to_string(),
+        headers_size: -1,
+        body_size: response.body_size as i64,
+    }
+}
+
+fn import_entry(id: u64, page: Option<u64>, entry: Entry) -> NetworkEntry {
+    let failed = entry.response.status == 0;
+    let timings = &entry.timings;
+    let tls = duration(timings.ssl);
+    let timings = monitor::Timings {
+        dns: duration(timings.dns),
+        connect: duration(timings.connect)
+            .map(|connect| connect.saturating_sub(tls.unwrap_or_default())),
+        tls,
+        ttfb: duration(timings.blocked.max(0.0) + timings.send.max(0.0) + timings.wait.max(0.0))
+            .unwrap_or_default(),
+        download: duration(timings.receive.max(0.0)).unwrap_or_default(),
+    };
+    let request = RequestData {
+        url: entry.request.url,
+        method: entry.request.method,
+        headers: pairs(entry.request.headers),
+        body: entry.request.post_data.map(|data| data.text.into_bytes()),
+    };
+    let response = (!failed).then(|| {
+        let content = entry.response.content;
+        let mut response = ResponseData::new(entry.response.status, pairs(entry.response.headers));
+        if !content.mime_type.is_empty() {
+            response.content_type = Some(content.mime_type);
+        }
+        let body = match (content.text, content.encoding.as_deref()) {
+            (Some(text), Some("base64")) => base64::engine::general_purpose::STANDARD
+                .decode(text.trim())
+                .ok(),
+            (Some(text), _) => Some(text.into_bytes()),
+            (None, _) => None,
+        };
+        let received = body.as_ref().map_or(0, Vec::len) as u64;
+        response.body_size = u64::try_from(content.size)
+            .unwrap_or(received)
+            .max(received);
+        response.truncated = body.is_some() && received < response.body_size;
+        response.body = body.filter(|body| !body.is_empty());
+        response
+    });
+    NetworkEntry {
+        id,
+        started: entry.started_date_time,
+        page,
+        request,
+        error: entry
+            .error
+            .or_else(|| failed.then(|| "No response".to_string())),
+        timings: (!failed).then_some(timings),
+        response,
+    }
+}
+
+/// `body` with the codings in `encoding` undone, last applied first. A
+/// `truncated` body decodes to as much as arrived. `None` for codings we
+/// don't know and bodies that don't decode.
+fn decode(body: &[u8], encoding: &str, truncated: bool) -> Option<Vec<u8>> {
+    let mut body = body.to_vec();
+    for coding in encoding.rsplit(',').map(str::trim) {
+        let reader: Box<dyn Read + '_> = match coding.to_ascii_lowercase().as_str() {
+            "" | "identity" => continue,
+            "gzip" | "x-gzip" => Box::new(flate2::read::MultiGzDecoder::new(&body[..])),
+            // Meant to be zlib-wrapped, but some servers send it raw.
+            "deflate" if body.first().is_some_and(|b| b & 0x0f == 8) => {
+                Box::new(flate2::read::ZlibDecoder::new(&body[..]))
+            }
+            "deflate" => Box::new(flate2::read::DeflateDecoder::new(&body[..])),
+            "br" => Box::new(brotli_decompressor::Decompressor::new(&body[..], 4096)),
+            _ => return None,
+        };
+        let mut decoded = Vec::new();
+        match reader.take(MAX_DECODED_BYTES).read_to_end(&mut decoded) {
+            Ok(_) => {}
+            // What was read before the body ran out is kept.
+            Err(_) if truncated => {}
+            Err(_) => return None,
+        }
+        body = decoded;
+    }
+    Some(body)
+}
+
+fn millis(duration: Duration) -> f64 {
+    duration.as_secs_f64() * 1000.0
+}
+
+/// `None` for -1 and for anything else no [`Duration`] can hold, as HAR
+/// files come from anywhere.
+fn duration(millis: f64) -> Option<Duration> {
+    Duration::try_from_secs_f64(millis / 1000.0).ok()
+}
+
+fn name_values(headers: &[(String, String)]) -> Vec<NameValue> {
+    headers
+        .iter()
+        .map(|(name, value)| NameValue {
+            name: name.clone(),
+            value: value.clone(),
+        })
+        .collect()
+}
+
+fn pairs(headers: Vec<NameValue>) -> Vec<(String, String)> {
+    headers
+        .into_iter()
+        .map(|header| (header.name, header.value))
+        .collect()
+}
+
+fn query_string(url: &str) -> Vec<NameValue> {
+    url::Url::parse(url)
+        .map(|url| {
+            url.query_pairs()
+                .map(|(name, value)| NameValue {
+                    name: name.into_owned(),
+                    value: value.into_owned(),
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+fn request_cookies(request: &RequestData) -> Vec<Cookie> {
+    request
+        .headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("cookie"))
+        .flat_map(|(_, value)| value.split(';'))
+        .filter_map(cookie)
+        .collect()
+}
+
+/// Each `Set-Cookie` sets one cookie, before the first `;`.
+fn response_cookies(headers: &[(String, String)]) -> Vec<Cookie> {
+    headers
+        .iter()
+        .filter(|(name, _)| name.eq_ignore_ascii_case("set-cookie"))
+        .filter_map(|(_, value)| cookie(value.split(';').next().unwrap_or_default()))
+        .collect()
+}
+
+fn cookie(pair: &str) -> Option<Cookie> {
+    let (name, value) = pair.trim().split_once('=')?;
+    Some(Cookie {
+        name: name.trim().to_string(),
+        value: value.trim().to_string(),
+    })
+}
@@ -1,5 +1,17 @@
+pub mod ca;
+pub mod har;
 pub mod monitor;
+pub mod pattern;
+pub mod proxy;
 pub mod tools;
+pub mod upstream;
 
-pub use monitor::NetworkMonitor;
-pub use tools::NetworkTools;
+pub use ca::CertificateAuthority;
+pub use har::Har;
+pub use monitor::{
+    NetworkEntry, NetworkMonitor, NetworkQuery, RequestData, RequestId, ResponseData, StatusClass,
+    Timings,
+};
+pub use pattern::UrlPattern;
+pub use proxy::{HttpsInterception, InterceptingProxy};
+pub use tools::{HeaderDirection, HeaderModifications, MockResponse, NetworkTools};
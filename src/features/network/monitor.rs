@@ -1,13 +1,28 @@
+use super::har::Har;
+use super::pattern::UrlPattern;
+use crate::utils::write_atomic;
+use chrono::{DateTime, Utc};
 use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
 use std::collections::VecDeque;
+use std::path::Path;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use tracing::info;
+use std::time::Duration;
+use tracing::debug;
+
+/// Response bodies are kept up to this size unless told otherwise.
+const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+
+/// Identifies a recorded request, to attach its response to later.
+pub type RequestId = u64;
 
 pub struct NetworkMonitor {
-    requests: Arc<RwLock<VecDeque<RequestData>>>,
+    requests: Arc<RwLock<VecDeque<NetworkEntry>>>,
     filters: Arc<RwLock<Vec<RequestFilter>>>,
     max_requests: usize,
+    max_body_bytes: usize,
+    next_id: AtomicU64,
 }
 
 impl NetworkMonitor {
@@ -16,22 +31,163 @@ impl NetworkMonitor {
             requests: Arc::new(RwLock::new(VecDeque::with_capacity(100))),
             filters: Arc::new(RwLock::new(Vec::with_capacity(10))),
             max_requests: 1000,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            next_id: AtomicU64::new(1),
         }
     }
 
+    /// Keeps at most `max` bytes of each request and response body.
+    pub fn with_max_body_bytes(mut self, max: usize) -> Self {
+        self.max_body_bytes = max;
+        self
+    }
+
+    pub fn max_body_bytes(&self) -> usize {
+        self.max_body_bytes
+    }
+
+    /// Records `request`, if it matches one of the filters. Without any
+    /// filters every request is recorded.
     pub async fn intercept_request(&self, request: RequestData) -> anyhow::Result<()> {
+        self.record_request(request);
+        Ok(())
+    }
+
+    /// Like [`intercept_request`](Self::intercept_request), returning the
+    /// ID to record the response under, or `None` if the filters left the
+    /// request out.
+    pub fn record_request(&self, request: RequestData) -> Option<RequestId> {
         let filters = self.filters.read();
-        if filters.iter().any(|f| f.matches(&request)) {
-            let mut requests = self.requests.write();
-            if requests.len() >= self.max_requests {
-                requests.pop_front();
-            }
-            requests.push_back(request);
-            info!("Request intercepted");
+        if !filters.is_empty() && !filters.iter().any(|f| f.matches(&request)) {
+            return None;
+        }
+        // Numbered under the lock, so entries stay sorted by ID.
+        let mut requests = self.requests.write();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let page = if request.is_document() {
+            Some(id)
+        } else {
+            page_of(&requests, &request)
+        };
+        debug!("Request intercepted: {} {}", request.method, request.url);
+        self.push(
+            &mut requests,
+            NetworkEntry {
+                id,
+                started: Utc::now(),
+                page,
+                request,
+                response: None,
+                timings: None,
+                error: None,
+            },
+        );
+        Some(id)
+    }
+
+    fn push(&self, requests: &mut VecDeque<NetworkEntry>, entry: NetworkEntry) {
+        if requests.len() >= self.max_requests {
+            requests.pop_front();
+        }
+        requests.push_back(entry);
+    }
+
+    /// Attaches the response to the request recorded as `id`.
+    pub fn record_response(&self, id: RequestId, response: ResponseData, timings: Timings) {
+        self.update(id, |entry| {
+            entry.response = Some(response);
+            entry.timings = Some(timings);
+        });
+    }
+
+    /// Notes that the request recorded as `id` got no response.
+    pub fn record_failure(&self, id: RequestId, error: String) {
+        self.update(id, |entry| entry.error = Some(error));
+    }
+
+    fn update(&self, id: RequestId, change: impl FnOnce(&mut NetworkEntry)) {
+        let mut requests = self.requests.write();
+        // IDs only grow, so the entries are sorted by them.
+        let index = requests.partition_point(|entry| entry.id < id);
+        if let Some(entry) = requests.get_mut(index).filter(|entry| entry.id == id) {
+            change(entry);
         }
+    }
+
+    /// Only records requests matching `filter`, or any other filter added.
+    pub fn add_filter(&self, filter: RequestFilter) {
+        self.filters.write().push(filter);
+    }
+
+    /// Goes back to recording every request.
+    pub fn clear_filters(&self) {
+        self.filters.write().clear();
+    }
+
+    pub fn entry(&self, id: RequestId) -> Option<NetworkEntry> {
+        let requests = self.requests.read();
+        let index = requests.partition_point(|entry| entry.id < id);
+        requests.get(index).filter(|entry| entry.id == id).cloned()
+    }
+
+    /// Recorded requests matching `query`, newest first.
+    pub fn query(&self, query: &NetworkQuery) -> Vec<NetworkEntry> {
+        self.requests
+            .read()
+            .iter()
+            .rev()
+            .filter(|entry| query.matches(entry))
+            .take(query.limit.unwrap_or(usize::MAX))
+            .cloned()
+            .collect()
+    }
+
+    /// The most recent `limit` recorded requests, newest first.
+    pub fn recent_requests(&self, limit: usize) -> Vec<RequestData> {
+        self.requests
+            .read()
+            .iter()
+            .rev()
+            .take(limit)
+            .map(|entry| entry.request.clone())
+            .collect()
+    }
+
+    /// Everything recorded so far as a HAR log. Requests still waiting for
+    /// their response and `CONNECT` tunnels are left out.
+    pub fn to_har(&self) -> Har {
+        Har::from_entries(self.requests.read().iter())
+    }
+
+    /// Writes [`to_har`](Self::to_har) to `path`.
+    pub fn export_har(&self, path: &Path) -> anyhow::Result<()> {
+        let json = serde_json::to_string_pretty(&self.to_har())?;
+        write_atomic(path, json.as_bytes())?;
         Ok(())
     }
 
+    /// Adds the entries of the HAR file at `path` as if they had been
+    /// recorded, ignoring the filters. Returns how many were added.
+    pub fn import_har(&self, path: &Path) -> anyhow::Result<usize> {
+        let har = Har::read(path)?;
+        Ok(self.load_har(har))
+    }
+
+    /// Like [`import_har`](Self::import_har), for a HAR log already read.
+    pub fn load_har(&self, har: Har) -> usize {
+        let entries = har.into_entries();
+        let count = entries.len();
+        let mut requests = self.requests.write();
+        // The log numbers its entries from 0, which become consecutive IDs.
+        let base = self.next_id.fetch_add(count as u64, Ordering::Relaxed);
+        for mut entry in entries {
+            entry.id += base;
+            entry.page = entry.page.map(|page| page + base);
+            self.push(&mut requests, entry);
+        }
+        count
+    }
+
     pub async fn clear_old_requests(&self) -> anyhow::Result<()> {
         let mut requests = self.requests.write();
         requests.clear();
@@ -39,7 +195,13 @@ impl NetworkMonitor {
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Default for NetworkMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RequestData {
     pub url: String,
     pub method: String,
@@ -47,6 +209,192 @@ pub struct RequestData {
     pub body: Option<Vec<u8>>,
 }
 
+impl RequestData {
+    /// The first header called `name`, ignoring case.
+    pub fn header(&self, name: &str) -> Option<&str> {
+        find_header(&self.headers, name)
+    }
+
+    /// Whether this loads a page into a tab, as opposed to something on one.
+    pub fn is_document(&self) -> bool {
+        self.header("sec-fetch-dest") == Some("document")
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ResponseData {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub content_type: Option<String>,
+    /// The body as received, still compressed if `Content-Encoding` says
+    /// so, and cut short at the monitor's size cap.
+    pub body: Option<Vec<u8>>,
+    /// Size of the whole body in bytes.
+    pub body_size: u64,
+    /// Whether `body` was cut short.
+    pub truncated: bool,
+}
+
+impl ResponseData {
+    /// A response with `status` and `headers` and no body yet.
+    pub fn new(status: u16, headers: Vec<(String, String)>) -> Self {
+        let content_type = find_header(&headers, "content-type").map(str::to_string);
+        Self {
+            status,
+            headers,
+            content_type,
+            body: None,
+            body_size: 0,
+            truncated: false,
+        }
+    }
+}
+
+/// How long each phase of a request took. The connection phases are
+/// `None` when an already open connection was reused.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Timings {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    /// `None` for plain HTTP too.
+    pub tls: Option<Duration>,
+    /// From sending the request to the response headers.
+    pub ttfb: Duration,
+    /// Receiving the response body.
+    pub download: Duration,
+}
+
+impl Timings {
+    pub fn total(&self) -> Duration {
+        [self.dns, self.connect, self.tls]
+            .into_iter()
+            .flatten()
+            .chain([self.ttfb, self.download])
+            .fold(Duration::ZERO, Duration::saturating_add)
+    }
+}
+
+/// A recorded request and, once it has come back, its response.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NetworkEntry {
+    pub id: RequestId,
+    pub started: DateTime<Utc>,
+    /// The page the request was made for, as the ID of the request that
+    /// loaded it. Page loads point at themselves.
+    pub page: Option<RequestId>,
+    pub request: RequestData,
+    pub response: Option<ResponseData>,
+    pub timings: Option<Timings>,
+    /// Why there is no response, if the request failed.
+    pub error: Option<String>,
+}
+
+/// The first digit of a status code, or a failed request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StatusClass {
+    Informational,
+    Success,
+    Redirection,
+    ClientError,
+    ServerError,
+    /// No response at all.
+    Failed,
+}
+
+impl StatusClass {
+    pub fn of(status: u16) -> Option<Self> {
+        Some(match status {
+            100..=199 => StatusClass::Informational,
+            200..=299 => StatusClass::Success,
+            300..=399 => StatusClass::Redirection,
+            400..=499 => StatusClass::ClientError,
+            500..=599 => StatusClass::ServerError,
+            _ => return None,
+        })
+    }
+
+    /// Parses `2xx`-style classes, and `failed`.
+    pub fn parse(class: &str) -> Option<Self> {
+        Some(match class.to_ascii_lowercase().as_str() {
+            "1xx" => StatusClass::Informational,
+            "2xx" => StatusClass::Success,
+            "3xx" => StatusClass::Redirection,
+            "4xx" => StatusClass::ClientError,
+            "5xx" => StatusClass::ServerError,
+            "failed" => StatusClass::Failed,
+            _ => return None,
+        })
+    }
+
+    fn matches(self, entry: &NetworkEntry) -> bool {
+        match &entry.response {
+            Some(response) => StatusClass::of(response.status) == Some(self),
+            None => self == StatusClass::Failed && entry.error.is_some(),
+        }
+    }
+}
+
+/// Which recorded requests [`NetworkMonitor::query`] returns. Every field
+/// left `None` matches everything.
+#[derive(Debug, Clone, Default)]
+pub struct NetworkQuery {
+    pub url: Option<UrlPattern>,
+    pub method: Option<String>,
+    pub status: Option<StatusClass>,
+    /// Only requests started at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only requests started before this time.
+    pub until: Option<DateTime<Utc>>,
+    pub limit: Option<usize>,
+}
+
+impl NetworkQuery {
+    pub fn url(pattern: &str) -> anyhow::Result<Self> {
+        Ok(Self {
+            url: Some(UrlPattern::parse(pattern)?),
+            ..Self::default()
+        })
+    }
+
+    fn matches(&self, entry: &NetworkEntry) -> bool {
+        self.url
+            .as_ref()
+            .is_none_or(|url| url.matches(&entry.request.url))
+            && self
+                .method
+                .as_ref()
+                .is_none_or(|method| method.eq_ignore_ascii_case(&entry.request.method))
+            && self.status.is_none_or(|status| status.matches(entry))
+            && self.since.is_none_or(|since| entry.started >= since)
+            && self.until.is_none_or(|until| entry.started < until)
+    }
+}
+
+/// The latest page load the `Referer` of `request` points to. Referrers
+/// are often cut down to the origin, so any page under it will do.
+fn page_of(requests: &VecDeque<NetworkEntry>, request: &RequestData) -> Option<RequestId> {
+    let referer = without_fragment(request.header("referer")?);
+    requests
+        .iter()
+        .rev()
+        .find(|entry| {
+            entry.page == Some(entry.id)
+                && without_fragment(&entry.request.url).starts_with(referer)
+        })
+        .map(|entry| entry.id)
+}
+
+fn without_fragment(url: &str) -> &str {
+    url.split('#').next().unwrap_or(url)
+}
+
+pub(crate) fn find_header<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(header, _)| header.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value.as_str())
+}
+
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RequestFilter {
     pub url_pattern: String,
@@ -0,0 +1,93 @@
+//! URL patterns for picking out requests.
+
+use regex::Regex;
+use std::fmt;
+use std::str::FromStr;
+
+/// Matches URLs one of three ways, depending on how it is written:
+///
+/// - `/pattern/` is a regular expression, searched for anywhere in the URL.
+/// - Anything with a `*` is a glob over the whole URL, `*` matching any run
+///   of characters: `https://api.example.com/v1/*`.
+/// - Anything else matches URLs containing it.
+#[derive(Debug, Clone)]
+pub enum UrlPattern {
+    Contains(String),
+    Glob(String),
+    Regex(Regex),
+}
+
+impl UrlPattern {
+    pub fn parse(pattern: &str) -> anyhow::Result<Self> {
+        if let Some(regex) = pattern
+            .strip_prefix('/')
+            .and_then(|rest| rest.strip_suffix('/'))
+            .filter(|regex| !regex.is_empty())
+        {
+            return Ok(UrlPattern::Regex(Regex::new(regex)?));
+        }
+        Ok(if pattern.contains('*') {
+            UrlPattern::Glob(pattern.to_string())
+        } else {
+            UrlPattern::Contains(pattern.to_string())
+        })
+    }
+
+    pub fn matches(&self, url: &str) -> bool {
+        match self {
+            UrlPattern::Contains(text) => url.contains(text.as_str()),
+            UrlPattern::Glob(glob) => glob_matches(glob, url),
+            UrlPattern::Regex(regex) => regex.is_match(url),
+        }
+    }
+
+    /// Whether the pattern could match a URL on `origin`, such as
+    /// `https://example.com`. Only globs are narrowed down, by what comes
+    /// before their first `*`; other patterns are taken to match anywhere.
+    pub fn may_match_origin(&self, origin: &str) -> bool {
+        let UrlPattern::Glob(glob) = self else {
+            return true;
+        };
+        let literal = glob.split('*').next().unwrap_or_default();
+        let root = format!("{}/", origin);
+        root.starts_with(literal) || literal.starts_with(&root)
+    }
+}
+
+impl FromStr for UrlPattern {
+    type Err = anyhow::Error;
+
+    fn from_str(pattern: &str) -> anyhow::Result<Self> {
+        Self::parse(pattern)
+    }
+}
+
+impl fmt::Display for UrlPattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UrlPattern::Contains(text) | UrlPattern::Glob(text) => f.write_str(text),
+            UrlPattern::Regex(regex) => write!(f, "/{}/", regex.as_str()),
+        }
+    }
+}
+
+/// Whether `text` as a whole matches `glob`, where `*` matches anything.
+fn glob_matches(glob: &str, text: &str) -> bool {
+    let mut parts = glob.split('*');
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = text.strip_prefix(first) else {
+        return false;
+    };
+    let mut parts: Vec<_> = parts.collect();
+    let Some(last) = parts.pop() else {
+        // No `*` at all.
+        return rest.is_empty();
+    };
+    for part in parts {
+        match rest.find(part) {
+            Some(at) => rest = &rest[at + part.len()..],
+            None => return false,
+        }
+    }
+    rest.len() >= last.len() && rest.ends_with(last)
+}
@@ -0,0 +1,537 @@
+//! An in-process HTTP proxy the browser is pointed at, so the network
+//! monitor and the ad blocker see the requests pages really make.
+//!
+//! Requests and their responses are recorded in full, timings included.
+//! Given a [`CertificateAuthority`], the proxy ends the TLS of HTTPS
+//! `CONNECT` tunnels itself, with a certificate for the site signed by it,
+//! and handles the requests inside like plain HTTP ones. Depending on the
+//! [`HttpsInterception`], that is done for every site or only for those the
+//! network tools have rules for. Other tunnels are passed through blind:
+//! only their host and port are recorded, and hosts the ad blocker blocks
+//! are refused.
+
+use super::ca::CertificateAuthority;
+use super::monitor::{NetworkMonitor, RequestData, RequestId, ResponseData, Timings};
+use super::tools::{HeaderDirection, NetworkTools};
+use super::upstream::{self, Connection, Upstream};
+use crate::features::adblock::{AdBlocker, ResourceType};
+use colored::*;
+use hyper::body::HttpBody;
+use hyper::header::{HeaderMap, CONNECTION, REFERER};
+use hyper::server::conn::Http;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode, Uri};
+use log::{debug, error, info, warn};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::task::JoinHandle;
+
+/// Headers that only apply to one connection and must not be forwarded.
+const HOP_BY_HOP: &[&str] = &[
+    "connection",
+    "keep-alive",
+    "proxy-authenticate",
+    "proxy-authorization",
+    "proxy-connection",
+    "te",
+    "trailer",
+    "transfer-encoding",
+    "upgrade",
+];
+
+/// Which HTTPS tunnels the proxy looks into.
+#[derive(Clone, Default)]
+pub enum HttpsInterception {
+    /// None of them.
+    #[default]
+    Off,
+    /// Those to sites a mock or header rule could apply to.
+    RuledHosts(Arc<CertificateAuthority>),
+    /// All of them.
+    All(Arc<CertificateAuthority>),
+}
+
+pub struct InterceptingProxy {
+    addr: SocketAddr,
+    intercepts_https: bool,
+    task: JoinHandle<()>,
+}
+
+#[derive(Clone)]
+struct ProxyState {
+    upstream: Arc<Upstream>,
+    monitor: Arc<NetworkMonitor>,
+    ad_blocker: Option<Arc<AdBlocker>>,
+    tools: Arc<NetworkTools>,
+    https: HttpsInterception,
+}
+
+impl InterceptingProxy {
+    /// Starts the proxy on `addr` (port 0 picks a free one). Requests are
+    /// recorded in `monitor`, those `ad_blocker` blocks are answered with
+    /// 403 instead of being forwarded, and the mocks in `tools` are served
+    /// ahead of both. Headers are rewritten by the rules in `tools`. HTTPS
+    /// is looked into as `https` says.
+    pub async fn start(
+        addr: SocketAddr,
+        monitor: Arc<NetworkMonitor>,
+        ad_blocker: Option<Arc<AdBlocker>>,
+        tools: Arc<NetworkTools>,
+        https: HttpsInterception,
+    ) -> anyhow::Result<Self> {
+        let intercepts_https = !matches!(https, HttpsInterception::Off);
+        let state = ProxyState {
+            upstream: Arc::new(Upstream::new()?),
+            monitor,
+            ad_blocker,
+            tools,
+            https,
+        };
+        let make = make_service_fn(move |_| {
+            let state = state.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(state.handle(request).await) }
+                }))
+            }
+        });
+        let server = Server::try_bind(&addr)?
+            .http1_preserve_header_case(true)
+            .serve(make);
+        let addr = server.local_addr();
+        let task = tokio::spawn(async move {
+            if let Err(e) = server.await {
+                error!("Network proxy stopped: {}", e);
+            }
+        });
+        info!(
+            "{}",
+            format!("Network proxy listening on {} (=^･ω･^=)", addr).cyan()
+        );
+        Ok(Self {
+            addr,
+            intercepts_https,
+            task,
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// Whether any HTTPS is looked into rather than tunnelled blind, and so
+    /// whether browsers meet certificates signed by the proxy.
+    pub fn intercepts_https(&self) -> bool {
+        self.intercepts_https
+    }
+}
+
+impl Drop for InterceptingProxy {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+impl ProxyState {
+    async fn handle(&self, request: Request<Body>) -> Response<Body> {
+        let result = if request.method() == Method::CONNECT {
+            self.tunnel(request).await
+        } else {
+            self.forward(request).await
+        };
+        result.unwrap_or_else(|e| {
+            warn!("Proxy request failed: {}", e);
+            text_response(StatusCode::BAD_GATEWAY, &e.to_string())
+        })
+    }
+
+    async fn forward(&self, request: Request<Body>) -> anyhow::Result<Response<Body>> {
+        if request.uri().scheme().is_none() {
+            return Ok(text_response(
+                StatusCode::BAD_REQUEST,
+                "This is a proxy, requests need an absolute URL",
+            ));
+        }
+        let (mut parts, body) = request.into_parts();
+        let (captured, body) = capture_start(body, self.monitor.max_body_bytes()).await?;
+        let url = parts.uri.to_string();
+        // Recorded as rewritten, which is what servers get.
+        self.tools
+            .rewrite_headers(HeaderDirection::Request, &url, &mut parts.headers);
+        let id = self.monitor.record_request(RequestData {
+            url: url.clone(),
+            method: parts.method.to_string(),
+            headers: header_pairs(&parts.headers),
+            body: (!captured.is_empty()).then_some(captured),
+        });
+
+        if let Some(mock) = self.tools.find_mock(parts.method.as_str(), &url) {
+            debug!("Proxy mocked {}", url);
+            let start = Instant::now();
+            if let Some(delay) = mock.delay() {
+                tokio::time::sleep(delay).await;
+            }
+            let mut response = mock.response();
+            self.tools
+                .rewrite_headers(HeaderDirection::Response, &url, response.headers_mut());
+            if let Some(id) = id {
+                let mut mocked =
+                    ResponseData::new(response.status().as_u16(), header_pairs(response.headers()));
+                let body = mock.body();
+                let kept = body.len().min(self.monitor.max_body_bytes());
+                mocked.body_size = body.len() as u64;
+                mocked.truncated = kept < body.len();
+                mocked.body = (!body.is_empty()).then(|| body[..kept].to_vec());
+                let timings = Timings {
+                    ttfb: start.elapsed(),
+                    ..Timings::default()
+                };
+                self.monitor.record_response(id, mocked, timings);
+            }
+            return Ok(response);
+        }
+
+        if let Some(ad_blocker) = &self.ad_blocker {
+            let resource_type = parts
+                .headers
+                .get("sec-fetch-dest")
+                .and_then(|dest| dest.to_str().ok())
+                .map_or(ResourceType::Other, ResourceType::from_fetch_dest);
+            // The referrer of a navigation is the page navigated away from,
+            // not the one making the request.
+            let source = match resource_type {
+                ResourceType::Document => None,
+                _ => parts.headers.get(REFERER).and_then(|r| r.to_str().ok()),
+            };
+            if ad_blocker.should_block(&url, source, resource_type) {
+                debug!("Proxy blocked {}", url);
+                let response = text_response(StatusCode::FORBIDDEN, "Blocked by Nyan Browser");
+                if let Some(id) = id {
+                    let blocked = ResponseData::new(
+                        response.status().as_u16(),
+                        header_pairs(response.headers()),
+                    );
+                    self.monitor
+                        .record_response(id, blocked, Timings::default());
+                }
+                return Ok(response);
+            }
+        }
+
+        strip_hop_by_hop(&mut parts.headers);
+        let sent = self.upstream.send(Request::from_parts(parts, body)).await;
+        let (response, connection, timings) = match sent {
+            Ok(sent) => sent,
+            Err(e) => {
+                if let Some(id) = id {
+                    self.monitor.record_failure(id, e.to_string());
+                }
+                return Err(e);
+            }
+        };
+        let (mut parts, body) = response.into_parts();
+        strip_hop_by_hop(&mut parts.headers);
+        self.tools
+            .rewrite_headers(HeaderDirection::Response, &url, &mut parts.headers);
+        let recorded = id.map(|id| {
+            let response = ResponseData::new(parts.status.as_u16(), header_pairs(&parts.headers));
+            (id, response, timings)
+        });
+        Ok(Response::from_parts(
+            parts,
+            self.relay(body, connection, recorded),
+        ))
+    }
+
+    /// Passes `body` on to the browser, keeping a copy of it up to the size
+    /// cap for the monitor. The connection goes back to the pool once the
+    /// whole body has been read.
+    fn relay(
+        &self,
+        mut body: Body,
+        connection: Connection,
+        recorded: Option<(RequestId, ResponseData, Timings)>,
+    ) -> Body {
+        let (mut sender, relayed) = Body::channel();
+        let monitor = Arc::clone(&self.monitor);
+        let upstream = Arc::clone(&self.upstream);
+        let max_body = monitor.max_body_bytes();
+        tokio::spawn(async move {
+            let start = Instant::now();
+            let mut captured = Vec::new();
+            let mut size = 0u64;
+            let mut error = None;
+            while let Some(chunk) = body.data().await {
+                let chunk = match chunk {
+                    Ok(chunk) => chunk,
+                    Err(e) => {
+                        sender.abort();
+                        error = Some(e.to_string());
+                        break;
+                    }
+                };
+                size += chunk.len() as u64;
+                if recorded.is_some() && captured.len() < max_body {
+                    let room = max_body - captured.len();
+                    captured.extend_from_slice(&chunk[..chunk.len().min(room)]);
+                }
+                if sender.send_data(chunk).await.is_err() {
+                    error = Some("The browser went away".to_string());
+                    break;
+                }
+            }
+            if error.is_none() {
+                upstream.release(connection);
+            }
+            let Some((id, mut response, mut timings)) = recorded else {
+                return;
+            };
+            if let Some(error) = error {
+                monitor.record_failure(id, error);
+                return;
+            }
+            timings.download = start.elapsed();
+            response.truncated = size > captured.len() as u64;
+            response.body_size = size;
+            response.body = (size > 0).then_some(captured);
+            monitor.record_response(id, response, timings);
+        });
+        relayed
+    }
+
+    /// Answers `CONNECT host:port`, then either looks into the tunnel or
+    /// shovels bytes both ways.
+    async fn tunnel(&self, request: Request<Body>) -> anyhow::Result<Response<Body>> {
+        let Some(authority) = request.uri().authority().map(|a| a.to_string()) else {
+            return Ok(text_response(
+                StatusCode::BAD_REQUEST,
+                "CONNECT needs a host:port",
+            ));
+        };
+        let (host, port) = match request.uri().port_u16() {
+            Some(port) => (request.uri().host().unwrap_or_default(), port),
+            None => (authority.as_str(), 443),
+        };
+        let host = host
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let origin = format!(
+            "https://{}",
+            authority.strip_suffix(":443").unwrap_or(&authority)
+        );
+        let ca = match &self.https {
+            HttpsInterception::All(ca) => Some(ca),
+            HttpsInterception::RuledHosts(ca) if self.tools.has_rules_for(&origin) => Some(ca),
+            _ => None,
+        };
+        if let Some(ca) = ca {
+            return Ok(self.intercept(request, Arc::clone(ca), host, origin));
+        }
+
+        let id = self.monitor.record_request(RequestData {
+            url: authority.clone(),
+            method: Method::CONNECT.to_string(),
+            headers: header_pairs(request.headers()),
+            body: None,
+        });
+        // Only the host is known, so that is all there is to block on.
+        if let Some(ad_blocker) = &self.ad_blocker {
+            if ad_blocker.should_block(&format!("{}/", origin), None, ResourceType::Other) {
+                debug!("Proxy blocked a tunnel to {}", authority);
+                let response = text_response(StatusCode::FORBIDDEN, "Blocked by Nyan Browser");
+                if let Some(id) = id {
+                    let blocked = ResponseData::new(
+                        response.status().as_u16(),
+                        header_pairs(response.headers()),
+                    );
+                    self.monitor
+                        .record_response(id, blocked, Timings::default());
+                }
+                return Ok(response);
+            }
+        }
+
+        let mut timings = Timings::default();
+        let mut upstream = match upstream::connect(&host, port, &mut timings).await {
+            Ok(upstream) => upstream,
+            Err(e) => {
+                if let Some(id) = id {
+                    self.monitor.record_failure(id, e.to_string());
+                }
+                return Err(e);
+            }
+        };
+        if let Some(id) = id {
+            self.monitor
+                .record_response(id, ResponseData::new(200, Vec::new()), timings);
+        }
+        tokio::spawn(async move {
+            match hyper::upgrade::on(request).await {
+                Ok(mut client) => {
+                    if let Err(e) = tokio::io::copy_bidirectional(&mut client, &mut upstream).await
+                    {
+                        debug!("Tunnel to {} closed: {}", authority, e);
+                    }
+                }
+                Err(e) => warn!("Could not open a tunnel to {}: {}", authority, e),
+            }
+        });
+        Ok(Response::new(Body::empty()))
+    }
+
+    /// Plays the server at the other end of the tunnel: TLS ends here, with
+    /// a certificate for `host` from `ca`, and the requests inside go
+    /// through [`forward`](Self::forward) as if they had been sent to
+    /// `origin` in plain HTTP.
+    fn intercept(
+        &self,
+        request: Request<Body>,
+        ca: Arc<CertificateAuthority>,
+        host: String,
+        origin: String,
+    ) -> Response<Body> {
+        let state = self.clone();
+        tokio::spawn(async move {
+            let client = match hyper::upgrade::on(request).await {
+                Ok(client) => client,
+                Err(e) => {
+                    warn!("Could not open a tunnel to {}: {}", origin, e);
+                    return;
+                }
+            };
+            let tls = match ca.acceptor(&host) {
+                Ok(acceptor) => acceptor.accept(client).await,
+                Err(e) => {
+                    error!("Could not make a certificate for {}: {}", host, e);
+                    return;
+                }
+            };
+            let tls = match tls {
+                Ok(tls) => tls,
+                Err(e) => {
+                    debug!(
+                        "The browser turned down our certificate for {}: {}",
+                        host, e
+                    );
+                    return;
+                }
+            };
+            let service = service_fn(move |mut request: Request<Body>| {
+                let state = state.clone();
+                // Requests in the tunnel only carry the path.
+                let path = request.uri().path_and_query().map_or("/", |p| p.as_str());
+                let uri = format!("{}{}", origin, path).parse::<Uri>();
+                async move {
+                    Ok::<_, Infallible>(match uri {
+                        Ok(uri) => {
+                            *request.uri_mut() = uri;
+                            state.handle(request).await
+                        }
+                        Err(e) => text_response(StatusCode::BAD_REQUEST, &e.to_string()),
+                    })
+                }
+            });
+            if let Err(e) = Http::new()
+                .http1_only(true)
+                .http1_preserve_header_case(true)
+                .serve_connection(tls, service)
+                .await
+            {
+                debug!("Intercepted connection to {} closed: {}", host, e);
+            }
+        });
+        Response::new(Body::empty())
+    }
+}
+
+/// Reads `body` until `max` bytes or its end, whichever comes first, and
+/// returns them along with a body that streams everything, those bytes
+/// included. Only the start of a large upload is held in memory.
+async fn capture_start(mut body: Body, max: usize) -> anyhow::Result<(Vec<u8>, Body)> {
+    let mut chunks = Vec::new();
+    let mut size = 0;
+    while size <= max {
+        match body.data().await {
+            Some(chunk) => {
+                let chunk = chunk?;
+                size += chunk.len();
+                chunks.push(chunk);
+            }
+            None => {
+                let whole = chunks.concat();
+                return Ok((whole.clone(), Body::from(whole)));
+            }
+        }
+    }
+
+    let mut captured = Vec::with_capacity(max);
+    for chunk in &chunks {
+        let room = max - captured.len();
+        captured.extend_from_slice(&chunk[..chunk.len().min(room)]);
+    }
+    let (mut sender, streamed) = Body::channel();
+    tokio::spawn(async move {
+        for chunk in chunks {
+            if sender.send_data(chunk).await.is_err() {
+                return;
+            }
+        }
+        while let Some(chunk) = body.data().await {
+            let sent = match chunk {
+                Ok(chunk) => sender.send_data(chunk).await,
+                Err(e) => {
+                    debug!("Request body cut short: {}", e);
+                    sender.abort();
+                    return;
+                }
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+    });
+    Ok((captured, streamed))
+}
+
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect()
+}
+
+/// Removes the hop-by-hop headers, and any others `Connection` lists.
+fn strip_hop_by_hop(headers: &mut HeaderMap) {
+    let listed: Vec<String> = headers
+        .get_all(CONNECTION)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_ascii_lowercase())
+        .filter(|name| !name.is_empty())
+        .collect();
+    for name in HOP_BY_HOP
+        .iter()
+        .copied()
+        .chain(listed.iter().map(String::as_str))
+    {
+        headers.remove(name);
+    }
+}
+
+fn text_response(status: StatusCode, text: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/plain; charset=utf-8")
+        .body(Body::from(text.to_string()))
+        .expect("static response parts are valid")
+}
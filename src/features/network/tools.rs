@@ -1,35 +1,367 @@
+//! Rules the proxy applies to the traffic going through it.
+//!
+//! Mocks answer matching requests without touching the network, and header
+//! rules rewrite the headers of requests and responses on their way. Both
+//! apply to HTTPS too, as the proxy looks into the tunnels of the sites they
+//! could match.
+
+use super::pattern::UrlPattern;
+use colored::*;
+use hyper::body::Bytes;
+use hyper::header::{HeaderMap, HeaderName, HeaderValue};
+use hyper::{Body, Response, StatusCode};
+use log::info;
+use parking_lot::RwLock;
 use serde::{Deserialize, Serialize};
-use std::error::Error;
+use std::fmt;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
 
-pub struct NetworkTools;
+/// Shared between the browser, which sets the rules, and the proxy.
+#[derive(Default)]
+pub struct NetworkTools {
+    mocks: RwLock<Vec<Arc<Mock>>>,
+    header_rules: RwLock<Vec<HeaderRule>>,
+}
 
 impl NetworkTools {
-    pub async fn modify_headers(
-        &mut self,
-        _headers: HeaderModifications,
-    ) -> Result<(), Box<dyn Error>> {
-        todo!()
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rewrites the headers of the requests or responses `headers` is
+    /// scoped to, after any rules added before it.
+    ///
+    /// HTTPS traffic is only rewritten when the proxy has a CA to look into
+    /// it with.
+    pub async fn modify_headers(&self, headers: HeaderModifications) -> anyhow::Result<()> {
+        let rule = HeaderRule::compile(headers)?;
+        let urls = match rule.pattern.to_string() {
+            pattern if pattern.is_empty() => "every URL".to_string(),
+            pattern => pattern,
+        };
+        let scope = format!("{} headers for {}", rule.direction, urls);
+        info!("{}", format!("Rewriting {} (=^･ω･^=)", scope).cyan());
+        self.header_rules.write().push(rule);
+        Ok(())
+    }
+
+    pub fn clear_header_rules(&self) {
+        self.header_rules.write().clear();
+    }
+
+    /// Applies the header rules for `direction` that match `url`.
+    pub(crate) fn rewrite_headers(
+        &self,
+        direction: HeaderDirection,
+        url: &str,
+        headers: &mut HeaderMap,
+    ) {
+        for rule in self.header_rules.read().iter() {
+            if rule.direction == direction && rule.pattern.matches(url) {
+                rule.apply(headers);
+            }
+        }
+    }
+
+    /// Serves `mocks` instead of forwarding the requests they match. When
+    /// several mocks match a request, the one added last wins.
+    ///
+    /// HTTPS requests are only mocked when the proxy has a CA to look into
+    /// them with.
+    pub async fn mock_responses(&self, mocks: Vec<MockResponse>) -> anyhow::Result<()> {
+        let mut compiled = Vec::with_capacity(mocks.len());
+        for mock in mocks {
+            compiled.push(Arc::new(Mock::compile(mock).await?));
+        }
+        let count = compiled.len();
+        self.mocks.write().extend(compiled);
+        info!(
+            "{}",
+            format!("Mocking {} responses (=^･ω･^=)", count).cyan()
+        );
+        Ok(())
+    }
+
+    /// How many requests the mocks for `url_pattern` have answered.
+    pub fn mock_hits(&self, url_pattern: &str) -> usize {
+        self.mocks
+            .read()
+            .iter()
+            .filter(|mock| mock.url_pattern == url_pattern)
+            .map(|mock| mock.hits.load(Ordering::Relaxed))
+            .sum()
+    }
+
+    pub fn clear_mocks(&self) {
+        self.mocks.write().clear();
+    }
+
+    /// Whether any rule could apply to requests to `origin`, such as
+    /// `https://example.com`.
+    pub(crate) fn has_rules_for(&self, origin: &str) -> bool {
+        self.mocks
+            .read()
+            .iter()
+            .any(|mock| mock.pattern.may_match_origin(origin))
+            || self
+                .header_rules
+                .read()
+                .iter()
+                .any(|rule| rule.pattern.may_match_origin(origin))
+    }
+
+    /// The mock answering `method url`, if any, counting the hit.
+    pub(crate) fn find_mock(&self, method: &str, url: &str) -> Option<Arc<Mock>> {
+        let mocks = self.mocks.read();
+        let mock = mocks.iter().rev().find(|mock| mock.matches(method, url))?;
+        mock.hits.fetch_add(1, Ordering::Relaxed);
+        Some(Arc::clone(mock))
     }
+}
 
-    pub async fn mock_responses(
-        &mut self,
-        _mocks: Vec<MockResponse>,
-    ) -> Result<(), Box<dyn Error>> {
-        todo!()
+/// Which way the headers a rule rewrites are going.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum HeaderDirection {
+    /// From the browser to servers.
+    #[default]
+    Request,
+    /// From servers, or mocks, to the browser.
+    Response,
+}
+
+impl fmt::Display for HeaderDirection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            HeaderDirection::Request => "request",
+            HeaderDirection::Response => "response",
+        })
     }
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+/// A header rewrite for the requests or responses whose URL matches
+/// `url_pattern`, written the way [`UrlPattern`] reads it. An empty pattern
+/// matches every URL.
+///
+/// Headers in `remove` go first, then those in `modify` replace every value
+/// the header had, or are added if it had none, and those in `add` are added
+/// next to any already there.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct HeaderModifications {
+    #[serde(default)]
+    pub url_pattern: String,
+    #[serde(default)]
+    pub direction: HeaderDirection,
+    #[serde(default)]
     pub add: Vec<(String, String)>,
+    #[serde(default)]
     pub remove: Vec<String>,
+    #[serde(default)]
     pub modify: Vec<(String, String)>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl HeaderModifications {
+    /// No changes yet to requests going to `url_pattern`.
+    pub fn request(url_pattern: &str) -> Self {
+        Self {
+            url_pattern: url_pattern.to_string(),
+            ..Self::default()
+        }
+    }
+
+    /// No changes yet to responses from `url_pattern`.
+    pub fn response(url_pattern: &str) -> Self {
+        Self {
+            url_pattern: url_pattern.to_string(),
+            direction: HeaderDirection::Response,
+            ..Self::default()
+        }
+    }
+
+    pub fn add_header(mut self, name: &str, value: &str) -> Self {
+        self.add.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn remove_header(mut self, name: &str) -> Self {
+        self.remove.push(name.to_string());
+        self
+    }
+
+    pub fn set_header(mut self, name: &str, value: &str) -> Self {
+        self.modify.push((name.to_string(), value.to_string()));
+        self
+    }
+}
+
+/// [`HeaderModifications`] checked and ready to apply.
+struct HeaderRule {
+    pattern: UrlPattern,
+    direction: HeaderDirection,
+    add: Vec<(HeaderName, HeaderValue)>,
+    remove: Vec<HeaderName>,
+    modify: Vec<(HeaderName, HeaderValue)>,
+}
+
+impl HeaderRule {
+    fn compile(headers: HeaderModifications) -> anyhow::Result<Self> {
+        Ok(Self {
+            pattern: UrlPattern::parse(&headers.url_pattern)?,
+            direction: headers.direction,
+            add: header_values(&headers.add)?,
+            remove: headers
+                .remove
+                .iter()
+                .map(|name| HeaderName::from_bytes(name.as_bytes()))
+                .collect::<Result<_, _>>()?,
+            modify: header_values(&headers.modify)?,
+        })
+    }
+
+    fn apply(&self, headers: &mut HeaderMap) {
+        for name in &self.remove {
+            headers.remove(name);
+        }
+        for (name, value) in &self.modify {
+            headers.insert(name, value.clone());
+        }
+        for (name, value) in &self.add {
+            headers.append(name, value.clone());
+        }
+    }
+}
+
+fn header_values(headers: &[(String, String)]) -> anyhow::Result<Vec<(HeaderName, HeaderValue)>> {
+    headers
+        .iter()
+        .map(|(name, value)| {
+            Ok((
+                HeaderName::from_bytes(name.as_bytes())?,
+                HeaderValue::from_str(value)?,
+            ))
+        })
+        .collect()
+}
+
+/// A canned response for requests whose URL matches `url_pattern`, written
+/// the way [`UrlPattern`] reads it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct MockResponse {
     pub url_pattern: String,
+    /// Any method when `None`.
+    #[serde(default)]
+    pub method: Option<String>,
     pub status: u16,
+    #[serde(default)]
     pub headers: Vec<(String, String)>,
+    #[serde(default)]
     pub body: String,
+    /// Served instead of `body` when set. It is read once, when the mock is
+    /// added.
+    #[serde(default)]
+    pub body_file: Option<PathBuf>,
+    /// How long to wait before answering.
+    #[serde(default)]
+    pub delay: Option<Duration>,
+}
+
+impl MockResponse {
+    pub fn new(url_pattern: &str, status: u16) -> Self {
+        Self {
+            url_pattern: url_pattern.to_string(),
+            method: None,
+            status,
+            headers: Vec::new(),
+            body: String::new(),
+            body_file: None,
+            delay: None,
+        }
+    }
+
+    pub fn with_method(mut self, method: &str) -> Self {
+        self.method = Some(method.to_string());
+        self
+    }
+
+    pub fn with_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn with_body(mut self, body: &str) -> Self {
+        self.body = body.to_string();
+        self
+    }
+
+    pub fn with_body_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.body_file = Some(path.into());
+        self
+    }
+
+    pub fn with_delay(mut self, delay: Duration) -> Self {
+        self.delay = Some(delay);
+        self
+    }
+}
+
+/// A [`MockResponse`] checked and ready to serve.
+pub(crate) struct Mock {
+    url_pattern: String,
+    pattern: UrlPattern,
+    method: Option<String>,
+    status: StatusCode,
+    headers: HeaderMap,
+    body: Bytes,
+    delay: Option<Duration>,
+    hits: AtomicUsize,
+}
+
+impl Mock {
+    async fn compile(mock: MockResponse) -> anyhow::Result<Self> {
+        let mut headers = HeaderMap::new();
+        for (name, value) in header_values(&mock.headers)? {
+            headers.append(name, value);
+        }
+        let body = match &mock.body_file {
+            Some(path) => tokio::fs::read(path).await.map_err(|e| {
+                anyhow::anyhow!("Could not read mock body {}: {}", path.display(), e)
+            })?,
+            None => mock.body.into_bytes(),
+        };
+        Ok(Self {
+            pattern: UrlPattern::parse(&mock.url_pattern)?,
+            url_pattern: mock.url_pattern,
+            method: mock.method,
+            status: StatusCode::from_u16(mock.status)?,
+            headers,
+            body: body.into(),
+            delay: mock.delay,
+            hits: AtomicUsize::new(0),
+        })
+    }
+
+    fn matches(&self, method: &str, url: &str) -> bool {
+        self.method
+            .as_ref()
+            .is_none_or(|m| m.eq_ignore_ascii_case(method))
+            && self.pattern.matches(url)
+    }
+
+    pub(crate) fn delay(&self) -> Option<Duration> {
+        self.delay
+    }
+
+    pub(crate) fn body(&self) -> &Bytes {
+        &self.body
+    }
+
+    pub(crate) fn response(&self) -> Response<Body> {
+        let mut response = Response::new(Body::from(self.body.clone()));
+        *response.status_mut() = self.status;
+        *response.headers_mut() = self.headers.clone();
+        response
+    }
 }
@@ -0,0 +1,167 @@
+//! The proxy's connections to the servers it forwards requests to.
+//!
+//! Connections are opened by hand, rather than through `hyper::Client`, so
+//! each phase of setting one up can be timed. Idle connections are kept per
+//! origin and reused; requests sent on a reused connection have no DNS,
+//! connect or TLS time.
+
+use super::monitor::Timings;
+use futures::future::{poll_fn, FutureExt};
+use hyper::client::conn::{self, SendRequest};
+use hyper::header::{HeaderValue, HOST};
+use hyper::{Body, Request, Response, Uri};
+use log::debug;
+use parking_lot::Mutex;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::time::Instant;
+use tokio::io::{AsyncRead, AsyncWrite};
+use tokio::net::TcpStream;
+
+/// Idle connections kept per origin.
+const MAX_IDLE_PER_ORIGIN: usize = 6;
+
+/// A connection a response came back on, to hand back with
+/// [`Upstream::release`] once its body has been read.
+pub struct Connection {
+    origin: String,
+    sender: SendRequest<Body>,
+}
+
+pub struct Upstream {
+    tls: tokio_native_tls::TlsConnector,
+    idle: Mutex<HashMap<String, Vec<SendRequest<Body>>>>,
+}
+
+impl Upstream {
+    pub fn new() -> anyhow::Result<Self> {
+        Ok(Self {
+            tls: tokio_native_tls::native_tls::TlsConnector::new()?.into(),
+            idle: Mutex::new(HashMap::new()),
+        })
+    }
+
+    /// Sends `request`, which must have an absolute URL. The timings cover
+    /// everything up to the response headers; `download` is left to the
+    /// caller.
+    pub async fn send(
+        &self,
+        mut request: Request<Body>,
+    ) -> anyhow::Result<(Response<Body>, Connection, Timings)> {
+        let uri = request.uri().clone();
+        let https = match uri.scheme_str() {
+            Some("http") => false,
+            Some("https") => true,
+            _ => anyhow::bail!("Can't forward {}", uri),
+        };
+        let host = uri
+            .host()
+            .ok_or_else(|| anyhow::anyhow!("No host in {}", uri))?
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .to_string();
+        let port = uri.port_u16().unwrap_or(if https { 443 } else { 80 });
+        let origin = format!(
+            "{}://{}:{}",
+            if https { "https" } else { "http" },
+            host,
+            port
+        );
+
+        let mut timings = Timings::default();
+        let mut sender = match self.take_idle(&origin) {
+            Some(sender) => sender,
+            None => {
+                let tcp = connect(&host, port, &mut timings).await?;
+                if https {
+                    let start = Instant::now();
+                    let tls = self.tls.connect(&host, tcp).await?;
+                    timings.tls = Some(start.elapsed());
+                    handshake(tls).await?
+                } else {
+                    handshake(tcp).await?
+                }
+            }
+        };
+
+        // Servers get the path, the host goes in `Host`.
+        if let Some(authority) = uri.authority() {
+            if !request.headers().contains_key(HOST) {
+                request
+                    .headers_mut()
+                    .insert(HOST, HeaderValue::from_str(authority.as_str())?);
+            }
+        }
+        *request.uri_mut() = uri
+            .path_and_query()
+            .map_or("/", |p| p.as_str())
+            .parse::<Uri>()?;
+
+        let start = Instant::now();
+        let response = sender.send_request(request).await?;
+        timings.ttfb = start.elapsed();
+        Ok((response, Connection { origin, sender }, timings))
+    }
+
+    /// Keeps `connection` for the next request to the same origin.
+    pub fn release(&self, connection: Connection) {
+        let mut idle = self.idle.lock();
+        let senders = idle.entry(connection.origin).or_default();
+        if senders.len() < MAX_IDLE_PER_ORIGIN {
+            senders.push(connection.sender);
+        }
+    }
+
+    /// An idle connection to `origin` that is still open.
+    fn take_idle(&self, origin: &str) -> Option<SendRequest<Body>> {
+        let mut idle = self.idle.lock();
+        let senders = idle.get_mut(origin)?;
+        while let Some(mut sender) = senders.pop() {
+            match poll_fn(|cx| sender.poll_ready(cx)).now_or_never() {
+                Some(Ok(())) => return Some(sender),
+                _ => debug!("Dropping closed connection to {}", origin),
+            }
+        }
+        None
+    }
+}
+
+/// Resolves `host` and connects to the first address that answers, timing
+/// both in `timings`.
+pub async fn connect(host: &str, port: u16, timings: &mut Timings) -> anyhow::Result<TcpStream> {
+    let start = Instant::now();
+    let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host, port)).await?.collect();
+    timings.dns = Some(start.elapsed());
+
+    let start = Instant::now();
+    let mut last_error = None;
+    for addr in addrs {
+        match TcpStream::connect(addr).await {
+            Ok(stream) => {
+                timings.connect = Some(start.elapsed());
+                return Ok(stream);
+            }
+            Err(e) => last_error = Some(e),
+        }
+    }
+    Err(match last_error {
+        Some(e) => anyhow::anyhow!("Could not connect to {}:{}: {}", host, port, e),
+        None => anyhow::anyhow!("{} has no addresses", host),
+    })
+}
+
+async fn handshake<T>(io: T) -> anyhow::Result<SendRequest<Body>>
+where
+    T: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let (sender, connection) = conn::Builder::new()
+        .http1_preserve_header_case(true)
+        .handshake(io)
+        .await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            debug!("Upstream connection closed: {}", e);
+        }
+    });
+    Ok(sender)
+}
@@ -1,6 +1,3 @@
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-
 pub struct VpnManager;
 
 impl VpnManager {
@@ -21,3 +18,9 @@ impl VpnManager {
         false
     }
 }
+
+impl Default for VpnManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -9,12 +9,47 @@ pub struct Workspace {
     pub notes: Vec<Note>,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Bookmark {
     pub title: String,
     pub url: String,
     pub tags: Vec<String>,
     pub created_at: chrono::DateTime<chrono::Utc>,
+    /// Folder path from the root, e.g. `["Dev", "Rust"]`. Empty for the root.
+    #[serde(default)]
+    pub folder: Vec<String>,
+    /// Address bar shortcut. Typing the keyword, followed by a query if the
+    /// URL contains `%s`, opens this bookmark.
+    #[serde(default)]
+    pub keyword: Option<String>,
+}
+
+impl Bookmark {
+    pub fn new(url: &str, title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            url: url.to_string(),
+            tags: Vec::new(),
+            created_at: chrono::Utc::now(),
+            folder: Vec::new(),
+            keyword: None,
+        }
+    }
+
+    pub fn in_folder(mut self, folder: &[&str]) -> Self {
+        self.folder = folder.iter().map(|s| s.to_string()).collect();
+        self
+    }
+
+    pub fn with_keyword(mut self, keyword: &str) -> Self {
+        self.keyword = Some(keyword.to_string());
+        self
+    }
+
+    pub fn with_tags(mut self, tags: &[&str]) -> Self {
+        self.tags = tags.iter().map(|s| s.to_string()).collect();
+        self
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -1,11 +1,14 @@
 use std::error::Error;
 
+pub mod assets;
 pub mod browser;
 pub mod config;
 pub mod constants;
 pub mod core;
 pub mod features;
 pub mod monitoring;
+#[cfg(feature = "test-util")]
+pub mod test_util;
 pub mod utils;
 
 // Re-export main types for convenience
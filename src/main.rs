@@ -1,5 +1,8 @@
 #![recursion_limit = "256"]
 
+mod cli;
+
+use clap::Parser;
 use colored::*;
 use log::info;
 use std::error::Error;
@@ -88,6 +91,11 @@ const ADDITIONAL_STYLES: &str = r#"
 async fn main() -> Result<(), Box<dyn Error>> {
     env_logger::init();
 
+    if let Some(command) = cli::Cli::parse().command {
+        cli::run(command).await?;
+        return Ok(());
+    }
+
     println!("{}", KAWAII_BANNER.magenta());
     println!("{}", GECKO_BANNER.magenta());
     println!("{}", GOODBYE_BANNER.magenta());
@@ -115,8 +123,8 @@ async fn main() -> Result<(), Box<dyn Error>> {
         println!("{}", mascot.magenta());
     }
 
-    let config = config::BrowserConfig::default();
-    let browser = browser::NyanBrowser::new(config).await?;
+    let config = config::BrowserConfig::load()?;
+    let mut browser = browser::NyanBrowser::new(config).await?;
     browser.navigate(DEFAULT_URL).await?;
 
     info!(
@@ -131,9 +139,9 @@ async fn main() -> Result<(), Box<dyn Error>> {
         .magenta()
     );
 
-    // Keep the browser instance alive until Ctrl+C
-    let _ = browser;
+    // Keep the browser running until Ctrl+C, then stop the driver gracefully
     tokio::signal::ctrl_c().await?;
+    browser.shutdown().await?;
     Ok(())
 }
 
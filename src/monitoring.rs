@@ -1,7 +1,12 @@
-use parking_lot::RwLock;
+use crate::features::adblock::AdBlocker;
+use std::fmt::Write;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
+use std::time::Duration;
 
+/// How many of the most blocking filters `get_stats` lists.
+const TOP_RULES: usize = 5;
+
+#[allow(dead_code)]
 #[derive(Debug, Default)]
 pub struct PerformanceMetrics {
     navigation_times: Vec<Duration>,
@@ -9,11 +14,20 @@ pub struct PerformanceMetrics {
     errors: usize,
 }
 
-pub struct PerformanceMonitor;
+#[derive(Default)]
+pub struct PerformanceMonitor {
+    ad_blocker: Option<Arc<AdBlocker>>,
+}
 
 impl PerformanceMonitor {
     pub fn new() -> Self {
-        Self
+        Self::default()
+    }
+
+    /// Includes the requests `ad_blocker` blocked in the stats.
+    pub fn with_ad_blocker(mut self, ad_blocker: Arc<AdBlocker>) -> Self {
+        self.ad_blocker = Some(ad_blocker);
+        self
     }
 
     pub fn record_navigation(&self, _duration: Duration) {
@@ -21,6 +35,25 @@ impl PerformanceMonitor {
     }
 
     pub fn get_stats(&self) -> String {
-        "Performance stats not implemented".to_string()
+        let Some(ad_blocker) = &self.ad_blocker else {
+            return "Performance stats not implemented".to_string();
+        };
+        let blocked = ad_blocker.stats().global();
+        let mut stats = format!(
+            "Ad blocking: {}, {} requests blocked",
+            if ad_blocker.is_enabled() { "on" } else { "off" },
+            blocked.total
+        );
+        for (list, count) in &blocked.by_list {
+            let _ = write!(stats, "\n  {}: {}", list, count);
+        }
+        let top = blocked.top_rules(TOP_RULES);
+        if !top.is_empty() {
+            stats.push_str("\nTop filters:");
+            for (rule, count) in top {
+                let _ = write!(stats, "\n  {}: {}", rule, count);
+            }
+        }
+        stats
     }
 }
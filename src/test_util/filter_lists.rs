@@ -0,0 +1,81 @@
+//! Synthetic Adblock Plus filter lists with roughly the mix of rules found
+//! in EasyList, for benchmarks and tests that need a realistically big list.
+
+/// A small deterministic PRNG (xorshift64*), so generated lists are the
+/// same on every run.
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545_f491_4f6c_dd1d)
+    }
+
+    fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    fn word(&mut self) -> String {
+        const SYLLABLES: &[&str] = &[
+            "ad", "ban", "click", "dat", "el", "fo", "gram", "hub", "ix", "jo", "kit", "lo", "mo",
+            "net", "ox", "pix", "qu", "ra", "stat", "trk", "ul", "vid", "wa", "zen",
+        ];
+        (0..2 + self.below(3))
+            .map(|_| SYLLABLES[self.below(SYLLABLES.len())])
+            .collect()
+    }
+
+    fn host(&mut self) -> String {
+        const TLDS: &[&str] = &["com", "net", "org", "io", "co.uk", "de"];
+        let tld = TLDS[self.below(TLDS.len())];
+        match self.below(3) {
+            0 => format!("{}.{}.{}", self.word(), self.word(), tld),
+            _ => format!("{}.{}", self.word(), tld),
+        }
+    }
+}
+
+/// A filter list of `count` filters: mostly `||host^` rules, plus path
+/// patterns, filters with options, exceptions and element hiding rules.
+pub fn synthetic_filter_list(count: usize, seed: u64) -> String {
+    let mut rng = Rng(seed | 1);
+    let mut list = String::from("[Adblock Plus 2.0]\n! Title: Synthetic list\n");
+    for _ in 0..count {
+        let filter = match rng.below(100) {
+            0..=59 => format!("||{}^", rng.host()),
+            60..=69 => format!("||{}/{}/*$script,third-party", rng.host(), rng.word()),
+            70..=79 => format!("/{}-{}/*", rng.word(), rng.word()),
+            80..=84 => format!("-{}-{}.", rng.word(), rng.word()),
+            85..=87 => format!("||{}^$image,domain={}", rng.host(), rng.host()),
+            88..=89 => format!("@@||{}/{}^", rng.host(), rng.word()),
+            90..=91 => format!("&{}=", rng.word()),
+            92..=95 => format!("##.{}-{}", rng.word(), rng.word()),
+            _ => format!("{}##div[id^=\"{}\"]", rng.host(), rng.word()),
+        };
+        list.push_str(&filter);
+        list.push('\n');
+    }
+    list
+}
+
+/// `count` request URLs with the pages making them, mostly not matching
+/// any filter, as on a typical page.
+pub fn synthetic_requests(count: usize, seed: u64) -> Vec<(String, String)> {
+    let mut rng = Rng(seed | 1);
+    (0..count)
+        .map(|_| {
+            let url = format!(
+                "https://{}/{}/{}-{}.js?{}={}",
+                rng.host(),
+                rng.word(),
+                rng.word(),
+                rng.word(),
+                rng.word(),
+                rng.next() % 100_000
+            );
+            (url, format!("https://{}/", rng.host()))
+        })
+        .collect()
+}
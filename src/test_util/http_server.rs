@@ -0,0 +1,163 @@
+//! A small in-process HTTP server standing in for the web, for tests of the
+//! network proxy.
+//!
+//! Routes:
+//!
+//! - `/hello` answers `hello nyan` as plain text.
+//! - `/echo` answers JSON describing the request it got: `method`, `path`,
+//!   `headers` (lowercase names) and `body`.
+//! - `/status/<code>` answers with that status.
+//! - `/bytes/<n>` answers `n` bytes of `a`.
+//!
+//! Anything else is a 404. Every request is recorded.
+
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Request, Response, Server, StatusCode};
+use parking_lot::Mutex;
+use serde_json::{json, Map, Value};
+use std::convert::Infallible;
+use std::net::{SocketAddr, TcpListener};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+/// A request received by the test server.
+#[derive(Debug, Clone)]
+pub struct ServedRequest {
+    pub method: String,
+    /// Path and query.
+    pub path: String,
+    pub headers: Vec<(String, String)>,
+}
+
+/// Handle to a running test server. The server stops when dropped.
+pub struct TestHttpServer {
+    addr: SocketAddr,
+    requests: Arc<Mutex<Vec<ServedRequest>>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl TestHttpServer {
+    /// Starts the server on a random localhost port.
+    pub async fn start() -> anyhow::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let requests = Arc::new(Mutex::new(Vec::new()));
+
+        let served = Arc::clone(&requests);
+        let make_service = make_service_fn(move |_| {
+            let served = Arc::clone(&served);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(Arc::clone(&served), request)
+                }))
+            }
+        });
+
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let server = Server::from_tcp(listener)?
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                let _ = shutdown_rx.await;
+            });
+        tokio::spawn(server);
+
+        Ok(Self {
+            addr,
+            requests,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// The URL of `path` on this server, e.g. `url("/hello")`.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://{}{}", self.addr, path)
+    }
+
+    /// Every request received so far, in order.
+    pub fn requests(&self) -> Vec<ServedRequest> {
+        self.requests.lock().clone()
+    }
+}
+
+impl Drop for TestHttpServer {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+async fn handle(
+    requests: Arc<Mutex<Vec<ServedRequest>>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let path = parts
+        .uri
+        .path_and_query()
+        .map_or("/", |p| p.as_str())
+        .to_string();
+    let headers: Vec<_> = parts
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.to_string(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+    requests.lock().push(ServedRequest {
+        method: parts.method.to_string(),
+        path: path.clone(),
+        headers: headers.clone(),
+    });
+
+    let segments: Vec<_> = parts.uri.path().trim_matches('/').split('/').collect();
+    let response = match segments.as_slice() {
+        ["hello"] => text(StatusCode::OK, "hello nyan".to_string()),
+        ["echo"] => {
+            let headers: Map<String, Value> = headers
+                .into_iter()
+                .map(|(name, value)| (name, Value::String(value)))
+                .collect();
+            let echo = json!({
+                "method": parts.method.as_str(),
+                "path": path,
+                "headers": headers,
+                "body": String::from_utf8_lossy(&body),
+            });
+            Response::builder()
+                .header("content-type", "application/json")
+                .body(Body::from(echo.to_string()))
+                .unwrap()
+        }
+        ["status", code] => match code.parse().ok().and_then(|c| StatusCode::from_u16(c).ok()) {
+            Some(status) => text(status, status.to_string()),
+            None => text(StatusCode::BAD_REQUEST, format!("Not a status: {}", code)),
+        },
+        ["bytes", n] => match n.parse::<usize>() {
+            Ok(n) => Response::builder()
+                .header("content-type", "application/octet-stream")
+                .body(Body::from(vec![b'a'; n]))
+                .unwrap(),
+            Err(_) => text(StatusCode::BAD_REQUEST, format!("Not a size: {}", n)),
+        },
+        _ => text(StatusCode::NOT_FOUND, "Not found".to_string()),
+    };
+    Ok(response)
+}
+
+fn text(status: StatusCode, body: String) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("content-type", "text/plain; charset=utf-8")
+        .body(Body::from(body))
+        .unwrap()
+}
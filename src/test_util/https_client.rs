@@ -0,0 +1,40 @@
+//! Speaks HTTPS through the network proxy the way a browser does: `CONNECT`
+//! first, then TLS inside the tunnel.
+
+use hyper::client::conn::{self, SendRequest};
+use hyper::{Body, Request, StatusCode};
+use std::net::SocketAddr;
+use tokio::net::TcpStream;
+use tokio_native_tls::native_tls::{Certificate, TlsConnector};
+
+/// Opens a tunnel to `host:443` through the proxy on `proxy` and starts TLS
+/// in it, trusting only the CA in `ca_pem`. Requests sent on the result
+/// need a `Host` header and only a path.
+pub async fn https_via_proxy(
+    proxy: SocketAddr,
+    host: &str,
+    ca_pem: &str,
+) -> anyhow::Result<SendRequest<Body>> {
+    let (mut sender, connection) = conn::handshake(TcpStream::connect(proxy).await?).await?;
+    tokio::spawn(connection);
+    let authority = format!("{}:443", host);
+    let request = Request::connect(authority.as_str())
+        .header("host", authority.as_str())
+        .body(Body::empty())?;
+    let response = sender.send_request(request).await?;
+    if response.status() != StatusCode::OK {
+        anyhow::bail!("CONNECT {} answered {}", authority, response.status());
+    }
+    let tunnel = hyper::upgrade::on(response).await?;
+
+    let connector = TlsConnector::builder()
+        .disable_built_in_roots(true)
+        .add_root_certificate(Certificate::from_pem(ca_pem.as_bytes())?)
+        .build()?;
+    let tls = tokio_native_tls::TlsConnector::from(connector)
+        .connect(host, tunnel)
+        .await?;
+    let (sender, connection) = conn::handshake(tls).await?;
+    tokio::spawn(connection);
+    Ok(sender)
+}
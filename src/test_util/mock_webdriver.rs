@@ -0,0 +1,540 @@
+//! A minimal in-process W3C WebDriver server.
+//!
+//! It understands enough of the protocol for fantoccini to open sessions,
+//! navigate, run scripts, manage cookies, windows and screenshots. Every
+//! command is recorded so tests can assert on what the browser actually sent,
+//! and any command can be scripted to return a custom value or error.
+
+use crate::config::{BrowserConfig, DriverKind};
+use base64::Engine;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use parking_lot::Mutex;
+use serde_json::{json, Value};
+use std::collections::{HashMap, VecDeque};
+use std::convert::Infallible;
+use std::net::{IpAddr, SocketAddr, TcpListener};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use tokio::sync::oneshot;
+
+/// A command received by the mock server.
+#[derive(Debug, Clone)]
+pub struct RecordedCommand {
+    /// Short command name, e.g. `goto`, `execute`, `delete_all_cookies`.
+    pub command: String,
+    pub method: String,
+    pub path: String,
+    pub session_id: Option<String>,
+    pub body: Value,
+}
+
+impl RecordedCommand {
+    /// The `script` argument of an `execute`/`execute_async` command.
+    pub fn script(&self) -> Option<&str> {
+        self.body["script"].as_str()
+    }
+}
+
+/// A scripted response to a WebDriver command.
+#[derive(Debug, Clone)]
+pub enum MockReply {
+    /// Reply successfully with this `value`.
+    Value(Value),
+    /// Reply with a W3C error. `error` must be one of the error codes from
+    /// the spec (e.g. `"unknown error"`), fantoccini rejects anything else.
+    Error { error: String, message: String },
+}
+
+impl MockReply {
+    pub fn value(value: impl Into<Value>) -> Self {
+        Self::Value(value.into())
+    }
+
+    pub fn error(error: impl Into<String>, message: impl Into<String>) -> Self {
+        Self::Error {
+            error: error.into(),
+            message: message.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct MockWindow {
+    handle: String,
+    url: String,
+}
+
+#[derive(Debug, Default)]
+struct MockSession {
+    windows: Vec<MockWindow>,
+    current: usize,
+    cookies: Vec<Value>,
+}
+
+impl MockSession {
+    fn new(handle: String) -> Self {
+        Self {
+            windows: vec![MockWindow {
+                handle,
+                url: "about:blank".to_string(),
+            }],
+            current: 0,
+            cookies: Vec::new(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct MockState {
+    commands: Vec<RecordedCommand>,
+    queued: HashMap<String, VecDeque<MockReply>>,
+    stubs: HashMap<String, MockReply>,
+    execute_stubs: Vec<(String, MockReply)>,
+    titles: HashMap<String, String>,
+    screenshot: Vec<u8>,
+    sessions: HashMap<String, MockSession>,
+    next_id: u64,
+    not_ready: bool,
+}
+
+impl MockState {
+    fn next_id(&mut self, prefix: &str) -> String {
+        self.next_id += 1;
+        format!("{}-{}", prefix, self.next_id)
+    }
+
+    fn scripted_reply(&mut self, command: &RecordedCommand) -> Option<MockReply> {
+        if let Some(reply) = self
+            .queued
+            .get_mut(&command.command)
+            .and_then(VecDeque::pop_front)
+        {
+            return Some(reply);
+        }
+        if let Some(script) = command.script() {
+            if let Some((_, reply)) = self
+                .execute_stubs
+                .iter()
+                .find(|(needle, _)| script.contains(needle.as_str()))
+            {
+                return Some(reply.clone());
+            }
+        }
+        self.stubs.get(&command.command).cloned()
+    }
+}
+
+/// Handle to a running mock WebDriver server. The server stops when dropped.
+pub struct MockWebDriver {
+    addr: SocketAddr,
+    profile_dir: PathBuf,
+    state: Arc<Mutex<MockState>>,
+    shutdown: Option<oneshot::Sender<()>>,
+}
+
+impl MockWebDriver {
+    /// Starts the server on a random localhost port.
+    pub async fn start() -> anyhow::Result<Self> {
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        listener.set_nonblocking(true)?;
+        let addr = listener.local_addr()?;
+        let state = Arc::new(Mutex::new(MockState::default()));
+
+        let service_state = Arc::clone(&state);
+        let make_service = make_service_fn(move |_| {
+            let state = Arc::clone(&service_state);
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    handle(Arc::clone(&state), request)
+                }))
+            }
+        });
+
+        let (shutdown, shutdown_rx) = oneshot::channel::<()>();
+        let server = Server::from_tcp(listener)?
+            .serve(make_service)
+            .with_graceful_shutdown(async {
+                let _ = shutdown_rx.await;
+            });
+        tokio::spawn(server);
+
+        let profile_dir = std::env::temp_dir().join(format!(
+            "nyan-browser-test-{}-{}",
+            std::process::id(),
+            addr.port()
+        ));
+        std::fs::create_dir_all(&profile_dir)?;
+
+        Ok(Self {
+            addr,
+            profile_dir,
+            state,
+            shutdown: Some(shutdown),
+        })
+    }
+
+    /// Base URL to hand to a WebDriver client.
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// A default config that attaches to this server and keeps all files
+    /// in [`profile_dir`](Self::profile_dir).
+    pub fn config(&self) -> BrowserConfig {
+        BrowserConfig {
+            driver: DriverKind::Remote,
+            webdriver_url: Some(self.url()),
+            // The stand-in server runs here, so it can use a localhost proxy.
+            network_proxy_host: Some(IpAddr::from([127, 0, 0, 1])),
+            config_dir: self.profile_dir.join("config"),
+            data_dir: self.profile_dir.join("data"),
+            legacy_bookmarks_file: self.profile_dir.join("kawaii_bookmarks.json"),
+            ..BrowserConfig::default()
+        }
+    }
+
+    /// Scratch directory removed when the server is dropped.
+    pub fn profile_dir(&self) -> &Path {
+        &self.profile_dir
+    }
+
+    /// Every command received so far, in order.
+    pub fn commands(&self) -> Vec<RecordedCommand> {
+        self.state.lock().commands.clone()
+    }
+
+    /// Every command with the given name received so far, in order.
+    pub fn commands_named(&self, command: &str) -> Vec<RecordedCommand> {
+        self.state
+            .lock()
+            .commands
+            .iter()
+            .filter(|c| c.command == command)
+            .cloned()
+            .collect()
+    }
+
+    /// Scripts of every `execute` command received so far.
+    pub fn executed_scripts(&self) -> Vec<String> {
+        self.commands_named("execute")
+            .iter()
+            .filter_map(|c| c.script().map(str::to_string))
+            .collect()
+    }
+
+    pub fn clear_commands(&self) {
+        self.state.lock().commands.clear();
+    }
+
+    /// Replies to the next `command` with `reply` instead of the default.
+    pub fn queue(&self, command: &str, reply: MockReply) {
+        self.state
+            .lock()
+            .queued
+            .entry(command.to_string())
+            .or_default()
+            .push_back(reply);
+    }
+
+    /// Replies to every `command` with `reply` instead of the default.
+    pub fn stub(&self, command: &str, reply: MockReply) {
+        self.state.lock().stubs.insert(command.to_string(), reply);
+    }
+
+    /// Replies to every `execute` whose script contains `needle` with `reply`.
+    pub fn stub_execute(&self, needle: &str, reply: MockReply) {
+        self.state
+            .lock()
+            .execute_stubs
+            .push((needle.to_string(), reply));
+    }
+
+    /// Title reported after navigating to `url`.
+    pub fn set_title(&self, url: &str, title: &str) {
+        self.state
+            .lock()
+            .titles
+            .insert(url.to_string(), title.to_string());
+    }
+
+    /// Raw PNG bytes returned by `screenshot`.
+    pub fn set_screenshot(&self, png: Vec<u8>) {
+        self.state.lock().screenshot = png;
+    }
+
+    /// Makes `/status` report the server as (not) ready.
+    pub fn set_ready(&self, ready: bool) {
+        self.state.lock().not_ready = !ready;
+    }
+
+    /// Replaces the cookie jar of every open session.
+    pub fn set_cookies(&self, cookies: Vec<Value>) {
+        for session in self.state.lock().sessions.values_mut() {
+            session.cookies = cookies.clone();
+        }
+    }
+
+    /// Cookies currently stored in the given session.
+    pub fn cookies(&self, session_id: &str) -> Vec<Value> {
+        self.state
+            .lock()
+            .sessions
+            .get(session_id)
+            .map(|s| s.cookies.clone())
+            .unwrap_or_default()
+    }
+
+    /// Ids of the sessions that are currently open.
+    pub fn sessions(&self) -> Vec<String> {
+        self.state.lock().sessions.keys().cloned().collect()
+    }
+}
+
+impl Drop for MockWebDriver {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+        let _ = std::fs::remove_dir_all(&self.profile_dir);
+    }
+}
+
+async fn handle(
+    state: Arc<Mutex<MockState>>,
+    request: Request<Body>,
+) -> Result<Response<Body>, Infallible> {
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+    let body = hyper::body::to_bytes(request.into_body())
+        .await
+        .unwrap_or_default();
+    let body: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let session_id = match segments.as_slice() {
+        ["session", id, ..] => Some(id.to_string()),
+        _ => None,
+    };
+    let rest = segments.get(2..).unwrap_or_default();
+    let command = command_name(&method, segments.as_slice(), rest);
+
+    let recorded = RecordedCommand {
+        command: command.to_string(),
+        method: method.to_string(),
+        path,
+        session_id: session_id.clone(),
+        body,
+    };
+
+    let mut state = state.lock();
+    state.commands.push(recorded.clone());
+
+    let reply = match state.scripted_reply(&recorded) {
+        Some(reply) => reply,
+        None => default_reply(&mut state, &recorded),
+    };
+
+    Ok(to_response(reply))
+}
+
+fn command_name(method: &Method, segments: &[&str], rest: &[&str]) -> &'static str {
+    match (method.clone(), segments.len(), rest) {
+        (Method::GET, _, _) if segments == ["status"] => "status",
+        (Method::POST, 1, _) if segments[0] == "session" => "new_session",
+        (Method::DELETE, 2, []) => "delete_session",
+        (Method::POST, _, ["url"]) => "goto",
+        (Method::GET, _, ["url"]) => "current_url",
+        (Method::GET, _, ["title"]) => "title",
+        (Method::POST, _, ["back"]) => "back",
+        (Method::POST, _, ["forward"]) => "forward",
+        (Method::POST, _, ["refresh"]) => "refresh",
+        (Method::GET, _, ["source"]) => "source",
+        (Method::POST, _, ["execute", "sync"]) => "execute",
+        (Method::POST, _, ["execute", "async"]) => "execute_async",
+        (Method::GET, _, ["cookie"]) => "get_cookies",
+        (Method::POST, _, ["cookie"]) => "add_cookie",
+        (Method::DELETE, _, ["cookie"]) => "delete_all_cookies",
+        (Method::GET, _, ["cookie", _]) => "get_cookie",
+        (Method::DELETE, _, ["cookie", _]) => "delete_cookie",
+        (Method::GET, _, ["screenshot"]) => "screenshot",
+        (Method::GET, _, ["window"]) => "window",
+        (Method::POST, _, ["window"]) => "switch_to_window",
+        (Method::DELETE, _, ["window"]) => "close_window",
+        (Method::GET, _, ["window", "handles"]) => "windows",
+        (Method::POST, _, ["window", "new"]) => "new_window",
+        (Method::GET, _, ["timeouts"]) => "get_timeouts",
+        (Method::POST, _, ["timeouts"]) => "set_timeouts",
+        _ => "unknown",
+    }
+}
+
+fn default_reply(state: &mut MockState, command: &RecordedCommand) -> MockReply {
+    match command.command.as_str() {
+        "status" => MockReply::value(json!({
+            "ready": !state.not_ready,
+            "message": if state.not_ready { "busy" } else { "ready" },
+        })),
+        "new_session" => {
+            let id = state.next_id("session");
+            let handle = state.next_id("window");
+            state.sessions.insert(id.clone(), MockSession::new(handle));
+            MockReply::value(json!({
+                "sessionId": id,
+                "capabilities": command.body["capabilities"]["alwaysMatch"],
+            }))
+        }
+        "unknown" => MockReply::error("unknown command", command.path.clone()),
+        "screenshot" => {
+            MockReply::value(base64::engine::general_purpose::STANDARD.encode(&state.screenshot))
+        }
+        "execute" | "execute_async" | "get_timeouts" | "set_timeouts" | "back" | "forward"
+        | "refresh" => MockReply::Value(Value::Null),
+        _ => session_reply(state, command),
+    }
+}
+
+fn session_reply(state: &mut MockState, command: &RecordedCommand) -> MockReply {
+    let Some(id) = command.session_id.clone() else {
+        return MockReply::error("unknown command", command.path.clone());
+    };
+
+    if command.command == "delete_session" {
+        state.sessions.remove(&id);
+        return MockReply::Value(Value::Null);
+    }
+    if command.command == "new_window" {
+        let handle = state.next_id("window");
+        let kind = command.body["type"].as_str().unwrap_or("tab").to_string();
+        if let Some(session) = state.sessions.get_mut(&id) {
+            session.windows.push(MockWindow {
+                handle: handle.clone(),
+                url: "about:blank".to_string(),
+            });
+        }
+        return MockReply::value(json!({ "handle": handle, "type": kind }));
+    }
+
+    let titles = state.titles.clone();
+    let Some(session) = state.sessions.get_mut(&id) else {
+        return MockReply::error("invalid session id", id);
+    };
+    if session.windows.is_empty() {
+        return MockReply::error("no such window", "all windows are closed");
+    }
+    let current = session.current;
+
+    match command.command.as_str() {
+        "goto" => {
+            session.windows[current].url = command.body["url"]
+                .as_str()
+                .unwrap_or("about:blank")
+                .to_string();
+            MockReply::Value(Value::Null)
+        }
+        "current_url" => MockReply::value(session.windows[current].url.clone()),
+        "title" => MockReply::value(
+            titles
+                .get(&session.windows[current].url)
+                .cloned()
+                .unwrap_or_default(),
+        ),
+        "source" => MockReply::value("<html><head></head><body></body></html>"),
+        "get_cookies" => MockReply::Value(Value::Array(session.cookies.clone())),
+        "add_cookie" => {
+            let cookie = command.body["cookie"].clone();
+            session.cookies.retain(|c| c["name"] != cookie["name"]);
+            session.cookies.push(cookie);
+            MockReply::Value(Value::Null)
+        }
+        "delete_all_cookies" => {
+            session.cookies.clear();
+            MockReply::Value(Value::Null)
+        }
+        "get_cookie" | "delete_cookie" => {
+            let name = command.path.rsplit('/').next().unwrap_or_default();
+            let found = session.cookies.iter().position(|c| c["name"] == name);
+            match (command.command.as_str(), found) {
+                ("get_cookie", Some(index)) => MockReply::Value(session.cookies[index].clone()),
+                ("delete_cookie", Some(index)) => {
+                    session.cookies.remove(index);
+                    MockReply::Value(Value::Null)
+                }
+                ("delete_cookie", None) => MockReply::Value(Value::Null),
+                _ => MockReply::error("no such cookie", name.to_string()),
+            }
+        }
+        "window" => MockReply::value(session.windows[current].handle.clone()),
+        "windows" => MockReply::value(
+            session
+                .windows
+                .iter()
+                .map(|w| w.handle.clone())
+                .collect::<Vec<_>>(),
+        ),
+        "switch_to_window" => {
+            let handle = command.body["handle"].as_str().unwrap_or_default();
+            match session.windows.iter().position(|w| w.handle == handle) {
+                Some(index) => {
+                    session.current = index;
+                    MockReply::Value(Value::Null)
+                }
+                None => MockReply::error("no such window", handle.to_string()),
+            }
+        }
+        "close_window" => {
+            session.windows.remove(current);
+            session.current = 0;
+            MockReply::value(
+                session
+                    .windows
+                    .iter()
+                    .map(|w| w.handle.clone())
+                    .collect::<Vec<_>>(),
+            )
+        }
+        _ => MockReply::error("unknown command", command.path.clone()),
+    }
+}
+
+fn to_response(reply: MockReply) -> Response<Body> {
+    let (status, body) = match reply {
+        MockReply::Value(value) => (StatusCode::OK, json!({ "value": value })),
+        MockReply::Error { error, message } => (
+            error_status(&error),
+            json!({ "value": { "error": error, "message": message, "stacktrace": "" } }),
+        ),
+    };
+
+    Response::builder()
+        .status(status)
+        .header("Content-Type", "application/json; charset=utf-8")
+        .header("Cache-Control", "no-cache")
+        .body(Body::from(body.to_string()))
+        .expect("static response parts are valid")
+}
+
+/// HTTP status for a W3C error code, as listed in the spec's error table.
+fn error_status(error: &str) -> StatusCode {
+    match error {
+        "element click intercepted"
+        | "element not selectable"
+        | "element not interactable"
+        | "insecure certificate"
+        | "invalid argument"
+        | "invalid cookie domain"
+        | "invalid coordinates"
+        | "invalid element state"
+        | "invalid selector"
+        | "no such frame" => StatusCode::BAD_REQUEST,
+        "unknown command"
+        | "no such cookie"
+        | "invalid session id"
+        | "no such element"
+        | "no such window"
+        | "no such alert"
+        | "stale element reference" => StatusCode::NOT_FOUND,
+        "unknown method" => StatusCode::METHOD_NOT_ALLOWED,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
@@ -0,0 +1,13 @@
+//! Test support for code that drives a [`NyanBrowser`](crate::NyanBrowser).
+//!
+//! Only compiled with the `test-util` feature.
+
+pub mod filter_lists;
+pub mod http_server;
+pub mod https_client;
+pub mod mock_webdriver;
+
+pub use filter_lists::{synthetic_filter_list, synthetic_requests};
+pub use http_server::{ServedRequest, TestHttpServer};
+pub use https_client::https_via_proxy;
+pub use mock_webdriver::{MockReply, MockWebDriver, RecordedCommand};
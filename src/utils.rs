@@ -1,12 +1,17 @@
 // Utility functions will go here
 
+pub mod bookmarks;
+pub mod history;
+
+pub use bookmarks::BookmarkManager;
+pub use history::{HistoryEntry, HistoryOrder, HistoryQuery, HistoryStore, RetentionPolicy, Visit};
+
 use colored::*;
 use image::{DynamicImage, GenericImageView, ImageBuffer, Rgba};
-use serde_json;
 use std::collections::HashMap;
-use std::error::Error;
 use std::fs;
 use std::io::{self, Write};
+use std::path::Path;
 use std::sync::{Arc, RwLock};
 use std::time::Instant;
 use thiserror::Error;
@@ -38,34 +43,17 @@ pub fn add_kawaii_frame(original: &DynamicImage) -> DynamicImage {
     DynamicImage::ImageRgba8(framed)
 }
 
-pub fn load_bookmarks() -> Result<Vec<serde_json::Value>, Box<dyn Error>> {
-    let bookmarks_file = "kawaii_bookmarks.json";
-    if let Ok(contents) = fs::read_to_string(bookmarks_file) {
-        Ok(serde_json::from_str(&contents)?)
-    } else {
-        Ok(Vec::new())
-    }
-}
-
-pub fn save_bookmarks(bookmarks: &[serde_json::Value]) -> Result<(), Box<dyn Error>> {
-    let bookmarks_file = "kawaii_bookmarks.json";
-    let json = serde_json::to_string_pretty(bookmarks)?;
-    fs::write(bookmarks_file, json)?;
-    Ok(())
-}
-
-pub fn append_to_history(entry: &serde_json::Value) -> Result<(), Box<dyn Error>> {
-    let history_file = "kawaii_history.json";
-    let mut history = if let Ok(contents) = fs::read_to_string(history_file) {
-        serde_json::from_str(&contents)?
-    } else {
-        Vec::new()
-    };
-
-    history.push(entry.clone());
-    let json = serde_json::to_string_pretty(&history)?;
-    fs::write(history_file, json)?;
-    Ok(())
+/// Writes `contents` to a temporary sibling of `path` and renames it into
+/// place, so readers never observe a half-written file.
+pub fn write_atomic(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let mut tmp = path.as_os_str().to_owned();
+    tmp.push(".tmp");
+    let tmp = Path::new(&tmp);
+
+    let mut file = fs::File::create(tmp)?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(tmp, path)
 }
 
 #[derive(Error, Debug)]
@@ -157,6 +145,12 @@ impl KawaiiLogger {
     }
 }
 
+impl Default for KawaiiLogger {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[derive(Default)]
 struct LogMetrics {
     enabled: bool,
@@ -208,6 +202,12 @@ impl KawaiiSpinner {
     }
 }
 
+impl Default for KawaiiSpinner {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 pub fn show_kawaii_progress(progress: f32) -> String {
     let hearts = "♥".repeat((progress * 10.0) as usize);
     let empty = "♡".repeat((10.0 - progress * 10.0) as usize);
@@ -241,10 +241,13 @@ impl PerformanceMonitor {
         if let Some(start) = start_time {
             let duration = start.elapsed().as_secs_f64() * 1000.0;
             let mut metrics = self.metrics.write().unwrap();
-            metrics
-                .entry(name.to_string())
-                .or_insert_with(Vec::new)
-                .push(duration);
+            metrics.entry(name.to_string()).or_default().push(duration);
         }
     }
 }
+
+impl Default for PerformanceMonitor {
+    fn default() -> Self {
+        Self::new()
+    }
+}
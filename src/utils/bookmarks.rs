@@ -0,0 +1,473 @@
+//! Bookmarks, organised in folders and tagged.
+//!
+//! Bookmarks are kept in memory and written to `bookmarks.json` in the data
+//! dir after every change. A URL is bookmarked at most once: adding it again
+//! updates the existing bookmark instead of creating a duplicate.
+
+pub mod netscape;
+
+use crate::features::workspace::Bookmark;
+use crate::utils::write_atomic;
+use anyhow::{anyhow, bail};
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use parking_lot::RwLock;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+const BOOKMARKS_FILE: &str = "bookmarks.json";
+
+/// Where bookmarks used to live, relative to the working directory.
+pub const LEGACY_BOOKMARKS_FILE: &str = "kawaii_bookmarks.json";
+
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct BookmarkData {
+    /// Every folder, including empty ones, as paths from the root.
+    folders: Vec<Vec<String>>,
+    /// When folders were created. Folders from before this was kept have
+    /// no entry.
+    #[serde(default)]
+    folder_dates: Vec<(Vec<String>, DateTime<Utc>)>,
+    bookmarks: Vec<Bookmark>,
+}
+
+impl BookmarkData {
+    fn position(&self, url: &str) -> Option<usize> {
+        self.bookmarks.iter().position(|b| b.url == url)
+    }
+
+    fn bookmark_mut(&mut self, url: &str) -> anyhow::Result<&mut Bookmark> {
+        self.bookmarks
+            .iter_mut()
+            .find(|b| b.url == url)
+            .ok_or_else(|| anyhow!("No bookmark for {}", url))
+    }
+
+    fn has_folder(&self, path: &[String]) -> bool {
+        path.is_empty() || self.folders.iter().any(|f| f == path)
+    }
+
+    /// Adds `bookmark`, or merges it into the one with the same URL. Returns
+    /// whether a new bookmark was created.
+    fn insert(&mut self, bookmark: Bookmark) -> bool {
+        self.ensure_folder(&bookmark.folder);
+        match self.position(&bookmark.url) {
+            Some(index) => {
+                let existing = &mut self.bookmarks[index];
+                if !bookmark.title.is_empty() {
+                    existing.title = bookmark.title;
+                }
+                existing.folder = bookmark.folder;
+                if bookmark.keyword.is_some() {
+                    existing.keyword = bookmark.keyword;
+                }
+                for tag in bookmark.tags {
+                    if !existing.tags.contains(&tag) {
+                        existing.tags.push(tag);
+                    }
+                }
+                false
+            }
+            None => {
+                self.bookmarks.push(bookmark);
+                true
+            }
+        }
+    }
+
+    /// Registers `path` and all its ancestors.
+    fn ensure_folder(&mut self, path: &[String]) {
+        for depth in 1..=path.len() {
+            if !self.has_folder(&path[..depth]) {
+                self.folders.push(path[..depth].to_vec());
+                self.folder_dates.push((path[..depth].to_vec(), Utc::now()));
+            }
+        }
+    }
+
+    fn set_folder_date(&mut self, path: &[String], added: DateTime<Utc>) {
+        self.folder_dates.retain(|(folder, _)| folder != path);
+        self.folder_dates.push((path.to_vec(), added));
+    }
+
+    /// Moves the folder at `from`, with everything inside it, to `to`.
+    fn relocate_folder(&mut self, from: &[String], to: Vec<String>) -> anyhow::Result<()> {
+        if !self.has_folder(from) || from.is_empty() {
+            bail!("No folder {}", from.join("/"));
+        }
+        if self.has_folder(&to) {
+            bail!("Folder {} already exists", to.join("/"));
+        }
+        if to.starts_with(from) {
+            bail!("Cannot move {} into itself", from.join("/"));
+        }
+
+        let rebase = |path: &mut Vec<String>| {
+            if path.starts_with(from) {
+                let rest = path.split_off(from.len());
+                *path = to.iter().cloned().chain(rest).collect();
+            }
+        };
+        self.folders.iter_mut().for_each(rebase);
+        self.folder_dates
+            .iter_mut()
+            .for_each(|(folder, _)| rebase(folder));
+        self.bookmarks
+            .iter_mut()
+            .for_each(|b| rebase(&mut b.folder));
+        if let Some(parent) = to.split_last().map(|(_, parent)| parent.to_vec()) {
+            self.ensure_folder(&parent);
+        }
+        Ok(())
+    }
+}
+
+fn folder_path(folder: &[&str]) -> Vec<String> {
+    folder.iter().map(|s| s.to_string()).collect()
+}
+
+pub struct BookmarkManager {
+    path: PathBuf,
+    data: RwLock<BookmarkData>,
+}
+
+impl BookmarkManager {
+    /// Opens the bookmarks in `data_dir`, migrating `kawaii_bookmarks.json`
+    /// from the working directory the first time.
+    pub fn open(data_dir: &Path) -> anyhow::Result<Self> {
+        Self::open_with_legacy(data_dir, Path::new(LEGACY_BOOKMARKS_FILE))
+    }
+
+    /// Like [`open`](Self::open), migrating from `legacy` instead.
+    pub fn open_with_legacy(data_dir: &Path, legacy: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(BOOKMARKS_FILE);
+
+        let data = match fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => BookmarkData::default(),
+            Err(e) => return Err(e.into()),
+        };
+        let manager = Self {
+            path,
+            data: RwLock::new(data),
+        };
+
+        if !manager.path.exists() && legacy.exists() {
+            manager.migrate(legacy)?;
+        }
+        Ok(manager)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Imports the old untyped bookmark list and renames it out of the way
+    /// so it is only imported once.
+    fn migrate(&self, legacy: &Path) -> anyhow::Result<()> {
+        let entries: Vec<serde_json::Value> = serde_json::from_str(&fs::read_to_string(legacy)?)?;
+        let mut migrated = 0;
+        for entry in &entries {
+            match legacy_bookmark(entry) {
+                Some(bookmark) => {
+                    self.add(bookmark)?;
+                    migrated += 1;
+                }
+                None => warn!("Skipping unreadable legacy bookmark: {}", entry),
+            }
+        }
+        // Leaves an empty file behind when nothing was migrated, so we do not
+        // try again on every start.
+        self.save(&self.data.read())?;
+
+        let mut done = legacy.as_os_str().to_owned();
+        done.push(".migrated");
+        fs::rename(legacy, done)?;
+        info!("Migrated {} bookmarks from {}", migrated, legacy.display());
+        Ok(())
+    }
+
+    /// Adds a bookmark. If the URL is already bookmarked, the existing one
+    /// takes the new title, folder and any new tags instead. Returns whether
+    /// a new bookmark was created.
+    pub fn add(&self, bookmark: Bookmark) -> anyhow::Result<bool> {
+        let mut data = self.data.write();
+        let created = data.insert(bookmark);
+        self.save(&data)?;
+        Ok(created)
+    }
+
+    pub fn get(&self, url: &str) -> Option<Bookmark> {
+        let data = self.data.read();
+        data.position(url)
+            .map(|index| data.bookmarks[index].clone())
+    }
+
+    pub fn is_bookmarked(&self, url: &str) -> bool {
+        self.data.read().position(url).is_some()
+    }
+
+    pub fn remove(&self, url: &str) -> anyhow::Result<Option<Bookmark>> {
+        let mut data = self.data.write();
+        let Some(index) = data.position(url) else {
+            return Ok(None);
+        };
+        let removed = data.bookmarks.remove(index);
+        self.save(&data)?;
+        Ok(Some(removed))
+    }
+
+    /// All bookmarks, in the order they were added.
+    pub fn list(&self) -> Vec<Bookmark> {
+        self.data.read().bookmarks.clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.data.read().bookmarks.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Bookmarks directly inside `folder`, not in its subfolders.
+    pub fn in_folder(&self, folder: &[&str]) -> Vec<Bookmark> {
+        let folder = folder_path(folder);
+        self.data
+            .read()
+            .bookmarks
+            .iter()
+            .filter(|b| b.folder == folder)
+            .cloned()
+            .collect()
+    }
+
+    pub fn with_tag(&self, tag: &str) -> Vec<Bookmark> {
+        self.data
+            .read()
+            .bookmarks
+            .iter()
+            .filter(|b| b.tags.iter().any(|t| t == tag))
+            .cloned()
+            .collect()
+    }
+
+    /// Every tag in use, sorted.
+    pub fn tags(&self) -> Vec<String> {
+        let mut tags: Vec<_> = self
+            .data
+            .read()
+            .bookmarks
+            .iter()
+            .flat_map(|b| b.tags.iter().cloned())
+            .collect();
+        tags.sort();
+        tags.dedup();
+        tags
+    }
+
+    pub fn rename(&self, url: &str, title: &str) -> anyhow::Result<()> {
+        self.update(url, |b| b.title = title.to_string())
+    }
+
+    pub fn move_to(&self, url: &str, folder: &[&str]) -> anyhow::Result<()> {
+        let folder = folder_path(folder);
+        let mut data = self.data.write();
+        data.ensure_folder(&folder);
+        data.bookmark_mut(url)?.folder = folder;
+        self.save(&data)
+    }
+
+    pub fn add_tag(&self, url: &str, tag: &str) -> anyhow::Result<()> {
+        self.update(url, |b| {
+            if !b.tags.iter().any(|t| t == tag) {
+                b.tags.push(tag.to_string());
+            }
+        })
+    }
+
+    pub fn remove_tag(&self, url: &str, tag: &str) -> anyhow::Result<()> {
+        self.update(url, |b| b.tags.retain(|t| t != tag))
+    }
+
+    /// Keyword to URL for every bookmark with a keyword.
+    pub fn keywords(&self) -> HashMap<String, String> {
+        self.data
+            .read()
+            .bookmarks
+            .iter()
+            .filter_map(|b| Some((b.keyword.clone()?.to_lowercase(), b.url.clone())))
+            .collect()
+    }
+
+    pub fn set_keyword(&self, url: &str, keyword: Option<&str>) -> anyhow::Result<()> {
+        self.update(url, |b| b.keyword = keyword.map(str::to_string))
+    }
+
+    /// Every folder, sorted by path.
+    pub fn folders(&self) -> Vec<Vec<String>> {
+        let mut folders = self.data.read().folders.clone();
+        folders.sort();
+        folders
+    }
+
+    /// When `folder` was created, if known.
+    pub fn folder_created(&self, folder: &[&str]) -> Option<DateTime<Utc>> {
+        let folder = folder_path(folder);
+        self.data
+            .read()
+            .folder_dates
+            .iter()
+            .find(|(f, _)| *f == folder)
+            .map(|(_, added)| *added)
+    }
+
+    /// Creates `folder` and any missing parents.
+    pub fn create_folder(&self, folder: &[&str]) -> anyhow::Result<()> {
+        let mut data = self.data.write();
+        data.ensure_folder(&folder_path(folder));
+        self.save(&data)
+    }
+
+    pub fn rename_folder(&self, folder: &[&str], name: &str) -> anyhow::Result<()> {
+        let from = folder_path(folder);
+        let Some((_, parent)) = from.split_last() else {
+            bail!("The root folder cannot be renamed");
+        };
+        let to = parent.iter().cloned().chain([name.to_string()]).collect();
+
+        let mut data = self.data.write();
+        data.relocate_folder(&from, to)?;
+        self.save(&data)
+    }
+
+    /// Moves `folder` and its contents under `parent`.
+    pub fn move_folder(&self, folder: &[&str], parent: &[&str]) -> anyhow::Result<()> {
+        let from = folder_path(folder);
+        let Some(name) = from.last() else {
+            bail!("The root folder cannot be moved");
+        };
+        let to = folder_path(parent)
+            .into_iter()
+            .chain([name.clone()])
+            .collect();
+
+        let mut data = self.data.write();
+        data.relocate_folder(&from, to)?;
+        self.save(&data)
+    }
+
+    /// Deletes `folder`, its subfolders and every bookmark in them. Returns
+    /// the number of bookmarks removed.
+    pub fn remove_folder(&self, folder: &[&str]) -> anyhow::Result<usize> {
+        let folder = folder_path(folder);
+        if folder.is_empty() {
+            bail!("The root folder cannot be removed");
+        }
+
+        let mut data = self.data.write();
+        if !data.has_folder(&folder) {
+            bail!("No folder {}", folder.join("/"));
+        }
+        data.folders.retain(|f| !f.starts_with(&folder));
+        data.folder_dates.retain(|(f, _)| !f.starts_with(&folder));
+        let before = data.bookmarks.len();
+        data.bookmarks.retain(|b| !b.folder.starts_with(&folder));
+        let removed = before - data.bookmarks.len();
+        self.save(&data)?;
+        Ok(removed)
+    }
+
+    /// Imports a Netscape bookmark file, as exported by Firefox and Chrome,
+    /// under `folder`. Returns the number of new bookmarks.
+    pub fn import_html(&self, html: &str, folder: &[&str]) -> anyhow::Result<usize> {
+        let imported = netscape::parse(html);
+        let prefix = folder_path(folder);
+        let under = |path: Vec<String>| prefix.iter().cloned().chain(path).collect::<Vec<_>>();
+
+        let mut data = self.data.write();
+        data.ensure_folder(&prefix);
+        for path in imported.folders {
+            let added = imported
+                .folder_dates
+                .iter()
+                .find(|(folder, _)| *folder == path)
+                .map(|(_, added)| *added);
+            let path = under(path);
+            // Folders that already exist keep their own date.
+            if data.has_folder(&path) {
+                continue;
+            }
+            data.ensure_folder(&path);
+            if let Some(added) = added {
+                data.set_folder_date(&path, added);
+            }
+        }
+        let mut created = 0;
+        for mut bookmark in imported.bookmarks {
+            bookmark.folder = under(bookmark.folder);
+            if data.insert(bookmark) {
+                created += 1;
+            }
+        }
+        self.save(&data)?;
+        Ok(created)
+    }
+
+    /// All bookmarks as a Netscape bookmark file.
+    pub fn export_html(&self) -> String {
+        let data = self.data.read();
+        netscape::render(&data.folders, &data.folder_dates, &data.bookmarks)
+    }
+
+    pub fn import_html_file(&self, path: &Path, folder: &[&str]) -> anyhow::Result<usize> {
+        self.import_html(&fs::read_to_string(path)?, folder)
+    }
+
+    pub fn export_html_file(&self, path: &Path) -> anyhow::Result<()> {
+        write_atomic(path, self.export_html().as_bytes())?;
+        Ok(())
+    }
+
+    fn update(&self, url: &str, change: impl FnOnce(&mut Bookmark)) -> anyhow::Result<()> {
+        let mut data = self.data.write();
+        change(data.bookmark_mut(url)?);
+        self.save(&data)
+    }
+
+    fn save(&self, data: &BookmarkData) -> anyhow::Result<()> {
+        write_atomic(&self.path, serde_json::to_string_pretty(data)?.as_bytes())?;
+        Ok(())
+    }
+}
+
+/// Reads one entry of the old format, which was whatever JSON object the
+/// caller saved. `url` is the only field we insist on.
+fn legacy_bookmark(entry: &serde_json::Value) -> Option<Bookmark> {
+    let url = entry.get("url")?.as_str()?;
+    let title = entry
+        .get("title")
+        .or_else(|| entry.get("name"))
+        .and_then(|v| v.as_str())
+        .unwrap_or_default();
+
+    let mut bookmark = Bookmark::new(url, title);
+    if let Some(tags) = entry.get("tags").and_then(|v| v.as_array()) {
+        bookmark.tags = tags
+            .iter()
+            .filter_map(|t| t.as_str().map(str::to_string))
+            .collect();
+    }
+    if let Some(created_at) = entry
+        .get("created_at")
+        .or_else(|| entry.get("timestamp"))
+        .and_then(|v| v.as_str())
+        .and_then(|s| s.parse::<DateTime<Utc>>().ok())
+    {
+        bookmark.created_at = created_at;
+    }
+    Some(bookmark)
+}
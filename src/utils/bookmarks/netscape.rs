@@ -0,0 +1,290 @@
+//! The Netscape bookmark file format, the HTML that every browser can import
+//! and export.
+//!
+//! The format is a loose HTML dialect where `<H3>` names a folder, the `<DL>`
+//! that follows holds its contents and `<A>` is a bookmark. Real exports are
+//! rarely well-formed, so the parser only looks at those tags and ignores
+//! everything else.
+
+use crate::features::workspace::Bookmark;
+use chrono::{DateTime, Utc};
+
+const HEADER: &str = "<!DOCTYPE NETSCAPE-Bookmark-file-1>
+<!-- This is an automatically generated file.
+     It will be read and overwritten.
+     DO NOT EDIT! -->
+<META HTTP-EQUIV=\"Content-Type\" CONTENT=\"text/html; charset=UTF-8\">
+<TITLE>Bookmarks</TITLE>
+<H1>Bookmarks</H1>
+";
+
+/// Everything read from a bookmark file.
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct NetscapeBookmarks {
+    /// Every folder, in document order, including empty ones.
+    pub folders: Vec<Vec<String>>,
+    /// `ADD_DATE` of the folders that have one.
+    pub folder_dates: Vec<(Vec<String>, DateTime<Utc>)>,
+    pub bookmarks: Vec<Bookmark>,
+}
+
+enum Capture {
+    Folder(Option<DateTime<Utc>>),
+    Link(Vec<(String, String)>),
+}
+
+pub fn parse(html: &str) -> NetscapeBookmarks {
+    let mut result = NetscapeBookmarks::default();
+    // One entry per open `<DL>`; `None` for lists that are not a folder,
+    // like the top-level one.
+    let mut open_lists: Vec<Option<String>> = Vec::new();
+    // A folder name waiting for its `<DL>`, and its `ADD_DATE`.
+    let mut pending_folder: Option<(String, Option<DateTime<Utc>>)> = None;
+    let mut capture: Option<Capture> = None;
+    let mut text = String::new();
+
+    let current_folder = |open_lists: &[Option<String>]| -> Vec<String> {
+        open_lists.iter().flatten().cloned().collect()
+    };
+
+    let mut rest = html;
+    while let Some(start) = rest.find('<') {
+        if capture.is_some() {
+            text.push_str(&rest[..start]);
+        }
+        rest = &rest[start..];
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            rest = comment.find("-->").map_or("", |end| &comment[end + 3..]);
+            continue;
+        }
+        let Some(end) = rest.find('>') else {
+            break;
+        };
+        let tag = &rest[1..end];
+        rest = &rest[end + 1..];
+
+        let name_end = tag.find(|c: char| c.is_whitespace()).unwrap_or(tag.len());
+        match tag[..name_end].to_ascii_lowercase().as_str() {
+            "h3" => {
+                let attrs = attributes(&tag[name_end..]);
+                capture = Some(Capture::Folder(add_date(&attrs)));
+                text.clear();
+            }
+            // A folder without a list of its own holds nothing, and must not
+            // lend its name to the next list.
+            "dt" => pending_folder = None,
+            "a" => {
+                pending_folder = None;
+                capture = Some(Capture::Link(attributes(&tag[name_end..])));
+                text.clear();
+            }
+            "/h3" => {
+                if let Some(Capture::Folder(added)) = capture.take() {
+                    pending_folder = Some((decode_entities(text.trim()), added));
+                }
+            }
+            "/a" => {
+                if let Some(Capture::Link(attrs)) = capture.take() {
+                    if let Some(bookmark) = link(&attrs, &text, current_folder(&open_lists)) {
+                        result.bookmarks.push(bookmark);
+                    }
+                }
+            }
+            "dl" => {
+                let (name, added) = pending_folder.take().unzip();
+                open_lists.push(name);
+                let folder = current_folder(&open_lists);
+                if open_lists.last().is_some_and(Option::is_some)
+                    && !result.folders.contains(&folder)
+                {
+                    if let Some(added) = added.flatten() {
+                        result.folder_dates.push((folder.clone(), added));
+                    }
+                    result.folders.push(folder);
+                }
+            }
+            "/dl" => {
+                open_lists.pop();
+            }
+            _ => {}
+        }
+    }
+    result
+}
+
+fn link(attrs: &[(String, String)], text: &str, folder: Vec<String>) -> Option<Bookmark> {
+    let attr = |name: &str| {
+        attrs
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.as_str())
+    };
+    let url = attr("href").filter(|url| !url.is_empty())?;
+
+    let mut bookmark = Bookmark::new(url, &decode_entities(text.trim()));
+    bookmark.folder = folder;
+    if let Some(tags) = attr("tags") {
+        bookmark.tags = tags
+            .split(',')
+            .map(str::trim)
+            .filter(|t| !t.is_empty())
+            .map(str::to_string)
+            .collect();
+    }
+    bookmark.keyword = attr("shortcuturl")
+        .filter(|k| !k.is_empty())
+        .map(str::to_string);
+    if let Some(added) = add_date(attrs) {
+        bookmark.created_at = added;
+    }
+    Some(bookmark)
+}
+
+/// The `ADD_DATE` attribute, in seconds since the epoch.
+fn add_date(attrs: &[(String, String)]) -> Option<DateTime<Utc>> {
+    attrs
+        .iter()
+        .find(|(key, _)| key == "add_date")
+        .and_then(|(_, secs)| secs.parse::<i64>().ok())
+        .and_then(|secs| DateTime::<Utc>::from_timestamp(secs, 0))
+}
+
+/// Attributes of a start tag, with lowercased names and decoded values.
+fn attributes(mut s: &str) -> Vec<(String, String)> {
+    let mut attrs = Vec::new();
+    loop {
+        s = s.trim_start();
+        let name_end = s
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(s.len());
+        if name_end == 0 {
+            break;
+        }
+        let name = s[..name_end].to_ascii_lowercase();
+        s = s[name_end..].trim_start();
+
+        let Some(value) = s.strip_prefix('=') else {
+            attrs.push((name, String::new()));
+            continue;
+        };
+        let value = value.trim_start();
+        let (raw, remaining) = match value.chars().next() {
+            Some(quote @ ('"' | '\'')) => {
+                let value = &value[1..];
+                let end = value.find(quote).unwrap_or(value.len());
+                (&value[..end], value.get(end + 1..).unwrap_or(""))
+            }
+            _ => {
+                let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                (&value[..end], &value[end..])
+            }
+        };
+        attrs.push((name, decode_entities(raw)));
+        s = remaining;
+    }
+    attrs
+}
+
+fn decode_entities(s: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    let mut rest = s;
+    while let Some(start) = rest.find('&') {
+        out.push_str(&rest[..start]);
+        rest = &rest[start..];
+        let decoded = rest.find(';').and_then(|end| {
+            let entity = &rest[1..end];
+            let c = match entity {
+                "amp" => '&',
+                "lt" => '<',
+                "gt" => '>',
+                "quot" => '"',
+                "apos" => '\'',
+                _ => {
+                    let code = match entity.strip_prefix('#') {
+                        Some(hex) if hex.starts_with(['x', 'X']) => {
+                            u32::from_str_radix(&hex[1..], 16).ok()?
+                        }
+                        Some(dec) => dec.parse().ok()?,
+                        None => return None,
+                    };
+                    char::from_u32(code)?
+                }
+            };
+            Some((c, end + 1))
+        });
+        match decoded {
+            Some((c, len)) => {
+                out.push(c);
+                rest = &rest[len..];
+            }
+            None => {
+                out.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+fn escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Renders bookmarks as a Netscape bookmark file. Within a folder, bookmarks
+/// come before subfolders. Folders in `folder_dates` get an `ADD_DATE`.
+pub fn render(
+    folders: &[Vec<String>],
+    folder_dates: &[(Vec<String>, DateTime<Utc>)],
+    bookmarks: &[Bookmark],
+) -> String {
+    let mut html = String::from(HEADER);
+    render_folder(&mut html, &[], folders, folder_dates, bookmarks, 0);
+    html
+}
+
+fn render_folder(
+    html: &mut String,
+    path: &[String],
+    folders: &[Vec<String>],
+    folder_dates: &[(Vec<String>, DateTime<Utc>)],
+    bookmarks: &[Bookmark],
+    depth: usize,
+) {
+    let indent = "    ".repeat(depth);
+    html.push_str(&format!("{}<DL><p>\n", indent));
+
+    for bookmark in bookmarks.iter().filter(|b| b.folder == path) {
+        html.push_str(&format!(
+            "{}    <DT><A HREF=\"{}\" ADD_DATE=\"{}\"",
+            indent,
+            escape(&bookmark.url),
+            bookmark.created_at.timestamp()
+        ));
+        if let Some(keyword) = &bookmark.keyword {
+            html.push_str(&format!(" SHORTCUTURL=\"{}\"", escape(keyword)));
+        }
+        if !bookmark.tags.is_empty() {
+            html.push_str(&format!(" TAGS=\"{}\"", escape(&bookmark.tags.join(","))));
+        }
+        html.push_str(&format!(">{}</A>\n", escape(&bookmark.title)));
+    }
+
+    let children = folders
+        .iter()
+        .filter(|f| f.len() == path.len() + 1 && f.starts_with(path));
+    for child in children {
+        html.push_str(&format!("{}    <DT><H3", indent));
+        if let Some((_, added)) = folder_dates.iter().find(|(folder, _)| folder == child) {
+            html.push_str(&format!(" ADD_DATE=\"{}\"", added.timestamp()));
+        }
+        html.push_str(&format!(">{}</H3>\n", escape(&child[path.len()])));
+        render_folder(html, child, folders, folder_dates, bookmarks, depth + 1);
+    }
+
+    html.push_str(&format!("{}</DL><p>\n", indent));
+}
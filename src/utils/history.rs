@@ -0,0 +1,469 @@
+//! Browsing history, stored as an append-only log of visits.
+//!
+//! Every visit is one JSON line in `history.jsonl` in the data dir, so
+//! recording a visit is a single append no matter how large the history is.
+//! The log is replayed into per-URL [`HistoryEntry`] aggregates on open, and
+//! only rewritten when history is cleared or expired.
+//!
+//! The browser and the `history` CLI may have the log open at the same time.
+//! Appends and rewrites take an advisory lock on it, and rewrites happen in
+//! place, so an append handle opened earlier keeps writing to the live file.
+
+use crate::config::BrowserConfig;
+use chrono::{DateTime, Duration, Utc};
+use log::warn;
+use parking_lot::{Mutex, RwLock};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+const HISTORY_FILE: &str = "history.jsonl";
+
+/// One page load, as stored on disk.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Visit {
+    pub url: String,
+    pub title: String,
+    pub visited_at: DateTime<Utc>,
+}
+
+/// Everything known about one URL.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HistoryEntry {
+    pub url: String,
+    pub title: String,
+    pub visit_count: u32,
+    pub first_visit: DateTime<Utc>,
+    pub last_visit: DateTime<Utc>,
+}
+
+impl HistoryEntry {
+    fn from_visit(visit: &Visit) -> Self {
+        Self {
+            url: visit.url.clone(),
+            title: visit.title.clone(),
+            visit_count: 1,
+            first_visit: visit.visited_at,
+            last_visit: visit.visited_at,
+        }
+    }
+
+    fn add_visit(&mut self, visit: &Visit) {
+        self.visit_count += 1;
+        self.first_visit = self.first_visit.min(visit.visited_at);
+        if visit.visited_at >= self.last_visit {
+            self.last_visit = visit.visited_at;
+            // A page that failed to report a title keeps the one we knew.
+            if !visit.title.is_empty() {
+                self.title = visit.title.clone();
+            }
+        }
+    }
+}
+
+/// Sort order for [`HistoryStore::search`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum HistoryOrder {
+    /// Most recently visited first.
+    #[default]
+    Recent,
+    /// Highest visit count first.
+    MostVisited,
+    /// Highest frecency score first, like Firefox's address bar.
+    Frecency,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct HistoryQuery {
+    /// Whitespace separated terms that must all appear in the URL or title,
+    /// ignoring case.
+    pub text: Option<String>,
+    /// Only count visits at or after this time.
+    pub since: Option<DateTime<Utc>>,
+    /// Only count visits before this time.
+    pub until: Option<DateTime<Utc>>,
+    pub order: HistoryOrder,
+    pub limit: Option<usize>,
+}
+
+impl HistoryQuery {
+    pub fn text(text: &str) -> Self {
+        Self {
+            text: Some(text.to_string()),
+            ..Self::default()
+        }
+    }
+
+    fn in_range(&self, at: DateTime<Utc>) -> bool {
+        self.since.is_none_or(|since| at >= since) && self.until.is_none_or(|until| at < until)
+    }
+}
+
+/// Limits on how much history is kept.
+#[derive(Debug, Clone, Default)]
+pub struct RetentionPolicy {
+    /// Visits older than this are dropped.
+    pub max_age: Option<Duration>,
+    /// Only this many of the most recently visited URLs are kept.
+    pub max_entries: Option<usize>,
+}
+
+impl RetentionPolicy {
+    pub fn from_config(config: &BrowserConfig) -> Self {
+        Self {
+            max_age: config
+                .history_max_age_days
+                .map(|days| Duration::days(days.into())),
+            max_entries: config.history_max_entries,
+        }
+    }
+}
+
+/// Frecency of `entry` given its visits, following the shape of Firefox's
+/// algorithm: the ten most recent visits are weighted by how long ago they
+/// happened, and the average weight is scaled by the total visit count.
+fn frecency(entry: &HistoryEntry, visits: &[&Visit], now: DateTime<Utc>) -> f64 {
+    let mut recent: Vec<_> = visits.iter().map(|v| v.visited_at).collect();
+    recent.sort_by_key(|at| Reverse(*at));
+    recent.truncate(10);
+    if recent.is_empty() {
+        return 0.0;
+    }
+
+    let weights: f64 = recent
+        .iter()
+        .map(|at| match (now - *at).num_days() {
+            ..=4 => 100.0,
+            5..=14 => 70.0,
+            15..=31 => 50.0,
+            32..=90 => 30.0,
+            _ => 10.0,
+        })
+        .sum();
+    f64::from(entry.visit_count) * weights / recent.len() as f64
+}
+
+#[derive(Default)]
+struct HistoryIndex {
+    visits: Vec<Visit>,
+    entries: HashMap<String, HistoryEntry>,
+}
+
+impl HistoryIndex {
+    fn insert(&mut self, visit: Visit) -> HistoryEntry {
+        let entry = match self.entries.get_mut(&visit.url) {
+            Some(entry) => {
+                entry.add_visit(&visit);
+                entry.clone()
+            }
+            None => {
+                let entry = HistoryEntry::from_visit(&visit);
+                self.entries.insert(visit.url.clone(), entry.clone());
+                entry
+            }
+        };
+        self.visits.push(visit);
+        entry
+    }
+
+    fn rebuild(visits: Vec<Visit>) -> Self {
+        let mut index = Self::default();
+        for visit in visits {
+            index.insert(visit);
+        }
+        index
+    }
+}
+
+pub struct HistoryStore {
+    path: PathBuf,
+    log: Mutex<File>,
+    index: RwLock<HistoryIndex>,
+}
+
+impl HistoryStore {
+    /// Opens (or creates) the history log in `data_dir`.
+    pub fn open(data_dir: &Path) -> anyhow::Result<Self> {
+        fs::create_dir_all(data_dir)?;
+        let path = data_dir.join(HISTORY_FILE);
+
+        let log = OpenOptions::new().create(true).append(true).open(&path)?;
+        log.lock()?;
+        let visits = drop_torn_tail(&path).and_then(|()| read_visits(&path));
+        log.unlock()?;
+        let index = HistoryIndex::rebuild(visits?);
+
+        Ok(Self {
+            path,
+            log: Mutex::new(log),
+            index: RwLock::new(index),
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Records a visit to `url` happening now.
+    pub fn record_visit(&self, url: &str, title: &str) -> anyhow::Result<HistoryEntry> {
+        self.record(Visit {
+            url: url.to_string(),
+            title: title.to_string(),
+            visited_at: Utc::now(),
+        })
+    }
+
+    /// Appends `visit` to the log and returns the updated entry for its URL.
+    pub fn record(&self, visit: Visit) -> anyhow::Result<HistoryEntry> {
+        let mut line = serde_json::to_string(&visit)?;
+        line.push('\n');
+        {
+            // One write per line keeps concurrent appenders from interleaving,
+            // and the lock keeps them out of the middle of a rewrite.
+            let mut log = self.log.lock();
+            log.lock()?;
+            let written = log.write_all(line.as_bytes());
+            log.unlock()?;
+            written?;
+        }
+        Ok(self.index.write().insert(visit))
+    }
+
+    pub fn get(&self, url: &str) -> Option<HistoryEntry> {
+        self.index.read().entries.get(url).cloned()
+    }
+
+    /// All entries, most recently visited first.
+    pub fn entries(&self) -> Vec<HistoryEntry> {
+        let mut entries: Vec<_> = self.index.read().entries.values().cloned().collect();
+        entries.sort_by_key(|e| Reverse(e.last_visit));
+        entries
+    }
+
+    /// Every recorded visit, oldest first.
+    pub fn visits(&self) -> Vec<Visit> {
+        self.index.read().visits.clone()
+    }
+
+    /// Number of distinct URLs in the history.
+    pub fn len(&self) -> usize {
+        self.index.read().entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Entries matching `query`. Visit counts and first/last visit times
+    /// only cover visits inside the query's date range.
+    pub fn search(&self, query: &HistoryQuery) -> Vec<HistoryEntry> {
+        let terms: Vec<String> = query
+            .text
+            .as_deref()
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_lowercase)
+            .collect();
+
+        let index = self.index.read();
+        let mut matched = HistoryIndex::default();
+        for visit in index.visits.iter().filter(|v| query.in_range(v.visited_at)) {
+            let entry = &index.entries[&visit.url];
+            let haystack = format!("{} {}", entry.url, entry.title).to_lowercase();
+            if terms.iter().all(|term| haystack.contains(term.as_str())) {
+                matched.insert(visit.clone());
+            }
+        }
+        // Show the newest known title even if it came from a visit outside
+        // the range.
+        for entry in matched.entries.values_mut() {
+            entry.title = index.entries[&entry.url].title.clone();
+        }
+
+        let mut entries: Vec<_> = matched.entries.values().cloned().collect();
+        match query.order {
+            HistoryOrder::Recent => entries.sort_by_key(|e| Reverse(e.last_visit)),
+            HistoryOrder::MostVisited => {
+                entries.sort_by_key(|e| (Reverse(e.visit_count), Reverse(e.last_visit)))
+            }
+            HistoryOrder::Frecency => {
+                let now = Utc::now();
+                let mut visits_by_url: HashMap<&str, Vec<&Visit>> = HashMap::new();
+                for visit in &matched.visits {
+                    visits_by_url
+                        .entry(visit.url.as_str())
+                        .or_default()
+                        .push(visit);
+                }
+                let mut scored: Vec<_> = entries
+                    .into_iter()
+                    .map(|e| (frecency(&e, &visits_by_url[e.url.as_str()], now), e))
+                    .collect();
+                scored.sort_by(|a, b| b.0.total_cmp(&a.0));
+                entries = scored.into_iter().map(|(_, e)| e).collect();
+            }
+        }
+        if let Some(limit) = query.limit {
+            entries.truncate(limit);
+        }
+        entries
+    }
+
+    /// The `limit` URLs with the most visits.
+    pub fn most_visited(&self, limit: usize) -> Vec<HistoryEntry> {
+        self.search(&HistoryQuery {
+            order: HistoryOrder::MostVisited,
+            limit: Some(limit),
+            ..HistoryQuery::default()
+        })
+    }
+
+    /// The `limit` URLs with the highest frecency.
+    pub fn frecent(&self, limit: usize) -> Vec<HistoryEntry> {
+        self.search(&HistoryQuery {
+            order: HistoryOrder::Frecency,
+            limit: Some(limit),
+            ..HistoryQuery::default()
+        })
+    }
+
+    /// Frecency score of `url`, or 0 if it was never visited.
+    pub fn frecency(&self, url: &str) -> f64 {
+        let index = self.index.read();
+        let Some(entry) = index.entries.get(url) else {
+            return 0.0;
+        };
+        let visits: Vec<_> = index.visits.iter().filter(|v| v.url == url).collect();
+        frecency(entry, &visits, Utc::now())
+    }
+
+    /// Removes visits in `[since, until)`; open ends are unbounded. Returns
+    /// the number of visits removed.
+    pub fn clear_range(
+        &self,
+        since: Option<DateTime<Utc>>,
+        until: Option<DateTime<Utc>>,
+    ) -> anyhow::Result<usize> {
+        let range = HistoryQuery {
+            since,
+            until,
+            ..HistoryQuery::default()
+        };
+        self.rewrite(|index| {
+            index
+                .visits
+                .iter()
+                .filter(|visit| !range.in_range(visit.visited_at))
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Removes everything.
+    pub fn clear(&self) -> anyhow::Result<usize> {
+        self.clear_range(None, None)
+    }
+
+    /// Drops whatever falls outside `policy`. Returns the number of visits
+    /// removed.
+    pub fn apply_retention(&self, policy: &RetentionPolicy) -> anyhow::Result<usize> {
+        let cutoff = policy.max_age.map(|age| Utc::now() - age);
+        self.rewrite(|index| {
+            let keep_urls: Option<HashSet<&str>> = policy.max_entries.map(|max| {
+                let mut entries: Vec<_> = index.entries.values().collect();
+                entries.sort_by_key(|e| Reverse(e.last_visit));
+                entries.truncate(max);
+                entries.into_iter().map(|e| e.url.as_str()).collect()
+            });
+            index
+                .visits
+                .iter()
+                .filter(|visit| {
+                    cutoff.is_none_or(|cutoff| visit.visited_at >= cutoff)
+                        && keep_urls
+                            .as_ref()
+                            .is_none_or(|urls| urls.contains(visit.url.as_str()))
+                })
+                .cloned()
+                .collect()
+        })
+    }
+
+    /// Rewrites the log with the visits `select` picks from it. The log is
+    /// read back first, so visits other processes appended since it was
+    /// opened are seen too. Returns the number of visits removed.
+    fn rewrite(&self, select: impl FnOnce(&HistoryIndex) -> Vec<Visit>) -> anyhow::Result<usize> {
+        let log = self.log.lock();
+        log.lock()?;
+        let rewritten = self.rewrite_locked(select);
+        log.unlock()?;
+        rewritten
+    }
+
+    fn rewrite_locked(
+        &self,
+        select: impl FnOnce(&HistoryIndex) -> Vec<Visit>,
+    ) -> anyhow::Result<usize> {
+        let current = HistoryIndex::rebuild(read_visits(&self.path)?);
+        let kept = select(&current);
+        let removed = current.visits.len() - kept.len();
+        if removed == 0 {
+            *self.index.write() = current;
+            return Ok(0);
+        }
+
+        let mut contents = String::new();
+        for visit in &kept {
+            contents.push_str(&serde_json::to_string(visit)?);
+            contents.push('\n');
+        }
+        // Overwrite, then cut what's left of the old log: a crash in between
+        // leaves some visits twice rather than an empty log.
+        let mut file = OpenOptions::new().write(true).open(&self.path)?;
+        file.write_all(contents.as_bytes())?;
+        file.set_len(contents.len() as u64)?;
+        file.sync_data()?;
+        *self.index.write() = HistoryIndex::rebuild(kept);
+        Ok(removed)
+    }
+}
+
+/// Cuts off a last line left without its newline by a crash mid-write, so
+/// the next append starts on a line of its own.
+fn drop_torn_tail(path: &Path) -> anyhow::Result<()> {
+    let contents = fs::read(path)?;
+    let complete = contents
+        .iter()
+        .rposition(|&b| b == b'\n')
+        .map_or(0, |newline| newline + 1);
+    if complete < contents.len() {
+        warn!(
+            "Dropping {} bytes of a torn history line",
+            contents.len() - complete
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(path)?
+            .set_len(complete as u64)?;
+    }
+    Ok(())
+}
+
+fn read_visits(path: &Path) -> anyhow::Result<Vec<Visit>> {
+    let mut visits = Vec::new();
+    for (number, line) in BufReader::new(File::open(path)?).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str(&line) {
+            Ok(visit) => visits.push(visit),
+            // Most likely a line torn by a crash mid-write; the rest is fine.
+            Err(e) => warn!("Skipping history line {}: {}", number + 1, e),
+        }
+    }
+    Ok(visits)
+}
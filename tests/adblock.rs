@@ -0,0 +1,663 @@
+use nyan_browser::features::adblock::allowlist::ALLOWLIST_FILE;
+use nyan_browser::features::adblock::{
+    AdBlocker, Allowlist, Decision, Filter, FilterError, ListStats, NetworkFilter, Request,
+    ResourceType, CUSTOM_LIST,
+};
+use nyan_browser::test_util::{
+    synthetic_filter_list, synthetic_requests, MockReply, MockWebDriver,
+};
+use nyan_browser::NyanBrowser;
+use std::path::PathBuf;
+
+fn easylist() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/fixtures/adblock/easylist.txt")
+}
+
+fn blocker() -> AdBlocker {
+    let blocker = AdBlocker::new();
+    blocker.load_list(&easylist()).unwrap();
+    blocker
+}
+
+fn blocks(blocker: &AdBlocker, url: &str, source: &str, kind: ResourceType) -> bool {
+    let source = (!source.is_empty()).then_some(source);
+    blocker.should_block(url, source, kind)
+}
+
+const PAGE: &str = "https://news.example.com/today";
+
+#[test]
+fn loads_lists_and_counts_filters() {
+    let stats = AdBlocker::new().load_list(&easylist()).unwrap();
+    assert_eq!(
+        stats,
+        ListStats {
+            network: 14,
+            cosmetic: 5,
+            unsupported: 4,
+            invalid: 0,
+        }
+    );
+    assert!(AdBlocker::new()
+        .load_list(&easylist().with_extension("missing"))
+        .is_err());
+}
+
+#[test]
+fn hostname_anchors_match_the_domain_and_subdomains() {
+    let blocker = blocker();
+    for url in [
+        "https://doubleclick.net/",
+        "https://stats.g.doubleclick.net/pixel?x=1",
+        "http://ads.example.com:8080/banner.png",
+    ] {
+        assert!(blocks(&blocker, url, PAGE, ResourceType::Image), "{}", url);
+    }
+    for url in [
+        "https://notdoubleclick.net/",
+        "https://example.com/doubleclick.net/",
+        "https://doubleclick.network/",
+    ] {
+        assert!(!blocks(&blocker, url, PAGE, ResourceType::Image), "{}", url);
+    }
+}
+
+#[test]
+fn plain_patterns_and_separators() {
+    let blocker = blocker();
+    let any = ResourceType::Other;
+    assert!(blocks(
+        &blocker,
+        "https://cdn.site/img/top-ad-banner.gif",
+        PAGE,
+        any
+    ));
+    assert!(blocks(&blocker, "https://site/banner/ads/1.png", PAGE, any));
+    assert!(blocks(
+        &blocker,
+        "https://site/x?id=1&AD_TYPE=pop",
+        PAGE,
+        any
+    ));
+    assert!(blocks(&blocker, "https://site/pagead/123/x.js", PAGE, any));
+    assert!(!blocks(&blocker, "https://site/pagead/abc/x.js", PAGE, any));
+    // `|` anchors at the start and end of the URL.
+    assert!(blocks(&blocker, "http://insecure-ads.site/", PAGE, any));
+    assert!(!blocks(&blocker, "https://insecure-ads.site/", PAGE, any));
+    assert!(blocks(&blocker, "https://site/movie.swf", PAGE, any));
+    assert!(!blocks(
+        &blocker,
+        "https://site/movie.swf?autoplay",
+        PAGE,
+        any
+    ));
+}
+
+#[test]
+fn exceptions_override_blocking_filters() {
+    let blocker = blocker();
+    let request = Request::new(
+        "https://ads.example.com/allowed/logo.png",
+        Some(PAGE),
+        ResourceType::Image,
+    );
+    assert_eq!(
+        blocker.check(&request),
+        Decision::Exception("@@||ads.example.com/allowed/*".to_string())
+    );
+    assert_eq!(
+        blocker.check(&Request::new(
+            "https://ads.example.com/x",
+            Some(PAGE),
+            ResourceType::Image
+        )),
+        Decision::Block("||ads.example.com^".to_string())
+    );
+
+    let acceptable = "https://doubleclick.net/acceptable/ad";
+    assert!(!blocks(
+        &blocker,
+        acceptable,
+        "https://www.partner.example/",
+        ResourceType::Image
+    ));
+    assert!(blocks(&blocker, acceptable, PAGE, ResourceType::Image));
+}
+
+#[test]
+fn third_party_option() {
+    let blocker = blocker();
+    let url = "https://tracker.example.org/t.gif";
+    assert!(blocks(&blocker, url, PAGE, ResourceType::Image));
+    assert!(!blocks(
+        &blocker,
+        url,
+        "https://www.example.org/",
+        ResourceType::Image
+    ));
+    // Top-level navigations are never third-party.
+    assert!(!blocks(&blocker, url, "", ResourceType::Document));
+
+    let pixel = "https://pixel.example.net/p.gif";
+    assert!(blocks(
+        &blocker,
+        pixel,
+        "https://example.net/",
+        ResourceType::Image
+    ));
+    assert!(!blocks(&blocker, pixel, PAGE, ResourceType::Image));
+
+    // `co.uk` is a public suffix, so these are different sites.
+    let blocker = AdBlocker::new();
+    blocker.add_filter("||cdn.a.co.uk^$third-party").unwrap();
+    assert!(blocks(
+        &blocker,
+        "https://cdn.a.co.uk/x",
+        "https://b.co.uk/",
+        ResourceType::Script
+    ));
+    assert!(!blocks(
+        &blocker,
+        "https://cdn.a.co.uk/x",
+        "https://www.a.co.uk/",
+        ResourceType::Script
+    ));
+}
+
+#[test]
+fn resource_type_options() {
+    let blocker = blocker();
+    let url = "https://cdn.example.net/ads.js";
+    assert!(blocks(&blocker, url, PAGE, ResourceType::Script));
+    assert!(!blocks(&blocker, url, PAGE, ResourceType::XmlHttpRequest));
+
+    let blocker = AdBlocker::new();
+    blocker
+        .add_filter("/tracking/*$~image,~stylesheet")
+        .unwrap();
+    assert!(blocks(
+        &blocker,
+        "https://a.com/tracking/x",
+        PAGE,
+        ResourceType::Script
+    ));
+    assert!(!blocks(
+        &blocker,
+        "https://a.com/tracking/x",
+        PAGE,
+        ResourceType::Image
+    ));
+}
+
+#[test]
+fn domain_option_uses_the_most_specific_domain() {
+    let blocker = blocker();
+    let url = "https://widgets.example.net/w.js";
+    assert!(blocks(&blocker, url, PAGE, ResourceType::Script));
+    assert!(blocks(
+        &blocker,
+        url,
+        "https://m.news.example.com/",
+        ResourceType::Script
+    ));
+    assert!(!blocks(
+        &blocker,
+        url,
+        "https://sports.news.example.com/",
+        ResourceType::Script
+    ));
+    assert!(!blocks(
+        &blocker,
+        url,
+        "https://example.com/",
+        ResourceType::Script
+    ));
+}
+
+#[test]
+fn match_case_option() {
+    let blocker = AdBlocker::new();
+    blocker.add_filter("/Promo/$match-case").unwrap();
+    assert!(blocks(
+        &blocker,
+        "https://a.com/Promo/1",
+        PAGE,
+        ResourceType::Image
+    ));
+    assert!(!blocks(
+        &blocker,
+        "https://a.com/promo/1",
+        PAGE,
+        ResourceType::Image
+    ));
+}
+
+#[test]
+fn parses_element_hiding_filters() {
+    let Some(Filter::Cosmetic(filter)) =
+        Filter::parse("example.com,~shop.example.com##div[data-ad]").unwrap()
+    else {
+        panic!("not an element hiding filter");
+    };
+    assert_eq!(filter.selector, "div[data-ad]");
+    assert!(!filter.exception);
+    assert!(filter.applies_to("www.example.com"));
+    assert!(!filter.applies_to("shop.example.com"));
+    assert!(!filter.applies_to("example.org"));
+
+    let Some(Filter::Cosmetic(generic)) = Filter::parse("###sponsored").unwrap() else {
+        panic!("not an element hiding filter");
+    };
+    assert_eq!(generic.selector, "#sponsored");
+    assert!(generic.applies_to("anything.test"));
+
+    assert!(matches!(
+        Filter::parse("a.com#?#div:-abp-has(.ad)"),
+        Err(FilterError::Unsupported(_))
+    ));
+    assert!(matches!(Filter::parse("! comment"), Ok(None)));
+
+    let cosmetic = blocker().cosmetic_filters();
+    assert_eq!(cosmetic.iter().filter(|f| f.exception).count(), 1);
+}
+
+#[test]
+fn rejects_unsupported_options() {
+    let blocker = AdBlocker::new();
+    assert_eq!(
+        blocker.add_filter("||a.com^$popup"),
+        Err(FilterError::Unsupported("$popup".to_string()))
+    );
+    assert!(!blocks(
+        &blocker,
+        "https://a.com/",
+        PAGE,
+        ResourceType::Document
+    ));
+}
+
+#[tokio::test]
+async fn navigate_skips_blocked_pages() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config.adblock_lists = vec![easylist()];
+    let browser = NyanBrowser::new(config).await.unwrap();
+
+    browser.navigate("https://doubleclick.net/").await.unwrap();
+    assert!(driver.commands_named("goto").is_empty());
+    browser.navigate("https://example.com/").await.unwrap();
+    assert_eq!(driver.commands_named("goto").len(), 1);
+}
+
+#[test]
+fn hiding_selectors_combine_generic_and_site_rules() {
+    let blocker = blocker();
+    assert_eq!(
+        blocker.hiding_selectors("www.example.com"),
+        vec![".ad-banner", "#sponsored", ".sidebar-ad", "div[data-ad]"]
+    );
+    // `#@#` lifts the generic `.ad-banner` rule, `~` the site rule.
+    assert_eq!(
+        blocker.hiding_selectors("shop.example.com"),
+        vec!["#sponsored", ".sidebar-ad"]
+    );
+    assert_eq!(
+        blocker.hiding_selectors("other.test"),
+        vec![".ad-banner", "#sponsored"]
+    );
+
+    let css = blocker.hiding_stylesheet("other.test").unwrap();
+    assert_eq!(
+        css,
+        ".ad-banner { display: none !important; }\n#sponsored { display: none !important; }\n"
+    );
+    assert!(AdBlocker::new().hiding_stylesheet("other.test").is_none());
+}
+
+#[test]
+fn generic_exceptions_apply_everywhere() {
+    let blocker = AdBlocker::new();
+    blocker.add_filters("##.ad\n##.promo\n#@#.promo\na.com##.promo");
+    assert_eq!(blocker.hiding_selectors("a.com"), vec![".ad"]);
+}
+
+#[test]
+fn selectors_cannot_inject_rules() {
+    assert!(matches!(
+        Filter::parse("##.ad { } body { display: none"),
+        Err(FilterError::Invalid(_))
+    ));
+}
+
+#[tokio::test]
+async fn injects_hiding_css_after_navigation() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config.adblock_lists = vec![easylist()];
+    let browser = NyanBrowser::new(config).await.unwrap();
+
+    browser.navigate("https://www.example.com/").await.unwrap();
+    let inject = driver.commands_named("execute").pop().unwrap();
+    let css = inject.body["args"][0].as_str().unwrap();
+    assert!(css.contains(".sidebar-ad { display: none !important; }"));
+    assert!(!inject.script().unwrap().contains("sidebar-ad"));
+
+    driver.stub_execute("hidden.size", MockReply::value(3));
+    assert_eq!(browser.hidden_element_count().await.unwrap(), 3);
+    let count = driver.commands_named("execute").pop().unwrap();
+    assert_eq!(count.body["args"][0][2], ".sidebar-ad");
+}
+
+#[tokio::test]
+async fn nothing_is_injected_without_cosmetic_filters() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+
+    browser.navigate("https://www.example.com/").await.unwrap();
+    assert!(driver.commands_named("execute").is_empty());
+    assert_eq!(browser.hidden_element_count().await.unwrap(), 0);
+}
+
+#[test]
+fn tokens_are_whole_words() {
+    let tokens = |filter: &str| NetworkFilter::parse(filter).unwrap().tokens();
+    assert_eq!(tokens("||ads.example.com^"), vec!["ads", "example", "com"]);
+    // Unanchored ends may be part of a longer word in the URL.
+    assert_eq!(tokens("banner/ads/top"), vec!["ads"]);
+    assert_eq!(tokens("|https://ads*/pixel|"), vec!["https", "pixel"]);
+    assert!(tokens("/pagead[0-9]/").is_empty());
+    assert!(tokens("$script,domain=a.com").is_empty());
+}
+
+/// What a plain scan over every filter decides, to check the index against.
+fn scan(filters: &[NetworkFilter], request: &Request) -> bool {
+    filters.iter().any(|f| !f.exception && f.matches(request))
+        && !filters.iter().any(|f| f.exception && f.matches(request))
+}
+
+#[test]
+fn index_agrees_with_a_linear_scan() {
+    let list = synthetic_filter_list(5_000, 3);
+    let blocker = AdBlocker::new();
+    blocker.add_filters(&list);
+    let filters: Vec<_> = list
+        .lines()
+        .filter_map(|line| match Filter::parse(line) {
+            Ok(Some(Filter::Network(filter))) => Some(filter),
+            _ => None,
+        })
+        .collect();
+
+    let mut blocked = 0;
+    for (url, source) in synthetic_requests(2_000, 11) {
+        for kind in [ResourceType::Script, ResourceType::Image] {
+            let request = Request::new(&url, Some(&source), kind);
+            let expected = scan(&filters, &request);
+            assert_eq!(blocker.check(&request).is_blocked(), expected, "{}", url);
+            blocked += expected as usize;
+        }
+    }
+    // Make sure the requests exercise both outcomes.
+    assert!(blocked > 100 && blocked < 3_900, "{} blocked", blocked);
+}
+
+#[test]
+fn compiled_filters_are_cached() {
+    let dir = std::env::temp_dir().join(format!("nyan-adblock-cache-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let list = dir.join("list.txt");
+    let cache = dir.join("cache/filters.json");
+    std::fs::write(&list, "||ads.test^\n##.banner\n").unwrap();
+    let lists = [list.clone(), dir.join("missing.txt")];
+
+    let cold = AdBlocker::new().load_lists_cached(&lists, &cache);
+    assert!(cache.exists());
+    let blocker = AdBlocker::new();
+    assert_eq!(blocker.load_lists_cached(&lists, &cache), cold);
+    assert!(blocker.should_block("https://ads.test/", None, ResourceType::Document));
+    assert_eq!(blocker.hiding_selectors("a.test"), vec![".banner"]);
+
+    // A changed list is compiled again rather than served from the cache.
+    std::fs::write(&list, "||tracker.test^\n").unwrap();
+    blocker.load_lists_cached(&lists, &cache);
+    assert!(!blocker.should_block("https://ads.test/", None, ResourceType::Document));
+    assert!(blocker.should_block("https://tracker.test/", None, ResourceType::Document));
+
+    // Filters added by hand outlive a reload.
+    blocker.add_filter("||mine.test^").unwrap();
+    std::fs::write(&list, "||tracker.test^\n||ads.test^\n").unwrap();
+    blocker.load_lists_cached(&lists, &cache);
+    assert!(blocker.should_block("https://mine.test/", None, ResourceType::Document));
+    assert!(blocker.should_block("https://ads.test/", None, ResourceType::Document));
+    assert_eq!(blocker.stats().global().by_list[CUSTOM_LIST], 1);
+
+    // A corrupt cache is ignored.
+    std::fs::write(&cache, "not json").unwrap();
+    let blocker = AdBlocker::new();
+    blocker.load_lists_cached(&lists, &cache);
+    assert!(blocker.should_block("https://tracker.test/", None, ResourceType::Document));
+}
+
+#[test]
+fn checks_are_fast_on_an_easylist_sized_list() {
+    let blocker = AdBlocker::new();
+    blocker.add_filters(&synthetic_filter_list(60_000, 42));
+    assert_eq!(blocker.untokenized_filter_count(), 0);
+
+    let requests: Vec<_> = synthetic_requests(10_000, 7)
+        .into_iter()
+        .map(|(url, source)| Request::new(&url, Some(&source), ResourceType::Script))
+        .collect();
+    let start = std::time::Instant::now();
+    for request in &requests {
+        blocker.check(request);
+    }
+    let per_request = start.elapsed() / requests.len() as u32;
+    // Generous so slow CI machines pass; the bench shows a few µs.
+    assert!(
+        per_request.as_micros() < 500,
+        "{:?} per request",
+        per_request
+    );
+}
+
+#[test]
+fn blocking_can_be_turned_off() {
+    let blocker = blocker();
+    assert!(blocker.is_enabled());
+    blocker.set_enabled(false);
+    assert!(!blocks(
+        &blocker,
+        "https://doubleclick.net/ad.js",
+        PAGE,
+        ResourceType::Script
+    ));
+    assert!(blocker.hiding_selectors("www.example.com").is_empty());
+    assert!(blocker.toggle());
+    assert!(blocks(
+        &blocker,
+        "https://doubleclick.net/ad.js",
+        PAGE,
+        ResourceType::Script
+    ));
+    assert!(!blocker.toggle());
+}
+
+#[test]
+fn allowlisted_sites_are_left_alone() {
+    let blocker = blocker();
+    assert!(blocker
+        .allowlist()
+        .add("https://Example.com/some/page")
+        .unwrap());
+    assert!(!blocker.allowlist().add("example.com").unwrap());
+    assert_eq!(blocker.allowlist().sites(), vec!["example.com"]);
+
+    // Requests from allowlisted pages and their subdomains go through...
+    assert!(!blocks(
+        &blocker,
+        "https://doubleclick.net/ad.js",
+        PAGE,
+        ResourceType::Script
+    ));
+    assert!(!blocks(
+        &blocker,
+        "https://ads.example.com/",
+        "",
+        ResourceType::Document
+    ));
+    assert!(blocker.hiding_selectors("www.example.com").is_empty());
+    // ...but not those from elsewhere.
+    let other = "https://other.test/";
+    assert!(blocks(
+        &blocker,
+        "https://ads.example.com/",
+        other,
+        ResourceType::Script
+    ));
+    assert_eq!(
+        blocker.hiding_selectors("other.test"),
+        vec![".ad-banner", "#sponsored"]
+    );
+
+    assert!(blocker.allowlist().remove("example.com").unwrap());
+    assert!(!blocker.allowlist().remove("example.com").unwrap());
+    assert!(blocks(
+        &blocker,
+        "https://doubleclick.net/ad.js",
+        PAGE,
+        ResourceType::Script
+    ));
+}
+
+#[test]
+fn allowlist_is_saved() {
+    let dir = std::env::temp_dir().join(format!("nyan-adblock-allowlist-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let file = dir.join("allowlist.txt");
+    let allowlist = Allowlist::open(&file).unwrap();
+    assert!(allowlist.sites().is_empty());
+    allowlist.add("shop.test").unwrap();
+    allowlist.add("news.test").unwrap();
+    assert!(Allowlist::open(&file).unwrap().contains("www.shop.test"));
+
+    // Edited by hand.
+    std::fs::write(&file, "# mine\n\nWiki.test\nnews.test\n").unwrap();
+    let allowlist = Allowlist::open(&file).unwrap();
+    assert_eq!(allowlist.sites(), vec!["news.test", "wiki.test"]);
+    assert!(!allowlist.contains("shop.test"));
+    assert!(!allowlist.contains("notwiki.test"));
+    assert!(allowlist.add("not a site").is_err());
+}
+
+#[test]
+fn counts_blocked_requests_by_page_list_and_rule() {
+    let blocker = blocker();
+    blocker.add_filter("||custom.test^").unwrap();
+    let other = "https://other.test/";
+    assert!(blocks(
+        &blocker,
+        "https://doubleclick.net/a.js",
+        PAGE,
+        ResourceType::Script
+    ));
+    assert!(blocks(
+        &blocker,
+        "https://doubleclick.net/b.js",
+        PAGE,
+        ResourceType::Script
+    ));
+    assert!(blocks(
+        &blocker,
+        "https://custom.test/c.js",
+        other,
+        ResourceType::Script
+    ));
+    assert!(blocks(
+        &blocker,
+        "https://x.test/banner/ads/1.png",
+        other,
+        ResourceType::Image
+    ));
+    assert!(!blocks(
+        &blocker,
+        "https://example.org/",
+        other,
+        ResourceType::Script
+    ));
+    // Checking alone doesn't count.
+    let request = Request::new("https://doubleclick.net/", None, ResourceType::Document);
+    assert!(blocker.check(&request).is_blocked());
+
+    let global = blocker.stats().global();
+    assert_eq!(global.total, 4);
+    assert_eq!(global.by_list["EasyList sample"], 3);
+    assert_eq!(global.by_list[CUSTOM_LIST], 1);
+    assert_eq!(global.top_rules(1), vec![("||doubleclick.net^", 2)]);
+
+    let page = blocker.stats().page(&format!("{}#comments", PAGE)).unwrap();
+    assert_eq!(page.total, 2);
+    assert_eq!(page.by_rule.len(), 1);
+    assert_eq!(blocker.stats().page(other).unwrap().by_rule.len(), 2);
+    let pages: Vec<_> = blocker
+        .stats()
+        .pages()
+        .into_iter()
+        .map(|(url, _)| url)
+        .collect();
+    assert_eq!(pages, vec![other, PAGE]);
+
+    blocker.stats().reset();
+    assert_eq!(blocker.stats().global().total, 0);
+    assert!(blocker.stats().page(PAGE).is_none());
+}
+
+#[test]
+fn cached_filters_remember_their_list() {
+    let dir = std::env::temp_dir().join(format!("nyan-adblock-origin-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    let untitled = dir.join("untitled.txt");
+    std::fs::write(&untitled, "||ads.test^\n").unwrap();
+    let lists = [easylist(), untitled];
+    let cache = dir.join("filters.json");
+    AdBlocker::new().load_lists_cached(&lists, &cache);
+
+    let blocker = AdBlocker::new();
+    blocker.load_lists_cached(&lists, &cache);
+    blocker.should_block("https://ads.test/", None, ResourceType::Document);
+    blocker.should_block("https://doubleclick.net/", None, ResourceType::Document);
+    let by_list = blocker.stats().global().by_list;
+    assert_eq!(by_list["untitled.txt"], 1);
+    assert_eq!(by_list["EasyList sample"], 1);
+}
+
+#[tokio::test]
+async fn blocked_requests_show_up_in_stats() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config.adblock_lists = vec![easylist()];
+    let browser = NyanBrowser::new(config).await.unwrap();
+
+    browser.navigate("https://doubleclick.net/").await.unwrap();
+    let stats = browser.get_stats();
+    assert!(
+        stats.starts_with("Ad blocking: on, 1 requests blocked"),
+        "{}",
+        stats
+    );
+    assert!(stats.contains("EasyList sample: 1"));
+    assert!(stats.contains("||doubleclick.net^: 1"));
+
+    // The allowlist lives next to config.toml.
+    browser
+        .ad_blocker()
+        .allowlist()
+        .add("doubleclick.net")
+        .unwrap();
+    let config_dir = browser.get_config().config_dir.clone();
+    assert!(config_dir.join(ALLOWLIST_FILE).exists());
+    browser.navigate("https://doubleclick.net/").await.unwrap();
+    assert_eq!(driver.commands_named("goto").len(), 1);
+}
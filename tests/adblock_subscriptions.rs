@@ -0,0 +1,268 @@
+use hyper::body::Bytes;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Response, Server, StatusCode};
+use nyan_browser::config::BrowserConfig;
+use nyan_browser::features::adblock::subscription::{
+    parse_expires, verify_checksum, ListHeader, SUBSCRIPTIONS_FILE,
+};
+use nyan_browser::features::adblock::{RefreshOutcome, ResourceType, SubscriptionManager};
+use nyan_browser::test_util::MockWebDriver;
+use nyan_browser::NyanBrowser;
+use std::convert::Infallible;
+use std::fs;
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use url::Url;
+
+fn fixture(name: &str) -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/adblock")
+        .join(name)
+}
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "nyan-subscriptions-{}-{}",
+        name,
+        std::process::id()
+    ));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn config(dir: &Path) -> BrowserConfig {
+    BrowserConfig {
+        config_dir: dir.join("config"),
+        data_dir: dir.join("data"),
+        ..BrowserConfig::default()
+    }
+}
+
+fn file_url(path: &Path) -> String {
+    Url::from_file_path(path).unwrap().to_string()
+}
+
+fn updated(url: &str) -> RefreshOutcome {
+    RefreshOutcome::Updated {
+        url: url.to_string(),
+    }
+}
+
+#[test]
+fn parses_list_headers() {
+    let list = fs::read_to_string(fixture("checksummed.txt")).unwrap();
+    assert_eq!(
+        ListHeader::parse(&list),
+        ListHeader {
+            title: Some("Nyan Test List".to_string()),
+            version: Some("202610180930".to_string()),
+            expires_hours: Some(96),
+            checksum: Some("ag2is3nR1DMpSzRW4s9P3Q".to_string()),
+        }
+    );
+    assert_eq!(parse_expires("12 hours"), Some(12));
+    assert_eq!(parse_expires("2 days (update frequency)"), Some(48));
+    // Clamped to between an hour and two weeks.
+    assert_eq!(parse_expires("0 hours"), Some(1));
+    assert_eq!(parse_expires("90 days"), Some(14 * 24));
+    assert_eq!(parse_expires("soon"), None);
+}
+
+#[test]
+fn verifies_checksums() {
+    let list = fs::read_to_string(fixture("checksummed.txt")).unwrap();
+    verify_checksum(&list).unwrap();
+    // Line endings and blank lines don't count, the filters do.
+    verify_checksum(&list.replace("\r\n", "\n").replace("\n\n", "\n")).unwrap();
+    assert!(verify_checksum(&list.replace("promo", "banner")).is_err());
+    // Lists without a checksum are accepted as they are.
+    verify_checksum("[Adblock Plus 2.0]\n||a.test^\n").unwrap();
+}
+
+#[tokio::test]
+async fn subscribes_to_local_files() {
+    let dir = temp_dir("file");
+    let list = dir.join("list.txt");
+    fs::copy(fixture("checksummed.txt"), &list).unwrap();
+    let url = file_url(&list);
+
+    let manager = SubscriptionManager::open(&config(&dir)).unwrap();
+    assert!(manager.subscribe(&url, None).unwrap());
+    assert!(!manager.subscribe(&url, None).unwrap());
+    assert!(manager.list_paths().is_empty());
+
+    assert_eq!(manager.refresh(false).await, vec![updated(&url)]);
+    let subscription = manager.get(&url).unwrap();
+    assert_eq!(subscription.title.as_deref(), Some("Nyan Test List"));
+    assert_eq!(subscription.version.as_deref(), Some("202610180930"));
+    assert_eq!(subscription.expires_hours, Some(96));
+    assert!(subscription.last_updated.is_some());
+    assert!(subscription.last_error.is_none());
+    assert_eq!(manager.list_paths().len(), 1);
+
+    // Not due again for four days, unless forced.
+    assert!(manager.refresh(false).await.is_empty());
+    assert_eq!(manager.refresh(true).await, vec![updated(&url)]);
+
+    // The state lives next to config.toml and survives a restart.
+    assert!(dir.join("config").join(SUBSCRIPTIONS_FILE).exists());
+    let reopened = SubscriptionManager::open(&config(&dir)).unwrap();
+    assert_eq!(reopened.list(), manager.list());
+
+    assert!(manager.unsubscribe(&url).unwrap());
+    assert!(manager.list_paths().is_empty());
+}
+
+#[tokio::test]
+async fn failed_updates_keep_the_cached_copy() {
+    let dir = temp_dir("fallback");
+    let list = dir.join("list.txt");
+    fs::copy(fixture("checksummed.txt"), &list).unwrap();
+    let url = file_url(&list);
+    let manager = SubscriptionManager::open(&config(&dir)).unwrap();
+    manager.subscribe(&url, Some("Mine")).unwrap();
+    manager.refresh(false).await;
+    let cached = manager.list_paths().pop().unwrap();
+    let good = fs::read_to_string(&cached).unwrap();
+
+    // Tampered with: the checksum no longer matches.
+    fs::write(&list, good.replace("promo", "banner")).unwrap();
+    let outcomes = manager.refresh(true).await;
+    assert!(
+        matches!(&outcomes[..], [RefreshOutcome::Failed { error, .. }] if error.contains("checksum"))
+    );
+    assert_eq!(fs::read_to_string(&cached).unwrap(), good);
+
+    // Gone, or replaced by an error page.
+    fs::remove_file(&list).unwrap();
+    assert!(matches!(
+        &manager.refresh(true).await[..],
+        [RefreshOutcome::Failed { .. }]
+    ));
+    fs::write(&list, "<html>Service unavailable</html>").unwrap();
+    assert!(matches!(
+        &manager.refresh(true).await[..],
+        [RefreshOutcome::Failed { .. }]
+    ));
+    assert_eq!(fs::read_to_string(&cached).unwrap(), good);
+
+    let subscription = manager.get(&url).unwrap();
+    assert!(subscription.last_error.is_some());
+    assert_eq!(subscription.title.as_deref(), Some("Mine"));
+    // Failed lists are retried after an hour rather than four days.
+    let retry = subscription.next_update().unwrap() - subscription.last_checked.unwrap();
+    assert_eq!(retry.num_hours(), 1);
+    assert_eq!(manager.list_paths(), vec![cached]);
+}
+
+#[tokio::test]
+async fn fetches_from_the_local_mirror() {
+    let dir = temp_dir("mirror");
+    let mirror = dir.join("mirror");
+    fs::create_dir_all(mirror.join("lists.example/v1")).unwrap();
+    fs::copy(
+        fixture("checksummed.txt"),
+        mirror.join("lists.example/v1/nyan.txt"),
+    )
+    .unwrap();
+    fs::copy(fixture("easylist.txt"), mirror.join("easylist.txt")).unwrap();
+
+    let mut config = config(&dir);
+    config.adblock_mirror = Some(mirror);
+    let manager = SubscriptionManager::open(&config).unwrap();
+    let by_path = "https://lists.example/v1/nyan.txt";
+    let by_name = "https://easylist.example/easylist.txt";
+    manager.subscribe(by_path, None).unwrap();
+    manager.subscribe(by_name, None).unwrap();
+
+    // Neither host exists, so these can only come from the mirror.
+    assert_eq!(
+        manager.refresh(false).await,
+        vec![updated(by_path), updated(by_name)]
+    );
+    assert_eq!(
+        manager.get(by_name).unwrap().title.as_deref(),
+        Some("EasyList sample")
+    );
+}
+
+/// Serves `list` at `/list.txt`, redirects `/old.txt` there and streams a
+/// list that never ends at `/endless.txt`.
+async fn serve(list: String) -> SocketAddr {
+    let make = make_service_fn(move |_| {
+        let list = list.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                let response = match request.uri().path() {
+                    "/list.txt" => Response::new(Body::from(list.clone())),
+                    "/endless.txt" => {
+                        let (mut sender, body) = Body::channel();
+                        tokio::spawn(async move {
+                            let chunk = Bytes::from(vec![b'!'; 64 * 1024]);
+                            while sender.send_data(chunk.clone()).await.is_ok() {}
+                        });
+                        Response::new(body)
+                    }
+                    "/old.txt" => Response::builder()
+                        .status(StatusCode::MOVED_PERMANENTLY)
+                        .header("Location", "/list.txt")
+                        .body(Body::empty())
+                        .unwrap(),
+                    _ => Response::builder()
+                        .status(StatusCode::NOT_FOUND)
+                        .body(Body::empty())
+                        .unwrap(),
+                };
+                async move { Ok::<_, Infallible>(response) }
+            }))
+        }
+    });
+    let server = Server::bind(&([127, 0, 0, 1], 0).into()).serve(make);
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+#[tokio::test]
+async fn downloads_over_http() {
+    let addr = serve(fs::read_to_string(fixture("checksummed.txt")).unwrap()).await;
+    let dir = temp_dir("http");
+    let manager = SubscriptionManager::open(&config(&dir)).unwrap();
+    let moved = format!("http://{}/old.txt", addr);
+    let missing = format!("http://{}/missing.txt", addr);
+    let endless = format!("http://{}/endless.txt", addr);
+    manager.subscribe(&moved, None).unwrap();
+    manager.subscribe(&missing, None).unwrap();
+    manager.subscribe(&endless, None).unwrap();
+
+    let outcomes = manager.refresh(false).await;
+    assert_eq!(outcomes[0], updated(&moved));
+    assert!(matches!(&outcomes[1], RefreshOutcome::Failed { error, .. } if error.contains("404")));
+    assert!(
+        matches!(&outcomes[2], RefreshOutcome::Failed { error, .. } if error.contains("too big"))
+    );
+}
+
+#[tokio::test]
+async fn browser_blocks_with_subscribed_lists() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let list = driver.config().data_dir.with_file_name("list.txt");
+    fs::create_dir_all(list.parent().unwrap()).unwrap();
+    fs::write(&list, "[Adblock Plus 2.0]\n||first.test^\n").unwrap();
+
+    let config = driver.config();
+    let manager = SubscriptionManager::open(&config).unwrap();
+    manager.subscribe(&file_url(&list), None).unwrap();
+    manager.refresh(false).await;
+
+    let browser = NyanBrowser::new(config).await.unwrap();
+    let blocker = browser.ad_blocker();
+    assert!(blocker.should_block("https://first.test/", None, ResourceType::Document));
+
+    fs::write(&list, "[Adblock Plus 2.0]\n||second.test^\n").unwrap();
+    let outcomes = browser.refresh_filter_lists(true).await;
+    assert!(matches!(&outcomes[..], [RefreshOutcome::Updated { .. }]));
+    assert!(!blocker.should_block("https://first.test/", None, ResourceType::Document));
+    assert!(blocker.should_block("https://second.test/", None, ResourceType::Document));
+}
@@ -0,0 +1,70 @@
+use nyan_browser::assets::{self, Assets};
+use nyan_browser::constants::templates::default_page;
+use nyan_browser::test_util::MockWebDriver;
+use nyan_browser::NyanBrowser;
+use std::fs;
+use std::path::PathBuf;
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nyan-assets-{}-{}", name, std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(dir.join("templates")).unwrap();
+    dir
+}
+
+#[test]
+fn every_asset_is_embedded() {
+    let assets = Assets::embedded();
+    for name in Assets::names() {
+        assert!(!assets.text(name).is_empty(), "{} is empty", name);
+    }
+    assert!(assets.get("templates/missing.html").is_none());
+}
+
+#[test]
+fn dev_dir_overrides_and_falls_back() {
+    let dir = temp_dir("override");
+    let assets = Assets::with_dev_dir(&dir);
+
+    // Nothing overridden yet.
+    assert_eq!(
+        assets.text(assets::HOME_HTML),
+        Assets::embedded().text(assets::HOME_HTML)
+    );
+
+    // Edits show up without reloading anything.
+    let home = dir.join(assets::HOME_HTML);
+    fs::write(&home, "<h1>first</h1>").unwrap();
+    assert_eq!(assets.text(assets::HOME_HTML), "<h1>first</h1>");
+    fs::write(&home, "<h1>second</h1>").unwrap();
+    assert_eq!(assets.text(assets::HOME_HTML), "<h1>second</h1>");
+
+    // Files outside the known set are never read.
+    fs::write(dir.join("templates/extra.html"), "nope").unwrap();
+    assert!(assets.get("templates/extra.html").is_none());
+}
+
+#[tokio::test]
+async fn default_page_works_inside_a_runtime() {
+    let page = default_page(&Assets::embedded());
+    assert!(page.starts_with("<style>"));
+    assert!(page.contains(&*Assets::embedded().text(assets::HOME_HTML)));
+}
+
+#[tokio::test]
+async fn custom_page_is_passed_as_an_argument() {
+    let dir = temp_dir("escape");
+    let home = r#"<p class="motd">It's a "kawaii" day</p>`${alert(1)}`"#;
+    fs::write(dir.join(assets::HOME_HTML), home).unwrap();
+
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config.assets_dev_dir = Some(dir);
+    let browser = NyanBrowser::new(config).await.unwrap();
+
+    browser.setup_custom_page().await.unwrap();
+    let execute = driver.commands_named("execute").pop().unwrap();
+    assert!(!execute.script().unwrap().contains("kawaii\" day"));
+    let html = execute.body["args"][0].as_str().unwrap();
+    assert!(html.ends_with(home));
+}
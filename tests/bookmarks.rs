@@ -0,0 +1,267 @@
+use nyan_browser::features::workspace::Bookmark;
+use nyan_browser::test_util::MockWebDriver;
+use nyan_browser::utils::BookmarkManager;
+use nyan_browser::NyanBrowser;
+use std::path::{Path, PathBuf};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nyan-bookmarks-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn open(dir: &Path) -> BookmarkManager {
+    BookmarkManager::open_with_legacy(dir, &dir.join("no-legacy.json")).unwrap()
+}
+
+#[test]
+fn dedupes_by_url_and_merges_tags() {
+    let dir = temp_dir("dedupe");
+    let bookmarks = open(&dir);
+
+    let rust = Bookmark::new("https://www.rust-lang.org/", "Rust").with_tags(&["lang"]);
+    assert!(bookmarks.add(rust).unwrap());
+    let again = Bookmark::new("https://www.rust-lang.org/", "Rust Language")
+        .in_folder(&["Dev"])
+        .with_tags(&["lang", "fav"]);
+    assert!(!bookmarks.add(again).unwrap());
+
+    assert_eq!(bookmarks.len(), 1);
+    let rust = bookmarks.get("https://www.rust-lang.org/").unwrap();
+    assert_eq!(rust.title, "Rust Language");
+    assert_eq!(rust.folder, ["Dev"]);
+    assert_eq!(rust.tags, ["lang", "fav"]);
+    assert_eq!(bookmarks.tags(), ["fav", "lang"]);
+    assert_eq!(bookmarks.with_tag("fav").len(), 1);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn folders_can_be_renamed_moved_and_removed() {
+    let dir = temp_dir("folders");
+    let bookmarks = open(&dir);
+    bookmarks
+        .add(Bookmark::new("https://tokio.rs/", "Tokio").in_folder(&["Dev", "Async"]))
+        .unwrap();
+    bookmarks
+        .add(Bookmark::new("https://docs.rs/", "Docs").in_folder(&["Dev"]))
+        .unwrap();
+    bookmarks.create_folder(&["Reading"]).unwrap();
+    assert_eq!(
+        bookmarks.folders(),
+        [vec!["Dev"], vec!["Dev", "Async"], vec!["Reading"]]
+    );
+
+    bookmarks.rename_folder(&["Dev"], "Code").unwrap();
+    assert_eq!(
+        bookmarks.get("https://tokio.rs/").unwrap().folder,
+        ["Code", "Async"]
+    );
+
+    bookmarks
+        .move_folder(&["Code", "Async"], &["Reading"])
+        .unwrap();
+    assert_eq!(bookmarks.in_folder(&["Reading", "Async"]).len(), 1);
+    assert!(bookmarks
+        .move_folder(&["Reading"], &["Reading", "Async"])
+        .is_err());
+    assert!(bookmarks.rename_folder(&["Reading"], "Code").is_err());
+
+    bookmarks.move_to("https://docs.rs/", &[]).unwrap();
+    bookmarks.rename("https://docs.rs/", "docs.rs").unwrap();
+    assert_eq!(bookmarks.remove_folder(&["Reading"]).unwrap(), 1);
+    assert_eq!(bookmarks.folders(), [vec!["Code"]]);
+    drop(bookmarks);
+
+    let reopened = open(&dir);
+    assert_eq!(reopened.list().len(), 1);
+    assert_eq!(reopened.in_folder(&[])[0].title, "docs.rs");
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn migrates_the_legacy_file_once() {
+    let dir = temp_dir("migrate");
+    let legacy = dir.join("kawaii_bookmarks.json");
+    std::fs::write(
+        &legacy,
+        r#"[
+            {"url": "https://example.com/", "title": "Example", "tags": ["demo"], "timestamp": "2025-01-25T23:06:15.154141+08:00"},
+            {"url": "https://example.com/", "name": "Example again"},
+            {"title": "no url"}
+        ]"#,
+    )
+    .unwrap();
+
+    let data_dir = dir.join("data");
+    let bookmarks = BookmarkManager::open_with_legacy(&data_dir, &legacy).unwrap();
+    assert_eq!(bookmarks.len(), 1);
+    let example = bookmarks.get("https://example.com/").unwrap();
+    assert_eq!(example.tags, ["demo"]);
+    assert_eq!(
+        example.created_at.to_rfc3339(),
+        "2025-01-25T15:06:15.154141+00:00"
+    );
+    assert!(!legacy.exists());
+    assert!(dir.join("kawaii_bookmarks.json.migrated").exists());
+
+    bookmarks.remove("https://example.com/").unwrap();
+    std::fs::write(&legacy, r#"[{"url": "https://late.example/"}]"#).unwrap();
+    let reopened = BookmarkManager::open_with_legacy(&data_dir, &legacy).unwrap();
+    assert!(reopened.is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn bookmarks_the_current_page() {
+    let driver = MockWebDriver::start().await.unwrap();
+    driver.set_title("https://example.com/", "Example Domain");
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+
+    browser.navigate("https://example.com/").await.unwrap();
+    assert!(browser.bookmark_current_page(&["Saved"]).await.unwrap());
+
+    let bookmark = browser
+        .bookmarks()
+        .unwrap()
+        .get("https://example.com/")
+        .unwrap();
+    assert_eq!(bookmark.title, "Example Domain");
+    assert_eq!(bookmark.folder, ["Saved"]);
+}
+
+fn fixture(name: &str) -> String {
+    std::fs::read_to_string(
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("tests/fixtures/bookmarks")
+            .join(name),
+    )
+    .unwrap()
+}
+
+#[test]
+fn imports_firefox_bookmarks() {
+    let dir = temp_dir("firefox");
+    let bookmarks = open(&dir);
+    assert_eq!(
+        bookmarks
+            .import_html(&fixture("firefox.html"), &[])
+            .unwrap(),
+        4
+    );
+
+    let tokio = bookmarks.get("https://tokio.rs/").unwrap();
+    assert_eq!(tokio.title, "Tokio — an asynchronous runtime");
+    assert_eq!(tokio.folder, ["Dev & Docs", "Async"]);
+    assert_eq!(tokio.tags, ["rust", "async"]);
+    assert_eq!(tokio.created_at.timestamp(), 1_700_002_100);
+
+    let example = bookmarks.get("https://example.com/?a=1&b=2").unwrap();
+    assert_eq!(example.title, "<Example> \"quoted\"");
+    assert_eq!(example.folder, ["Bookmarks Toolbar"]);
+    assert!(bookmarks
+        .folders()
+        .contains(&vec!["Dev & Docs".to_string(), "Empty".to_string()]));
+    assert_eq!(
+        bookmarks
+            .folder_created(&["Dev & Docs", "Async"])
+            .unwrap()
+            .timestamp(),
+        1_700_002_000
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_folder_without_a_list_does_not_capture_the_next_one() {
+    let dir = temp_dir("no-list");
+    let bookmarks = open(&dir);
+    let html = r#"<DL><p>
+    <DT><H3 ADD_DATE="1700000000">Stray</H3>
+    <DT><A HREF="https://a.example/">A</A>
+    <DL><p>
+        <DT><A HREF="https://b.example/">B</A>
+    </DL><p>
+</DL><p>"#;
+    assert_eq!(bookmarks.import_html(html, &[]).unwrap(), 2);
+
+    assert!(bookmarks
+        .get("https://a.example/")
+        .unwrap()
+        .folder
+        .is_empty());
+    assert!(bookmarks
+        .get("https://b.example/")
+        .unwrap()
+        .folder
+        .is_empty());
+    assert!(bookmarks.folders().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn exports_what_it_imported() {
+    let dir = temp_dir("export");
+    let bookmarks = open(&dir);
+    bookmarks
+        .import_html(&fixture("firefox.html"), &[])
+        .unwrap();
+
+    let exported = bookmarks.export_html();
+    assert_eq!(exported, fixture("firefox_exported.html"));
+
+    let round_trip = open(&temp_dir("export-again"));
+    round_trip.import_html(&exported, &[]).unwrap();
+    assert_eq!(round_trip.list(), bookmarks.list());
+    assert_eq!(round_trip.folders(), bookmarks.folders());
+    assert_eq!(round_trip.export_html(), exported);
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_dir_all(temp_dir("export-again")).unwrap();
+}
+
+#[test]
+fn imports_chrome_bookmarks_into_a_folder_without_duplicates() {
+    let dir = temp_dir("chrome");
+    let bookmarks = open(&dir);
+    bookmarks
+        .add(Bookmark::new("https://www.rust-lang.org/", "Rust").with_tags(&["lang"]))
+        .unwrap();
+
+    let path = dir.join("chrome.html");
+    std::fs::write(&path, fixture("chrome.html")).unwrap();
+    assert_eq!(
+        bookmarks.import_html_file(&path, &["From Chrome"]).unwrap(),
+        2
+    );
+
+    assert_eq!(bookmarks.len(), 3);
+    assert_eq!(
+        bookmarks.in_folder(&["From Chrome", "Bookmarks bar", "Anime"])[0].url,
+        "https://myanimelist.net/"
+    );
+    let rust = bookmarks.get("https://www.rust-lang.org/").unwrap();
+    assert_eq!(rust.folder, ["From Chrome"]);
+    assert_eq!(rust.tags, ["lang"]);
+
+    let exported = dir.join("exported.html");
+    bookmarks.export_html_file(&exported).unwrap();
+    let reimported = open(&temp_dir("chrome-again"));
+    reimported.import_html_file(&exported, &[]).unwrap();
+    // ADD_DATE only has whole seconds.
+    let summary = |b: Bookmark| (b.url, b.title, b.folder, b.tags, b.created_at.timestamp());
+    assert_eq!(
+        reimported
+            .list()
+            .into_iter()
+            .map(summary)
+            .collect::<Vec<_>>(),
+        bookmarks
+            .list()
+            .into_iter()
+            .map(summary)
+            .collect::<Vec<_>>()
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+    std::fs::remove_dir_all(temp_dir("chrome-again")).unwrap();
+}
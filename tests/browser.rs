@@ -0,0 +1,141 @@
+use nyan_browser::config::{BrowserConfig, DriverKind};
+use nyan_browser::core::driver::RemoteBackend;
+use nyan_browser::core::error::BrowserError;
+use nyan_browser::test_util::{MockReply, MockWebDriver};
+use nyan_browser::NyanBrowser;
+
+async fn browser() -> (MockWebDriver, NyanBrowser) {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    (driver, browser)
+}
+
+#[tokio::test]
+async fn navigate_sends_goto() {
+    let (driver, browser) = browser().await;
+
+    browser.navigate("https://example.com/").await.unwrap();
+
+    let gotos = driver.commands_named("goto");
+    assert_eq!(gotos.len(), 1);
+    assert_eq!(gotos[0].body["url"], "https://example.com/");
+}
+
+#[tokio::test]
+async fn navigate_surfaces_driver_errors() {
+    let (driver, browser) = browser().await;
+    driver.queue("goto", MockReply::error("unknown error", "dns failure"));
+
+    let err = browser.navigate("https://example.com/").await.unwrap_err();
+
+    assert!(matches!(err, BrowserError::NavigationError(ref m) if m.contains("dns failure")));
+}
+
+#[tokio::test]
+async fn cleanup_clears_storage_and_cookies() {
+    let (driver, mut browser) = browser().await;
+
+    browser.cleanup().await.unwrap();
+
+    let scripts = driver.executed_scripts();
+    assert!(scripts.iter().any(|s| s.contains("localStorage.clear")));
+    assert!(scripts.iter().any(|s| s.contains("sessionStorage.clear")));
+    assert_eq!(driver.commands_named("delete_all_cookies").len(), 1);
+}
+
+#[tokio::test]
+async fn cleanup_memory_requests_gc() {
+    let (driver, browser) = browser().await;
+
+    browser.cleanup_memory().await.unwrap();
+
+    assert!(driver
+        .executed_scripts()
+        .iter()
+        .any(|s| s.contains("window.gc")));
+}
+
+#[tokio::test]
+async fn shutdown_deletes_the_session() {
+    let (driver, mut browser) = browser().await;
+    assert_eq!(driver.sessions().len(), 1);
+
+    browser.health_check().await.unwrap();
+    browser.shutdown().await.unwrap();
+
+    assert!(driver.sessions().is_empty());
+    assert_eq!(driver.commands_named("delete_session").len(), 1);
+}
+
+#[tokio::test]
+async fn batch_navigate_spreads_urls_over_pooled_sessions() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config.max_sessions = 3;
+    let browser = NyanBrowser::new(config).await.unwrap();
+    let main_session = driver.sessions().pop().unwrap();
+    let urls: Vec<String> = (0..6)
+        .map(|i| format!("https://example.com/{}", i))
+        .collect();
+
+    let results = browser.batch_navigate(&urls).await;
+
+    assert_eq!(results.len(), 6);
+    assert!(results.iter().all(|(_, r)| r.is_ok()));
+    assert_eq!(results[4].0, urls[4]);
+
+    let pooled: std::collections::HashSet<_> = driver
+        .commands_named("goto")
+        .into_iter()
+        .filter_map(|c| c.session_id)
+        .collect();
+    assert_eq!(pooled.len(), 3);
+    assert!(!pooled.contains(&main_session));
+}
+
+#[tokio::test]
+async fn batch_navigate_reports_failures_per_url() {
+    let (driver, browser) = browser().await;
+    driver.queue(
+        "goto",
+        MockReply::error("unknown error", "connection refused"),
+    );
+    let urls = vec![
+        "https://a.example/".to_string(),
+        "https://b.example/".to_string(),
+    ];
+
+    let results = browser.batch_navigate(&urls).await;
+
+    assert_eq!(results.iter().filter(|(_, r)| r.is_err()).count(), 1);
+    assert_eq!(results.iter().filter(|(_, r)| r.is_ok()).count(), 1);
+}
+
+#[tokio::test]
+async fn pooled_sessions_use_the_browsers_backend_and_close_on_drop() {
+    let driver = MockWebDriver::start().await.unwrap();
+    // The config alone would have the pool spawn geckodriver.
+    let config = BrowserConfig {
+        driver: DriverKind::Gecko,
+        webdriver_url: None,
+        ..driver.config()
+    };
+    let browser = NyanBrowser::with_backend(config, Box::new(RemoteBackend::new(driver.url())))
+        .await
+        .unwrap();
+
+    let results = browser
+        .batch_navigate(&["https://example.com/".to_string()])
+        .await;
+    assert!(results[0].1.is_ok());
+    assert_eq!(driver.sessions().len(), 2);
+
+    drop(browser);
+    for _ in 0..50 {
+        if driver.sessions().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert!(driver.sessions().is_empty());
+}
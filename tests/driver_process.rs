@@ -0,0 +1,99 @@
+#![cfg(unix)]
+
+use nyan_browser::core::driver::{DriverCommand, DriverProcess};
+use nyan_browser::core::error::BrowserError;
+use nyan_browser::test_util::MockWebDriver;
+use std::time::Duration;
+
+/// `sleep` stands in for the driver binary while the mock serves `/status`.
+fn command(driver: &MockWebDriver, program: &str) -> DriverCommand {
+    DriverCommand {
+        name: "fake-driver",
+        program: program.to_string(),
+        args: vec!["30".to_string()],
+        url: driver.url(),
+        startup_timeout: Duration::from_millis(500),
+        max_restarts: 1,
+        shutdown_grace: Duration::from_secs(1),
+    }
+}
+
+#[tokio::test]
+async fn restarts_a_crashed_driver_and_stops_it() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut process = DriverProcess::spawn(command(&driver, "sleep"))
+        .await
+        .unwrap();
+    let first_pid = process.pid().unwrap();
+
+    std::process::Command::new("kill")
+        .args(["-9", &first_pid.to_string()])
+        .status()
+        .unwrap();
+    for _ in 0..50 {
+        if process.restarts() == 1 && process.pid().is_some() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+
+    assert_eq!(process.restarts(), 1);
+    assert_ne!(process.pid(), Some(first_pid));
+    assert!(process.is_running());
+
+    process.shutdown().await.unwrap();
+    assert_eq!(process.pid(), None);
+    assert!(!process.is_running());
+}
+
+#[tokio::test]
+async fn fails_when_driver_never_becomes_ready() {
+    let driver = MockWebDriver::start().await.unwrap();
+    driver.set_ready(false);
+
+    let err = DriverProcess::spawn(command(&driver, "sleep"))
+        .await
+        .err()
+        .unwrap();
+
+    assert!(matches!(err, BrowserError::DriverStartError(ref m) if m.contains("ready")));
+}
+
+#[tokio::test]
+async fn gives_up_on_a_driver_that_never_answers() {
+    let driver = MockWebDriver::start().await.unwrap();
+    // Takes connections and never replies.
+    let silent = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}", silent.local_addr().unwrap());
+    tokio::spawn(async move {
+        let mut held = Vec::new();
+        while let Ok((stream, _)) = silent.accept().await {
+            held.push(stream);
+        }
+    });
+
+    let started = std::time::Instant::now();
+    let err = DriverProcess::spawn(DriverCommand {
+        url,
+        ..command(&driver, "sleep")
+    })
+    .await
+    .err()
+    .unwrap();
+
+    assert!(matches!(err, BrowserError::DriverStartError(ref m) if m.contains("ready")));
+    assert!(started.elapsed() < Duration::from_secs(5));
+}
+
+#[tokio::test]
+async fn fails_when_driver_exits_during_startup() {
+    let driver = MockWebDriver::start().await.unwrap();
+    driver.set_ready(false);
+
+    let err = DriverProcess::spawn(command(&driver, "false"))
+        .await
+        .err()
+        .unwrap();
+
+    assert!(matches!(err, BrowserError::DriverStartError(ref m) if m.contains("exited")));
+}
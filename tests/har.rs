@@ -0,0 +1,346 @@
+use hyper::{Body, Request};
+use nyan_browser::features::network::{
+    Har, HttpsInterception, InterceptingProxy, NetworkEntry, NetworkMonitor, NetworkQuery,
+    RequestData, ResponseData, StatusClass, Timings,
+};
+use nyan_browser::test_util::TestHttpServer;
+use serde_json::Value;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::net::TcpStream;
+
+const FIXTURE: &str = "tests/fixtures/har/example.har";
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("nyan-har-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+/// Everything recorded, oldest first.
+fn entries(monitor: &NetworkMonitor) -> Vec<NetworkEntry> {
+    let mut entries = monitor.query(&NetworkQuery::default());
+    entries.reverse();
+    entries
+}
+
+fn import(path: &Path) -> Vec<NetworkEntry> {
+    let monitor = NetworkMonitor::new();
+    monitor.import_har(path).unwrap();
+    entries(&monitor)
+}
+
+fn ms(millis: u64) -> Option<Duration> {
+    Some(Duration::from_millis(millis))
+}
+
+/// Timings to the microsecond, as HAR milliseconds don't convert back
+/// exactly.
+fn rounded(timings: Option<Timings>) -> Option<[Option<u128>; 5]> {
+    timings.map(|t| {
+        [t.dns, t.connect, t.tls, Some(t.ttfb), Some(t.download)]
+            .map(|phase| phase.map(|phase| (phase.as_nanos() + 500) / 1000))
+    })
+}
+
+#[test]
+fn imports_har_files() {
+    let monitor = NetworkMonitor::new();
+    // Something already recorded keeps its place.
+    monitor.record_request(RequestData {
+        url: "https://before.test/".to_string(),
+        method: "GET".to_string(),
+        headers: Vec::new(),
+        body: None,
+    });
+    assert_eq!(monitor.import_har(Path::new(FIXTURE)).unwrap(), 5);
+    let entries = entries(&monitor);
+    assert_eq!(entries.len(), 6);
+    let [_, page, css, png, login, pixel] = &entries[..] else {
+        unreachable!()
+    };
+
+    assert_eq!(page.request.url, "https://example.com/");
+    assert_eq!(page.started.to_rfc3339(), "2024-05-01T08:00:00+00:00");
+    let response = page.response.as_ref().unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.content_type.as_deref(),
+        Some("text/html; charset=UTF-8")
+    );
+    assert_eq!(
+        response.body.as_deref(),
+        Some(&b"<html><body><h1>Example Domain</h1></body></html>"[..])
+    );
+    assert!(!response.truncated);
+    let timings = page.timings.unwrap();
+    assert_eq!(
+        (timings.dns, timings.connect, timings.tls),
+        (ms(5), ms(10), ms(20))
+    );
+    assert_eq!(
+        (timings.ttfb, timings.download),
+        (ms(25).unwrap(), ms(3).unwrap())
+    );
+
+    // Pages group their entries around the first one.
+    assert_eq!(page.page, Some(page.id));
+    assert_eq!(css.page, Some(page.id));
+    assert_eq!(png.page, Some(page.id));
+    assert_eq!(login.page, Some(login.id));
+    assert_eq!(pixel.page, Some(login.id));
+
+    let png = png.response.as_ref().unwrap();
+    assert_eq!(png.body.as_deref(), Some(&b"\x89PNG\r\n\x1a\n"[..]));
+    assert_eq!(
+        login.request.body.as_deref(),
+        Some(&br#"{"user":"nyan","password":"meow"}"#[..])
+    );
+    assert_eq!(login.response.as_ref().unwrap().status, 302);
+    assert_eq!(login.timings.unwrap().ttfb, ms(38).unwrap());
+
+    assert!(pixel.response.is_none());
+    assert_eq!(pixel.error.as_deref(), Some("net::ERR_BLOCKED_BY_CLIENT"));
+    let failed = NetworkQuery {
+        status: Some(StatusClass::Failed),
+        ..NetworkQuery::default()
+    };
+    assert_eq!(monitor.query(&failed).len(), 1);
+}
+
+#[test]
+fn round_trips_through_export() {
+    let dir = temp_dir("round-trip");
+    let monitor = NetworkMonitor::new();
+    monitor.import_har(Path::new(FIXTURE)).unwrap();
+    let exported = dir.join("exported.har");
+    monitor.export_har(&exported).unwrap();
+
+    let before = import(Path::new(FIXTURE));
+    let after = import(&exported);
+    assert_eq!(before.len(), after.len());
+    for (before, after) in before.iter().zip(&after) {
+        assert_eq!(before.started, after.started);
+        assert_eq!(before.request.url, after.request.url);
+        assert_eq!(before.request.method, after.request.method);
+        assert_eq!(before.request.headers, after.request.headers);
+        assert_eq!(before.request.body, after.request.body);
+        assert_eq!(before.response, after.response);
+        assert_eq!(before.error, after.error);
+        assert_eq!(rounded(before.timings), rounded(after.timings));
+        assert_eq!(
+            before.page.map(|page| before.id - page),
+            after.page.map(|page| after.id - page)
+        );
+    }
+
+    let har: Value = serde_json::from_str(&std::fs::read_to_string(&exported).unwrap()).unwrap();
+    let log = &har["log"];
+    assert_eq!(log["version"], "1.2");
+    assert_eq!(log["creator"]["name"], "Nyan Browser");
+    assert_eq!(log["pages"].as_array().unwrap().len(), 2);
+    for page in log["pages"].as_array().unwrap() {
+        assert!(page["startedDateTime"].is_string() && page["pageTimings"].is_object());
+    }
+    for entry in log["entries"].as_array().unwrap() {
+        for field in [
+            "startedDateTime",
+            "time",
+            "request",
+            "response",
+            "cache",
+            "timings",
+        ] {
+            assert!(!entry[field].is_null(), "entry without {}", field);
+        }
+        for field in ["cookies", "headers", "queryString"] {
+            assert!(entry["request"][field].is_array());
+        }
+        assert!(entry["response"]["content"]["size"].is_number());
+        assert!(entry["response"]["content"]["mimeType"].is_string());
+        assert!(entry["response"]["redirectURL"].is_string());
+        for phase in ["send", "wait", "receive"] {
+            assert!(entry["timings"][phase].as_f64().unwrap() >= 0.0);
+        }
+    }
+    let css = &log["entries"][1];
+    assert_eq!(css["pageref"], log["pages"][0]["id"]);
+    assert_eq!(css["request"]["queryString"][1]["name"], "theme");
+    assert_eq!(css["request"]["cookies"][0]["value"], "abc123");
+    assert_eq!(
+        log["entries"][0]["response"]["cookies"][0]["name"],
+        "session"
+    );
+    assert_eq!(log["entries"][0]["timings"]["connect"], 30.0);
+    assert_eq!(
+        log["entries"][2]["response"]["content"]["encoding"],
+        "base64"
+    );
+    assert_eq!(
+        log["entries"][3]["response"]["redirectURL"],
+        "https://example.com/home"
+    );
+    assert_eq!(log["entries"][4]["response"]["status"], 0);
+    assert_eq!(log["entries"][4]["_error"], "net::ERR_BLOCKED_BY_CLIENT");
+}
+
+#[test]
+fn exports_compressed_bodies_decoded() {
+    use flate2::write::{GzEncoder, ZlibEncoder};
+    use std::io::Write;
+
+    let text = "hello nyan ".repeat(100);
+    let mut gzip = GzEncoder::new(Vec::new(), flate2::Compression::default());
+    gzip.write_all(text.as_bytes()).unwrap();
+    let gzip = gzip.finish().unwrap();
+    let mut deflate = ZlibEncoder::new(Vec::new(), flate2::Compression::default());
+    deflate.write_all(text.as_bytes()).unwrap();
+    let deflate = deflate.finish().unwrap();
+    // One uncompressed meta-block holding "hello nyan", then the last one.
+    let brotli = [&[0x90, 0x00, 0x10][..], b"hello nyan", &[0x03]].concat();
+
+    let monitor = NetworkMonitor::new();
+    let record = |encoding: &str, body: &[u8], truncated: bool| {
+        let id = monitor
+            .record_request(RequestData {
+                url: format!("https://example.com/{}", encoding),
+                method: "GET".to_string(),
+                headers: Vec::new(),
+                body: None,
+            })
+            .unwrap();
+        let mut response = ResponseData::new(
+            200,
+            vec![("Content-Encoding".to_string(), encoding.to_string())],
+        );
+        response.body_size = body.len() as u64 + if truncated { 100 } else { 0 };
+        response.body = Some(body.to_vec());
+        response.truncated = truncated;
+        monitor.record_response(id, response, Timings::default());
+    };
+    record("gzip", &gzip, false);
+    record("deflate", &deflate, false);
+    record("br", &brotli, false);
+    record("gzip", &gzip[..gzip.len() / 2], true);
+    record("zstd", b"(\xb5/\xfd", false);
+
+    let har = monitor.to_har();
+    let contents: Vec<_> = har
+        .log
+        .entries
+        .iter()
+        .map(|entry| &entry.response.content)
+        .collect();
+    assert_eq!(contents[0].text.as_deref(), Some(text.as_str()));
+    assert_eq!(contents[0].size, text.len() as i64);
+    assert_eq!(contents[1].text.as_deref(), Some(text.as_str()));
+    assert_eq!(contents[2].text.as_deref(), Some("hello nyan"));
+    let partial = contents[3].text.as_deref().unwrap();
+    assert!(text.starts_with(partial) && partial.len() < text.len());
+    assert!(contents[3]
+        .comment
+        .as_deref()
+        .unwrap()
+        .contains("cut short"));
+    assert_eq!(contents[4].text, None);
+    assert_eq!(
+        contents[4].comment.as_deref(),
+        Some("Body not decoded from Content-Encoding: zstd")
+    );
+}
+
+#[test]
+fn timings_too_big_to_hold_do_not_apply() {
+    let entries = import(Path::new("tests/fixtures/har/huge_timings.har"));
+    let timings = entries[0].timings.unwrap();
+    assert_eq!(
+        (timings.dns, timings.connect, timings.tls),
+        (None, ms(4), None)
+    );
+    assert_eq!(
+        (timings.ttfb, timings.download),
+        (Duration::ZERO, Duration::ZERO)
+    );
+
+    // Each phase fits, their sum doesn't.
+    assert_eq!(entries[1].timings.unwrap().total(), Duration::MAX);
+}
+
+#[test]
+fn rejects_what_is_not_a_har_file() {
+    let dir = temp_dir("invalid");
+    let path = dir.join("broken.har");
+    std::fs::write(&path, r#"{"log": {"entries": "nope"}}"#).unwrap();
+    assert!(NetworkMonitor::new().import_har(&path).is_err());
+    std::fs::write(
+        &path,
+        r#"{"log": {"version": "2.0", "creator": {"name": "x", "version": "1"},
+        "entries": []}}"#,
+    )
+    .unwrap();
+    assert!(Har::read(&path).is_err());
+    assert!(NetworkMonitor::new()
+        .import_har(&dir.join("missing.har"))
+        .is_err());
+}
+
+#[tokio::test]
+async fn exports_recorded_traffic_as_pages() {
+    let server = TestHttpServer::start().await.unwrap();
+    let monitor = Arc::new(NetworkMonitor::new());
+    let proxy = InterceptingProxy::start(
+        ([127, 0, 0, 1], 0).into(),
+        Arc::clone(&monitor),
+        None,
+        Arc::default(),
+        HttpsInterception::Off,
+    )
+    .await
+    .unwrap();
+    let stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let requests = [
+        Request::get(server.url("/hello")).header("sec-fetch-dest", "document"),
+        Request::get(server.url("/bytes/3")).header("referer", server.url("/hello")),
+        Request::get(server.url("/status/404")).header("referer", server.url("/")),
+    ];
+    for request in requests {
+        let response = sender
+            .send_request(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        hyper::body::to_bytes(response.into_body()).await.unwrap();
+    }
+    // Responses are recorded once their bodies have gone through.
+    for _ in 0..100 {
+        if entries(&monitor)
+            .iter()
+            .all(|entry| entry.response.is_some())
+        {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+
+    let har = monitor.to_har();
+    assert_eq!(har.log.pages.len(), 1);
+    assert_eq!(har.log.pages[0].title, server.url("/hello"));
+    let entries = &har.log.entries;
+    assert_eq!(entries.len(), 3);
+    assert!(entries
+        .iter()
+        .all(|entry| entry.pageref.as_ref() == Some(&har.log.pages[0].id)));
+    assert_eq!(
+        entries[0].response.content.text.as_deref(),
+        Some("hello nyan")
+    );
+    assert_eq!(entries[0].response.status_text, "OK");
+    assert!(entries[0].timings.connect >= 0.0 && entries[0].timings.ssl < 0.0);
+    assert_eq!(entries[1].response.content.text.as_deref(), Some("aaa"));
+    assert_eq!(entries[2].response.status, 404);
+    assert_eq!(entries[2].response.status_text, "Not Found");
+    assert!(entries.iter().all(|entry| entry.time >= entry.timings.wait));
+}
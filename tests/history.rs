@@ -0,0 +1,202 @@
+use chrono::{Duration, Utc};
+use nyan_browser::test_util::MockWebDriver;
+use nyan_browser::utils::{HistoryQuery, HistoryStore, RetentionPolicy, Visit};
+use nyan_browser::NyanBrowser;
+use std::io::Write;
+
+#[tokio::test]
+async fn navigate_records_visits_with_page_titles() {
+    let driver = MockWebDriver::start().await.unwrap();
+    driver.set_title("https://www.rust-lang.org/", "Rust Programming Language");
+    let config = driver.config();
+    let browser = NyanBrowser::new(config.clone()).await.unwrap();
+
+    browser
+        .navigate("https://www.rust-lang.org/")
+        .await
+        .unwrap();
+    browser.navigate("https://example.com/").await.unwrap();
+    browser
+        .navigate("https://www.rust-lang.org/")
+        .await
+        .unwrap();
+
+    let history = browser.history().unwrap();
+    let rust = history.get("https://www.rust-lang.org/").unwrap();
+    assert_eq!(rust.visit_count, 2);
+    assert_eq!(rust.title, "Rust Programming Language");
+    assert_eq!(history.entries()[0].url, "https://www.rust-lang.org/");
+
+    let reopened = HistoryStore::open(&config.data_dir).unwrap();
+    assert_eq!(reopened.len(), 2);
+    assert_eq!(reopened.visits().len(), 3);
+}
+
+#[test]
+fn keeps_titles_and_skips_torn_lines() {
+    let dir = std::env::temp_dir().join(format!("nyan-history-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = HistoryStore::open(&dir).unwrap();
+
+    store.record_visit("https://a.example/", "A").unwrap();
+    let entry = store.record_visit("https://a.example/", "").unwrap();
+    assert_eq!(entry.title, "A");
+    assert_eq!(entry.visit_count, 2);
+
+    let mut log = std::fs::OpenOptions::new()
+        .append(true)
+        .open(store.path())
+        .unwrap();
+    log.write_all(b"{\"url\": \"https://torn").unwrap();
+    drop(store);
+
+    let reopened = HistoryStore::open(&dir).unwrap();
+    assert_eq!(reopened.get("https://a.example/").unwrap().visit_count, 2);
+
+    // The next visit doesn't get glued onto the torn line.
+    reopened.record_visit("https://b.example/", "B").unwrap();
+    drop(reopened);
+    let reopened = HistoryStore::open(&dir).unwrap();
+    assert_eq!(reopened.get("https://b.example/").unwrap().title, "B");
+    assert_eq!(reopened.visits().len(), 3);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+fn temp_store(name: &str) -> (std::path::PathBuf, HistoryStore) {
+    let dir = std::env::temp_dir().join(format!("nyan-history-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+    let store = HistoryStore::open(&dir).unwrap();
+    (dir, store)
+}
+
+fn visit(store: &HistoryStore, url: &str, title: &str, days_ago: i64) {
+    store
+        .record(Visit {
+            url: url.to_string(),
+            title: title.to_string(),
+            visited_at: Utc::now() - Duration::days(days_ago),
+        })
+        .unwrap();
+}
+
+#[test]
+fn search_matches_all_terms_within_a_date_range() {
+    let (dir, store) = temp_store("search");
+    visit(
+        &store,
+        "https://doc.rust-lang.org/book/",
+        "The Rust Book",
+        40,
+    );
+    visit(
+        &store,
+        "https://doc.rust-lang.org/book/",
+        "The Rust Book",
+        2,
+    );
+    visit(&store, "https://docs.rs/tokio", "tokio - Rust", 1);
+    visit(&store, "https://example.com/", "Example Domain", 1);
+
+    let urls = |query: &HistoryQuery| -> Vec<String> {
+        store.search(query).into_iter().map(|e| e.url).collect()
+    };
+    assert_eq!(
+        urls(&HistoryQuery::text("rust")),
+        ["https://docs.rs/tokio", "https://doc.rust-lang.org/book/"]
+    );
+    assert_eq!(
+        urls(&HistoryQuery::text("RUST book")),
+        ["https://doc.rust-lang.org/book/"]
+    );
+
+    let recent = store.search(&HistoryQuery {
+        since: Some(Utc::now() - Duration::days(7)),
+        ..HistoryQuery::text("book")
+    });
+    assert_eq!(recent.len(), 1);
+    assert_eq!(recent[0].visit_count, 1);
+
+    let old = HistoryQuery {
+        until: Some(Utc::now() - Duration::days(7)),
+        ..HistoryQuery::default()
+    };
+    assert_eq!(urls(&old), ["https://doc.rust-lang.org/book/"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn frecency_prefers_recent_visits_over_old_ones() {
+    let (dir, store) = temp_store("frecency");
+    for _ in 0..3 {
+        visit(&store, "https://old.example/", "", 200);
+    }
+    visit(&store, "https://new.example/", "", 0);
+    visit(&store, "https://new.example/", "", 1);
+
+    assert_eq!(store.most_visited(1)[0].url, "https://old.example/");
+    assert_eq!(store.frecent(1)[0].url, "https://new.example/");
+    assert_eq!(store.frecency("https://new.example/"), 200.0);
+    assert_eq!(store.frecency("https://old.example/"), 30.0);
+    assert_eq!(store.frecency("https://unknown.example/"), 0.0);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn retention_and_clearing_survive_a_reopen() {
+    let (dir, store) = temp_store("retention");
+    visit(&store, "https://a.example/", "", 100);
+    visit(&store, "https://a.example/", "", 1);
+    visit(&store, "https://b.example/", "", 3);
+    visit(&store, "https://c.example/", "", 2);
+
+    let removed = store
+        .apply_retention(&RetentionPolicy {
+            max_age: Some(Duration::days(30)),
+            max_entries: Some(2),
+        })
+        .unwrap();
+    assert_eq!(removed, 2);
+    assert_eq!(store.get("https://a.example/").unwrap().visit_count, 1);
+    assert!(store.get("https://b.example/").is_none());
+
+    store
+        .clear_range(Some(Utc::now() - Duration::hours(36)), None)
+        .unwrap();
+    visit(&store, "https://d.example/", "", 0);
+    drop(store);
+
+    let reopened = HistoryStore::open(&dir).unwrap();
+    let urls: Vec<_> = reopened.entries().into_iter().map(|e| e.url).collect();
+    assert_eq!(urls, ["https://d.example/", "https://c.example/"]);
+    assert_eq!(reopened.clear().unwrap(), 2);
+    assert!(HistoryStore::open(&dir).unwrap().is_empty());
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn a_second_store_on_the_same_log_does_not_lose_visits() {
+    let (dir, browser) = temp_store("shared");
+    visit(&browser, "https://a.example/", "", 0);
+
+    // Like `nyan_browser history clear` while the browser runs.
+    let cli = HistoryStore::open(&dir).unwrap();
+    visit(&browser, "https://b.example/", "", 0);
+    assert_eq!(cli.clear().unwrap(), 2);
+    visit(&browser, "https://c.example/", "", 0);
+
+    let cli = HistoryStore::open(&dir).unwrap();
+    visit(&browser, "https://d.example/", "", 0);
+    let removed = cli
+        .apply_retention(&RetentionPolicy {
+            max_age: None,
+            max_entries: Some(1),
+        })
+        .unwrap();
+    assert_eq!(removed, 1);
+    visit(&browser, "https://e.example/", "", 0);
+
+    let reopened = HistoryStore::open(&dir).unwrap();
+    let urls: Vec<_> = reopened.entries().into_iter().map(|e| e.url).collect();
+    assert_eq!(urls, ["https://e.example/", "https://d.example/"]);
+    std::fs::remove_dir_all(&dir).unwrap();
+}
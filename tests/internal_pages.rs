@@ -0,0 +1,193 @@
+use async_trait::async_trait;
+use base64::Engine;
+use nyan_browser::core::scheme::{escape_html, layout, PageRequest};
+use nyan_browser::core::InternalPage;
+use nyan_browser::features::adblock::ResourceType;
+use nyan_browser::test_util::MockWebDriver;
+use nyan_browser::NyanBrowser;
+
+/// The HTML of the last `data:` URL the browser loaded.
+fn last_page(driver: &MockWebDriver) -> String {
+    let gotos = driver.commands_named("goto");
+    let url = gotos.last().unwrap().body["url"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    let encoded = url
+        .strip_prefix("data:text/html;charset=utf-8;base64,")
+        .unwrap_or_else(|| panic!("{} is not an internal page", url));
+    let html = base64::engine::general_purpose::STANDARD
+        .decode(encoded)
+        .unwrap();
+    String::from_utf8(html).unwrap()
+}
+
+#[test]
+fn parses_internal_urls() {
+    let request = PageRequest::parse("kawaii://History/today?q=rust+lang").unwrap();
+    assert_eq!(request.page, "history");
+    assert_eq!(request.path, "/today");
+    assert_eq!(request.param("q"), Some("rust lang"));
+    assert!(PageRequest::parse("https://example.com/").is_err());
+}
+
+#[tokio::test]
+async fn home_page_is_embedded() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+
+    browser.navigate("kawaii://home").await.unwrap();
+    let html = last_page(&driver);
+    assert!(html.contains("Welcome to Kawaii Browser!"));
+    assert!(html.contains("--primary-color"));
+
+    browser.navigate("").await.unwrap();
+    assert_eq!(last_page(&driver), html);
+}
+
+#[tokio::test]
+async fn history_and_bookmarks_pages_escape_what_they_show() {
+    let driver = MockWebDriver::start().await.unwrap();
+    driver.set_title("https://example.com/", "<script>alert(1)</script>");
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+
+    browser.navigate("https://example.com/").await.unwrap();
+    browser
+        .bookmark_current_page(&["Fun & Games"])
+        .await
+        .unwrap();
+
+    browser.navigate("kawaii://history").await.unwrap();
+    let history = last_page(&driver);
+    assert!(history.contains("&lt;script&gt;alert(1)&lt;/script&gt;"));
+    assert!(!history.contains("<script>alert"));
+
+    browser
+        .navigate("kawaii://history?q=nothing-matches")
+        .await
+        .unwrap();
+    assert!(last_page(&driver).contains("Nothing here yet"));
+
+    browser.navigate("kawaii://bookmarks").await.unwrap();
+    let bookmarks = last_page(&driver);
+    assert!(bookmarks.contains("<h2>Fun &amp; Games</h2>"));
+    assert!(bookmarks.contains("href=\"https://example.com/\""));
+}
+
+#[tokio::test]
+async fn every_builtin_page_renders() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+
+    let pages = browser.scheme_router().pages();
+    let names: Vec<_> = pages.keys().map(String::as_str).collect();
+    assert_eq!(
+        names,
+        [
+            "about",
+            "adblock",
+            "bookmarks",
+            "downloads",
+            "history",
+            "home",
+            "network",
+            "settings"
+        ]
+    );
+    for name in names {
+        browser
+            .navigate(&format!("kawaii://{}", name))
+            .await
+            .unwrap();
+        assert!(
+            last_page(&driver).starts_with("<!DOCTYPE html>"),
+            "{}",
+            name
+        );
+    }
+
+    browser.navigate("kawaii://settings").await.unwrap();
+    assert!(last_page(&driver).contains("custom_search"));
+    browser.navigate("kawaii://about").await.unwrap();
+    assert!(last_page(&driver).contains("kawaii://downloads"));
+    browser.navigate("kawaii://nope").await.unwrap();
+    assert!(last_page(&driver).contains("There is no page called <code>nope</code>"));
+}
+
+#[tokio::test]
+async fn downloads_page_links_to_escaped_file_urls() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let config = driver.config();
+    let dir = config.data_dir.join("downloads");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("nyan cat #1.gif"), b"GIF89a").unwrap();
+    let browser = NyanBrowser::new(nyan_browser::config::BrowserConfig {
+        download_dir: dir.clone(),
+        ..config
+    })
+    .await
+    .unwrap();
+
+    browser.navigate("kawaii://downloads").await.unwrap();
+    let html = last_page(&driver);
+    let url = url::Url::from_file_path(dir.join("nyan cat #1.gif")).unwrap();
+    assert!(url.as_str().ends_with("/nyan%20cat%20%231.gif"));
+    assert!(html.contains(&format!("href=\"{}\"", url)));
+}
+
+#[tokio::test]
+async fn adblock_page_shows_what_was_blocked() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    let blocker = browser.ad_blocker();
+    blocker.add_filter("||ads.test^").unwrap();
+    blocker.allowlist().add("friendly.test").unwrap();
+    let page = "https://news.test/?a=1&b=2";
+    blocker.should_block("https://ads.test/1.js", Some(page), ResourceType::Script);
+
+    browser.navigate("kawaii://adblock").await.unwrap();
+    let html = last_page(&driver);
+    assert!(html.contains("<td>friendly.test</td>"));
+    assert!(html.contains("<code>||ads.test^</code>"));
+    assert!(html.contains("<code>Custom filters</code>"));
+    let details = "kawaii://adblock?page=https%3A%2F%2Fnews.test%2F%3Fa%3D1%26b%3D2";
+    assert!(html.contains(&escape_html(details)));
+
+    browser.navigate(details).await.unwrap();
+    assert!(last_page(&driver).contains("1 requests blocked on"));
+    browser
+        .navigate("kawaii://adblock?page=https://elsewhere.test/")
+        .await
+        .unwrap();
+    assert!(last_page(&driver).contains("Nothing blocked on this page"));
+}
+
+struct Greeting;
+
+#[async_trait]
+impl InternalPage for Greeting {
+    fn description(&self) -> &str {
+        "Says hi"
+    }
+
+    async fn render(&self, request: &PageRequest, browser: &NyanBrowser) -> anyhow::Result<String> {
+        let name = request.param("name").unwrap_or("friend");
+        Ok(layout(
+            browser.assets(),
+            "Hi",
+            &request.url,
+            &escape_html(&format!("Hello {}!", name)),
+        ))
+    }
+}
+
+#[tokio::test]
+async fn custom_pages_can_be_registered() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    browser.scheme_router().register("hello", Greeting);
+
+    browser.navigate("kawaii://hello?name=Nyan").await.unwrap();
+    assert!(last_page(&driver).contains("Hello Nyan!"));
+    assert!(browser.scheme_router().unregister("hello"));
+}
@@ -0,0 +1,43 @@
+use fantoccini::ClientBuilder;
+use nyan_browser::test_util::{MockReply, MockWebDriver};
+use serde_json::json;
+
+#[tokio::test]
+async fn serves_scripted_execute_and_screenshot() {
+    let driver = MockWebDriver::start().await.unwrap();
+    driver.stub_execute("return 1 + 1", MockReply::value(2));
+    driver.set_screenshot(vec![0x89, b'P', b'N', b'G']);
+
+    let client = ClientBuilder::native()
+        .connect(&driver.url())
+        .await
+        .unwrap();
+
+    let value = client.execute("return 1 + 1", vec![]).await.unwrap();
+    assert_eq!(value, json!(2));
+    assert_eq!(
+        client.screenshot().await.unwrap(),
+        vec![0x89, b'P', b'N', b'G']
+    );
+
+    client.close().await.unwrap();
+}
+
+#[tokio::test]
+async fn tracks_cookies_per_session() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let client = ClientBuilder::native()
+        .connect(&driver.url())
+        .await
+        .unwrap();
+    let session = driver.sessions().pop().unwrap();
+
+    client
+        .add_cookie(fantoccini::cookies::Cookie::new("flavour", "strawberry"))
+        .await
+        .unwrap();
+    assert_eq!(driver.cookies(&session)[0]["value"], "strawberry");
+
+    client.delete_all_cookies().await.unwrap();
+    assert!(driver.cookies(&session).is_empty());
+}
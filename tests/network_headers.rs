@@ -0,0 +1,256 @@
+use hyper::{Body, Request, Response};
+use nyan_browser::features::network::{
+    CertificateAuthority, HeaderDirection, HeaderModifications, HttpsInterception,
+    InterceptingProxy, MockResponse, NetworkMonitor, NetworkQuery, NetworkTools,
+};
+use nyan_browser::test_util::{https_via_proxy, TestHttpServer};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+
+async fn start() -> (InterceptingProxy, Arc<NetworkMonitor>, Arc<NetworkTools>) {
+    start_with(HttpsInterception::Off).await
+}
+
+async fn start_with(
+    https: HttpsInterception,
+) -> (InterceptingProxy, Arc<NetworkMonitor>, Arc<NetworkTools>) {
+    let monitor = Arc::new(NetworkMonitor::new());
+    let tools = Arc::new(NetworkTools::new());
+    let proxy = InterceptingProxy::start(
+        ([127, 0, 0, 1], 0).into(),
+        Arc::clone(&monitor),
+        None,
+        Arc::clone(&tools),
+        https,
+    )
+    .await
+    .unwrap();
+    (proxy, monitor, tools)
+}
+
+async fn send(proxy: SocketAddr, request: Request<Body>) -> Response<Body> {
+    let stream = TcpStream::connect(proxy).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    sender.send_request(request).await.unwrap()
+}
+
+/// The headers `/echo` saw.
+async fn echoed(proxy: SocketAddr, request: Request<Body>) -> Value {
+    let response = send(proxy, request).await;
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    serde_json::from_slice::<Value>(&body).unwrap()["headers"].take()
+}
+
+#[tokio::test]
+async fn rewrites_request_headers() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, monitor, tools) = start().await;
+    tools
+        .modify_headers(
+            HeaderModifications::request(&server.url("/echo"))
+                .add_header("Authorization", "Bearer staging")
+                .add_header("X-Tag", "second")
+                .remove_header("X-Debug")
+                .set_header("User-Agent", "NyanBot/1.0"),
+        )
+        .await
+        .unwrap();
+
+    let request = Request::get(server.url("/echo"))
+        .header("user-agent", "Mozilla/5.0")
+        .header("x-debug", "1")
+        .header("x-tag", "first")
+        .body(Body::empty())
+        .unwrap();
+    let headers = echoed(proxy.addr(), request).await;
+    assert_eq!(headers["authorization"], "Bearer staging");
+    assert_eq!(headers["user-agent"], "NyanBot/1.0");
+    assert!(headers.get("x-debug").is_none());
+    let tags: Vec<_> = server.requests()[0]
+        .headers
+        .iter()
+        .filter(|(name, _)| name == "x-tag")
+        .map(|(_, value)| value.clone())
+        .collect();
+    assert_eq!(tags, ["first", "second"]);
+
+    // What was recorded is what the server got.
+    let entry = monitor
+        .query(&NetworkQuery::url("/echo").unwrap())
+        .remove(0);
+    assert_eq!(
+        entry.request.header("authorization"),
+        Some("Bearer staging")
+    );
+    assert_eq!(entry.request.header("x-debug"), None);
+
+    // Other URLs are left alone.
+    let request = Request::get(server.url("/hello"))
+        .header("user-agent", "Mozilla/5.0")
+        .body(Body::empty())
+        .unwrap();
+    hyper::body::to_bytes(send(proxy.addr(), request).await.into_body())
+        .await
+        .unwrap();
+    let hello = &server.requests()[1];
+    assert_eq!(hello.path, "/hello");
+    assert!(hello
+        .headers
+        .contains(&("user-agent".to_string(), "Mozilla/5.0".to_string())));
+    assert!(!hello
+        .headers
+        .iter()
+        .any(|(name, _)| name == "authorization"));
+}
+
+#[tokio::test]
+async fn rewrites_response_headers() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, monitor, tools) = start().await;
+    tools
+        .mock_responses(vec![MockResponse::new("http://strict.test/", 200)
+            .with_header("Content-Security-Policy", "default-src 'none'")
+            .with_header("Content-Type", "text/html")
+            .with_body("<script>alert('nyan')</script>")])
+        .await
+        .unwrap();
+    tools
+        .modify_headers(HeaderModifications::response("*").remove_header("content-security-policy"))
+        .await
+        .unwrap();
+    tools
+        .modify_headers(
+            HeaderModifications::response("/hello")
+                .set_header("Content-Type", "text/html")
+                .add_header("X-Nyan", "1"),
+        )
+        .await
+        .unwrap();
+
+    let response = send(
+        proxy.addr(),
+        Request::get("http://strict.test/")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert!(response.headers().get("content-security-policy").is_none());
+    assert_eq!(response.headers()["content-type"], "text/html");
+
+    let response = send(
+        proxy.addr(),
+        Request::get(server.url("/hello"))
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.headers()["content-type"], "text/html");
+    assert_eq!(response.headers()["x-nyan"], "1");
+    hyper::body::to_bytes(response.into_body()).await.unwrap();
+    // Response rules leave requests alone.
+    assert!(!server.requests()[0]
+        .headers
+        .iter()
+        .any(|(name, _)| name == "x-nyan"));
+
+    // Recorded once the body has gone through.
+    let id = monitor.query(&NetworkQuery::url("/hello").unwrap())[0].id;
+    let mut recorded = None;
+    for _ in 0..100 {
+        recorded = monitor.entry(id).unwrap().response;
+        if recorded.is_some() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    assert_eq!(recorded.unwrap().content_type.as_deref(), Some("text/html"));
+
+    tools.clear_header_rules();
+    let response = send(
+        proxy.addr(),
+        Request::get("http://strict.test/")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert!(response.headers().get("content-security-policy").is_some());
+}
+
+#[tokio::test]
+async fn rewrites_headers_for_https_sites() {
+    let ca = Arc::new(CertificateAuthority::generate().unwrap());
+    let (proxy, monitor, tools) = start_with(HttpsInterception::RuledHosts(Arc::clone(&ca))).await;
+    tools
+        .modify_headers(
+            HeaderModifications::request("https://api.test/*").add_header("x-token", "nyan"),
+        )
+        .await
+        .unwrap();
+
+    // The rule alone is enough for the proxy to look into the tunnel. The
+    // request is recorded as rewritten, then fails as there is no api.test.
+    let mut sender = https_via_proxy(proxy.addr(), "api.test", ca.certificate_pem())
+        .await
+        .unwrap();
+    let get = || {
+        Request::get("/users")
+            .header("host", "api.test")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = sender.send_request(get()).await.unwrap();
+    assert_eq!(response.status(), 502);
+    let entry = monitor
+        .query(&NetworkQuery::url("api.test").unwrap())
+        .remove(0);
+    assert!(entry
+        .request
+        .headers
+        .contains(&("x-token".to_string(), "nyan".to_string())));
+
+    tools
+        .modify_headers(
+            HeaderModifications::response("https://api.test/*").set_header("x-frame", "deny"),
+        )
+        .await
+        .unwrap();
+    tools
+        .mock_responses(vec![MockResponse::new("https://api.test/*", 200)])
+        .await
+        .unwrap();
+    let response = sender.send_request(get()).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-frame"], "deny");
+}
+
+#[tokio::test]
+async fn rejects_broken_rules() {
+    let tools = NetworkTools::new();
+    let broken = [
+        HeaderModifications::request("/(/"),
+        HeaderModifications::request("").add_header("bad header", "x"),
+        HeaderModifications::request("").set_header("x-ok", "bad\nvalue"),
+        HeaderModifications::response("").remove_header(""),
+    ];
+    for rule in broken {
+        assert!(tools.modify_headers(rule).await.is_err());
+    }
+    assert!(tools
+        .modify_headers(HeaderModifications::request("").add_header("x-ok", "fine"))
+        .await
+        .is_ok());
+}
+
+#[test]
+fn header_modifications_read_from_json() {
+    let rule: HeaderModifications = serde_json::from_str(
+        r#"{"url_pattern": "example.com", "direction": "response", "remove": ["x-frame-options"]}"#,
+    )
+    .unwrap();
+    assert_eq!(rule.direction, HeaderDirection::Response);
+    assert_eq!(rule.remove, ["x-frame-options"]);
+    assert!(rule.add.is_empty() && rule.modify.is_empty());
+}
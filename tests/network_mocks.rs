@@ -0,0 +1,244 @@
+use hyper::{Body, Request, Response};
+use nyan_browser::features::network::{
+    CertificateAuthority, HttpsInterception, InterceptingProxy, MockResponse, NetworkMonitor,
+    NetworkQuery, NetworkTools,
+};
+use nyan_browser::test_util::{https_via_proxy, MockWebDriver, TestHttpServer};
+use nyan_browser::NyanBrowser;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::net::TcpStream;
+
+async fn start() -> (InterceptingProxy, Arc<NetworkMonitor>, Arc<NetworkTools>) {
+    start_with(HttpsInterception::Off).await
+}
+
+async fn start_with(
+    https: HttpsInterception,
+) -> (InterceptingProxy, Arc<NetworkMonitor>, Arc<NetworkTools>) {
+    let monitor = Arc::new(NetworkMonitor::new());
+    let tools = Arc::new(NetworkTools::new());
+    let proxy = InterceptingProxy::start(
+        ([127, 0, 0, 1], 0).into(),
+        Arc::clone(&monitor),
+        None,
+        Arc::clone(&tools),
+        https,
+    )
+    .await
+    .unwrap();
+    (proxy, monitor, tools)
+}
+
+async fn send(proxy: SocketAddr, request: Request<Body>) -> Response<Body> {
+    let stream = TcpStream::connect(proxy).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    sender.send_request(request).await.unwrap()
+}
+
+async fn get(proxy: SocketAddr, url: &str) -> (u16, String) {
+    let response = send(proxy, Request::get(url).body(Body::empty()).unwrap()).await;
+    let status = response.status().as_u16();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    (status, String::from_utf8(body.to_vec()).unwrap())
+}
+
+#[tokio::test]
+async fn serves_mocks_instead_of_the_network() {
+    let (proxy, monitor, tools) = start().await;
+    tools
+        .mock_responses(vec![MockResponse::new("http://api.test/users/*", 200)
+            .with_header("Content-Type", "application/json")
+            .with_body(r#"[{"name":"nyan"}]"#)])
+        .await
+        .unwrap();
+
+    // Nothing listens at api.test: the proxy never goes looking.
+    let response = send(
+        proxy.addr(),
+        Request::get("http://api.test/users/1")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "application/json");
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], br#"[{"name":"nyan"}]"#);
+    assert_eq!(tools.mock_hits("http://api.test/users/*"), 1);
+
+    // Recorded like any other response.
+    let entry = monitor
+        .query(&NetworkQuery::url("api.test").unwrap())
+        .remove(0);
+    let response = entry.response.unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(response.content_type.as_deref(), Some("application/json"));
+    assert_eq!(response.body.as_deref(), Some(&br#"[{"name":"nyan"}]"#[..]));
+
+    // Other URLs still go out.
+    let (status, _) = get(proxy.addr(), "http://api.test/posts").await;
+    assert_eq!(status, 502);
+    assert_eq!(tools.mock_hits("http://api.test/users/*"), 1);
+}
+
+#[tokio::test]
+async fn matches_on_method_and_latest_mock_wins() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, _monitor, tools) = start().await;
+    tools
+        .mock_responses(vec![
+            MockResponse::new("/echo", 201)
+                .with_method("post")
+                .with_body("created"),
+            MockResponse::new(r"/\/status\/[0-9]+$/", 418).with_body("teapot"),
+        ])
+        .await
+        .unwrap();
+    tools
+        .mock_responses(vec![
+            MockResponse::new("/status/500", 503).with_body("later")
+        ])
+        .await
+        .unwrap();
+
+    let post = Request::post(server.url("/echo"))
+        .body(Body::from("hi"))
+        .unwrap();
+    let response = send(proxy.addr(), post).await;
+    assert_eq!(response.status(), 201);
+    // A GET isn't mocked, so the server answers.
+    let (status, body) = get(proxy.addr(), &server.url("/echo")).await;
+    assert_eq!(status, 200);
+    assert!(body.contains("\"method\":\"GET\""));
+
+    assert_eq!(
+        get(proxy.addr(), &server.url("/status/500")).await,
+        (503, "later".into())
+    );
+    assert_eq!(
+        get(proxy.addr(), &server.url("/status/404")).await,
+        (418, "teapot".into())
+    );
+    assert_eq!(tools.mock_hits("/echo"), 1);
+    assert_eq!(tools.mock_hits(r"/\/status\/[0-9]+$/"), 1);
+    assert_eq!(tools.mock_hits("/status/500"), 1);
+    assert_eq!(tools.mock_hits("/never-added"), 0);
+    // Only the unmocked GET reached the server.
+    assert_eq!(server.requests().len(), 1);
+
+    tools.clear_mocks();
+    assert_eq!(get(proxy.addr(), &server.url("/status/404")).await.0, 404);
+}
+
+#[tokio::test]
+async fn serves_bodies_from_files_after_a_delay() {
+    let dir = std::env::temp_dir().join(format!("nyan-mocks-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = dir.join("page.html");
+    std::fs::write(&file, "<h1>offline nyan</h1>").unwrap();
+
+    let (proxy, monitor, tools) = start().await;
+    tools
+        .mock_responses(vec![MockResponse::new("http://slow.test/", 200)
+            .with_body_file(&file)
+            .with_delay(Duration::from_millis(200))])
+        .await
+        .unwrap();
+    // The file is read when the mock is added.
+    std::fs::remove_file(&file).unwrap();
+
+    let start = Instant::now();
+    let (status, body) = get(proxy.addr(), "http://slow.test/").await;
+    assert!(start.elapsed() >= Duration::from_millis(200));
+    assert_eq!((status, body.as_str()), (200, "<h1>offline nyan</h1>"));
+    let timings = monitor.query(&NetworkQuery::default())[0].timings.unwrap();
+    assert!(timings.ttfb >= Duration::from_millis(200));
+}
+
+#[tokio::test]
+async fn serves_mocks_for_https_sites() {
+    let ca = Arc::new(CertificateAuthority::generate().unwrap());
+    let (proxy, monitor, tools) = start_with(HttpsInterception::RuledHosts(Arc::clone(&ca))).await;
+    tools
+        .mock_responses(vec![
+            MockResponse::new("https://api.test/users/*", 200).with_body("[]")
+        ])
+        .await
+        .unwrap();
+
+    let mut sender = https_via_proxy(proxy.addr(), "api.test", ca.certificate_pem())
+        .await
+        .unwrap();
+    let request = Request::get("/users/1")
+        .header("host", "api.test")
+        .body(Body::empty())
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"[]");
+    assert_eq!(tools.mock_hits("https://api.test/users/*"), 1);
+    assert_eq!(
+        monitor.recent_requests(1)[0].url,
+        "https://api.test/users/1"
+    );
+
+    // Sites no mock could answer for are tunnelled untouched: whatever goes
+    // through is between the browser and the server, plain HTTP here.
+    let server = TestHttpServer::start().await.unwrap();
+    let target = server.addr().to_string();
+    let stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let request = Request::connect(target.as_str())
+        .header("host", target.as_str())
+        .body(Body::empty())
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), 200);
+    let tunnel = hyper::upgrade::on(response).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(tunnel).await.unwrap();
+    tokio::spawn(connection);
+    let response = sender
+        .send_request(Request::get("/hello").body(Body::empty()).unwrap())
+        .await
+        .unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(&body[..], b"hello nyan");
+    assert_eq!(monitor.recent_requests(1)[0].method, "CONNECT");
+}
+
+#[tokio::test]
+async fn rejects_broken_mocks() {
+    let tools = NetworkTools::new();
+    let broken = [
+        MockResponse::new("/(/", 200),
+        MockResponse::new("x", 1000),
+        MockResponse::new("x", 200).with_header("bad header", "x"),
+        MockResponse::new("x", 200).with_body_file("/nonexistent/nyan.json"),
+    ];
+    for mock in broken {
+        assert!(tools.mock_responses(vec![mock]).await.is_err());
+    }
+    let batch = vec![MockResponse::new("ok", 200), MockResponse::new("x", 99)];
+    assert!(tools.mock_responses(batch).await.is_err());
+}
+
+#[tokio::test]
+async fn the_browser_proxy_serves_mocks() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    browser
+        .network_tools()
+        .mock_responses(vec![
+            MockResponse::new("http://nyan.test/", 200).with_body("mocked")
+        ])
+        .await
+        .unwrap();
+    let (status, body) = get(browser.proxy_addr().unwrap(), "http://nyan.test/").await;
+    assert_eq!((status, body.as_str()), (200, "mocked"));
+    assert_eq!(browser.network_tools().mock_hits("http://nyan.test/"), 1);
+}
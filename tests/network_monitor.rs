@@ -0,0 +1,299 @@
+use chrono::{Duration, Utc};
+use hyper::{Body, Request, Response};
+use nyan_browser::features::network::{
+    HttpsInterception, InterceptingProxy, NetworkEntry, NetworkMonitor, NetworkQuery, RequestData,
+    ResponseData, StatusClass, Timings, UrlPattern,
+};
+use nyan_browser::test_util::{MockWebDriver, TestHttpServer};
+use nyan_browser::NyanBrowser;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpStream;
+
+async fn through_proxy(proxy: SocketAddr, request: Request<Body>) -> Response<Body> {
+    let stream = TcpStream::connect(proxy).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    sender.send_request(request).await.unwrap()
+}
+
+/// Fetches `url` through the proxy, reading the whole body.
+async fn fetch(proxy: SocketAddr, url: &str) -> Vec<u8> {
+    let response = through_proxy(proxy, Request::get(url).body(Body::empty()).unwrap()).await;
+    hyper::body::to_bytes(response.into_body())
+        .await
+        .unwrap()
+        .to_vec()
+}
+
+/// The newest entry for `url`, once it is complete: responses are recorded
+/// after their body has gone through.
+async fn finished(monitor: &NetworkMonitor, url: &str) -> NetworkEntry {
+    let query = NetworkQuery {
+        url: Some(UrlPattern::Contains(url.to_string())),
+        limit: Some(1),
+        ..NetworkQuery::default()
+    };
+    for _ in 0..100 {
+        if let Some(entry) = monitor.query(&query).pop() {
+            if entry.response.is_some() || entry.error.is_some() {
+                return entry;
+            }
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    panic!("{} never finished", url);
+}
+
+async fn start(monitor: NetworkMonitor) -> (InterceptingProxy, Arc<NetworkMonitor>) {
+    let monitor = Arc::new(monitor);
+    let proxy = InterceptingProxy::start(
+        ([127, 0, 0, 1], 0).into(),
+        Arc::clone(&monitor),
+        None,
+        Arc::default(),
+        HttpsInterception::Off,
+    )
+    .await
+    .unwrap();
+    (proxy, monitor)
+}
+
+fn request(url: &str, method: &str) -> RequestData {
+    RequestData {
+        url: url.to_string(),
+        method: method.to_string(),
+        headers: Vec::new(),
+        body: None,
+    }
+}
+
+#[tokio::test]
+async fn records_responses_with_timings() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, monitor) = start(NetworkMonitor::new()).await;
+
+    assert_eq!(
+        fetch(proxy.addr(), &server.url("/hello")).await,
+        b"hello nyan"
+    );
+    let entry = finished(&monitor, "/hello").await;
+    let response = entry.response.unwrap();
+    assert_eq!(response.status, 200);
+    assert_eq!(
+        response.content_type.as_deref(),
+        Some("text/plain; charset=utf-8")
+    );
+    assert_eq!(response.body.as_deref(), Some(&b"hello nyan"[..]));
+    assert_eq!(response.body_size, 10);
+    assert!(!response.truncated);
+    assert!(response
+        .headers
+        .contains(&("content-length".to_string(), "10".to_string())));
+    let timings = entry.timings.unwrap();
+    assert!(timings.dns.is_some() && timings.connect.is_some());
+    assert_eq!(timings.tls, None);
+    assert!(timings.total() >= timings.ttfb);
+    assert_eq!(monitor.entry(entry.id).unwrap().id, entry.id);
+
+    // The second request reuses the connection, so it has no setup time.
+    fetch(proxy.addr(), &server.url("/echo")).await;
+    let timings = finished(&monitor, "/echo").await.timings.unwrap();
+    assert_eq!((timings.dns, timings.connect), (None, None));
+}
+
+#[tokio::test]
+async fn caps_recorded_bodies() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, monitor) = start(NetworkMonitor::new().with_max_body_bytes(100)).await;
+
+    // The browser still gets all of it.
+    assert_eq!(
+        fetch(proxy.addr(), &server.url("/bytes/5000")).await.len(),
+        5000
+    );
+    let response = finished(&monitor, "/bytes/5000").await.response.unwrap();
+    assert_eq!(response.body.unwrap().len(), 100);
+    assert_eq!(response.body_size, 5000);
+    assert!(response.truncated);
+    assert_eq!(
+        response.content_type.as_deref(),
+        Some("application/octet-stream")
+    );
+}
+
+#[tokio::test]
+async fn queries_by_status_class() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, monitor) = start(NetworkMonitor::new()).await;
+    for path in ["/hello", "/status/404", "/status/503", "/status/302"] {
+        fetch(proxy.addr(), &server.url(path)).await;
+        finished(&monitor, path).await;
+    }
+    let closed = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    fetch(proxy.addr(), &format!("http://{}/gone", closed)).await;
+    finished(&monitor, "/gone").await;
+
+    let urls = |status: StatusClass| -> Vec<String> {
+        let query = NetworkQuery {
+            status: Some(status),
+            ..NetworkQuery::default()
+        };
+        monitor
+            .query(&query)
+            .into_iter()
+            .map(|e| e.request.url)
+            .collect()
+    };
+    assert_eq!(urls(StatusClass::Success), [server.url("/hello")]);
+    assert_eq!(urls(StatusClass::Redirection), [server.url("/status/302")]);
+    assert_eq!(urls(StatusClass::ClientError), [server.url("/status/404")]);
+    assert_eq!(urls(StatusClass::ServerError), [server.url("/status/503")]);
+    assert_eq!(
+        urls(StatusClass::Failed),
+        [format!("http://{}/gone", closed)]
+    );
+    assert!(urls(StatusClass::Informational).is_empty());
+
+    let failed = finished(&monitor, "/gone").await;
+    assert!(failed.response.is_none());
+    assert!(failed.error.is_some());
+    assert_eq!(StatusClass::parse("4XX"), Some(StatusClass::ClientError));
+    assert_eq!(StatusClass::parse("failed"), Some(StatusClass::Failed));
+    assert_eq!(StatusClass::parse("404"), None);
+}
+
+#[test]
+fn queries_by_url_method_and_time() {
+    let monitor = NetworkMonitor::new();
+    let before = Utc::now();
+    let api = monitor
+        .record_request(request("https://api.example.com/v1/cats?page=2", "GET"))
+        .unwrap();
+    monitor.record_request(request("https://api.example.com/v2/dogs", "POST"));
+    monitor.record_request(request("https://cdn.example.com/cat.png", "GET"));
+    let after = Utc::now() + Duration::milliseconds(1);
+
+    let urls = |query: NetworkQuery| -> Vec<String> {
+        monitor
+            .query(&query)
+            .into_iter()
+            .map(|e| e.request.url)
+            .collect()
+    };
+    assert_eq!(urls(NetworkQuery::url("cat").unwrap()).len(), 2);
+    assert_eq!(
+        urls(NetworkQuery::url("https://api.example.com/v1/*").unwrap()),
+        ["https://api.example.com/v1/cats?page=2"]
+    );
+    assert_eq!(
+        urls(NetworkQuery::url(r"/\.png$/").unwrap()),
+        ["https://cdn.example.com/cat.png"]
+    );
+    assert!(NetworkQuery::url("/(/").is_err());
+    assert_eq!(
+        urls(NetworkQuery {
+            method: Some("post".to_string()),
+            ..NetworkQuery::default()
+        }),
+        ["https://api.example.com/v2/dogs"]
+    );
+    // Newest first.
+    assert_eq!(
+        urls(NetworkQuery {
+            limit: Some(1),
+            ..NetworkQuery::url("example.com").unwrap()
+        }),
+        ["https://cdn.example.com/cat.png"]
+    );
+    let window = |since, until| {
+        urls(NetworkQuery {
+            since: Some(since),
+            until: Some(until),
+            ..NetworkQuery::default()
+        })
+        .len()
+    };
+    assert_eq!(window(before, after), 3);
+    assert_eq!(window(after, after + Duration::hours(1)), 0);
+    assert_eq!(window(before - Duration::hours(1), before), 0);
+
+    // Nothing has answered yet.
+    assert!(monitor.entry(api).unwrap().response.is_none());
+    let response = ResponseData::new(
+        200,
+        vec![("Content-Type".into(), "application/json".into())],
+    );
+    monitor.record_response(api, response, Timings::default());
+    let entry = monitor.entry(api).unwrap();
+    assert_eq!(
+        entry.response.unwrap().content_type.as_deref(),
+        Some("application/json")
+    );
+    assert!(monitor.entry(api + 100).is_none());
+}
+
+#[test]
+fn url_patterns() {
+    let matches = |pattern: &str, url: &str| UrlPattern::parse(pattern).unwrap().matches(url);
+    assert!(matches("example.com/api", "https://example.com/api/cats"));
+    assert!(!matches("example.org", "https://example.com/"));
+    assert!(matches(
+        "https://*.example.com/*.js",
+        "https://cdn.example.com/app.js"
+    ));
+    assert!(!matches(
+        "https://*.example.com/*.js",
+        "https://cdn.example.com/app.json"
+    ));
+    assert!(matches("*cats*", "https://example.com/cats/1"));
+    assert!(matches("https://example.com/*", "https://example.com/"));
+    assert!(matches("/cats\\/[0-9]+$/", "https://example.com/cats/12"));
+    assert!(!matches("/cats\\/[0-9]+$/", "https://example.com/cats/new"));
+    assert_eq!(UrlPattern::parse("/a+/").unwrap().to_string(), "/a+/");
+
+    let on =
+        |pattern: &str, origin: &str| UrlPattern::parse(pattern).unwrap().may_match_origin(origin);
+    assert!(on("https://api.example.com/*", "https://api.example.com"));
+    assert!(on("https://*.example.com/*", "https://cdn.example.com"));
+    assert!(on("*.js", "https://cdn.example.com"));
+    assert!(on("example.org", "https://cdn.example.com"));
+    assert!(!on("https://api.example.com/*", "https://example.com"));
+    assert!(!on(
+        "https://api.example.com/*",
+        "https://api.example.com:8443"
+    ));
+    assert!(!on("http://api.example.com/*", "https://api.example.com"));
+}
+
+#[tokio::test]
+async fn network_page_shows_responses() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    let network = browser.network();
+    let ok = network
+        .record_request(request("https://ok.test/", "GET"))
+        .unwrap();
+    network.record_response(ok, ResponseData::new(204, Vec::new()), Timings::default());
+    let broken = network
+        .record_request(request("https://broken.test/", "GET"))
+        .unwrap();
+    network.record_failure(broken, "connection refused".to_string());
+
+    let page = browser
+        .scheme_router()
+        .render("kawaii://network", &browser)
+        .await
+        .unwrap();
+    assert!(page.contains("<td>204</td>"));
+    assert!(page.contains("title=\"connection refused\""));
+    let page = browser
+        .scheme_router()
+        .render("kawaii://network?status=failed", &browser)
+        .await
+        .unwrap();
+    assert!(page.contains("broken.test") && !page.contains("ok.test"));
+}
@@ -0,0 +1,478 @@
+use hyper::{Body, Method, Request, Response, StatusCode};
+use nyan_browser::core::driver::{self, DriverBackend, GeckoBackend};
+use nyan_browser::features::adblock::AdBlocker;
+use nyan_browser::features::network::ca::{CA_CERT_FILE, CA_KEY_FILE};
+use nyan_browser::features::network::monitor::RequestFilter;
+use nyan_browser::features::network::{
+    CertificateAuthority, HttpsInterception, InterceptingProxy, NetworkMonitor, NetworkQuery,
+    StatusClass,
+};
+use nyan_browser::test_util::{https_via_proxy, MockWebDriver, TestHttpServer};
+use nyan_browser::NyanBrowser;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+async fn start_proxy(
+    ad_blocker: Option<Arc<AdBlocker>>,
+) -> (InterceptingProxy, Arc<NetworkMonitor>) {
+    start_proxy_with(ad_blocker, HttpsInterception::Off).await
+}
+
+async fn start_proxy_with(
+    ad_blocker: Option<Arc<AdBlocker>>,
+    https: HttpsInterception,
+) -> (InterceptingProxy, Arc<NetworkMonitor>) {
+    let monitor = Arc::new(NetworkMonitor::new());
+    let proxy = InterceptingProxy::start(
+        ([127, 0, 0, 1], 0).into(),
+        Arc::clone(&monitor),
+        ad_blocker,
+        Arc::default(),
+        https,
+    )
+    .await
+    .unwrap();
+    (proxy, monitor)
+}
+
+/// Sends `CONNECT authority` through the proxy, returning the response and,
+/// if it was let through, the tunnel.
+async fn connect(
+    proxy: SocketAddr,
+    authority: &str,
+) -> (StatusCode, Option<hyper::upgrade::Upgraded>) {
+    let stream = TcpStream::connect(proxy).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let request = Request::connect(authority)
+        .header("host", authority)
+        .body(Body::empty())
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    let status = response.status();
+    let tunnel = match status {
+        StatusCode::OK => Some(hyper::upgrade::on(response).await.unwrap()),
+        _ => None,
+    };
+    (status, tunnel)
+}
+
+/// Sends `request` over a fresh connection, as is: requests to a proxy use
+/// absolute URLs, which `hyper::Client` would rewrite.
+async fn send(stream: TcpStream, request: Request<Body>) -> Response<Body> {
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    sender.send_request(request).await.unwrap()
+}
+
+async fn through_proxy(proxy: SocketAddr, request: Request<Body>) -> Response<Body> {
+    send(TcpStream::connect(proxy).await.unwrap(), request).await
+}
+
+async fn text(response: Response<Body>) -> String {
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    String::from_utf8(body.to_vec()).unwrap()
+}
+
+fn get(url: &str) -> Request<Body> {
+    Request::get(url).body(Body::empty()).unwrap()
+}
+
+#[tokio::test]
+async fn forwards_and_records_plain_http() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, monitor) = start_proxy(None).await;
+
+    let response = through_proxy(proxy.addr(), get(&server.url("/hello"))).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(text(response).await, "hello nyan");
+
+    let request = Request::post(server.url("/echo?x=1"))
+        .header("x-nyan", "meow")
+        .header("proxy-connection", "keep-alive")
+        .header("connection", "x-hop")
+        .header("x-hop", "only to the proxy")
+        .body(Body::from("paws"))
+        .unwrap();
+    let response = through_proxy(proxy.addr(), request).await;
+    let echo: Value = serde_json::from_str(&text(response).await).unwrap();
+    assert_eq!(echo["method"], "POST");
+    assert_eq!(echo["path"], "/echo?x=1");
+    assert_eq!(echo["body"], "paws");
+    assert_eq!(echo["headers"]["x-nyan"], "meow");
+    // Hop-by-hop headers stay between the browser and the proxy.
+    assert!(echo["headers"].get("proxy-connection").is_none());
+    assert!(echo["headers"].get("x-hop").is_none());
+
+    let recorded = monitor.recent_requests(10);
+    assert_eq!(recorded.len(), 2);
+    assert_eq!(recorded[0].method, "POST");
+    assert_eq!(recorded[0].url, server.url("/echo?x=1"));
+    assert_eq!(recorded[0].body.as_deref(), Some(&b"paws"[..]));
+    assert!(recorded[0]
+        .headers
+        .contains(&("x-nyan".to_string(), "meow".to_string())));
+    assert_eq!(recorded[1].url, server.url("/hello"));
+    assert_eq!(recorded[1].body, None);
+}
+
+#[tokio::test]
+async fn passes_on_upstream_errors() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, _) = start_proxy(None).await;
+
+    let response = through_proxy(proxy.addr(), get(&server.url("/status/418"))).await;
+    assert_eq!(response.status().as_u16(), 418);
+
+    // Nothing listens there.
+    let closed = std::net::TcpListener::bind("127.0.0.1:0")
+        .unwrap()
+        .local_addr()
+        .unwrap();
+    let response = through_proxy(proxy.addr(), get(&format!("http://{}/", closed))).await;
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+
+    // Not a proxy request at all.
+    let response = through_proxy(proxy.addr(), get("/hello")).await;
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+#[tokio::test]
+async fn streams_uploads_and_keeps_only_their_start() {
+    let server = TestHttpServer::start().await.unwrap();
+    let monitor = Arc::new(NetworkMonitor::new().with_max_body_bytes(4));
+    let proxy = InterceptingProxy::start(
+        ([127, 0, 0, 1], 0).into(),
+        Arc::clone(&monitor),
+        None,
+        Arc::default(),
+        HttpsInterception::Off,
+    )
+    .await
+    .unwrap();
+
+    let (mut sender, body) = Body::channel();
+    tokio::spawn(async move {
+        for chunk in ["pa", "ws ", "and ", "whiskers"] {
+            sender.send_data(chunk.into()).await.unwrap();
+        }
+    });
+    let request = Request::post(server.url("/echo")).body(body).unwrap();
+    let response = through_proxy(proxy.addr(), request).await;
+    let echo: Value = serde_json::from_str(&text(response).await).unwrap();
+    assert_eq!(echo["body"], "paws and whiskers");
+    assert_eq!(
+        monitor.recent_requests(1)[0].body.as_deref(),
+        Some(&b"paws"[..])
+    );
+}
+
+#[tokio::test]
+async fn bodies_cut_short_are_failures() {
+    // Promises more body than it sends.
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = [0; 1024];
+        let _ = stream.read(&mut request).await.unwrap();
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\nshort")
+            .await
+            .unwrap();
+    });
+    let (proxy, monitor) = start_proxy(None).await;
+
+    let response = through_proxy(proxy.addr(), get(&format!("http://{}/", addr))).await;
+    assert!(hyper::body::to_bytes(response.into_body()).await.is_err());
+    let failed = NetworkQuery {
+        status: Some(StatusClass::Failed),
+        ..NetworkQuery::default()
+    };
+    for _ in 0..50 {
+        if !monitor.query(&failed).is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    let entries = monitor.query(&failed);
+    assert_eq!(entries.len(), 1);
+    assert!(entries[0].response.is_none());
+    assert!(entries[0].error.is_some());
+}
+
+#[tokio::test]
+async fn tunnels_connect_requests() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, monitor) = start_proxy(None).await;
+
+    let mut stream = TcpStream::connect(proxy.addr()).await.unwrap();
+    let target = server.addr().to_string();
+    stream
+        .write_all(format!("CONNECT {0} HTTP/1.1\r\nHost: {0}\r\n\r\n", target).as_bytes())
+        .await
+        .unwrap();
+    let mut head = Vec::new();
+    while !head.ends_with(b"\r\n\r\n") {
+        let mut byte = [0];
+        stream.read_exact(&mut byte).await.unwrap();
+        head.push(byte[0]);
+    }
+    assert!(String::from_utf8_lossy(&head).starts_with("HTTP/1.1 200"));
+
+    // Whatever goes through the tunnel is the server's business: here it
+    // is plain HTTP, for a browser it would be TLS.
+    let response = send(stream, get("/hello")).await;
+    assert_eq!(text(response).await, "hello nyan");
+    assert_eq!(server.requests()[0].path, "/hello");
+
+    let recorded = monitor.recent_requests(10);
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].method, "CONNECT");
+    assert_eq!(recorded[0].url, target);
+}
+
+#[tokio::test]
+async fn refuses_tunnels_to_blocked_hosts() {
+    let ad_blocker = Arc::new(AdBlocker::new());
+    ad_blocker.add_filter("||ads.test^").unwrap();
+    let (proxy, monitor) = start_proxy(Some(Arc::clone(&ad_blocker))).await;
+
+    // Refused before looking the host up, which would fail.
+    let (status, _) = connect(proxy.addr(), "ads.test:443").await;
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    let entries = monitor.query(&NetworkQuery::default());
+    assert_eq!(entries[0].request.method, "CONNECT");
+    assert_eq!(entries[0].response.as_ref().unwrap().status, 403);
+    assert_eq!(ad_blocker.stats().global().by_rule["||ads.test^"], 1);
+}
+
+#[tokio::test]
+async fn looks_into_https_with_its_own_certificates() {
+    let ca = Arc::new(CertificateAuthority::generate().unwrap());
+    let ad_blocker = Arc::new(AdBlocker::new());
+    ad_blocker.add_filter("/ads/*$script").unwrap();
+    let (proxy, monitor) = start_proxy_with(
+        Some(Arc::clone(&ad_blocker)),
+        HttpsInterception::All(Arc::clone(&ca)),
+    )
+    .await;
+    assert!(proxy.intercepts_https());
+
+    let mut sender = https_via_proxy(proxy.addr(), "news.test", ca.certificate_pem())
+        .await
+        .unwrap();
+    let request = Request::get("/ads/banner.js?size=big")
+        .header("host", "news.test")
+        .header("sec-fetch-dest", "script")
+        .header("referer", "https://news.test/today")
+        .body(Body::empty())
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+    // The whole URL is recorded, not just the host.
+    let recorded = monitor.recent_requests(10);
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].url, "https://news.test/ads/banner.js?size=big");
+    let page = ad_blocker.stats().page("https://news.test/today").unwrap();
+    assert_eq!(page.by_rule["/ads/*$script"], 1);
+
+    // Requests that get through go on to the real site, which doesn't
+    // exist here.
+    let response = sender.send_request(get("/")).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_GATEWAY);
+    let failed = NetworkQuery {
+        status: Some(StatusClass::Failed),
+        ..NetworkQuery::default()
+    };
+    assert_eq!(monitor.query(&failed)[0].request.url, "https://news.test/");
+}
+
+#[test]
+fn the_ca_is_kept_with_the_profile() {
+    let dir = std::env::temp_dir().join(format!("nyan-proxy-ca-{}", std::process::id()));
+    let _ = std::fs::remove_dir_all(&dir);
+
+    let made = CertificateAuthority::load_or_create(&dir).unwrap();
+    assert!(made
+        .certificate_pem()
+        .starts_with("-----BEGIN CERTIFICATE-----"));
+    let loaded = CertificateAuthority::load_or_create(&dir).unwrap();
+    assert_eq!(loaded.certificate_pem(), made.certificate_pem());
+    assert_eq!(
+        std::fs::read_to_string(dir.join(CA_CERT_FILE)).unwrap(),
+        made.certificate_pem()
+    );
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let key = std::fs::metadata(dir.join(CA_KEY_FILE)).unwrap();
+        assert_eq!(key.permissions().mode() & 0o777, 0o600);
+    }
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[tokio::test]
+async fn blocks_what_the_ad_blocker_blocks() {
+    let server = TestHttpServer::start().await.unwrap();
+    let ad_blocker = Arc::new(AdBlocker::new());
+    ad_blocker.add_filter("/ads/*$script").unwrap();
+    let (proxy, monitor) = start_proxy(Some(Arc::clone(&ad_blocker))).await;
+
+    let script = |path: &str| {
+        Request::get(server.url(path))
+            .header("sec-fetch-dest", "script")
+            .header("referer", "http://news.test/today")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let response = through_proxy(proxy.addr(), script("/ads/banner.js")).await;
+    assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    let response = through_proxy(proxy.addr(), script("/hello")).await;
+    assert_eq!(response.status(), StatusCode::OK);
+    // Only scripts are blocked there.
+    let response = through_proxy(proxy.addr(), get(&server.url("/ads/banner.js"))).await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let paths: Vec<_> = server.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(paths, ["/hello", "/ads/banner.js"]);
+    // Blocked requests are still recorded, and counted for the page.
+    assert_eq!(monitor.recent_requests(10).len(), 3);
+    let page = ad_blocker.stats().page("http://news.test/today").unwrap();
+    assert_eq!(page.by_rule["/ads/*$script"], 1);
+}
+
+#[tokio::test]
+async fn filters_limit_what_is_recorded() {
+    let server = TestHttpServer::start().await.unwrap();
+    let (proxy, monitor) = start_proxy(None).await;
+    monitor.add_filter(RequestFilter {
+        url_pattern: "/echo".to_string(),
+        method: Some(Method::GET.to_string()),
+        headers: Vec::new(),
+    });
+
+    through_proxy(proxy.addr(), get(&server.url("/hello"))).await;
+    through_proxy(proxy.addr(), get(&server.url("/echo"))).await;
+    let recorded = monitor.recent_requests(10);
+    assert_eq!(recorded.len(), 1);
+    assert_eq!(recorded[0].url, server.url("/echo"));
+
+    monitor.clear_filters();
+    through_proxy(proxy.addr(), get(&server.url("/hello"))).await;
+    assert_eq!(monitor.recent_requests(10).len(), 2);
+}
+
+#[test]
+fn proxy_capabilities() {
+    let proxy: SocketAddr = "127.0.0.1:8123".parse().unwrap();
+    let config = Default::default();
+
+    let firefox = driver::with_proxy(GeckoBackend::new().capabilities(&config), proxy, false);
+    let prefs = &firefox["moz:firefoxOptions"]["prefs"];
+    assert_eq!(prefs["network.proxy.type"], 1);
+    assert_eq!(prefs["network.proxy.http"], "127.0.0.1");
+    assert_eq!(prefs["network.proxy.ssl_port"], 8123);
+    assert_eq!(prefs["network.proxy.allow_hijacking_localhost"], true);
+    // The profile prefs are kept.
+    assert_eq!(prefs["browser.startup.page"], 0);
+    assert!(firefox.get("acceptInsecureCerts").is_none());
+
+    let chrome = driver::with_proxy(
+        driver::ChromiumBackend::new().capabilities(&config),
+        proxy,
+        true,
+    );
+    let args = chrome["goog:chromeOptions"]["args"].as_array().unwrap();
+    assert!(args.contains(&json!("--proxy-server=127.0.0.1:8123")));
+    assert!(args.contains(&json!("--no-first-run")));
+    // The proxy answers HTTPS with certificates of its own.
+    assert_eq!(chrome["acceptInsecureCerts"], true);
+
+    let other = driver::with_proxy(Default::default(), proxy, false);
+    assert_eq!(other["proxy"]["proxyType"], "manual");
+    assert_eq!(other["proxy"]["sslProxy"], "127.0.0.1:8123");
+}
+
+#[tokio::test]
+async fn browser_sessions_use_the_proxy() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    let addr = browser.proxy_addr().unwrap();
+    let session = driver.commands_named("new_session").pop().unwrap();
+    let caps = &session.body["capabilities"]["alwaysMatch"];
+    assert_eq!(caps["proxy"]["httpProxy"], addr.to_string());
+    assert_eq!(caps["acceptInsecureCerts"], true);
+    assert!(driver.config().data_dir.join(CA_CERT_FILE).exists());
+
+    // Pooled sessions too.
+    drop(browser.sessions().lease().await.unwrap());
+    let pooled = driver.commands_named("new_session").pop().unwrap();
+    let caps = &pooled.body["capabilities"]["alwaysMatch"];
+    assert_eq!(caps["proxy"]["httpProxy"], addr.to_string());
+    assert_eq!(caps["acceptInsecureCerts"], true);
+
+    // The proxy feeds the browser's network monitor.
+    let server = TestHttpServer::start().await.unwrap();
+    through_proxy(addr, get(&server.url("/hello"))).await;
+    assert_eq!(
+        browser.network().recent_requests(1)[0].url,
+        server.url("/hello")
+    );
+}
+
+#[tokio::test]
+async fn https_interception_can_be_turned_off() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config.network_intercept_https = false;
+    let browser = NyanBrowser::new(config.clone()).await.unwrap();
+    // The CA is still made, for the sites with mocks or header rules.
+    let session = driver.commands_named("new_session").pop().unwrap();
+    assert_eq!(
+        session.body["capabilities"]["alwaysMatch"]["acceptInsecureCerts"],
+        true
+    );
+    assert!(config.data_dir.join(CA_CERT_FILE).exists());
+
+    // Other sites are tunnelled untouched.
+    let server = TestHttpServer::start().await.unwrap();
+    let (status, tunnel) = connect(browser.proxy_addr().unwrap(), &server.addr().to_string()).await;
+    assert_eq!(status, StatusCode::OK);
+    let (mut sender, connection) = hyper::client::conn::handshake(tunnel.unwrap())
+        .await
+        .unwrap();
+    tokio::spawn(connection);
+    let response = sender.send_request(get("/hello")).await.unwrap();
+    assert_eq!(text(response).await, "hello nyan");
+}
+
+#[tokio::test]
+async fn the_proxy_can_be_turned_off() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config.network_proxy = false;
+    let browser = NyanBrowser::new(config).await.unwrap();
+    assert!(browser.proxy_addr().is_none());
+    let session = driver.commands_named("new_session").pop().unwrap();
+    assert!(session.body["capabilities"]["alwaysMatch"]
+        .get("proxy")
+        .is_none());
+}
+
+#[tokio::test]
+async fn remote_drivers_only_get_a_proxy_they_can_reach() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config.network_proxy_host = None;
+    let browser = NyanBrowser::new(config).await.unwrap();
+    assert!(browser.proxy_addr().is_none());
+    let session = driver.commands_named("new_session").pop().unwrap();
+    assert!(session.body["capabilities"]["alwaysMatch"]
+        .get("proxy")
+        .is_none());
+}
@@ -0,0 +1,124 @@
+use nyan_browser::core::omnibox::{fill_template, resolve, Destination, OmniboxContext};
+use nyan_browser::features::workspace::Bookmark;
+use nyan_browser::test_util::MockWebDriver;
+use nyan_browser::NyanBrowser;
+
+fn context() -> OmniboxContext {
+    OmniboxContext::new("https://duckduckgo.com/?q=")
+        .with_keyword("rs", "https://doc.rust-lang.org/std/?search=%s")
+        .with_keyword("gh", "https://github.com/search?q={query}")
+        .with_keyword("home", "https://example.com/home")
+}
+
+fn url(input: &str) -> String {
+    resolve(input, &context()).url().to_string()
+}
+
+#[test]
+fn urls_keep_their_scheme_and_get_normalized() {
+    assert_eq!(
+        url("https://www.rust-lang.org"),
+        "https://www.rust-lang.org/"
+    );
+    assert_eq!(url("  HTTP://Example.COM/path "), "http://example.com/path");
+    assert_eq!(url("about:blank"), "about:blank");
+    assert_eq!(url("file:///tmp/page.html"), "file:///tmp/page.html");
+    assert_eq!(url("http://bücher.de/"), "http://xn--bcher-kva.de/");
+}
+
+#[test]
+fn bare_hosts_get_a_scheme() {
+    assert_eq!(
+        resolve("example.com", &context()),
+        Destination::Url("https://example.com/".into())
+    );
+    assert_eq!(url("docs.rs/serde"), "https://docs.rs/serde");
+    assert_eq!(url("bücher.de"), "https://xn--bcher-kva.de/");
+    assert_eq!(url("localhost:3000/api"), "http://localhost:3000/api");
+    assert_eq!(url("192.168.1.1"), "http://192.168.1.1/");
+    assert_eq!(url("[::1]:8080"), "http://[::1]:8080/");
+}
+
+#[test]
+fn everything_else_is_a_search() {
+    let search = |q: &str| format!("https://duckduckgo.com/?q={}", q);
+    assert_eq!(url("rust"), search("rust"));
+    assert_eq!(url("what is rust?"), search("what+is+rust%3F"));
+    assert_eq!(url("3.14"), search("3.14"));
+    assert_eq!(
+        url("example.com:notaport"),
+        search("example.com%3Anotaport")
+    );
+    assert_eq!(url("?example.com"), search("example.com"));
+    assert_eq!(
+        resolve("c++ & rust", &context()),
+        Destination::Search {
+            query: "c++ & rust".into(),
+            url: search("c%2B%2B+%26+rust"),
+        }
+    );
+}
+
+#[test]
+fn internal_pages_and_keywords() {
+    assert_eq!(
+        resolve("", &context()),
+        Destination::Internal("kawaii://home".into())
+    );
+    assert_eq!(
+        resolve("KAWAII://History?q=Rust", &context()),
+        Destination::Internal("kawaii://history?q=Rust".into())
+    );
+    assert_eq!(
+        resolve("rs Vec", &context()),
+        Destination::Keyword {
+            keyword: "rs".into(),
+            url: "https://doc.rust-lang.org/std/?search=Vec".into(),
+        }
+    );
+    assert_eq!(url("GH tokio rs"), "https://github.com/search?q=tokio+rs");
+    assert_eq!(url("home"), "https://example.com/home");
+    // A search keyword without a query is just a word.
+    assert_eq!(url("rs"), "https://duckduckgo.com/?q=rs");
+}
+
+#[test]
+fn templates_take_a_placeholder_or_a_prefix() {
+    assert_eq!(
+        fill_template("https://s.example/?q={query}&x=1", "a b"),
+        "https://s.example/?q=a+b&x=1"
+    );
+    assert_eq!(
+        fill_template("https://s.example/?q=", "ä"),
+        "https://s.example/?q=%C3%A4"
+    );
+}
+
+#[tokio::test]
+async fn navigate_resolves_searches_and_bookmark_keywords() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    browser
+        .bookmarks()
+        .unwrap()
+        .add(Bookmark::new("https://docs.rs/%s", "docs.rs").with_keyword("docs"))
+        .unwrap();
+
+    browser.navigate("nyan cat").await.unwrap();
+    browser.navigate("docs serde").await.unwrap();
+    browser.navigate("example.com").await.unwrap();
+
+    let visited: Vec<String> = driver
+        .commands_named("goto")
+        .iter()
+        .map(|c| c.body["url"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        visited,
+        [
+            "https://duckduckgo.com/?q=nyan+cat",
+            "https://docs.rs/serde",
+            "https://example.com/",
+        ]
+    );
+}
@@ -0,0 +1,44 @@
+use nyan_browser::core::error::BrowserError;
+use nyan_browser::core::PortAllocator;
+use std::collections::HashSet;
+use std::net::TcpListener;
+
+#[test]
+fn concurrent_allocations_never_collide() {
+    let handles: Vec<_> = (0..16)
+        .map(|_| std::thread::spawn(|| PortAllocator::new().allocate().unwrap()))
+        .collect();
+    let reservations: Vec<_> = handles.into_iter().map(|h| h.join().unwrap()).collect();
+
+    let ports: HashSet<u16> = reservations.iter().map(|r| r.port()).collect();
+    assert_eq!(ports.len(), reservations.len());
+}
+
+#[test]
+fn honors_preferred_port_until_it_is_reserved() {
+    let free = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = free.local_addr().unwrap().port();
+    drop(free);
+
+    let first = PortAllocator::new().prefer(port).allocate().unwrap();
+    let second = PortAllocator::new().prefer(port).allocate().unwrap();
+    assert_eq!(first.port(), port);
+    assert_ne!(second.port(), port);
+
+    drop(first);
+    let third = PortAllocator::new().prefer(port).allocate().unwrap();
+    assert_eq!(third.port(), port);
+}
+
+#[test]
+fn reports_exhausted_range() {
+    let busy = TcpListener::bind("127.0.0.1:0").unwrap();
+    let port = busy.local_addr().unwrap().port();
+
+    let err = PortAllocator::new()
+        .within(port..port + 1)
+        .allocate()
+        .unwrap_err();
+
+    assert!(matches!(err, BrowserError::NoPortsAvailable));
+}
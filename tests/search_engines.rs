@@ -0,0 +1,128 @@
+use nyan_browser::config::{BrowserConfig, SearchEngine};
+use nyan_browser::test_util::MockWebDriver;
+use nyan_browser::NyanBrowser;
+use std::path::{Path, PathBuf};
+
+fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/fixtures/opensearch")
+        .join(name)
+}
+
+#[test]
+fn imports_opensearch_descriptions() {
+    let docs = SearchEngine::from_opensearch_file(&fixture("docs_rs.xml")).unwrap();
+    assert_eq!(docs.name, "Docs.rs");
+    assert_eq!(
+        docs.template,
+        "https://docs.rs/releases/search?query={query}&i-am-feeling-lucky=1"
+    );
+    assert_eq!(
+        docs.suggestion_url.as_deref(),
+        Some("https://docs.rs/releases/search?query={query}&format=json")
+    );
+    assert_eq!(docs.keyword, None);
+
+    let wikipedia = SearchEngine::from_opensearch_file(&fixture("wikipedia.xml")).unwrap();
+    assert_eq!(wikipedia.keyword.as_deref(), Some("wp"));
+    assert_eq!(
+        wikipedia.template,
+        "https://en.wikipedia.org/w/index.php?title=Special:Search&search={query}&ie=UTF-8&page=1"
+    );
+
+    assert!(SearchEngine::from_opensearch("<OpenSearchDescription/>").is_err());
+}
+
+#[test]
+fn registry_keeps_keywords_unique_and_survives_a_save() {
+    let dir = std::env::temp_dir().join(format!("nyan-search-{}", std::process::id()));
+    let mut config = BrowserConfig {
+        config_dir: dir.clone(),
+        ..BrowserConfig::default()
+    };
+    assert_eq!(config.search_template(), "https://duckduckgo.com/?q=");
+    assert_eq!(
+        config.search_engine_for_keyword("DOCS").unwrap().name,
+        "docs.rs"
+    );
+
+    let clash = SearchEngine::new("Other", "https://other.example/?q={query}").with_keyword("gh");
+    assert!(config.add_search_engine(clash).is_err());
+
+    config
+        .import_opensearch(&fixture("wikipedia.xml"), Some("w"))
+        .unwrap();
+    config.default_search_engine = Some("wikipedia (en)".to_string());
+    assert!(config
+        .search_template()
+        .starts_with("https://en.wikipedia.org/"));
+    assert!(config.remove_search_engine("GitHub").is_some());
+    config.save().unwrap();
+
+    let saved: BrowserConfig =
+        toml::from_str(&std::fs::read_to_string(dir.join("config.toml")).unwrap()).unwrap();
+    assert_eq!(saved.search_engines, config.search_engines);
+    assert_eq!(
+        saved.search_engine_for_keyword("w").unwrap().name,
+        "Wikipedia (en)"
+    );
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn old_configs_get_the_default_engines() {
+    let defaults = toml::to_string(&BrowserConfig::default()).unwrap();
+    let mut table: toml::Table = toml::from_str(&defaults).unwrap();
+    table.remove("search_engines");
+    let config: BrowserConfig = toml::from_str(&table.to_string()).unwrap();
+    assert!(config.search_engine("DuckDuckGo").is_some());
+}
+
+#[tokio::test]
+async fn engine_keywords_and_the_default_engine_drive_navigation() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+
+    browser.navigate("docs serde json").await.unwrap();
+    browser.update_config(|config| {
+        config.default_search_engine = Some("GitHub".to_string());
+    });
+    browser.navigate("nyan browser").await.unwrap();
+
+    let visited: Vec<String> = driver
+        .commands_named("goto")
+        .iter()
+        .map(|c| c.body["url"].as_str().unwrap().to_string())
+        .collect();
+    assert_eq!(
+        visited,
+        [
+            "https://docs.rs/releases/search?query=serde+json",
+            "https://github.com/search?q=nyan+browser",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn a_saved_config_drives_the_omnibox_of_the_next_browser() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let mut config = driver.config();
+    config
+        .add_search_engine(
+            SearchEngine::new("Crates", "https://crates.io/search?q={query}").with_keyword("cr"),
+        )
+        .unwrap();
+    config.default_search_engine = Some("GitHub".to_string());
+    config.save().unwrap();
+
+    let loaded = BrowserConfig::load_from(&driver.config().config_dir).unwrap();
+    let browser = NyanBrowser::new(loaded).await.unwrap();
+    assert_eq!(
+        browser.resolve_input("cr serde").url(),
+        "https://crates.io/search?q=serde"
+    );
+    assert_eq!(
+        browser.resolve_input("nyan browser").url(),
+        "https://github.com/search?q=nyan+browser"
+    );
+}
@@ -0,0 +1,138 @@
+use nyan_browser::core::{SessionSnapshot, SessionStore, TabKind, TabSnapshot};
+use nyan_browser::test_util::{MockReply, MockWebDriver};
+use nyan_browser::NyanBrowser;
+use serde_json::json;
+
+#[tokio::test]
+async fn saves_open_tabs_with_scroll_position() {
+    let driver = MockWebDriver::start().await.unwrap();
+    driver.set_title("https://docs.rs/", "Docs.rs");
+    driver.stub_execute("window.scrollX", MockReply::value(json!([0.0, 640.0])));
+    let config = driver.config();
+    let browser = NyanBrowser::new(config.clone()).await.unwrap();
+
+    browser.navigate("https://example.com/").await.unwrap();
+    browser
+        .tabs()
+        .new_tab(Some("https://docs.rs/"))
+        .await
+        .unwrap();
+    browser.save_session().await.unwrap();
+
+    let saved = SessionStore::new(&config.config_dir)
+        .load()
+        .unwrap()
+        .unwrap();
+    assert!(!saved.clean_exit);
+    assert_eq!(saved.tabs.len(), 2);
+    assert_eq!(saved.tabs[0].url, "https://example.com/");
+    assert_eq!(saved.tabs[1].title, "Docs.rs");
+    assert_eq!(saved.tabs[1].scroll_y, 640.0);
+    assert!(saved.tabs[1].active);
+
+    drop(browser);
+    let saved = SessionStore::new(&config.config_dir)
+        .load()
+        .unwrap()
+        .unwrap();
+    assert!(saved.clean_exit);
+}
+
+#[tokio::test]
+async fn saves_internal_pages_by_their_kawaii_url() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let config = driver.config();
+    let browser = NyanBrowser::new(config.clone()).await.unwrap();
+
+    browser.navigate("kawaii://about").await.unwrap();
+    browser.save_session().await.unwrap();
+
+    let saved = SessionStore::new(&config.config_dir)
+        .load()
+        .unwrap()
+        .unwrap();
+    assert_eq!(saved.tabs[0].url, "kawaii://about");
+}
+
+#[tokio::test]
+async fn a_crash_before_the_first_snapshot_is_noticed() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let config = nyan_browser::config::BrowserConfig {
+        session_snapshot_interval_seconds: 0,
+        ..driver.config()
+    };
+    SessionStore::new(&config.config_dir)
+        .save(&SessionSnapshot {
+            saved_at: chrono::Utc::now(),
+            clean_exit: true,
+            tabs: vec![TabSnapshot {
+                url: "https://a.example/".to_string(),
+                title: String::new(),
+                kind: TabKind::Window,
+                scroll_x: 0.0,
+                scroll_y: 0.0,
+                active: true,
+            }],
+        })
+        .unwrap();
+
+    let browser = NyanBrowser::new(config.clone()).await.unwrap();
+    assert!(!browser.last_session_crashed());
+    // Killed without a chance to clean up.
+    std::mem::forget(browser);
+
+    let browser = NyanBrowser::new(config).await.unwrap();
+    assert!(browser.last_session_crashed());
+    assert_eq!(
+        browser.last_session().unwrap().tabs[0].url,
+        "https://a.example/"
+    );
+}
+
+#[tokio::test]
+async fn restores_a_crashed_session() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let config = driver.config();
+    let tab = |url: &str, kind, active| TabSnapshot {
+        url: url.to_string(),
+        title: String::new(),
+        kind,
+        scroll_x: 0.0,
+        scroll_y: if active { 120.0 } else { 0.0 },
+        active,
+    };
+    SessionStore::new(&config.config_dir)
+        .save(&SessionSnapshot {
+            saved_at: chrono::Utc::now(),
+            clean_exit: false,
+            tabs: vec![
+                tab("https://a.example/", TabKind::Window, false),
+                tab("https://b.example/", TabKind::Tab, true),
+                tab("https://c.example/", TabKind::Window, false),
+            ],
+        })
+        .unwrap();
+
+    let browser = NyanBrowser::new(config).await.unwrap();
+    assert!(browser.last_session_crashed());
+
+    assert_eq!(browser.restore_last_session().await.unwrap(), 3);
+    assert_eq!(browser.restore_last_session().await.unwrap(), 0);
+
+    let tabs = browser.tabs().list().await.unwrap();
+    let urls: Vec<_> = tabs.iter().map(|t| t.url.as_str()).collect();
+    assert_eq!(
+        urls,
+        [
+            "https://a.example/",
+            "https://b.example/",
+            "https://c.example/"
+        ]
+    );
+    assert!(tabs[1].active);
+    assert_eq!(tabs[2].kind, TabKind::Window);
+    assert!(driver
+        .executed_scripts()
+        .iter()
+        .any(|s| s.contains("scrollTo")));
+}
@@ -0,0 +1,94 @@
+use nyan_browser::core::{TabEvent, TabKind};
+use nyan_browser::test_util::MockWebDriver;
+use nyan_browser::NyanBrowser;
+use parking_lot::Mutex;
+use std::sync::Arc;
+
+#[tokio::test]
+async fn opens_lists_and_closes_tabs() {
+    let driver = MockWebDriver::start().await.unwrap();
+    driver.set_title("https://rust-lang.org/", "Rust");
+    driver.set_title("https://docs.rs/", "Docs.rs");
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    let tabs = browser.tabs();
+
+    let first = tabs.current().await.unwrap();
+    let rust = tabs.new_tab(Some("https://rust-lang.org/")).await.unwrap();
+    let docs = tabs.new_window(Some("https://docs.rs/")).await.unwrap();
+
+    let listed = tabs.list().await.unwrap();
+    assert_eq!(listed.len(), 3);
+    assert_eq!(listed[1].title, "Rust");
+    assert_eq!(listed[2].url, "https://docs.rs/");
+    assert_eq!(listed[2].kind, TabKind::Window);
+    assert!(listed[2].active);
+    assert_eq!(tabs.current().await.unwrap(), docs);
+
+    tabs.switch_to_index(0).await.unwrap();
+    assert_eq!(tabs.current().await.unwrap(), first);
+    tabs.focus_previous().await.unwrap();
+    assert_eq!(tabs.current().await.unwrap(), docs);
+
+    tabs.close(&docs).await.unwrap();
+    assert_eq!(tabs.current().await.unwrap(), rust);
+    assert_eq!(tabs.list().await.unwrap().len(), 2);
+}
+
+#[tokio::test]
+async fn reports_tab_events_to_subscribers() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    browser
+        .tab_tracker()
+        .subscribe(move |event| sink.lock().push(event.clone()));
+
+    let handle = browser.tabs().new_tab(None).await.unwrap();
+    browser.navigate("https://example.com/").await.unwrap();
+    browser.tabs().close_current().await.unwrap();
+
+    let events = events.lock();
+    assert_eq!(
+        events[0],
+        TabEvent::Opened {
+            handle: handle.clone(),
+            kind: TabKind::Tab
+        }
+    );
+    assert!(events.contains(&TabEvent::Navigated {
+        handle: handle.clone(),
+        url: "https://example.com/".to_string()
+    }));
+    assert!(events.contains(&TabEvent::Closed { handle }));
+}
+
+#[tokio::test]
+async fn closing_a_background_tab_does_not_activate_it() {
+    let driver = MockWebDriver::start().await.unwrap();
+    let browser = NyanBrowser::new(driver.config()).await.unwrap();
+    let tabs = browser.tabs();
+    let first = tabs.current().await.unwrap();
+    let second = tabs.new_tab(None).await.unwrap();
+
+    let events = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&events);
+    browser
+        .tab_tracker()
+        .subscribe(move |event| sink.lock().push(event.clone()));
+    tabs.close(&first).await.unwrap();
+
+    assert_eq!(*events.lock(), [TabEvent::Closed { handle: first }]);
+    assert_eq!(tabs.current().await.unwrap(), second);
+    assert_eq!(browser.tab_tracker().active(), Some(second.clone()));
+
+    // Focus stays put even when the closed tab sat before the focused one.
+    let third = tabs.new_tab(None).await.unwrap();
+    tabs.switch_to(&third).await.unwrap();
+    events.lock().clear();
+    tabs.close(&second).await.unwrap();
+
+    assert_eq!(*events.lock(), [TabEvent::Closed { handle: second }]);
+    assert_eq!(tabs.current().await.unwrap(), third);
+    assert_eq!(browser.tab_tracker().active(), Some(third));
+}